@@ -0,0 +1,26 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // the gRPC service code is generated from proto/transactions.proto, using a vendored protoc
+    // so building doesn't depend on one being installed
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/transactions.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc is available");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::compile_protos("proto/transactions.proto").expect("proto compiles");
+    }
+    // the C header for src/ffi.rs, kept in the tree so C callers needn't build with the feature
+    // to get it
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=src/bank.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir))
+            .expect("cbindgen.toml is valid");
+        cbindgen::generate_with_config(&dir, config)
+            .expect("src/ffi.rs has a C header")
+            .write_to_file(format!("{}/include/transactions.h", dir));
+    }
+}
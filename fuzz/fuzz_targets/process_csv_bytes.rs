@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use transactions::{Bank, OnError, OutputFormat, Policy};
+
+// Any bytes at all as a CSV file, with interest on so accruals are exercised too, then both kinds
+// of report of whatever balances came of it
+fuzz_target!(|data: &[u8]| {
+    let mut bank = Bank::with_policy(Policy {
+        on_error: OnError::Report,
+        interest_rate: "0.05".parse().expect("a valid rate"),
+        ..Policy::default()
+    });
+    let _ = bank.process_csv_bytes(data);
+    let _ = bank.write_report(std::io::sink());
+    let _ = bank.write_report_as(std::io::sink(), OutputFormat::Json);
+});
@@ -0,0 +1,114 @@
+#ifndef TRANSACTIONS_H
+#define TRANSACTIONS_H
+
+/* Generated by cbindgen from src/ffi.rs when building with the ffi feature; don't edit. */
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#define TX_WITHDRAWAL 1
+
+#define TX_DEPOSIT 2
+
+#define TX_DISPUTE 3
+
+#define TX_RESOLVE 4
+
+#define TX_CHARGEBACK 5
+
+#define TX_UNLOCK 6
+
+#define TX_TRANSFER 7
+
+#define TX_FEE 8
+
+#define TX_ACCRUE 9
+
+#define TX_CONVERT 10
+
+#define TX_REFUND 11
+
+#define TX_CLOSE 12
+
+#define TX_MERGE 13
+
+#define TX_HOLD 14
+
+#define TX_RELEASE 15
+
+#define TX_HAS_TO 1
+
+#define TX_HAS_TIMESTAMP (1 << 1)
+
+#define TX_HAS_CURRENCY (1 << 2)
+
+#define TX_HAS_TO_CURRENCY (1 << 3)
+
+#define TX_APPLIED 0
+
+#define TX_REJECTED 1
+
+#define TX_INVALID -1
+
+#define MAX_OCCURRENCES 10000
+
+typedef struct TxBank TxBank;
+
+#if !defined(TRANSACTIONS_WIDE_IDS)
+typedef uint16_t TxClientId;
+#endif
+
+#if defined(TRANSACTIONS_WIDE_IDS)
+typedef uint32_t TxClientId;
+#endif
+
+#if !defined(TRANSACTIONS_WIDE_IDS)
+typedef uint32_t TxTxnId;
+#endif
+
+#if defined(TRANSACTIONS_WIDE_IDS)
+typedef uint64_t TxTxnId;
+#endif
+
+typedef struct TxTransaction {
+  uint8_t tx_type;
+  uint8_t flags;
+  TxClientId client;
+  TxTxnId tx;
+  int64_t amount;
+  TxClientId to;
+  int64_t timestamp;
+  uint8_t currency[3];
+  uint8_t to_currency[3];
+} TxTransaction;
+
+typedef struct TxBalance {
+  int64_t available;
+  int64_t held;
+  int64_t total;
+  bool locked;
+  bool closed;
+} TxBalance;
+
+
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+struct TxBank *tx_bank_new(void);
+
+void tx_bank_free(struct TxBank *bank);
+
+int32_t tx_bank_submit(struct TxBank *bank, const struct TxTransaction *txn, const char **reason);
+
+bool tx_bank_balance(const struct TxBank *bank,
+                     TxClientId client,
+                     const char *currency,
+                     struct TxBalance *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TRANSACTIONS_H */
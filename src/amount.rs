@@ -0,0 +1,367 @@
+use crate::policy::PrecisionPolicy;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    error, fmt,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+// Amounts are tracked to four places past the decimal
+pub const SCALE: u32 = 4;
+
+// 10^SCALE, the units in one
+const UNIT: i64 = 10_000;
+
+// The largest amount, either way, the engine applies, a trillion. It was a quintillion while
+// amounts were Decimals, but an i64 of ten-thousandths only holds up to about 922 trillion, so
+// the limit came down with the switch to them. It still takes hundreds of the largest amounts to
+// overflow a balance, and a transaction that would is refused, see TxnError::BalanceOverflow.
+const LIMIT: i64 = 1_000_000_000_000;
+
+// A fixed-point money amount, counted in ten-thousandths. Unlike f32, sums of many small
+// deposits stay exact, and unlike a Decimal, adding and comparing them is plain integer
+// arithmetic on half the memory.
+//
+// Amounts are only fixed-point once they're parsed: an input amount with places past SCALE is
+// rounded or refused there, as the engine's PrecisionPolicy says, and one past an i64's range
+// fails to parse. Sums that could leave the range are made with checked_add and checked_sub;
+// the operators panic on overflow, as an i64's do in debug builds, but in every build.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Copy, Clone)]
+pub struct Amount(i64);
+
+// Why a written amount isn't one
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum AmountError {
+    Invalid,
+    // places past SCALE that aren't zeros, under PrecisionPolicy::Reject
+    TooPrecise,
+    OutOfRange,
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Invalid => write!(f, "invalid amount"),
+            AmountError::TooPrecise => write!(f, "amount has more than four decimal places"),
+            AmountError::OutOfRange => write!(f, "amount out of range"),
+        }
+    }
+}
+
+impl error::Error for AmountError {}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    // units ten-thousandths
+    pub const fn from_units(units: i64) -> Amount {
+        Amount(units)
+    }
+
+    // How many ten-thousandths it is
+    pub fn units(&self) -> i64 {
+        self.0
+    }
+
+    // value rounded half away from zero to SCALE places, None if that's out of range
+    pub fn new(value: Decimal) -> Option<Amount> {
+        Amount::from_decimal(value, PrecisionPolicy::Round).ok()
+    }
+
+    // A Decimal from the input, e.g. a Parquet DECIMAL column, rounded or refused as precision
+    // says when it has places past SCALE
+    pub fn from_decimal(value: Decimal, precision: PrecisionPolicy) -> Result<Amount, AmountError> {
+        let rounded = value.round_dp_with_strategy(SCALE, RoundingStrategy::MidpointAwayFromZero);
+        if rounded != value && precision == PrecisionPolicy::Reject {
+            return Err(AmountError::TooPrecise);
+        }
+        rounded
+            .checked_mul(Decimal::from(UNIT))
+            .and_then(|units| i64::try_from(units).ok())
+            .map(Amount)
+            .ok_or(AmountError::OutOfRange)
+    }
+
+    // An amount as the input writes it, plain digits with an optional sign and point, rounded
+    // half away from zero or refused as precision says when it has places past SCALE
+    pub fn parse(s: &str, precision: PrecisionPolicy) -> Result<Amount, AmountError> {
+        let s = s.trim();
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (whole, places) = digits.split_once('.').unwrap_or((digits, ""));
+        let numeric = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && places.is_empty()) || !numeric(whole) || !numeric(places) {
+            return Err(AmountError::Invalid);
+        }
+        let (kept, excess) = places.split_at(places.len().min(SCALE as usize));
+        let padding = (kept.len()..SCALE as usize).map(|_| b'0');
+        let mut units = whole
+            .bytes()
+            .chain(kept.bytes())
+            .chain(padding)
+            .try_fold(0_i64, |n, b| {
+                n.checked_mul(10)?.checked_add(i64::from(b - b'0'))
+            })
+            .ok_or(AmountError::OutOfRange)?;
+        if excess.bytes().any(|b| b != b'0') {
+            if precision == PrecisionPolicy::Reject {
+                return Err(AmountError::TooPrecise);
+            }
+            if excess.as_bytes()[0] >= b'5' {
+                units = units.checked_add(1).ok_or(AmountError::OutOfRange)?;
+            }
+        }
+        Ok(Amount(if negative { -units } else { units }))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn as_decimal(&self) -> Decimal {
+        Decimal::new(self.0, SCALE)
+    }
+
+    // Beyond LIMIT, see TxnError::AmountTooLarge
+    pub fn is_too_large(&self) -> bool {
+        self.0.unsigned_abs() > (LIMIT * UNIT) as u64
+    }
+
+    // None where the sum is out of range, see TxnError::BalanceOverflow
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
+    // The largest amount there is where the sum is out of range, for running totals that only
+    // ever compare against a limit or are only reported
+    pub fn saturating_add(self, rhs: Amount) -> Amount {
+        Amount(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Amount, AmountError> {
+        Amount::parse(s, PrecisionPolicy::Round)
+    }
+}
+
+// How amounts are written out in reports
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct AmountStyle {
+    // decimal places, rounding half away from zero when fewer than SCALE
+    pub precision: u32,
+    // drop trailing zeros after the point, and the point itself if nothing is left after it
+    pub trim_zeros: bool,
+    // write the amount as an integer count of 10^-precision units, e.g. cents for precision 2
+    pub minor_units: bool,
+}
+
+impl Default for AmountStyle {
+    fn default() -> Self {
+        AmountStyle {
+            precision: SCALE,
+            trim_zeros: false,
+            minor_units: false,
+        }
+    }
+}
+
+impl Amount {
+    pub fn format(&self, style: AmountStyle) -> String {
+        if style == AmountStyle::default() {
+            return self.to_string();
+        }
+        // a Decimal holds at most 28 places
+        let precision = style.precision.min(28);
+        let mut rounded = self
+            .as_decimal()
+            .round_dp_with_strategy(precision, RoundingStrategy::MidpointAwayFromZero);
+        if style.minor_units {
+            rounded.rescale(precision);
+            rounded.mantissa().to_string()
+        } else if style.trim_zeros {
+            rounded.normalize().to_string()
+        } else {
+            padded(rounded, precision)
+        }
+    }
+}
+
+// value, already rounded to no more than places, written with exactly that many. rust_decimal's
+// own `{:.*}` panics when the digits don't fit in its buffer, which a large enough balance reaches.
+fn padded(value: Decimal, places: u32) -> String {
+    let mut text = value.to_string();
+    let scale = value.scale();
+    if scale < places {
+        if scale == 0 {
+            text.push('.');
+        }
+        text.extend((scale..places).map(|_| '0'));
+    }
+    text
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let unit = UNIT as u64;
+        write!(f, "{}{}.{:04}", sign, units / unit, units % unit)
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Amount) -> Amount {
+        self.checked_add(rhs).expect("amount overflowed")
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, rhs: Amount) -> Amount {
+        self.checked_sub(rhs).expect("amount overflowed")
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, rhs: Amount) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, rhs: Amount) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount(self.0.checked_neg().expect("amount overflowed"))
+    }
+}
+
+// Parse from the textual form so no precision is lost going through a float. Places past SCALE
+// are rounded; inputs whose PrecisionPolicy may refuse them check the text themselves, see
+// source.rs.
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Amount, D::Error> {
+        struct AmountVisitor;
+
+        impl de::Visitor<'_> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a decimal amount")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Amount, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        de.deserialize_str(AmountVisitor)
+    }
+}
+
+// Serialized in the same four-place form used by the report
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str, precision: PrecisionPolicy) -> Result<String, AmountError> {
+        Amount::parse(s, precision).map(|amount| amount.to_string())
+    }
+
+    #[test]
+    fn parses_plain_digits() {
+        assert_eq!(parse("1.5", PrecisionPolicy::Round), Ok("1.5000".into()));
+        assert_eq!(
+            parse(" -.25 ", PrecisionPolicy::Round),
+            Ok("-0.2500".into())
+        );
+        assert_eq!(parse("+7", PrecisionPolicy::Round), Ok("7.0000".into()));
+        assert_eq!(parse("3.", PrecisionPolicy::Round), Ok("3.0000".into()));
+        for invalid in ["", "-", ".", "1e5", "1.2.3", "--1", "1,5", "0x10"] {
+            assert_eq!(
+                parse(invalid, PrecisionPolicy::Round),
+                Err(AmountError::Invalid)
+            );
+        }
+    }
+
+    #[test]
+    fn rounds_or_refuses_extra_places() {
+        assert_eq!(
+            parse("1.23445", PrecisionPolicy::Round),
+            Ok("1.2345".into())
+        );
+        assert_eq!(
+            parse("-1.23444999", PrecisionPolicy::Round),
+            Ok("-1.2344".into())
+        );
+        assert_eq!(
+            parse("2.000000", PrecisionPolicy::Reject),
+            Ok("2.0000".into())
+        );
+        assert_eq!(
+            parse("2.00001", PrecisionPolicy::Reject),
+            Err(AmountError::TooPrecise)
+        );
+        let decimal = Decimal::new(-123455, 5);
+        assert_eq!(
+            Amount::from_decimal(decimal, PrecisionPolicy::Round),
+            Ok(Amount::from_units(-12346))
+        );
+        assert_eq!(
+            Amount::from_decimal(decimal, PrecisionPolicy::Reject),
+            Err(AmountError::TooPrecise)
+        );
+    }
+
+    #[test]
+    fn refuses_amounts_past_an_i64() {
+        assert_eq!(
+            parse("922337203685477.5807", PrecisionPolicy::Round),
+            Ok("922337203685477.5807".into())
+        );
+        assert_eq!(
+            parse("922337203685477.5808", PrecisionPolicy::Round),
+            Err(AmountError::OutOfRange)
+        );
+        assert_eq!(
+            parse("922337203685477.58075", PrecisionPolicy::Round),
+            Err(AmountError::OutOfRange)
+        );
+        let max = Amount::from_units(i64::MAX);
+        assert_eq!(max.checked_add(Amount::from_units(1)), None);
+        assert_eq!(max.saturating_add(max), max);
+        assert!(Amount::from_units(LIMIT * UNIT + 1).is_too_large());
+        assert!(!Amount::from_units(-LIMIT * UNIT).is_too_large());
+    }
+}
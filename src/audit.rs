@@ -0,0 +1,279 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, ClientId, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::transfer;
+use serde::Serialize;
+use std::{fmt, io};
+
+// A record of every transaction handed to the bank and what was decided about it, one JSON
+// object per line, for compliance review. Each line has the transaction's fields, its outcome
+// code (or "rejected" and the reason code), and for an accepted one how it changed every balance
+// it touched: the client's, the recipient's for a transfer or a dispute of one, and both of the
+// client's currencies for a conversion. Changes are signed deltas, with the lock state after.
+//
+// The outcome stream is the short form of it, a CSV line per transaction for a downstream system
+// to ack or nack each record it sent: the transaction's type, client and tx id, its outcome code
+// (or "rejected" and the reason code), and the client's available and held balance after it, in
+// the currency it was in, left blank for a client that still has no account.
+//
+// Write errors don't stop processing; the first one is kept and returned by flush_audit. What's
+// still buffered when the bank is dropped is written then, ignoring errors.
+pub(crate) struct AuditLog {
+    out: io::BufWriter<Box<dyn io::Write + Send>>,
+    error: Option<io::Error>,
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
+
+pub(crate) struct OutcomeStream {
+    wtr: csv::Writer<Box<dyn io::Write + Send>>,
+    error: Option<csv::Error>,
+}
+
+impl fmt::Debug for OutcomeStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutcomeStream").finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize)]
+struct OutcomeRecord {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    outcome: &'static str,
+    reason: Option<&'static str>,
+    currency: Option<Currency>,
+    available: Option<Amount>,
+    held: Option<Amount>,
+}
+
+#[derive(Serialize)]
+struct AuditEntry {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount: Option<Amount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_currency: Option<Currency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<ClientId>,
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    // "velocity_exceeded" for a transaction a flagging VelocityRule let through
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert: Option<&'static str>,
+    changes: Vec<BalanceChange>,
+}
+
+#[derive(Serialize)]
+struct BalanceChange {
+    client: ClientId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    available: Amount,
+    held: Amount,
+    locked: bool,
+}
+
+// A balance a transaction may touch, as it was before it was applied
+struct Touched {
+    client: ClientId,
+    currency: Option<Currency>,
+    available: Amount,
+    held: Amount,
+}
+
+impl Bank {
+    // Record every later insert_txn in w, see AuditLog
+    pub fn audit_to<W: io::Write + Send + 'static>(&mut self, w: W) {
+        self.audit = Some(AuditLog {
+            out: io::BufWriter::new(Box::new(w)),
+            error: None,
+        });
+    }
+
+    // Write a line per later insert_txn to w, see OutcomeStream. A header line comes first
+    // unless header is false, e.g. for adding to a file that already has one.
+    pub fn outcomes_to<W: io::Write + Send + 'static>(&mut self, w: W, header: bool) {
+        let mut stream = OutcomeStream {
+            wtr: csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Box::new(w)),
+            error: None,
+        };
+        if header {
+            let columns = [
+                "type",
+                "client",
+                "tx",
+                "outcome",
+                "reason",
+                "currency",
+                "available",
+                "held",
+            ];
+            if let Err(err) = stream.wtr.write_record(columns) {
+                stream.error = Some(err);
+            }
+        }
+        self.outcomes = Some(stream);
+    }
+
+    // Write out what's buffered in the audit log and the outcome stream, returning the first error
+    // writing either if any
+    pub fn flush_audit(&mut self) -> Result<(), Error> {
+        if let Some(audit) = &mut self.audit {
+            if let Some(err) = audit.error.take() {
+                return Err(err.into());
+            }
+            io::Write::flush(&mut audit.out)?;
+        }
+        if let Some(outcomes) = &mut self.outcomes {
+            if let Some(err) = outcomes.error.take() {
+                return Err(err.into());
+            }
+            outcomes.wtr.flush()?;
+        }
+        Ok(())
+    }
+
+    // insert_txn's decision, recorded in the audit log and the outcome stream when there are ones
+    pub(crate) fn audited(
+        &mut self,
+        txn: Transaction,
+        apply: impl FnOnce(&mut Bank, Transaction) -> Result<TxnOutcome, TxnError>,
+    ) -> Result<TxnOutcome, TxnError> {
+        if self.audit.is_none() {
+            let result = apply(self, txn);
+            self.stream_outcome(&txn, &result);
+            return result;
+        }
+        let touched = self.touched(&txn);
+        let result = apply(self, txn);
+        self.stream_outcome(&txn, &result);
+        let changes = match result {
+            Ok(_) => touched
+                .into_iter()
+                .filter_map(|before| self.change(before))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let entry = AuditEntry {
+            tx_type: txn.tx_type,
+            client: txn.client,
+            tx: txn.tx,
+            amount: (txn.tx_type.moves_funds() || !txn.amount.is_zero()).then_some(txn.amount),
+            currency: txn.currency,
+            to_currency: txn.to_currency,
+            to: txn.to,
+            outcome: match &result {
+                Ok(outcome) => outcome.code(),
+                Err(_) => "rejected",
+            },
+            reason: result.as_ref().err().map(TxnError::code),
+            alert: self
+                .velocity
+                .alerted
+                .then_some(TxnError::VelocityExceeded.code()),
+            changes,
+        };
+        if let Some(audit) = &mut self.audit {
+            let written = serde_json::to_writer(&mut audit.out, &entry)
+                .map_err(io::Error::from)
+                .and_then(|_| io::Write::write_all(&mut audit.out, b"\n"));
+            if let Err(err) = written {
+                audit.error.get_or_insert(err);
+            }
+        }
+        result
+    }
+
+    fn stream_outcome(&mut self, txn: &Transaction, result: &Result<TxnOutcome, TxnError>) {
+        if self.outcomes.is_none() {
+            return;
+        }
+        let client = self.bank.get(&txn.client);
+        let currency = client
+            .map_or(Ok(txn.currency), |client| client.currency_of(txn))
+            .unwrap_or(txn.currency);
+        let balance = client.map(|client| client.balance(currency));
+        let record = OutcomeRecord {
+            tx_type: txn.tx_type,
+            client: txn.client,
+            tx: txn.tx,
+            outcome: match result {
+                Ok(outcome) => outcome.code(),
+                Err(_) => "rejected",
+            },
+            reason: result.as_ref().err().map(TxnError::code),
+            currency,
+            available: balance.map(|balance| balance.available),
+            held: balance.map(|balance| balance.held),
+        };
+        if let Some(outcomes) = &mut self.outcomes {
+            if let Err(err) = outcomes.wtr.serialize(record) {
+                outcomes.error.get_or_insert(err);
+            }
+        }
+    }
+
+    // The balances txn could change, as they are now
+    fn touched(&self, txn: &Transaction) -> Vec<Touched> {
+        let client = self.bank.get(&txn.client);
+        let currency = client
+            .map_or(Ok(txn.currency), |client| client.currency_of(txn))
+            .unwrap_or(txn.currency);
+        let mut touched = vec![(txn.client, currency)];
+        if txn.tx_type == TransactionType::Convert {
+            touched.push((txn.client, txn.to_currency));
+        }
+        let recipient = match client {
+            Some(client) if transfer::is_cross_client(Some(client), txn).unwrap_or(false) => {
+                transfer::recipient(client, txn).ok()
+            }
+            _ => None,
+        };
+        touched.extend(recipient.map(|recipient| (recipient, currency)));
+        touched
+            .into_iter()
+            .map(|(client, currency)| {
+                let balance = self
+                    .bank
+                    .get(&client)
+                    .map(|account| account.balance(currency))
+                    .unwrap_or_default();
+                Touched {
+                    client,
+                    currency,
+                    available: balance.available,
+                    held: balance.held,
+                }
+            })
+            .collect()
+    }
+
+    fn change(&self, before: Touched) -> Option<BalanceChange> {
+        let account = self.bank.get(&before.client)?;
+        let after = account.balance(before.currency);
+        Some(BalanceChange {
+            client: before.client,
+            currency: before.currency,
+            available: after.available - before.available,
+            held: after.held - before.held,
+            locked: account.locked,
+        })
+    }
+}
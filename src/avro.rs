@@ -0,0 +1,821 @@
+// Avro object container files, read by hand like the Parquet ones. The header carries the
+// writer's schema as JSON, which is checked before any rows are read: its top-level record needs
+// fields named like the CSV headers, at least type, client, tx and amount, each of a type that
+// converts to what the CSV column holds, optionally in a union with null. Fields with other names
+// are decoded and skipped.
+//
+// After the header come blocks of records, each uncompressed or compressed with deflate, Snappy
+// or zstandard, read one block at a time so a file of any size streams through.
+
+use crate::bank::{Transaction, TransactionType};
+use crate::columns::{self, Datum};
+use crate::error::Error;
+use crate::policy::PrecisionPolicy;
+use crate::snappy;
+use crate::snapshot::invalid;
+use crate::source::{decided, STANDARD_COLUMNS};
+use serde::{de::IntoDeserializer, Deserialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read},
+    sync::Arc,
+};
+
+const MAGIC: &[u8] = b"Obj\x01";
+const SYNC: usize = 16;
+
+// Transactions from an Avro container file, a block held in memory at a time
+pub(crate) struct AvroRows<R: Read> {
+    reader: BufReader<R>,
+    // read on the first call to next, so a bad one is that call's error
+    header: Option<Header>,
+    // the current block, decompressed, how far into it the records have been read and how many
+    // are left
+    block: Vec<u8>,
+    at: usize,
+    left: u64,
+    // rows returned from the whole file
+    row: u64,
+    done: bool,
+}
+
+struct Header {
+    // the top-level record's fields, and which of the STANDARD_COLUMNS each holds, if any
+    fields: Vec<(Schema, Option<usize>)>,
+    codec: Codec,
+    sync: [u8; SYNC],
+    // decimal places of an amount stored as a decimal, and how many of a timestamp's units make
+    // a second
+    scale: Option<u32>,
+    per_second: Option<i64>,
+}
+
+#[derive(Clone, Copy)]
+enum Codec {
+    Null,
+    #[cfg(feature = "gzip")]
+    Deflate,
+    Snappy,
+    #[cfg(feature = "zstd")]
+    Zstandard,
+}
+
+#[derive(Clone, Debug)]
+enum Schema {
+    Null,
+    Boolean,
+    Int,
+    // how many units make a second, for the timestamp logical types
+    Long(Option<i64>),
+    Float,
+    Double,
+    // the scale, for the decimal logical type
+    Bytes(Option<u32>),
+    String,
+    Fixed(usize, Option<u32>),
+    // the symbols
+    Enum(Vec<Arc<[u8]>>),
+    Array(Box<Schema>),
+    Map(Box<Schema>),
+    Union(Vec<Schema>),
+    Record(Vec<Schema>),
+}
+
+impl<R: Read> AvroRows<R> {
+    pub(crate) fn new(reader: R) -> AvroRows<R> {
+        AvroRows {
+            reader: BufReader::new(reader),
+            header: None,
+            block: Vec::new(),
+            at: 0,
+            left: 0,
+            row: 0,
+            done: false,
+        }
+    }
+
+    // The row number of the last row returned, counting from 1
+    pub(crate) fn row(&self) -> u64 {
+        self.row
+    }
+
+    fn read_header(&mut self) -> Result<Header, Error> {
+        let mut magic = [0; 4];
+        if self.reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+            return Err(invalid("not an Avro container file".to_string()));
+        }
+        let mut metadata = HashMap::new();
+        loop {
+            let mut count = read_long(&mut self.reader)?;
+            if count == 0 {
+                break;
+            }
+            if count < 0 {
+                // a negative count is followed by the block's size in bytes
+                count = -count;
+                read_long(&mut self.reader)?;
+            }
+            for _ in 0..count {
+                let key = read_bytes(&mut self.reader)?;
+                let value = read_bytes(&mut self.reader)?;
+                metadata.insert(key, value);
+            }
+        }
+        let mut sync = [0; SYNC];
+        self.reader.read_exact(&mut sync)?;
+
+        let codec = match metadata.get(b"avro.codec".as_slice()).map(Vec::as_slice) {
+            None | Some(b"null") => Codec::Null,
+            #[cfg(feature = "gzip")]
+            Some(b"deflate") => Codec::Deflate,
+            Some(b"snappy") => Codec::Snappy,
+            #[cfg(feature = "zstd")]
+            Some(b"zstandard") => Codec::Zstandard,
+            Some(other) => {
+                return Err(invalid(format!(
+                    "Avro file is compressed with {}, which this build can't read",
+                    String::from_utf8_lossy(other)
+                )))
+            }
+        };
+        let schema = metadata
+            .get(b"avro.schema".as_slice())
+            .ok_or_else(|| invalid("Avro file has no schema".to_string()))?;
+        let schema: Value = serde_json::from_slice(schema)
+            .map_err(|err| invalid(format!("Avro schema isn't valid JSON: {}", err)))?;
+        let Value::Object(record) = &schema else {
+            return Err(schema_error("the top level isn't a record"));
+        };
+        if record.get("type").and_then(Value::as_str) != Some("record") {
+            return Err(schema_error("the top level isn't a record"));
+        }
+        let namespace = record
+            .get("namespace")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let mut names = HashMap::new();
+        let mut fields = Vec::new();
+        for field in fields_of(record)? {
+            let name = field
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let ty = field
+                .get("type")
+                .ok_or_else(|| schema_error(&format!("field '{}' has no type", name)))?;
+            let schema = parse(ty, namespace, &mut names)?;
+            let column = STANDARD_COLUMNS.iter().position(|column| *column == name);
+            if let Some(column) = column {
+                check_column(column, &schema)?;
+            }
+            fields.push((schema, column));
+        }
+        if let Some(missing) = (0..4).find(|c| !fields.iter().any(|(_, f)| *f == Some(*c))) {
+            return Err(invalid(format!(
+                "Avro schema has no '{}' field",
+                STANDARD_COLUMNS[missing]
+            )));
+        }
+        let branches = |column: usize| {
+            fields
+                .iter()
+                .filter(move |(_, f)| *f == Some(column))
+                .flat_map(|(schema, _)| match schema {
+                    Schema::Union(branches) => branches.as_slice(),
+                    schema => std::slice::from_ref(schema),
+                })
+        };
+        let scale = branches(3).find_map(|schema| match schema {
+            Schema::Bytes(scale) | Schema::Fixed(_, scale) => *scale,
+            _ => None,
+        });
+        let per_second = branches(5).find_map(|schema| match schema {
+            Schema::Long(per_second) => *per_second,
+            _ => None,
+        });
+        Ok(Header {
+            fields,
+            codec,
+            sync,
+            scale,
+            per_second,
+        })
+    }
+
+    // Reads the next block into self.block, or returns false at the end of the file
+    fn read_block(&mut self, codec: Codec, sync: &[u8; SYNC]) -> Result<bool, Error> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let count = read_long(&mut self.reader)?;
+        let size = read_long(&mut self.reader)?;
+        if count < 0 || size < 0 {
+            return Err(corrupt("negative block length"));
+        }
+        let mut data = Vec::new();
+        self.reader
+            .by_ref()
+            .take(size as u64)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 != size as u64 {
+            return Err(corrupt("truncated block"));
+        }
+        let mut marker = [0; SYNC];
+        self.reader
+            .read_exact(&mut marker)
+            .map_err(|_| corrupt("truncated block"))?;
+        if marker != *sync {
+            return Err(corrupt("block doesn't end in the file's sync marker"));
+        }
+        self.block = match codec {
+            Codec::Null => data,
+            #[cfg(feature = "gzip")]
+            Codec::Deflate => {
+                let mut out = Vec::new();
+                flate2::read::DeflateDecoder::new(data.as_slice()).read_to_end(&mut out)?;
+                out
+            }
+            // the block ends in a big-endian CRC-32 of what it decompresses to
+            Codec::Snappy => {
+                let (compressed, crc) = data
+                    .split_last_chunk::<4>()
+                    .ok_or_else(|| corrupt("truncated block"))?;
+                let out = snappy::decompress(compressed)?;
+                if crc32(&out) != u32::from_be_bytes(*crc) {
+                    return Err(corrupt("block fails its checksum"));
+                }
+                out
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstandard => {
+                let mut out = Vec::new();
+                zstd::Decoder::with_buffer(data.as_slice())?.read_to_end(&mut out)?;
+                out
+            }
+        };
+        self.at = 0;
+        self.left = count as u64;
+        Ok(true)
+    }
+
+    fn next_row(&mut self) -> Result<Option<[Datum; 9]>, Error> {
+        if self.header.is_none() {
+            self.header = Some(self.read_header()?);
+        }
+        let header = self.header.as_ref().expect("read above");
+        let (codec, sync) = (header.codec, header.sync);
+        while self.left == 0 {
+            if self.at != self.block.len() {
+                return Err(corrupt("block holds more than its records"));
+            }
+            if !self.read_block(codec, &sync)? {
+                return Ok(None);
+            }
+        }
+        let header = self.header.as_ref().expect("read above");
+        let mut data = &self.block[self.at..];
+        let mut row = std::array::from_fn(|_| Datum::Null);
+        for (schema, column) in &header.fields {
+            let value = read_value(schema, &mut data)?;
+            if let Some(column) = column {
+                row[*column] = value;
+            }
+        }
+        self.at = self.block.len() - data.len();
+        self.left -= 1;
+        Ok(Some(row))
+    }
+}
+
+impl<R: Read> AvroRows<R> {
+    // The next row, with its amount parsed as precision says
+    pub(crate) fn next(
+        &mut self,
+        precision: PrecisionPolicy,
+    ) -> Option<Result<Transaction, Error>> {
+        if self.done {
+            return None;
+        }
+        let row = match self.next_row() {
+            Ok(Some(row)) => row,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            // a bad header or block means a corrupt file, so there's no carrying on past it
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+        self.row += 1;
+        let header = self.header.as_ref().expect("read with the first row");
+        let txn = decided(
+            precision,
+            |precision| {
+                columns::transaction(row.each_ref(), header.scale, header.per_second, precision)
+            },
+            Some,
+        );
+        Some(txn.map_err(|err| err.at_line(self.row)))
+    }
+}
+
+fn fields_of(record: &serde_json::Map<String, Value>) -> Result<&Vec<Value>, Error> {
+    match record.get("fields") {
+        Some(Value::Array(fields)) => Ok(fields),
+        _ => Err(schema_error("a record has no fields")),
+    }
+}
+
+// Named types are kept by their full name, to be looked up when referred to later
+fn parse(
+    json: &Value,
+    namespace: &str,
+    names: &mut HashMap<String, Schema>,
+) -> Result<Schema, Error> {
+    let object = match json {
+        Value::String(name) => return named(name, namespace, names),
+        Value::Array(branches) => {
+            let branches = branches
+                .iter()
+                .map(|branch| parse(branch, namespace, names))
+                .collect::<Result<Vec<_>, _>>()?;
+            if branches.iter().any(|b| matches!(b, Schema::Union(_))) {
+                return Err(schema_error("a union directly inside a union"));
+            }
+            return Ok(Schema::Union(branches));
+        }
+        Value::Object(object) => object,
+        _ => return Err(schema_error(&format!("'{}' isn't a type", json))),
+    };
+    let ty = match object.get("type") {
+        Some(Value::String(ty)) => ty.as_str(),
+        Some(ty) => return parse(ty, namespace, names),
+        None => return Err(schema_error("a type object has no type")),
+    };
+    let logical = object.get("logicalType").and_then(Value::as_str);
+    let scale = || match object.get("scale") {
+        None => Ok(Some(0)),
+        Some(scale) => scale
+            .as_u64()
+            .and_then(|scale| u32::try_from(scale).ok())
+            .map(Some)
+            .ok_or_else(|| schema_error("a decimal's scale isn't a whole number")),
+    };
+    let schema = match ty {
+        "bytes" if logical == Some("decimal") => Schema::Bytes(scale()?),
+        "long" => Schema::Long(match logical {
+            Some("timestamp-millis" | "local-timestamp-millis") => Some(1_000),
+            Some("timestamp-micros" | "local-timestamp-micros") => Some(1_000_000),
+            Some("timestamp-nanos" | "local-timestamp-nanos") => Some(1_000_000_000),
+            _ => None,
+        }),
+        "array" => Schema::Array(Box::new(parse(
+            object
+                .get("items")
+                .ok_or_else(|| schema_error("an array has no items"))?,
+            namespace,
+            names,
+        )?)),
+        "map" => Schema::Map(Box::new(parse(
+            object
+                .get("values")
+                .ok_or_else(|| schema_error("a map has no values"))?,
+            namespace,
+            names,
+        )?)),
+        "record" | "error" | "enum" | "fixed" => {
+            let name = object
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| schema_error(&format!("a {} has no name", ty)))?;
+            let namespace = object
+                .get("namespace")
+                .and_then(Value::as_str)
+                .unwrap_or(namespace);
+            let full = full_name(name, namespace);
+            let namespace = full.rsplit_once('.').map_or("", |(namespace, _)| namespace);
+            let schema = match ty {
+                "enum" => Schema::Enum(match object.get("symbols") {
+                    Some(Value::Array(symbols)) => symbols
+                        .iter()
+                        .map(|symbol| symbol.as_str().map(|s| Arc::from(s.as_bytes())))
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| schema_error("an enum symbol isn't a string"))?,
+                    _ => return Err(schema_error("an enum has no symbols")),
+                }),
+                "fixed" => Schema::Fixed(
+                    object
+                        .get("size")
+                        .and_then(Value::as_u64)
+                        .and_then(|size| usize::try_from(size).ok())
+                        .ok_or_else(|| schema_error("a fixed has no size"))?,
+                    if logical == Some("decimal") {
+                        scale()?
+                    } else {
+                        None
+                    },
+                ),
+                _ => Schema::Record(
+                    fields_of(object)?
+                        .iter()
+                        .map(|field| {
+                            let ty = field.get("type").ok_or_else(|| {
+                                schema_error(&format!("a field of {} has no type", full))
+                            })?;
+                            parse(ty, namespace, names)
+                        })
+                        .collect::<Result<_, _>>()?,
+                ),
+            };
+            names.insert(full.clone(), schema.clone());
+            schema
+        }
+        _ => named(ty, namespace, names)?,
+    };
+    Ok(schema)
+}
+
+// A primitive type's name, or one defined earlier in the schema
+fn named(name: &str, namespace: &str, names: &HashMap<String, Schema>) -> Result<Schema, Error> {
+    Ok(match name {
+        "null" => Schema::Null,
+        "boolean" => Schema::Boolean,
+        "int" => Schema::Int,
+        "long" => Schema::Long(None),
+        "float" => Schema::Float,
+        "double" => Schema::Double,
+        "bytes" => Schema::Bytes(None),
+        "string" => Schema::String,
+        // a type can't refer to itself, since it's only named once it's been parsed
+        _ => names
+            .get(&full_name(name, namespace))
+            .or_else(|| names.get(name))
+            .cloned()
+            .ok_or_else(|| schema_error(&format!("unknown type '{}'", name)))?,
+    })
+}
+
+fn full_name(name: &str, namespace: &str) -> String {
+    if name.contains('.') || namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", namespace, name)
+    }
+}
+
+// Whether a field can hold one of the STANDARD_COLUMNS. A union can if each of its branches can,
+// or is null.
+fn check_column(column: usize, schema: &Schema) -> Result<(), Error> {
+    let branches = match schema {
+        Schema::Union(branches) => branches.as_slice(),
+        schema => std::slice::from_ref(schema),
+    };
+    for branch in branches {
+        let fits = match (column, branch) {
+            (_, Schema::Null) => true,
+            // type, currency and to_currency
+            (0 | 6 | 7, Schema::String | Schema::Enum(_)) => true,
+            (8, Schema::String) => true,
+            // client, tx and to
+            (1 | 2 | 4, Schema::Int | Schema::Long(None) | Schema::String) => true,
+            (
+                3,
+                Schema::Int
+                | Schema::Long(None)
+                | Schema::Float
+                | Schema::Double
+                | Schema::String
+                | Schema::Bytes(Some(_))
+                | Schema::Fixed(_, Some(_)),
+            ) => true,
+            (5, Schema::Int | Schema::Long(_)) => true,
+            _ => false,
+        };
+        if !fits {
+            return Err(invalid(format!(
+                "Avro field '{}' can't be {}",
+                STANDARD_COLUMNS[column],
+                describe(branch)
+            )));
+        }
+        // every symbol of a type enum has to be a transaction type, not just the ones in the file
+        if let (0, Schema::Enum(symbols)) = (column, branch) {
+            for symbol in symbols {
+                let symbol = String::from_utf8_lossy(symbol);
+                TransactionType::deserialize(symbol.as_ref().into_deserializer()).map_err(
+                    |_: serde::de::value::Error| {
+                        invalid(format!(
+                            "Avro enum for 'type' has the symbol '{}', which isn't a transaction type",
+                            symbol
+                        ))
+                    },
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn describe(schema: &Schema) -> &'static str {
+    match schema {
+        Schema::Null => "a null",
+        Schema::Boolean => "a boolean",
+        Schema::Int => "an int",
+        Schema::Long(None) => "a long",
+        Schema::Long(Some(_)) => "a timestamp",
+        Schema::Float => "a float",
+        Schema::Double => "a double",
+        Schema::Bytes(None) => "bytes",
+        Schema::Bytes(Some(_)) | Schema::Fixed(_, Some(_)) => "a decimal",
+        Schema::String => "a string",
+        Schema::Fixed(_, None) => "a fixed",
+        Schema::Enum(_) => "an enum",
+        Schema::Array(_) => "an array",
+        Schema::Map(_) => "a map",
+        Schema::Union(_) => "a union",
+        Schema::Record(_) => "a record",
+    }
+}
+
+// Decodes a value off the front of data. Arrays, maps and records are only ever skipped, so they
+// decode to Null.
+fn read_value(schema: &Schema, data: &mut &[u8]) -> Result<Datum, Error> {
+    Ok(match schema {
+        Schema::Null => Datum::Null,
+        Schema::Boolean => Datum::Int(take(data, 1)?[0].into()),
+        Schema::Int | Schema::Long(_) => Datum::Int(long(data)?),
+        Schema::Float => {
+            Datum::Float(f32::from_le_bytes(take(data, 4)?.try_into().expect("4 bytes")) as f64)
+        }
+        Schema::Double => Datum::Float(f64::from_le_bytes(
+            take(data, 8)?.try_into().expect("8 bytes"),
+        )),
+        Schema::Bytes(_) | Schema::String => {
+            let len = length(data)?;
+            Datum::Bytes(take(data, len)?.into())
+        }
+        Schema::Fixed(size, _) => Datum::Bytes(take(data, *size)?.into()),
+        Schema::Enum(symbols) => {
+            let symbol = usize::try_from(long(data)?)
+                .ok()
+                .and_then(|i| symbols.get(i))
+                .ok_or_else(|| corrupt("enum index out of range"))?;
+            Datum::Bytes(symbol.clone())
+        }
+        Schema::Union(branches) => {
+            let branch = usize::try_from(long(data)?)
+                .ok()
+                .and_then(|i| branches.get(i))
+                .ok_or_else(|| corrupt("union index out of range"))?;
+            read_value(branch, data)?
+        }
+        Schema::Record(fields) => {
+            for field in fields {
+                read_value(field, data)?;
+            }
+            Datum::Null
+        }
+        Schema::Array(items) => {
+            skip_blocks(data, |data| read_value(items, data).map(drop))?;
+            Datum::Null
+        }
+        Schema::Map(values) => {
+            skip_blocks(data, |data| {
+                let len = length(data)?;
+                take(data, len)?;
+                read_value(values, data).map(drop)
+            })?;
+            Datum::Null
+        }
+    })
+}
+
+// An array's or map's blocks of items, up to the empty one that ends them
+fn skip_blocks(
+    data: &mut &[u8],
+    mut item: impl FnMut(&mut &[u8]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    loop {
+        let count = long(data)?;
+        if count == 0 {
+            return Ok(());
+        }
+        if count < 0 {
+            // the block's size in bytes follows, so it can be skipped whole
+            let size = length(data)?;
+            take(data, size)?;
+            continue;
+        }
+        // even a null item is counted, so a count past the bytes left is taken as corrupt rather
+        // than looped over
+        if count as u64 > data.len() as u64 {
+            return Err(corrupt("array or map longer than its block"));
+        }
+        for _ in 0..count {
+            item(data)?;
+        }
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    let (head, rest) = data
+        .split_at_checked(n)
+        .ok_or_else(|| corrupt("record past the end of its block"))?;
+    *data = rest;
+    Ok(head)
+}
+
+// A zigzag varint, which is how Avro encodes ints and longs alike
+fn long(data: &mut &[u8]) -> Result<i64, Error> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = take(data, 1)?[0];
+        v |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return Ok((v >> 1) as i64 ^ -((v & 1) as i64));
+        }
+    }
+    Err(corrupt("varint too long"))
+}
+
+// A string's or bytes' length, which can't be more than the bytes left to hold them
+fn length(data: &mut &[u8]) -> Result<usize, Error> {
+    usize::try_from(long(data)?)
+        .ok()
+        .filter(|len| *len <= data.len())
+        .ok_or_else(|| corrupt("length past the end of its block"))
+}
+
+fn read_long(reader: &mut impl Read) -> Result<i64, Error> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader
+            .read_exact(&mut byte)
+            .map_err(|_| corrupt("truncated header or block"))?;
+        v |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] < 0x80 {
+            return Ok((v >> 1) as i64 ^ -((v & 1) as i64));
+        }
+    }
+    Err(corrupt("varint too long"))
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>, Error> {
+    let len = u64::try_from(read_long(reader)?).map_err(|_| corrupt("negative length"))?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(corrupt("truncated header"));
+    }
+    Ok(bytes)
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+fn schema_error(what: &str) -> Error {
+    invalid(format!("Avro schema: {}", what))
+}
+
+fn corrupt(what: &str) -> Error {
+    invalid(format!("corrupt Avro data: {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::csv_row;
+
+    // The files tests/fixtures/avro/make.py writes
+    const NULL: &[u8] = include_bytes!("../tests/fixtures/avro/null.avro");
+    const DEFLATE: &[u8] = include_bytes!("../tests/fixtures/avro/deflate.avro");
+    const SNAPPY: &[u8] = include_bytes!("../tests/fixtures/avro/snappy.avro");
+    const ZSTANDARD: &[u8] = include_bytes!("../tests/fixtures/avro/zstandard.avro");
+
+    fn read(file: &[u8]) -> Result<Vec<String>, Error> {
+        let mut rows = AvroRows::new(file);
+        std::iter::from_fn(|| rows.next(PrecisionPolicy::Round))
+            .map(|txn| txn.map(|txn| csv_row(&txn)))
+            .collect()
+    }
+
+    // A row under the STANDARD_COLUMNS header, as csv_row writes it
+    fn row(mut columns: Vec<String>) -> String {
+        while columns.len() > 4 && columns.last().is_some_and(String::is_empty) {
+            columns.pop();
+        }
+        columns.join(",")
+    }
+
+    fn amount(ten_thousandths: u32) -> String {
+        format!(
+            "{}.{:04}",
+            ten_thousandths / 10_000,
+            ten_thousandths % 10_000
+        )
+    }
+
+    #[test]
+    fn skips_fields_it_has_no_use_for() {
+        assert_eq!(
+            read(NULL).unwrap(),
+            [
+                "deposit,1,1,10.0000,,1700000000",
+                "deposit,2,2,0.5000,,,USD",
+                "withdrawal,1,3,2.5000,,1700000060",
+                "dispute,1,1,,,1700000120",
+                "resolve,1,1,",
+                "deposit,1,4,2.1234",
+                "deposit,3,5,3.2768,,1700000240",
+            ]
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn reads_deflate_blocks() {
+        let expected = (1..=50u32).map(|i| {
+            let transfer = i % 10 == 0;
+            row(vec![
+                if transfer { "transfer" } else { "deposit" }.to_string(),
+                (i % 3 + 1).to_string(),
+                i.to_string(),
+                amount(i * 2_500),
+                if transfer {
+                    (i % 3 + 2).to_string()
+                } else {
+                    String::new()
+                },
+                String::new(),
+                String::new(),
+                String::new(),
+                if i % 4 == 0 {
+                    format!("2024-02-{:02}", i % 28 + 1)
+                } else {
+                    String::new()
+                },
+            ])
+        });
+        assert_eq!(read(DEFLATE).unwrap(), expected.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reads_snappy_blocks() {
+        let expected = (1..=200u32).map(|i| {
+            row(vec![
+                if i % 4 != 0 { "deposit" } else { "withdrawal" }.to_string(),
+                (i % 7 + 1).to_string(),
+                i.to_string(),
+                amount(i * 1_001),
+                String::new(),
+                (1_700_000_000 + i / 4).to_string(),
+                "EUR".to_string(),
+            ])
+        });
+        assert_eq!(read(SNAPPY).unwrap(), expected.collect::<Vec<_>>());
+        // the CRC at the end of the last block
+        let mut file = SNAPPY.to_vec();
+        file[SNAPPY.len() - SYNC - 1] ^= 1;
+        let err = read(&file).unwrap_err();
+        assert!(err.to_string().contains("fails its checksum"), "{}", err);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn reads_zstandard_blocks() {
+        assert_eq!(
+            read(ZSTANDARD).unwrap(),
+            ["deposit,1,1,1.2500", "withdrawal,1,2,0.5000"]
+        );
+    }
+
+    #[test]
+    fn refuses_what_it_cant_read() {
+        let err = read(include_bytes!("../tests/fixtures/avro/bzip2.avro")).unwrap_err();
+        assert!(err.to_string().contains("compressed with bzip2"), "{}", err);
+        let err = read(include_bytes!("../tests/fixtures/avro/bad_enum.avro")).unwrap_err();
+        assert!(err.to_string().contains("the symbol 'reversal'"), "{}", err);
+    }
+
+    // A corrupt file fails to read rather than panicking, wherever it's corrupt
+    #[test]
+    fn survives_corruption() {
+        for file in [NULL, DEFLATE, SNAPPY, ZSTANDARD] {
+            for at in 0..file.len() {
+                let _ = read(&file[..at]);
+                let mut file = file.to_vec();
+                file[at] ^= 0xff;
+                let _ = read(&file);
+            }
+        }
+    }
+}
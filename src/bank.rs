@@ -1,25 +1,174 @@
-use std::collections::HashMap;
-use serde::Deserialize;
+use crate::amount::Amount;
+use crate::audit::{AuditLog, OutcomeStream};
+use crate::currency::{Balance, Currency};
+use crate::date::Date;
+use crate::error::Error;
+use crate::imports::Imports;
+use crate::ledger::{Account, Ledger};
+use crate::metrics::Metrics;
+use crate::observer::Observers;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::policy::{DisputePolicy, Policy, RedisputePolicy, TxIdPolicy, WithdrawalPolicy};
+use crate::report::{self, OutputFormat};
+use crate::risk::Risk;
+use crate::rules::Rules;
+use crate::seen::Seen;
+use crate::source::{SourceStats, TransactionSource};
+use crate::spill::{Spill, TxnStore};
+use crate::velocity::Velocity;
+use crate::wal::Wal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, io, mem,
+};
+use tracing::{debug, trace};
 
-#[derive(PartialEq, Debug, Copy, Clone, Deserialize)]
+// Client and transaction ids are u16 and u32 as the spec has them, or u32 and u64 with the
+// wide-ids feature for systems that have outgrown that
+#[cfg(not(feature = "wide-ids"))]
+pub type ClientId = u16;
+#[cfg(not(feature = "wide-ids"))]
+pub type TxId = u32;
+#[cfg(feature = "wide-ids")]
+pub type ClientId = u32;
+#[cfg(feature = "wide-ids")]
+pub type TxId = u64;
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
-	Withdrawal,
-	Deposit,
-	Dispute,
-	Resolve,
-	Chargeback,
+    Withdrawal,
+    Deposit,
+    Dispute,
+    Resolve,
+    Chargeback,
+    // an admin reinstating an account locked by a chargeback; its tx id identifies the unlock itself
+    Unlock,
+    // funds moved from client to another client's account, see transfer.rs
+    Transfer,
+    // a charge the client can't dispute, e.g. from a billing file
+    Fee,
+    // pay interest on the client's balance, see interest.rs
+    Accrue,
+    // exchange funds between two of the client's currency balances, see fx.rs
+    Convert,
+    // a merchant giving back an earlier withdrawal, referenced by its tx id
+    Refund,
+    // an admin closing the account for good; its tx id identifies the close itself
+    Close,
+    // an admin folding the client's account into the `to` client's, see merge.rs; its tx id
+    // identifies the merge itself
+    Merge,
+    // an admin moving funds from available into held outside any dispute, until a release
+    Hold,
+    // an admin giving back the funds of an earlier hold, referenced by its tx id
+    Release,
+}
+
+impl TransactionType {
+    // deposits, withdrawals, transfers, fees, accruals, conversions and holds carry their own
+    // amount and tx id, the rest reference an earlier one
+    pub fn moves_funds(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Deposit
+                | TransactionType::Withdrawal
+                | TransactionType::Transfer
+                | TransactionType::Fee
+                | TransactionType::Accrue
+                | TransactionType::Convert
+                | TransactionType::Hold
+        )
+    }
+}
+
+// The lowercase name used in input files
+impl TransactionType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Deposit => "deposit",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Unlock => "unlock",
+            TransactionType::Transfer => "transfer",
+            TransactionType::Fee => "fee",
+            TransactionType::Accrue => "accrue",
+            TransactionType::Convert => "convert",
+            TransactionType::Refund => "refund",
+            TransactionType::Close => "close",
+            TransactionType::Merge => "merge",
+            TransactionType::Hold => "hold",
+            TransactionType::Release => "release",
+        }
+    }
+}
+
+// The one-byte code fixed-size records store, from 1 so that a zeroed record has none
+const TYPE_CODES: [TransactionType; 15] = [
+    TransactionType::Withdrawal,
+    TransactionType::Deposit,
+    TransactionType::Dispute,
+    TransactionType::Resolve,
+    TransactionType::Chargeback,
+    TransactionType::Unlock,
+    TransactionType::Transfer,
+    TransactionType::Fee,
+    TransactionType::Accrue,
+    TransactionType::Convert,
+    TransactionType::Refund,
+    TransactionType::Close,
+    TransactionType::Merge,
+    TransactionType::Hold,
+    TransactionType::Release,
+];
+
+impl TransactionType {
+    pub(crate) fn code(self) -> u8 {
+        TYPE_CODES
+            .iter()
+            .position(|kind| *kind == self)
+            .expect("every type has a code") as u8
+            + 1
+    }
+
+    pub(crate) fn from_code(code: u8) -> Option<TransactionType> {
+        TYPE_CODES.get(usize::from(code).checked_sub(1)?).copied()
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
-#[derive(Deserialize, Debug, Copy, Clone)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone)]
 pub struct Transaction {
     #[serde(rename = "type")]
-	pub tx_type: TransactionType,
-	pub client: u16,
-	pub tx: u32,
-    // this will allow deposits and withdrawals to have an empty amount field as well, but there is no harm in them, as it assumes a value of 0.0 and ignores them
+    pub tx_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TxId,
+    // this will allow deposits and withdrawals to have an empty amount field as well, but there is no harm in them, as it assumes a value of 0 and ignores them
     #[serde(deserialize_with = "default_if_empty")]
-	pub amount: f32,
+    pub amount: Amount,
+    // the receiving client of a transfer or merge, empty for every other type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<ClientId>,
+    // when it happened, in seconds since the Unix epoch, if the input says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    // what the amount is in, see currency.rs; the account's unlabelled balance if empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    // the currency a conversion is into, empty for every other type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_currency: Option<Currency>,
+    // the day it takes effect, for a forward-dated one; see Policy::as_of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_date: Option<Date>,
 }
 
 fn default_if_empty<'de, D, T>(de: D) -> Result<T, D::Error>
@@ -32,138 +181,900 @@ where
 
 #[derive(Debug)]
 pub struct Bank {
-	bank: HashMap<u16, Client>,
+    pub(crate) bank: HashMap<ClientId, Client>,
+    pub(crate) policy: Policy,
+    // every deposit/withdrawal tx id accepted so far, across all clients
+    pub(crate) tx_ids: HashSet<TxId>,
+    // where insert_logged records transactions, see Bank::open_wal
+    pub(crate) wal: Option<Wal>,
+    // set when older transactions are moved to disk, see Bank::spill_to_disk
+    pub(crate) spill: Option<Spill>,
+    // where every decision is recorded, see Bank::audit_to
+    pub(crate) audit: Option<AuditLog>,
+    // where every outcome is written, see Bank::outcomes_to
+    pub(crate) outcomes: Option<OutcomeStream>,
+    // told about every decision, see Bank::with_observer
+    pub(crate) observers: Observers,
+    // checked before every transaction, see Bank::with_rule
+    pub(crate) rules: Rules,
+    // counted by insert_logged, see Bank::enable_metrics
+    pub(crate) metrics: Option<Metrics>,
+    // the rows earlier runs processed, see Bank::dedup_seen
+    pub(crate) seen: Seen,
+    // the OFX and QIF entries applied, see imports.rs
+    pub(crate) imports: Imports,
+    // how fast each client has been moving funds, see Bank::with_velocity_rule
+    pub(crate) velocity: Velocity,
+    // screens every deposit, see Bank::with_risk_scorer
+    pub(crate) risk: Risk,
+    // forward-dated transactions not yet due, see pending.rs
+    pub(crate) pending: Vec<Transaction>,
+    // whether insert_txn checks every transaction, see Bank::check_invariants
+    pub(crate) invariants: bool,
 }
 
 impl Bank {
-	pub fn new() -> Bank {
-    	Bank { bank: HashMap::new(), }
-	}   
+    pub fn new() -> Bank {
+        Bank::with_policy(Policy::default())
+    }
+
+    pub fn with_policy(policy: Policy) -> Bank {
+        Bank {
+            bank: HashMap::new(),
+            policy,
+            tx_ids: HashSet::new(),
+            wal: None,
+            spill: None,
+            audit: None,
+            outcomes: None,
+            observers: Observers::default(),
+            rules: Rules::default(),
+            metrics: None,
+            seen: Seen::default(),
+            imports: Imports::default(),
+            velocity: Velocity::default(),
+            risk: Risk::default(),
+            pending: Vec::new(),
+            invariants: false,
+        }
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    // e.g. to apply this run's policies to a bank restored from a snapshot
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+    }
 
-	pub fn add_client(&mut self, client_id: u16) {
-    	if !self.bank.contains_key(&client_id) {
-        	let client = Client::new(client_id);
-        	self.bank.insert(client_id, client);
-    	}   
-	}   
+    pub fn add_client(&mut self, client_id: ClientId) {
+        self.bank
+            .entry(client_id)
+            .or_insert_with(|| Client::new(client_id));
+    }
 
     // Insert a transaction into the bank
-    // This assumes txn ID + client ID is the unique primary key for a txn
-	pub fn insert_txn(&mut self, txn: Transaction) {
-        if !self.bank.contains_key(&txn.client) {
-            // I'm assuming that the first transaction must be a deposit to open a new account
-            if txn.tx_type == TransactionType::Deposit {
-                self.add_client(txn.client);
+    // Under TxIdPolicy::PerClient this assumes txn ID + client ID is the unique primary key for a txn,
+    // under TxIdPolicy::Global the txn ID alone is
+    pub fn insert_txn(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
+        self.insert_read(txn, None)
+    }
+
+    // insert_txn for a transaction as read from a source, which refuses it with refusal rather
+    // than applying it where reading it already decided that, as TransactionSource::refusal says
+    pub(crate) fn insert_read(
+        &mut self,
+        txn: Transaction,
+        refusal: Option<TxnError>,
+    ) -> Result<TxnOutcome, TxnError> {
+        self.velocity.alerted = false;
+        let before = self.invariants.then(|| self.before_txn(&txn));
+        let result = match refusal {
+            Some(err) => self.audited(txn, |_, _| Err(err)),
+            None => self.audited(txn, Bank::apply_txn),
+        };
+        if let Some(before) = before {
+            self.check_txn(&txn, &result, before);
+        }
+        match &result {
+            Ok(outcome) => trace!(
+                client = txn.client,
+                tx = txn.tx,
+                outcome = outcome.code(),
+                "{}",
+                txn.tx_type
+            ),
+            Err(err) => debug!(
+                client = txn.client,
+                tx = txn.tx,
+                reason = err.code(),
+                "refused {}",
+                txn.tx_type
+            ),
+        }
+        self.notify(&txn, &result);
+        result
+    }
+
+    fn apply_txn(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
+        if self.queue_if_pending(&txn) {
+            return Ok(TxnOutcome::Pending);
+        }
+        self.check_seen(&txn)?;
+        self.check_rules(&txn)?;
+        self.check_velocity(&txn)?;
+        let risky = self.risky(&txn);
+        let moves_funds = txn.tx_type.moves_funds();
+        if moves_funds && self.policy.tx_ids == TxIdPolicy::Global && self.tx_ids.contains(&txn.tx)
+        {
+            // a repeat within the same client is reported by the client as a plain duplicate
+            if !self.owns(txn.client, txn.tx)? {
+                return Err(TxnError::DuplicateTxOtherClient);
+            }
+        }
+        if !self.bank.contains_key(&txn.client) && self.policy.opening.opens(txn.tx_type) {
+            self.add_client(txn.client);
+        }
+        if txn.tx_type == TransactionType::Merge {
+            return self.merge(txn.client, txn.to.ok_or(TxnError::InvalidRecipient)?);
+        }
+        let outcome = if self.is_cross_client(&txn)? {
+            self.process_transfer(txn)?
+        } else {
+            match self.bank.get_mut(&txn.client) {
+                Some(client) if risky => client.process_held(txn, &self.policy)?,
+                Some(client) => client.process_txn(txn, &self.policy)?,
+                None => return Err(TxnError::UnknownClient),
             }
+        };
+        if moves_funds {
+            self.tx_ids.insert(txn.tx);
+            self.recorded(txn.client);
+            self.record_velocity(&txn);
         }
-    	if self.bank.contains_key(&txn.client) {
-        	self.bank.get_mut(&txn.client).unwrap().process_txn(txn);
-    	}
-	}
+        Ok(outcome)
+    }
+
+    // whether tx is one of the client's own recorded deposits/withdrawals
+    pub(crate) fn owns(&self, client_id: ClientId, tx: TxId) -> Result<bool, TxnError> {
+        self.bank
+            .get(&client_id)
+            .map_or(Ok(false), |client| client.txns.contains_key(&tx))
+    }
+}
+
+impl Bank {
+    // Apply every transaction from a source in order. Rows that fail to parse either abort the
+    // run or are skipped, depending on the bank's OnError policy.
+    pub fn process_source<R: io::Read>(
+        &mut self,
+        source: TransactionSource<R>,
+    ) -> Result<SourceStats, Error> {
+        self.process_source_with(source, |_, _| Ok(()))
+    }
 
-    pub fn to_string(&self) -> String {
-        let mut client_string: String = "".to_owned();
-        for client_id in self.bank.keys() {
-            let clientstr = self.bank.get(&client_id).unwrap().to_string();
-            client_string.push_str(&clientstr);
-            client_string.push_str("\n");
+    // Like process_source, calling on_reject for every transaction the engine refuses
+    pub fn process_source_with<R, F>(
+        &mut self,
+        source: TransactionSource<R>,
+        mut on_reject: F,
+    ) -> Result<SourceStats, Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        let mut stats = SourceStats::default();
+        let mut source = source;
+        while let Some(txn) = self.next_from(&mut source, &mut stats)? {
+            let result = self.insert_read(txn, source.refusal());
+            self.check_spill()?;
+            match result {
+                Ok(outcome) => stats.applied(outcome),
+                Err(err) => {
+                    stats.reject(&txn, &err, source.line());
+                    on_reject(&txn, &err)?;
+                }
+            }
         }
+        Ok(stats)
+    }
 
-        format!("client, available, held, total, locked\n{}", client_string)
+    // Apply CSV transactions held in memory, like process_source but keeping the counts of a run
+    // that stops partway. It's meant as a fuzzing target (see fuzz/): no input, however malformed
+    // or hostile, makes it panic.
+    pub fn process_csv_bytes(&mut self, bytes: &[u8]) -> ProcessResult {
+        let mut stats = SourceStats::default();
+        let mut source = TransactionSource::csv(bytes);
+        loop {
+            match source.next_txn(&self.policy, &mut stats) {
+                Ok(Some(txn)) => {
+                    let result = self.insert_read(txn, source.refusal());
+                    if let Err(err) = self.check_spill() {
+                        return ProcessResult {
+                            stats,
+                            error: Some(err.into()),
+                        };
+                    }
+                    match result {
+                        Ok(outcome) => stats.applied(outcome),
+                        Err(err) => stats.reject(&txn, &err, source.line()),
+                    }
+                }
+                Ok(None) => return ProcessResult { stats, error: None },
+                Err(err) => {
+                    return ProcessResult {
+                        stats,
+                        error: Some(err),
+                    }
+                }
+            }
+        }
+    }
+
+    // Stream the account report as CSV, one record per client, without buffering the whole thing
+    pub fn write_report<W: io::Write>(&self, w: W) -> Result<(), Error> {
+        self.write_report_as(w, OutputFormat::Csv)
+    }
+
+    pub fn write_report_as<W: io::Write>(&self, w: W, format: OutputFormat) -> Result<(), Error> {
+        report::write_report(self, w, format)
+    }
+
+    pub fn get_client(&self, client_id: ClientId) -> Option<&Client> {
+        self.bank.get(&client_id)
+    }
+
+    // Every account, in no particular order
+    pub fn clients(&self) -> impl Iterator<Item = &Client> + '_ {
+        self.bank.values()
+    }
+
+    pub fn sorted_clients(&self) -> Vec<&Client> {
+        let mut clients: Vec<&Client> = self.clients().collect();
+        clients.sort_by_key(|client| client.client);
+        clients
+    }
+
+    pub fn record(&self, client_id: ClientId) -> Option<ClientRecord> {
+        self.bank.get(&client_id).map(Client::record)
+    }
+
+    // Every client's records, one per currency, in no particular order of clients
+    pub fn records(&self) -> impl Iterator<Item = ClientRecord> + '_ {
+        self.bank.values().flat_map(Client::records)
+    }
+
+    pub fn sorted_records(&self) -> Vec<ClientRecord> {
+        let mut records: Vec<ClientRecord> = self.records().collect();
+        // stable, so each client's records stay in currency order
+        records.sort_by_key(|record| record.client);
+        records
+    }
+}
+
+// What Bank::process_csv_bytes made of its input
+#[derive(Debug, Default)]
+pub struct ProcessResult {
+    // the counts up to where it stopped
+    pub stats: SourceStats,
+    // what stopped it before the end, if anything did: a malformed row under OnError::Abort, or
+    // input that isn't CSV at all
+    pub error: Option<Error>,
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Bank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "client, available, held, total, locked, currency, closed"
+        )?;
+        for client in self.bank.values() {
+            writeln!(f, "{}", client)?;
+        }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 pub struct Client {
-	client: u16,
-	txns: HashMap<u32, Transaction>,
-	available: f32,
-	held: f32,
-	locked: bool,
-    disputes: HashMap<u32, Transaction>
+    pub(crate) client: ClientId,
+    pub(crate) txns: TxnStore,
+    // the unlabelled balance, or the one in_currency is working on
+    pub(crate) available: Amount,
+    pub(crate) held: Amount,
+    // all the balance's withdrawals added up, for the withdrawal limits
+    pub(crate) withdrawn: Amount,
+    // the rest of the unlabelled balance's ledger, see ledger.rs
+    pub(crate) ledger: Ledger,
+    pub(crate) currencies: BTreeMap<Currency, Balance>,
+    pub(crate) locked: bool,
+    pub(crate) closed: bool,
+    pub(crate) rejected_withdrawals: Vec<Transaction>,
+    pub(crate) unlocks: Vec<Transaction>,
 }
 
 impl Client {
-	pub fn new(client: u16) -> Client {
-    	Client {
-        	client: client,
-        	txns: HashMap::new(),
-        	available: 0.0,
-        	held: 0.0,
-        	locked: false,
-            disputes: HashMap::new()
-    	}
-	}
-
-	pub fn to_string(&self) -> String {
-    	format!("{}, {:.4}, {:.4}, {:.4}, {}", self.client, self.available, self.held, self.available + self.held, self.locked)
-	}
-
-	pub fn process_txn(&mut self, txn: Transaction) {
-        // if the account is locked, no txns can be processed. There is currently no way to unlock a locked account
-        if !self.locked {
-        	match txn.tx_type {
-            	TransactionType::Withdrawal => self.withdrawal(txn),
-            	TransactionType::Deposit => self.deposit(txn),
-        	    TransactionType::Dispute => self.dispute(txn.tx),
-            	TransactionType::Resolve => self.resolve(txn.tx),
-            	TransactionType::Chargeback => self.chargeback(txn.tx)
-    	    }
-        }
-	}
-
-    // Note there is intentionally no protection on the account going negative. I'm assuming this is allowed.
-    // Alternatively, a withdrawal could fail if it would make the available amount go negative.
-	pub fn withdrawal(&mut self, txn: Transaction) {
+    pub fn new(client: ClientId) -> Client {
+        Client {
+            client,
+            txns: TxnStore::new(client),
+            available: Amount::ZERO,
+            held: Amount::ZERO,
+            withdrawn: Amount::ZERO,
+            ledger: Ledger::default(),
+            currencies: BTreeMap::new(),
+            locked: false,
+            closed: false,
+            rejected_withdrawals: Vec::new(),
+            unlocks: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> ClientId {
+        self.client
+    }
+
+    // The unlabelled balance's funds, see records for those in every currency
+    pub fn available(&self) -> Amount {
+        self.available
+    }
+
+    pub fn held(&self) -> Amount {
+        self.held
+    }
+
+    pub fn total(&self) -> Amount {
+        self.available + self.held
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+
+    // How many deposits and withdrawals (and other transactions with their own tx id) are
+    // recorded for the client. Reads back the spill file if some were moved there.
+    pub fn txn_count(&self) -> io::Result<usize> {
+        self.txns.len()
+    }
+
+    // Withdrawals turned away for insufficient funds, in the order they arrived
+    pub fn rejected_withdrawals(&self) -> &[Transaction] {
+        &self.rejected_withdrawals
+    }
+
+    // Every unlock applied to the account, in order, as a record of who reinstated it when
+    pub fn unlocks(&self) -> &[Transaction] {
+        &self.unlocks
+    }
+
+    pub fn process_txn(
+        &mut self,
+        txn: Transaction,
+        policy: &Policy,
+    ) -> Result<TxnOutcome, TxnError> {
+        let currency = self.admit(&txn, policy)?;
+        let outcome = self.in_currency(currency, |client| client.apply(txn, policy));
+        debug_assert!(self.balanced(), "client {} is off balance", self.client);
+        outcome
+    }
+
+    // The checks every transaction to the account goes through before it's applied, returning
+    // the currency of the balance it applies to
+    pub(crate) fn admit(
+        &self,
+        txn: &Transaction,
+        policy: &Policy,
+    ) -> Result<Option<Currency>, TxnError> {
+        if txn.amount.is_too_large() {
+            return Err(TxnError::AmountTooLarge);
+        }
+        policy.negative.check(txn)?;
+        // if the account is locked, no txns can be processed until an unlock reinstates it, though
+        // it can still be closed, and under LockedPolicy::Disputes its disputes still run
+        if self.locked && !policy.locked.allows(txn.tx_type) {
+            return Err(TxnError::AccountLocked);
+        }
+        // a closed account takes no more funds in or out, but disputes of what it already had
+        // can still run their course
+        if self.closed && txn.tx_type.moves_funds() {
+            return Err(TxnError::AccountClosed);
+        }
+        self.currency_of(txn)
+    }
+
+    fn apply(&mut self, txn: Transaction, policy: &Policy) -> Result<TxnOutcome, TxnError> {
+        match txn.tx_type {
+            TransactionType::Withdrawal => self.withdrawal(txn, policy),
+            TransactionType::Deposit => self.deposit(txn),
+            TransactionType::Dispute => self.dispute(txn, policy),
+            TransactionType::Resolve => self.resolve(txn.tx),
+            TransactionType::Chargeback => self.chargeback(txn.tx),
+            TransactionType::Unlock => self.unlock(txn),
+            // needs the recipient's account too, so the bank applies these (see transfer.rs)
+            TransactionType::Transfer => Err(TxnError::InvalidRecipient),
+            TransactionType::Fee => self.fee(txn, policy.withdrawal),
+            TransactionType::Accrue => self.accrue(txn, policy.interest_rate),
+            TransactionType::Convert => self.convert(txn, &policy.rates),
+            TransactionType::Refund => self.refund(txn),
+            TransactionType::Close => self.close(),
+            // needs the other account too, so the bank applies these (see merge.rs)
+            TransactionType::Merge => Err(TxnError::InvalidRecipient),
+            TransactionType::Hold => self.hold(txn),
+            TransactionType::Release => self.release(txn),
+        }
+    }
+
+    // Whether the account may go negative is decided by the bank's WithdrawalPolicy, how much
+    // can be withdrawn at all by its Limits. Withdrawals rejected for insufficient funds are kept
+    // aside rather than in txns, so they can't be disputed later.
+    pub fn withdrawal(
+        &mut self,
+        txn: Transaction,
+        policy: &Policy,
+    ) -> Result<TxnOutcome, TxnError> {
         // I'm assuming every withdrawal must have a tx ID that is unique from all other client's tx IDs
         // If not, discard the txn as duplicate / mistake
-        // Also ignore withdrawals with an amount of 0.0 as they are not useful
-        if !self.txns.contains_key(&txn.tx) && txn.amount != 0.0 {
-        	self.available -= txn.amount;
-            self.txns.insert(txn.tx, txn);
+        // Also ignore withdrawals with an amount of 0 as they are not useful
+        self.check_new(&txn)?;
+        let limit = policy.limits.get(self.client);
+        limit.check(txn.amount, self.withdrawn)?;
+        if policy.withdrawal == WithdrawalPolicy::RejectIfInsufficient
+            && txn.amount > self.available
+        {
+            self.rejected_withdrawals.push(txn);
+            return Err(TxnError::InsufficientFunds);
         }
-	}
+        self.post(Account::Available, Account::Settlement, txn.amount)?;
+        self.withdrawn = self.withdrawn.saturating_add(txn.amount);
+        self.txns.insert(txn.tx, TxnRecord::new(&txn));
+        Ok(TxnOutcome::Withdrawn)
+    }
 
-	fn deposit(&mut self, txn: Transaction) {
+    fn deposit(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
         // I'm assuming every deposit must have a tx ID that is unique from all other client's tx IDs
         // If not, discard the txn as duplicate / mistake
-        // Also ignore deposits with an amount of 0.0 as they are not useful
-        if !self.txns.contains_key(&txn.tx) && txn.amount != 0.0 {
-    	    self.available += txn.amount;
-            self.txns.insert(txn.tx, txn);
+        // Also ignore deposits with an amount of 0 as they are not useful
+        self.check_new(&txn)?;
+        self.post(Account::Settlement, Account::Available, txn.amount)?;
+        self.txns.insert(txn.tx, TxnRecord::new(&txn));
+        Ok(TxnOutcome::Deposited)
+    }
+
+    // Debited like a withdrawal under the same WithdrawalPolicy, but never disputable
+    fn fee(&mut self, txn: Transaction, policy: WithdrawalPolicy) -> Result<TxnOutcome, TxnError> {
+        self.check_new(&txn)?;
+        if policy == WithdrawalPolicy::RejectIfInsufficient && txn.amount > self.available {
+            return Err(TxnError::InsufficientFunds);
+        }
+        self.post(Account::Available, Account::Fees, txn.amount)?;
+        self.txns.insert(txn.tx, TxnRecord::new(&txn));
+        Ok(TxnOutcome::FeeCharged)
+    }
+
+    pub(crate) fn check_new(&self, txn: &Transaction) -> Result<(), TxnError> {
+        if self.txns.contains_key(&txn.tx)? {
+            Err(TxnError::DuplicateTx)
+        } else if txn.amount.is_zero() {
+            Err(TxnError::ZeroAmount)
+        } else {
+            Ok(())
         }
-	}
+    }
 
-	fn dispute(&mut self, tx: u32) {
+    fn dispute(&mut self, txn: Transaction, policy: &Policy) -> Result<TxnOutcome, TxnError> {
+        let tx = txn.tx;
         // if the tx is not found for this client, ignore
-        if self.txns.contains_key(&tx) {
-            let txn = self.txns.get(&tx).unwrap();
-            // Given the description of the problem, I am assuming only deposits can be disputed
-            if txn.tx_type == TransactionType::Deposit {
-                let amount = txn.amount;
-                self.available -= amount;
-                self.held += amount;
-                self.disputes.insert(tx, *txn);
-            }
+        let mut record = self.txns.get(&tx)?.ok_or(TxnError::TxNotFound)?;
+        // Given the description of the problem, by default I am assuming only deposits can be disputed
+        let disputable = match record.kind {
+            TransactionType::Deposit => true,
+            TransactionType::Withdrawal => policy.dispute == DisputePolicy::DepositsAndWithdrawals,
+            _ => false,
+        };
+        if !disputable {
+            return Err(TxnError::NotDisputable);
         }
-	}
+        let portion = record.open_dispute(txn.amount, policy.redispute)?;
+        record.check_window(&txn, policy)?;
+        self.post(Account::Available, Account::Held, record.signed(portion))?;
+        self.txns.insert(tx, record);
+        Ok(TxnOutcome::Disputed)
+    }
 
-	fn resolve(&mut self, tx: u32) {
+    // The record of tx moved on from its open dispute to `next`, not yet stored, and the portion
+    // of it that was under dispute
+    pub(crate) fn settle(
+        &self,
+        tx: TxId,
+        next: DisputeState,
+    ) -> Result<(TxnRecord, Amount), TxnError> {
         // if there is no active dispute for this client & tx id, ignore
-        if self.disputes.contains_key(&tx) {
-            let txn = self.disputes.remove(&tx).unwrap();
-            self.available += txn.amount;
-            self.held -= txn.amount;
+        let mut record = self.txns.get(&tx)?.ok_or(TxnError::NotDisputed)?;
+        record.state = record.state.transition(next)?;
+        if next == DisputeState::Resolved {
+            record.resolutions = record.resolutions.saturating_add(1);
         }
-	}
+        let portion = mem::replace(&mut record.disputed, Amount::ZERO);
+        Ok((record, portion))
+    }
 
-	fn chargeback(&mut self, tx: u32) {
-        // if there is no active dispute for this client & tx id, ignore
-        if self.disputes.contains_key(&tx) {
-            let txn = self.disputes.remove(&tx).unwrap();
-            self.held -= txn.amount;
-            self.locked = true;
+    // Resolves and chargebacks settle everything currently disputed, whether that was one dispute
+    // or several partial ones
+    fn resolve(&mut self, tx: TxId) -> Result<TxnOutcome, TxnError> {
+        let (record, portion) = self.settle(tx, DisputeState::Resolved)?;
+        self.post(Account::Held, Account::Available, record.signed(portion))?;
+        self.txns.insert(tx, record);
+        Ok(TxnOutcome::Resolved)
+    }
+
+    fn chargeback(&mut self, tx: TxId) -> Result<TxnOutcome, TxnError> {
+        let (record, portion) = self.settle(tx, DisputeState::ChargedBack)?;
+        self.post(Account::Held, Account::Chargebacks, record.signed(portion))?;
+        self.locked = true;
+        self.txns.insert(tx, record);
+        Ok(TxnOutcome::ChargedBack)
+    }
+
+    // Credits a withdrawal's amount back once. It's not a dispute: nothing is held, and a
+    // refunded withdrawal can't be disputed afterwards, nor a disputed one refunded until its
+    // dispute is resolved.
+    fn refund(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
+        let mut record = self.txns.get(&txn.tx)?.ok_or(TxnError::TxNotFound)?;
+        // an empty amount refunds the withdrawal, a given one has to be all of it
+        let whole = txn.amount.is_zero() || txn.amount == record.amount;
+        if record.kind != TransactionType::Withdrawal || !whole {
+            return Err(TxnError::NotRefundable);
+        }
+        record.state = record.state.transition(DisputeState::Refunded)?;
+        self.post(Account::Settlement, Account::Available, record.amount)?;
+        self.txns.insert(txn.tx, record);
+        Ok(TxnOutcome::Refunded)
+    }
+
+    // Only funds the account has can be held, whatever the WithdrawalPolicy
+    fn hold(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
+        self.check_new(&txn)?;
+        if txn.amount > self.available {
+            return Err(TxnError::InsufficientFunds);
+        }
+        self.post(Account::Available, Account::Held, txn.amount)?;
+        self.txns.insert(txn.tx, TxnRecord::new(&txn));
+        Ok(TxnOutcome::HoldPlaced)
+    }
+
+    fn release(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
+        let mut record = self.txns.get(&txn.tx)?.ok_or(TxnError::TxNotFound)?;
+        // an empty amount releases the hold, a given one has to be all of it
+        let whole = txn.amount.is_zero() || txn.amount == record.amount;
+        if record.kind != TransactionType::Hold || !whole {
+            return Err(TxnError::NotReleasable);
+        }
+        record.state = record.state.transition(DisputeState::Released)?;
+        self.post(Account::Held, Account::Available, record.amount)?;
+        self.txns.insert(txn.tx, record);
+        Ok(TxnOutcome::Released)
+    }
+
+    // Balances are left as they are, so held funds can still be released or charged back
+    fn close(&mut self) -> Result<TxnOutcome, TxnError> {
+        if self.closed {
+            return Err(TxnError::AccountClosed);
+        }
+        self.closed = true;
+        Ok(TxnOutcome::Closed)
+    }
+
+    // Balances are left as the chargeback left them, only the lock is lifted
+    fn unlock(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
+        if !self.locked {
+            return Err(TxnError::NotLocked);
+        }
+        self.locked = false;
+        self.unlocks.push(txn);
+        Ok(TxnOutcome::Unlocked)
+    }
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Where an accepted transaction is in its dispute lifecycle. A resolved one can be disputed
+// again as far as the RedisputePolicy allows, a charged back one is final. A refunded withdrawal
+// is out of the lifecycle for good. Holds are never disputed; a released one is done with.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub(crate) enum DisputeState {
+    #[default]
+    Undisputed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+    Refunded,
+    Released,
+}
+
+impl DisputeState {
+    // The state after moving to next, or why that isn't allowed from here
+    pub(crate) fn transition(self, next: DisputeState) -> Result<DisputeState, TxnError> {
+        use DisputeState::*;
+        match (self, next) {
+            (Undisputed | Resolved, Disputed | Refunded) | (Disputed, Resolved | ChargedBack) => {
+                Ok(next)
+            }
+            (Undisputed, Released) => Ok(next),
+            (Disputed, _) => Err(TxnError::AlreadyDisputed),
+            (Resolved, _) => Err(TxnError::AlreadyResolved),
+            (ChargedBack, _) => Err(TxnError::AlreadyChargedBack),
+            (Refunded, _) => Err(TxnError::AlreadyRefunded),
+            (Released, _) => Err(TxnError::AlreadyReleased),
+            (Undisputed, _) => Err(TxnError::NotDisputed),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            DisputeState::Undisputed => "undisputed",
+            DisputeState::Disputed => "disputed",
+            DisputeState::Resolved => "resolved",
+            DisputeState::ChargedBack => "charged_back",
+            DisputeState::Refunded => "refunded",
+            DisputeState::Released => "released",
+        }
+    }
+}
+
+// What's kept of an accepted deposit or withdrawal: only what a later dispute needs, since the
+// client and tx id are already known from where it's stored
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub(crate) struct TxnRecord {
+    pub(crate) kind: TransactionType,
+    pub(crate) amount: Amount,
+    pub(crate) state: DisputeState,
+    // how much of amount the open dispute covers, zero when there's none
+    pub(crate) disputed: Amount,
+    // how many of its disputes have been resolved, for the RedisputePolicy
+    pub(crate) resolutions: u32,
+    // the recipient, for a transfer
+    pub(crate) to: Option<ClientId>,
+    pub(crate) timestamp: Option<i64>,
+    pub(crate) currency: Option<Currency>,
+}
+
+impl TxnRecord {
+    pub(crate) fn new(txn: &Transaction) -> TxnRecord {
+        TxnRecord {
+            kind: txn.tx_type,
+            amount: txn.amount,
+            state: DisputeState::Undisputed,
+            disputed: Amount::ZERO,
+            resolutions: 0,
+            to: txn.to.filter(|_| txn.tx_type == TransactionType::Transfer),
+            timestamp: txn.timestamp,
+            currency: txn.currency,
+        }
+    }
+
+    pub(crate) fn transaction(&self, client: ClientId, tx: TxId) -> Transaction {
+        Transaction {
+            tx_type: self.kind,
+            client,
+            tx,
+            amount: self.amount,
+            to: self.to,
+            timestamp: self.timestamp,
+            currency: self.currency,
+            to_currency: None,
+            effective_date: None,
+        }
+    }
+
+    // Under a dispute window, whether dispute is raised soon enough after this transaction.
+    // Without both timestamps there's no telling, so the dispute is let through.
+    pub(crate) fn check_window(
+        &self,
+        dispute: &Transaction,
+        policy: &Policy,
+    ) -> Result<(), TxnError> {
+        let (Some(days), Some(original), Some(raised)) = (
+            policy.dispute_window_days,
+            self.timestamp,
+            dispute.timestamp,
+        ) else {
+            return Ok(());
+        };
+        if raised.saturating_sub(original) > i64::from(days) * SECONDS_PER_DAY {
+            return Err(TxnError::DisputeWindowExpired);
+        }
+        Ok(())
+    }
+
+    // Open a dispute of `amount`, or add it to the one that's open. A zero (i.e. empty) amount
+    // disputes whatever isn't disputed yet, all of it for a first dispute. Returns the portion
+    // newly disputed.
+    pub(crate) fn open_dispute(
+        &mut self,
+        amount: Amount,
+        redispute: RedisputePolicy,
+    ) -> Result<Amount, TxnError> {
+        if self.state == DisputeState::Resolved && !redispute.allows(self.resolutions) {
+            return Err(TxnError::RedisputeNotAllowed);
+        }
+        let remaining = self.amount - self.disputed;
+        // a partial dispute leaves room for more while the dispute is open
+        if self.state != DisputeState::Disputed || remaining.is_zero() {
+            self.state = self.state.transition(DisputeState::Disputed)?;
         }
-	}
+        let portion = if amount.is_zero() { remaining } else { amount };
+        if portion.is_negative() || portion > remaining {
+            return Err(TxnError::InvalidDisputeAmount);
+        }
+        self.disputed += portion;
+        Ok(portion)
+    }
+
+    // What moving portion of this transaction from available into held actually moves. A
+    // disputed withdrawal moves funds the other way, so its chargeback hands the withdrawn amount
+    // back to the client.
+    pub(crate) fn signed(&self, portion: Amount) -> Amount {
+        match self.kind {
+            TransactionType::Withdrawal => -portion,
+            _ => portion,
+        }
+    }
 }
 
+// A client's row in the account report, one per currency it holds
+#[derive(Serialize, Debug, Copy, Clone)]
+pub struct ClientRecord {
+    pub client: ClientId,
+    // None for the unlabelled balance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+    pub closed: bool,
+}
+
+impl Client {
+    // The unlabelled balance's record, see records for every currency
+    pub fn record(&self) -> ClientRecord {
+        ClientRecord {
+            client: self.client,
+            currency: None,
+            available: self.available,
+            held: self.held,
+            total: self.available + self.held,
+            locked: self.locked,
+            closed: self.closed,
+        }
+    }
+}
+
+// The record as a single report CSV row
+impl fmt::Display for ClientRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{},",
+            self.client, self.available, self.held, self.total, self.locked
+        )?;
+        if let Some(currency) = self.currency {
+            write!(f, "{}", currency)?;
+        }
+        write!(f, ",{}", self.closed)
+    }
+}
+
+// A line per balance, as Client::records has them, the currency empty for the unlabelled one
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, record) in self.records().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{}, {}, {}, {}, {}, ",
+                record.client, record.available, record.held, record.total, record.locked
+            )?;
+            if let Some(currency) = record.currency {
+                write!(f, "{}", currency)?;
+            }
+            write!(f, ", {}", record.closed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispute_states_move_only_where_they_may() {
+        use DisputeState::*;
+        let states = [
+            Undisputed,
+            Disputed,
+            Resolved,
+            ChargedBack,
+            Refunded,
+            Released,
+        ];
+        for from in states {
+            for next in states {
+                let allowed = match from {
+                    Undisputed => vec![Disputed, Refunded, Released],
+                    Disputed => vec![Resolved, ChargedBack],
+                    Resolved => vec![Disputed, Refunded],
+                    ChargedBack | Refunded | Released => vec![],
+                };
+                let refusal = match from {
+                    Undisputed => TxnError::NotDisputed,
+                    Disputed => TxnError::AlreadyDisputed,
+                    Resolved => TxnError::AlreadyResolved,
+                    ChargedBack => TxnError::AlreadyChargedBack,
+                    Refunded => TxnError::AlreadyRefunded,
+                    Released => TxnError::AlreadyReleased,
+                };
+                let expected = if allowed.contains(&next) {
+                    Ok(next)
+                } else {
+                    Err(refusal)
+                };
+                assert_eq!(from.transition(next), expected, "{:?} to {:?}", from, next);
+            }
+        }
+    }
+
+    #[test]
+    fn an_amount_too_precise_is_refused_not_failed() {
+        use crate::policy::{OnError, PrecisionPolicy};
+        use crate::report::RejectsWriter;
+        let mut bank = Bank::with_policy(Policy {
+            precision: PrecisionPolicy::Reject,
+            on_error: OnError::Abort,
+            ..Policy::default()
+        });
+        let csv = "type,client,tx,amount\n\
+                   deposit,1,1,1.5\n\
+                   deposit,1,2,0.00001\n\
+                   withdrawal,1,3,1.23456\n\
+                   deposit,2,4,2\n";
+        let mut rejects = RejectsWriter::new(Vec::new()).unwrap();
+        let stats = bank
+            .process_source_with(TransactionSource::csv(csv.as_bytes()), |txn, err| {
+                rejects.write(txn, err)
+            })
+            .unwrap();
+        assert_eq!(stats.rejected_by.get("too_precise"), Some(&2));
+        assert_eq!(stats.skipped, 0);
+        assert_eq!(
+            bank.get_client(1).unwrap().available(),
+            Amount::from_units(15000)
+        );
+        assert_eq!(
+            bank.get_client(2).unwrap().available(),
+            Amount::from_units(20000)
+        );
+        rejects.flush().unwrap();
+        let written = String::from_utf8(rejects.get_ref().clone()).unwrap();
+        assert_eq!(
+            written,
+            "type,client,tx,amount,reason\n\
+             deposit,1,2,0.0000,too_precise\n\
+             withdrawal,1,3,1.2346,too_precise\n"
+        );
+    }
+}
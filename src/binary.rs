@@ -0,0 +1,287 @@
+// A compact binary form of transactions, for inputs replayed often enough that parsing CSV is
+// most of the cost. `transactions convert` writes it from any input format, and
+// `--input-format binary` reads it back.
+//
+// A file starts with an 8-byte header: the magic "TXNB", the format version, the widths in bytes
+// of the client and tx ids of the build that wrote it, and a zero. Then come fixed-width records,
+// one per transaction, little-endian throughout:
+//
+//   type code          1 byte, see TransactionType::code
+//   flags              1 byte: which of to, timestamp, currency and to_currency are set
+//   client             the header's client id width
+//   tx                 the header's tx id width
+//   amount             16 bytes, rust_decimal's serialized form, exact to the input's precision
+//   to                 the client id width, zero if unset
+//   timestamp          8 bytes, zero if unset
+//   currency           3 bytes of ASCII, zeros if unset
+//   to_currency        3 bytes of ASCII, zeros if unset
+//
+// so with the default ids a record is 40 bytes. A file written with the wide-ids feature can be
+// read by a build without it, and the other way around, as long as the ids fit.
+
+use crate::amount::Amount;
+use crate::bank::{ClientId, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::policy::PrecisionPolicy;
+use crate::snapshot::invalid;
+use crate::source::decided;
+use rust_decimal::Decimal;
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem,
+};
+
+const MAGIC: &[u8] = b"TXNB";
+const VERSION: u8 = 1;
+const HEADER: usize = 8;
+
+// bits of the flags byte
+const TO: u8 = 1;
+const TIMESTAMP: u8 = 1 << 1;
+const CURRENCY: u8 = 1 << 2;
+const TO_CURRENCY: u8 = 1 << 3;
+
+// The id widths a file's records were written with
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    client: usize,
+    tx: usize,
+}
+
+impl Layout {
+    fn native() -> Layout {
+        Layout {
+            client: mem::size_of::<ClientId>(),
+            tx: mem::size_of::<TxId>(),
+        }
+    }
+
+    fn record(self) -> usize {
+        2 + self.client * 2 + self.tx + 16 + 8 + 3 + 3
+    }
+}
+
+// Writes transactions in the binary form, the header first
+pub struct BinaryWriter<W: Write> {
+    out: BufWriter<W>,
+    record: Vec<u8>,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    pub fn new(out: W) -> io::Result<BinaryWriter<W>> {
+        let mut out = BufWriter::new(out);
+        let layout = Layout::native();
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION, layout.client as u8, layout.tx as u8, 0])?;
+        Ok(BinaryWriter {
+            out,
+            record: Vec::with_capacity(layout.record()),
+        })
+    }
+
+    pub fn write(&mut self, txn: &Transaction) -> io::Result<()> {
+        let record = &mut self.record;
+        record.clear();
+        record.push(txn.tx_type.code());
+        let flags = [
+            (txn.to.is_some(), TO),
+            (txn.timestamp.is_some(), TIMESTAMP),
+            (txn.currency.is_some(), CURRENCY),
+            (txn.to_currency.is_some(), TO_CURRENCY),
+        ];
+        record.push(
+            flags
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, bit)| bit)
+                .sum(),
+        );
+        record.extend_from_slice(&txn.client.to_le_bytes());
+        record.extend_from_slice(&txn.tx.to_le_bytes());
+        record.extend_from_slice(&txn.amount.as_decimal().serialize());
+        record.extend_from_slice(&txn.to.unwrap_or(0).to_le_bytes());
+        record.extend_from_slice(&txn.timestamp.unwrap_or(0).to_le_bytes());
+        for currency in [txn.currency, txn.to_currency] {
+            record.extend_from_slice(&currency.map_or([0; 3], |currency| currency.bytes()));
+        }
+        self.out.write_all(record)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Transactions from a binary file, read a record at a time
+pub(crate) struct BinaryRows<R: Read> {
+    reader: BufReader<R>,
+    // read on the first call to next, so a bad header is that call's error
+    layout: Option<Layout>,
+    record: Vec<u8>,
+    // records returned, counting from 1
+    row: u64,
+    done: bool,
+}
+
+impl<R: Read> BinaryRows<R> {
+    pub(crate) fn new(reader: R) -> BinaryRows<R> {
+        BinaryRows {
+            reader: BufReader::new(reader),
+            layout: None,
+            record: Vec::new(),
+            row: 0,
+            done: false,
+        }
+    }
+
+    // The number of the last record returned, counting from 1
+    pub(crate) fn row(&self) -> u64 {
+        self.row
+    }
+
+    fn read_header(&mut self) -> Result<Layout, Error> {
+        let mut header = [0; HEADER];
+        if self.reader.read_exact(&mut header).is_err() || &header[..4] != MAGIC {
+            return Err(invalid("not a binary transactions file".to_string()));
+        }
+        if header[4] != VERSION {
+            return Err(invalid(format!(
+                "binary transactions file is version {}, this build reads version {}",
+                header[4], VERSION
+            )));
+        }
+        let width = |w: u8| (1..=8).contains(&w).then_some(usize::from(w));
+        match (width(header[5]), width(header[6])) {
+            (Some(client), Some(tx)) => Ok(Layout { client, tx }),
+            _ => Err(invalid(
+                "binary transactions file has an invalid id width".to_string(),
+            )),
+        }
+    }
+
+    // Ok(false) at the end of the file
+    fn read_record(&mut self, layout: Layout) -> Result<bool, Error> {
+        self.record.resize(layout.record(), 0);
+        let mut read = 0;
+        while read < self.record.len() {
+            match self.reader.read(&mut self.record[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => {
+                    return Err(invalid(format!(
+                        "binary transactions file ends partway through record {}",
+                        self.row + 1
+                    )))
+                }
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::from(err)),
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> BinaryRows<R> {
+    // The next row, with its amount parsed as precision says
+    pub(crate) fn next(
+        &mut self,
+        precision: PrecisionPolicy,
+    ) -> Option<Result<Transaction, Error>> {
+        if self.done {
+            return None;
+        }
+        let layout = match self.layout {
+            Some(layout) => Ok(layout),
+            None => self.read_header(),
+        };
+        let read = layout.and_then(|layout| {
+            self.layout = Some(layout);
+            self.read_record(layout).map(|read| read.then_some(layout))
+        });
+        let layout = match read {
+            Ok(Some(layout)) => layout,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            // a bad header or a torn record means the rest can't be found
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+        self.row += 1;
+        let txn = decided(
+            precision,
+            |precision| decode(&self.record, layout, precision),
+            Some,
+        );
+        Some(txn.map_err(|err| err.at_line(self.row)))
+    }
+}
+
+// A record whose fields are out of range is an error in that record alone; the next one is where
+// its fixed width says
+fn decode(
+    mut record: &[u8],
+    layout: Layout,
+    precision: PrecisionPolicy,
+) -> Result<Transaction, Error> {
+    let mut take = |n: usize| {
+        let (field, rest) = record.split_at(n);
+        record = rest;
+        field
+    };
+    let tx_type = take(1)[0];
+    let tx_type = TransactionType::from_code(tx_type)
+        .ok_or_else(|| Error::Value(format!("unknown transaction type code {}", tx_type)))?;
+    let flags = take(1)[0];
+    if flags & !(TO | TIMESTAMP | CURRENCY | TO_CURRENCY) != 0 {
+        return Err(Error::Value(format!("unknown flags {:#04x}", flags)));
+    }
+    let client = id::<ClientId>(take(layout.client), "client")?;
+    let tx = id::<TxId>(take(layout.tx), "tx")?;
+    let amount = amount(take(16).try_into().expect("16 bytes"), precision)?;
+    let to = id::<ClientId>(take(layout.client), "to")?;
+    let timestamp = i64::from_le_bytes(take(8).try_into().expect("8 bytes"));
+    let mut currency = |set: bool| -> Result<Option<Currency>, Error> {
+        let bytes = take(3).try_into().expect("3 bytes");
+        if !set {
+            return Ok(None);
+        }
+        Currency::from_bytes(bytes)
+            .map(Some)
+            .ok_or_else(|| Error::Value("invalid currency code".to_string()))
+    };
+    Ok(Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+        to: (flags & TO != 0).then_some(to),
+        timestamp: (flags & TIMESTAMP != 0).then_some(timestamp),
+        currency: currency(flags & CURRENCY != 0)?,
+        to_currency: currency(flags & TO_CURRENCY != 0)?,
+        effective_date: None,
+    })
+}
+
+// An id of whatever width the file has, if it fits this build's
+fn id<T: TryFrom<u64>>(bytes: &[u8], field: &str) -> Result<T, Error> {
+    let v = bytes
+        .iter()
+        .rev()
+        .fold(0u64, |v, byte| v << 8 | u64::from(*byte));
+    T::try_from(v).map_err(|_| Error::Value(format!("{} id {} out of range", field, v)))
+}
+
+fn amount(bytes: [u8; 16], precision: PrecisionPolicy) -> Result<Amount, Error> {
+    // the first four bytes are the sign and the scale, and only those bits can be set
+    let flags = u32::from_le_bytes(bytes[..4].try_into().expect("4 bytes"));
+    if flags & !0x80ff_0000 != 0 || (flags >> 16) & 0xff > 28 {
+        return Err(Error::Value("amount isn't a valid decimal".to_string()));
+    }
+    Amount::from_decimal(Decimal::deserialize(bytes), precision)
+        .map_err(|err| Error::Value(err.to_string()))
+}
@@ -0,0 +1,196 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, ClientId, ClientRecord, TransactionType};
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::error::Error;
+use crate::statement::StatementLine;
+use std::{collections::BTreeMap, io};
+
+// Statements as ISO 20022 camt.053 (BkToCstmrStmt, version 001.02) documents, for systems that
+// take nothing else. A document has a Stmt per account and currency, each with the balance it
+// opened with (OPBD), the booked and available ones it closed with (CLBD, CLAV) and an Ntry per
+// statement line that changed the booked balance: deposits, withdrawals, fees, transfers,
+// accruals and chargebacks, but not disputes or resolves, which only move funds between
+// available and held. A chargeback's or refund's entry is marked as a reversal. Each entry's
+// bank transaction code is proprietary, the engine's name for its type, and its booking date is
+// the day of its timestamp if it has one. A conversion's entry is only on the statement of the
+// currency it's out of, the line saying nothing of the one it's into.
+//
+// camt.053 amounts always name their currency, so the unlabelled balance's are given in
+// `unlabelled`, XXX (ISO 4217's "no currency") unless the caller knows better.
+pub struct Camt053 {
+    // each account's total in each currency before the lines
+    opening: BTreeMap<(ClientId, Option<Currency>), Amount>,
+    pub unlabelled: Currency,
+    // when the document is made, in seconds since the Unix epoch
+    pub created: i64,
+}
+
+impl Camt053 {
+    // A statement of what happens to bank from now on, made at created
+    pub fn open(bank: &Bank, created: i64) -> Camt053 {
+        Camt053 {
+            opening: bank
+                .records()
+                .map(|record| ((record.client, record.currency), record.total))
+                .collect(),
+            unlabelled: "XXX".parse().expect("XXX is a currency code"),
+            created,
+        }
+    }
+
+    // Writes the statements of every account bank now has, with lines as their movements
+    pub fn write<W: io::Write>(
+        &self,
+        bank: &Bank,
+        lines: &[StatementLine],
+        mut w: W,
+    ) -> Result<(), Error> {
+        let mut entries: BTreeMap<(ClientId, Option<Currency>), Vec<Entry>> = BTreeMap::new();
+        let mut totals = self.opening.clone();
+        for line in lines {
+            let key = (line.account, line.currency);
+            let before = totals.insert(key, line.total).unwrap_or(Amount::ZERO);
+            if line.total != before {
+                entries.entry(key).or_default().push(Entry {
+                    line: *line,
+                    amount: line.total - before,
+                });
+            }
+        }
+        let message = format!("TXN{}", self.created);
+        let created = datetime(self.created);
+        let today = Date::of_timestamp(self.created);
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">"#
+        )?;
+        writeln!(w, "  <BkToCstmrStmt>")?;
+        writeln!(w, "    <GrpHdr>")?;
+        writeln!(w, "      <MsgId>{}</MsgId>", message)?;
+        writeln!(w, "      <CreDtTm>{}</CreDtTm>", created)?;
+        writeln!(w, "    </GrpHdr>")?;
+        for record in bank.sorted_records() {
+            let key = (record.client, record.currency);
+            let currency = record.currency.unwrap_or(self.unlabelled);
+            let opening = self.opening.get(&key).copied().unwrap_or(Amount::ZERO);
+            writeln!(w, "    <Stmt>")?;
+            match record.currency {
+                Some(code) => writeln!(w, "      <Id>{}-{}-{}</Id>", message, record.client, code)?,
+                None => writeln!(w, "      <Id>{}-{}</Id>", message, record.client)?,
+            }
+            writeln!(w, "      <CreDtTm>{}</CreDtTm>", created)?;
+            writeln!(w, "      <Acct>")?;
+            writeln!(
+                w,
+                "        <Id><Othr><Id>{}</Id></Othr></Id>",
+                record.client
+            )?;
+            writeln!(w, "        <Ccy>{}</Ccy>", currency)?;
+            writeln!(w, "      </Acct>")?;
+            write_balance(&mut w, "OPBD", opening, currency, today)?;
+            write_balance(&mut w, "CLBD", record.total, currency, today)?;
+            write_balance(&mut w, "CLAV", record.available, currency, today)?;
+            for entry in entries.get(&key).into_iter().flatten() {
+                write_entry(&mut w, entry, currency, &record)?;
+            }
+            writeln!(w, "    </Stmt>")?;
+        }
+        writeln!(w, "  </BkToCstmrStmt>")?;
+        writeln!(w, "</Document>")?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+// A change to an account's booked balance, and the line that made it
+struct Entry {
+    line: StatementLine,
+    amount: Amount,
+}
+
+// camt.053 amounts are unsigned, with whether they're a credit or a debit alongside
+fn signed(amount: Amount) -> (Amount, &'static str) {
+    if amount.is_negative() {
+        (-amount, "DBIT")
+    } else {
+        (amount, "CRDT")
+    }
+}
+
+fn write_balance<W: io::Write>(
+    w: &mut W,
+    code: &str,
+    amount: Amount,
+    currency: Currency,
+    date: Date,
+) -> io::Result<()> {
+    let (amount, indicator) = signed(amount);
+    writeln!(w, "      <Bal>")?;
+    writeln!(
+        w,
+        "        <Tp><CdOrPrtry><Cd>{}</Cd></CdOrPrtry></Tp>",
+        code
+    )?;
+    writeln!(w, r#"        <Amt Ccy="{}">{}</Amt>"#, currency, amount)?;
+    writeln!(w, "        <CdtDbtInd>{}</CdtDbtInd>", indicator)?;
+    writeln!(w, "        <Dt><Dt>{}</Dt></Dt>", date)?;
+    writeln!(w, "      </Bal>")
+}
+
+fn write_entry<W: io::Write>(
+    w: &mut W,
+    entry: &Entry,
+    currency: Currency,
+    account: &ClientRecord,
+) -> io::Result<()> {
+    let line = &entry.line;
+    let (amount, indicator) = signed(entry.amount);
+    writeln!(w, "      <Ntry>")?;
+    writeln!(w, "        <NtryRef>{}</NtryRef>", line.tx)?;
+    writeln!(w, r#"        <Amt Ccy="{}">{}</Amt>"#, currency, amount)?;
+    writeln!(w, "        <CdtDbtInd>{}</CdtDbtInd>", indicator)?;
+    if matches!(
+        line.tx_type,
+        TransactionType::Chargeback | TransactionType::Refund
+    ) {
+        writeln!(w, "        <RvslInd>true</RvslInd>")?;
+    }
+    writeln!(w, "        <Sts>BOOK</Sts>")?;
+    if let Some(timestamp) = line.timestamp {
+        writeln!(
+            w,
+            "        <BookgDt><Dt>{}</Dt></BookgDt>",
+            Date::of_timestamp(timestamp)
+        )?;
+    }
+    writeln!(w, "        <AcctSvcrRef>{}</AcctSvcrRef>", line.tx)?;
+    writeln!(w, "        <BkTxCd>")?;
+    writeln!(w, "          <Prtry><Cd>{}</Cd></Prtry>", line.tx_type)?;
+    writeln!(w, "        </BkTxCd>")?;
+    // a transfer's other party, when the entry is on the recipient's statement
+    if line.client != account.client {
+        writeln!(w, "        <NtryDtls><TxDtls><RltdPties><Dbtr>")?;
+        writeln!(
+            w,
+            "          <Id><PrvtId><Othr><Id>{}</Id></Othr></PrvtId></Id>",
+            line.client
+        )?;
+        writeln!(w, "        </Dbtr></RltdPties></TxDtls></NtryDtls>")?;
+    }
+    writeln!(w, "      </Ntry>")
+}
+
+// An ISO 8601 date and time, in UTC
+fn datetime(seconds: i64) -> String {
+    let date = Date::of_timestamp(seconds);
+    let time = seconds - date.timestamp();
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
@@ -0,0 +1,112 @@
+use crate::bank::{Bank, Transaction};
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::snapshot::{invalid, write_atomically, Snapshot};
+use crate::source::{SourceStats, TransactionSource};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+// How far into a run's inputs a checkpoint was taken: rows of inputs before `file` have all been
+// applied, as have the first `rows` rows of `file` itself
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Position {
+    // index into the run's list of inputs
+    pub file: usize,
+    // that input's path, to catch a resume against a different list
+    pub path: PathBuf,
+    // rows read from it so far, malformed ones included
+    pub rows: u64,
+    // length of the run's rejects file at the checkpoint, if it writes one
+    pub rejects_len: Option<u64>,
+}
+
+// A checkpoint is the bank's snapshot and its position, in one file so they can't disagree
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    position: Position,
+    bank: Snapshot,
+}
+
+impl Bank {
+    // Like process_source_with, calling on_checkpoint after every `every` rows read with the
+    // number read so far
+    pub fn process_source_checkpointed<R, F, C>(
+        &mut self,
+        source: TransactionSource<R>,
+        every: u64,
+        mut on_reject: F,
+        mut on_checkpoint: C,
+    ) -> Result<SourceStats, Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+        C: FnMut(&Bank, u64) -> Result<(), Error>,
+    {
+        let mut stats = SourceStats::default();
+        let mut source = source;
+        let mut next_checkpoint = every;
+        while let Some(txn) = self.next_from(&mut source, &mut stats)? {
+            let result = self.insert_read(txn, source.refusal());
+            self.check_spill()?;
+            match result {
+                Ok(outcome) => stats.applied(outcome),
+                Err(err) => {
+                    stats.reject(&txn, &err, source.line());
+                    on_reject(&txn, &err)?;
+                }
+            }
+            // malformed rows count towards the position too, they're read past all the same; a
+            // recurring row is one row, and only read past once all of it has been applied, and
+            // batch control rows are rows of the input too
+            let read = stats.rows + stats.skipped + stats.controls - stats.expanded;
+            if read >= next_checkpoint && !source.mid_row() {
+                on_checkpoint(self, read)?;
+                next_checkpoint = read + every;
+            }
+        }
+        Ok(stats)
+    }
+
+    pub fn save_checkpoint<P: AsRef<Path>>(
+        &self,
+        path: P,
+        position: &Position,
+    ) -> Result<(), Error> {
+        let checkpoint = Checkpoint {
+            position: position.clone(),
+            bank: self.snapshot()?,
+        };
+        write_atomically(path.as_ref(), |w| {
+            Ok(serde_json::to_writer(w, &checkpoint)?)
+        })
+    }
+
+    // The restored bank has the default policy, see set_policy
+    pub fn load_checkpoint<P: AsRef<Path>>(path: P) -> Result<(Bank, Position), Error> {
+        let path = path.as_ref();
+        let load = || -> Result<(Bank, Position), Error> {
+            let checkpoint: Checkpoint =
+                serde_json::from_reader(BufReader::new(File::open(path)?))?;
+            Ok((Bank::from_snapshot(checkpoint.bank)?, checkpoint.position))
+        };
+        load().map_err(|err| err.in_file(path))
+    }
+}
+
+impl Position {
+    // Check the checkpoint was taken over the same list of inputs
+    pub fn check_inputs(&self, inputs: &[PathBuf]) -> Result<(), Error> {
+        match inputs.get(self.file) {
+            Some(path) if *path == self.path => Ok(()),
+            _ => Err(invalid(format!(
+                "checkpoint is for input {} ({}), which isn't in the same place in this run's inputs",
+                self.file + 1,
+                self.path.display()
+            ))),
+        }
+    }
+}
@@ -0,0 +1,730 @@
+use clap::{
+    builder::PossibleValuesParser, builder::TypedValueParser, ArgAction, Args, Parser, Subcommand,
+};
+use rust_decimal::Decimal;
+use std::{ops::RangeInclusive, path::PathBuf, str::FromStr, sync::Arc};
+use transactions::{
+    date::Date,
+    fx::Rates,
+    limits::{Limit, Limits},
+    AccountOpeningPolicy, Amount, AmountStyle, Bank, ClientId, ControlPolicy, CsvOptions, Currency,
+    DisputePolicy, InputFormat, LockedPolicy, NegativeAmountPolicy, OnError, OutputFormat, Policy,
+    PrecisionPolicy, RedisputePolicy, TxIdPolicy, VelocityRule, WithdrawalPolicy,
+};
+
+/// Toy payments engine: applies deposits, withdrawals and disputes and reports client balances
+#[derive(Parser, Debug)]
+#[command(name = "transactions", version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // running without a subcommand behaves like `process`
+    #[command(flatten)]
+    pub process: ProcessArgs,
+
+    /// Read option defaults from this TOML file, keyed by long option name
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Also log refused transactions to stderr, and with -vv accepted ones too
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only log errors to stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Apply transactions and write the final account report
+    Process(ProcessArgs),
+    /// Check that every input parses, without writing a report
+    Validate(ValidateArgs),
+    /// Render reports from processed transactions
+    #[command(subcommand)]
+    Report(ReportCommand),
+    /// Apply transactions, or only load --snapshot-in, and print one client's account
+    Query(QueryArgs),
+    /// Rewrite transactions in another format without applying them, e.g. CSV to binary for
+    /// faster replays
+    Convert(ConvertArgs),
+    /// Write a reproducible, made-up CSV workload, for benchmarks and load tests
+    Generate(GenerateArgs),
+    /// Compare two account reports and list the accounts that differ, e.g. to check an upgrade
+    /// against a golden output
+    Reconcile(ReconcileArgs),
+    /// Show how each account changed between two snapshots, e.g. from before and after a batch
+    Diff(DiffArgs),
+    /// Run as a long-lived service applying transactions to an in-memory bank
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ReportCommand {
+    /// Per-client available, held, total and locked state
+    Accounts(Box<ProcessArgs>),
+    /// One client's accepted transactions in order, each with the balance it left
+    Statement(Box<StatementArgs>),
+}
+
+#[derive(Args, Debug)]
+pub struct StatementArgs {
+    /// The client whose statement to write. A camt053 document has every client's if it's not
+    /// given.
+    #[arg(long)]
+    pub client: Option<ClientId>,
+
+    /// Format of the statement: csv lines, or an ISO 20022 camt.053 XML document
+    #[arg(long, value_enum, default_value = "csv")]
+    pub format: StatementFormat,
+
+    /// The currency to give camt.053 amounts of balances without one in, which it always names.
+    /// Defaults to XXX, ISO 4217's code for no currency.
+    #[arg(long, value_name = "CODE")]
+    pub currency: Option<Currency>,
+
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+
+    /// Write the statement to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatementFormat {
+    Csv,
+    Camt053,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+    /// The client whose account to print
+    #[arg(long)]
+    pub client: ClientId,
+
+    // with no files but a --snapshot-in, only the snapshot is read rather than stdin
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+
+    /// Format of the account
+    #[arg(long, default_value = "csv", value_parser = named::<OutputFormat>(OutputFormat::NAMES))]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct InputArgs {
+    /// Input files, directories or globs, processed in order ("-" or nothing reads stdin)
+    pub files: Vec<String>,
+
+    /// Format of the input transactions
+    #[arg(long, default_value = "csv", value_parser = named::<InputFormat>(InputFormat::NAMES))]
+    pub input_format: InputFormat,
+
+    /// CSV inputs have no header row; their columns are type, client, tx, amount, then optionally
+    /// to, timestamp, currency, to_currency and effective_date
+    #[arg(long)]
+    pub no_header: bool,
+
+    /// The character between CSV fields: a single character such as | or ;, or \t (or tab) for
+    /// tab-separated inputs
+    #[arg(long, value_name = "CHAR", default_value = ",", value_parser = delimiter)]
+    pub delimiter: u8,
+
+    /// The client OFX and QIF statements are of. OFX ones default to the client numbered like
+    /// the statement's ACCTID; QIF ones have no account number, so need it.
+    #[arg(long, value_name = "CLIENT")]
+    pub account_client: Option<ClientId>,
+
+    /// What to do when a CSV batch's rows don't match the count and amount totals its
+    /// batch_header or batch_trailer row declares: fail the run, or warn and report the balances
+    #[arg(long, default_value = "fail", value_parser = named::<ControlPolicy>(ControlPolicy::NAMES))]
+    pub control_totals: ControlPolicy,
+
+    /// Show lines read, throughput and an estimate of the time left on stderr while reading
+    #[arg(long)]
+    pub progress: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct EngineArgs {
+    /// What to do with a withdrawal or fee larger than the available funds
+    #[arg(long, default_value = "reject", value_parser = named::<WithdrawalPolicy>(WithdrawalPolicy::NAMES))]
+    pub withdrawal_policy: WithdrawalPolicy,
+
+    /// Which transaction types can be disputed
+    #[arg(long, default_value = "deposits", value_parser = named::<DisputePolicy>(DisputePolicy::NAMES))]
+    pub dispute_policy: DisputePolicy,
+
+    /// Refuse disputes raised more than this many days after the transaction. Needs a `timestamp`
+    /// column (seconds since the Unix epoch) on both.
+    #[arg(long, value_name = "DAYS")]
+    pub dispute_window_days: Option<u32>,
+
+    /// How often a transaction can be disputed again once a dispute of it is resolved: unlimited,
+    /// forbid, once, or a number of times
+    #[arg(long, value_name = "POLICY", default_value = "unlimited")]
+    pub redispute_policy: RedisputePolicy,
+
+    /// Whether deposit/withdrawal tx ids must be unique across all clients or only per client
+    #[arg(long, default_value = "global", value_parser = named::<TxIdPolicy>(TxIdPolicy::NAMES))]
+    pub tx_id_policy: TxIdPolicy,
+
+    /// What to do with a row that fails to parse: stop, skip it silently, or skip and report it
+    #[arg(long, default_value = "abort", value_parser = named::<OnError>(OnError::NAMES))]
+    pub on_error: OnError,
+
+    /// What to do with an amount given to more than four decimal places: round it half away from
+    /// zero, or refuse its transaction as too_precise
+    #[arg(long, default_value = "round", value_parser = named::<PrecisionPolicy>(PrecisionPolicy::NAMES))]
+    pub precision_policy: PrecisionPolicy,
+
+    /// Apply deposits and withdrawals with negative amounts, as adjustment feeds send to correct
+    /// earlier rows, instead of rejecting them as negative_amount
+    #[arg(long)]
+    pub allow_negative_amounts: bool,
+
+    /// Which transactions open an account for a client that has none
+    #[arg(long, default_value = "deposit", value_parser = named::<AccountOpeningPolicy>(AccountOpeningPolicy::NAMES))]
+    pub account_opening: AccountOpeningPolicy,
+
+    /// What a locked account still takes besides an unlock or close: nothing, or disputes,
+    /// resolves and chargebacks of what it already had
+    #[arg(long, default_value = "frozen", value_parser = named::<LockedPolicy>(LockedPolicy::NAMES))]
+    pub locked_policy: LockedPolicy,
+
+    /// Interest paid per period on positive available balances by `accrue` rows, e.g. 0.0001
+    #[arg(long, value_name = "RATE", default_value = "0")]
+    pub interest_rate: Decimal,
+
+    /// CSV of exchange rates (from,to,rate columns) for `convert` rows and --report-currency
+    #[arg(long, value_name = "PATH")]
+    pub rates: Option<PathBuf>,
+
+    /// Refuse any withdrawal for more than this, as limit_exceeded
+    #[arg(long, value_name = "AMOUNT")]
+    pub max_withdrawal: Option<Amount>,
+
+    /// Refuse withdrawals that would take a client's withdrawals, all added up, over this
+    #[arg(long, value_name = "AMOUNT")]
+    pub max_total_withdrawals: Option<Amount>,
+
+    /// CSV of per-client withdrawal limits (client,max_withdrawal,max_total columns, an empty one
+    /// keeping the bank-wide limit)
+    #[arg(long, value_name = "PATH")]
+    pub limits: Option<PathBuf>,
+
+    /// Flag or block clients moving funds too fast, e.g. count=5,window=1h or
+    /// amount=10000,window=20tx,action=block (repeatable)
+    #[arg(long, value_name = "RULE")]
+    pub velocity: Vec<VelocityRule>,
+
+    /// Apply only transactions whose effective_date is on or before this day (YYYY-MM-DD),
+    /// queueing later ones as pending; one queued in the --snapshot-in applies once it's due
+    #[arg(long, value_name = "DATE")]
+    pub as_of: Option<Date>,
+
+    /// Apply each client's transactions on a separate worker thread (reads each input fully into memory)
+    #[cfg(feature = "parallel")]
+    #[arg(long)]
+    pub parallel: bool,
+
+    /// Write every transaction the engine refused, with a reason code, to this CSV file
+    #[arg(long, value_name = "PATH")]
+    pub rejects_file: Option<PathBuf>,
+
+    /// Exit with status 5 when the engine refused any transaction, not only when rows were
+    /// skipped as malformed
+    #[arg(long)]
+    pub partial_on_rejects: bool,
+
+    /// Record every transaction with its outcome and the balance changes it made, one JSON object
+    /// per line, in this file
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Write a CSV line per transaction to this file, with its outcome or why it was refused and
+    /// the client's available and held balance after it, for acking each record downstream
+    #[arg(long, value_name = "PATH")]
+    pub outcomes_file: Option<PathBuf>,
+
+    /// Start from the state saved by an earlier run's --snapshot-out instead of an empty bank
+    #[arg(long, value_name = "PATH")]
+    pub snapshot_in: Option<PathBuf>,
+
+    /// Start from the balances in an earlier run's CSV account report. Its transactions aren't
+    /// known, so they can't be disputed; prefer --snapshot-in where there is one.
+    #[arg(long, value_name = "PATH", conflicts_with = "snapshot_in")]
+    pub initial_state: Option<PathBuf>,
+
+    /// Refuse rows the runs up to --snapshot-in already processed, as `already_seen`, and keep
+    /// this run's in --snapshot-out, so re-feeding an overlapping file doesn't apply them twice
+    #[arg(long)]
+    pub dedup_seen: bool,
+
+    /// Keep at most about this many deposits/withdrawals in memory, moving older ones to a
+    /// temporary file
+    #[arg(long, value_name = "COUNT")]
+    pub max_txns_in_memory: Option<usize>,
+
+    /// After every transaction, check that each balance's total matches its ledger, nothing is
+    /// held below zero and the totals moved by what the input says, aborting at the first that
+    /// doesn't; slow, for testing changes to the engine
+    #[arg(long)]
+    pub check_invariants: bool,
+}
+
+impl InputArgs {
+    pub fn csv_options(&self) -> CsvOptions {
+        CsvOptions {
+            header: !self.no_header,
+            delimiter: self.delimiter,
+        }
+    }
+}
+
+impl OutputArgs {
+    pub fn amount_style(&self) -> AmountStyle {
+        AmountStyle {
+            precision: self.precision,
+            trim_zeros: self.trim_zeros,
+            minor_units: self.minor_units,
+        }
+    }
+}
+
+impl EngineArgs {
+    pub fn policy(&self) -> Result<Policy, transactions::Error> {
+        let rates = match &self.rates {
+            Some(path) => Rates::load(path)?,
+            None => Rates::default(),
+        };
+        let limit = Limit {
+            max_withdrawal: self.max_withdrawal,
+            max_total: self.max_total_withdrawals,
+        };
+        let limits = match &self.limits {
+            Some(path) => Limits::load(path, limit)?,
+            None => Limits {
+                default: limit,
+                ..Limits::default()
+            },
+        };
+        Ok(Policy {
+            withdrawal: self.withdrawal_policy,
+            dispute: self.dispute_policy,
+            tx_ids: self.tx_id_policy,
+            on_error: self.on_error,
+            opening: self.account_opening,
+            locked: self.locked_policy,
+            dispute_window_days: self.dispute_window_days,
+            redispute: self.redispute_policy,
+            interest_rate: self.interest_rate,
+            rates: Arc::new(rates),
+            precision: self.precision_policy,
+            negative: if self.allow_negative_amounts {
+                NegativeAmountPolicy::Allow
+            } else {
+                NegativeAmountPolicy::Reject
+            },
+            limits: Arc::new(limits),
+            as_of: self.as_of,
+        })
+    }
+
+    // The bank to apply this run's transactions to
+    pub fn bank(&self) -> Result<Bank, transactions::Error> {
+        let mut bank = match (&self.snapshot_in, &self.initial_state) {
+            (Some(path), _) => Bank::load_snapshot(path)?,
+            (None, Some(path)) => Bank::load_accounts(path)?,
+            (None, None) => Bank::new(),
+        };
+        bank.set_policy(self.policy()?);
+        if self.dedup_seen {
+            bank.dedup_seen();
+        }
+        if let Some(max) = self.max_txns_in_memory {
+            bank.spill_to_disk(max)?;
+        }
+        for rule in &self.velocity {
+            bank = bank.with_velocity_rule(*rule);
+        }
+        if self.check_invariants {
+            bank.check_invariants();
+        }
+        Ok(bank)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct OutputArgs {
+    /// Format of the account report
+    #[arg(long, default_value = "csv", value_parser = named::<OutputFormat>(OutputFormat::NAMES))]
+    pub output_format: OutputFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// List clients in id order, so identical inputs give identical reports
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = ArgAction::Set)]
+    pub sorted: bool,
+
+    /// Decimal places in report amounts (rounding half away from zero below 4)
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u32).range(0..=28))]
+    pub precision: u32,
+
+    /// Drop trailing zeros from report amounts, e.g. 1.5 rather than 1.5000
+    #[arg(long)]
+    pub trim_zeros: bool,
+
+    /// Write report amounts as integer counts of the smallest unit at --precision, e.g. cents for 2
+    #[arg(long, conflicts_with = "trim_zeros")]
+    pub minor_units: bool,
+
+    /// Report one row per client with every balance converted to this currency at the --rates.
+    /// Balances without a currency are taken to be in it already.
+    #[arg(long, value_name = "CURRENCY", requires = "rates")]
+    pub report_currency: Option<Currency>,
+
+    /// Only report these clients, e.g. 1,5,100-200; every transaction is still applied
+    #[arg(long, value_name = "IDS")]
+    pub clients: Option<ClientIds>,
+
+    /// Also save the final engine state here, to carry on from it with --snapshot-in
+    #[arg(long, value_name = "PATH")]
+    pub snapshot_out: Option<PathBuf>,
+
+    /// Write the transactions left pending by --as-of to this CSV file
+    #[arg(long, value_name = "PATH")]
+    pub pending_file: Option<PathBuf>,
+
+    /// Write end-of-run totals (rows, rejections by reason, clients, balances, time taken) to this
+    /// file, or to stderr for "-"
+    #[arg(long, value_name = "PATH")]
+    #[cfg_attr(feature = "kafka", arg(conflicts_with = "source"))]
+    pub summary: Option<PathBuf>,
+
+    /// Apply every input as usual but only report how many rows would be accepted and rejected
+    /// and why, leaving any --database untouched and writing no account report
+    #[arg(long, conflicts_with_all = ["snapshot_out", "checkpoint"])]
+    #[cfg_attr(feature = "kafka", arg(conflicts_with = "source"))]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ProcessArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[cfg(feature = "kafka")]
+    #[command(flatten)]
+    pub kafka: KafkaArgs,
+
+    #[cfg(feature = "sqlite")]
+    #[command(flatten)]
+    pub store: StoreArgs,
+
+    #[command(flatten)]
+    pub checkpoint: CheckpointArgs,
+
+    #[command(flatten)]
+    pub follow: FollowArgs,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct FollowArgs {
+    /// Keep the (single, uncompressed) input file open after reading it, applying rows as they're
+    /// appended like `tail -f` and rewriting the report periodically
+    #[arg(long, conflicts_with_all = ["checkpoint", "summary", "dry_run", "progress"])]
+    #[cfg_attr(feature = "parallel", arg(conflicts_with = "parallel"))]
+    #[cfg_attr(feature = "sqlite", arg(conflicts_with = "database"))]
+    #[cfg_attr(feature = "kafka", arg(conflicts_with = "source"))]
+    pub follow: bool,
+
+    /// Seconds between account reports while rows keep arriving
+    #[arg(long, value_name = "SECS", default_value_t = 5, requires = "follow")]
+    pub report_interval: u64,
+}
+
+#[cfg(feature = "kafka")]
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    File,
+    Kafka,
+}
+
+#[cfg(feature = "kafka")]
+#[derive(Args, Debug)]
+pub struct KafkaArgs {
+    /// Read the input files, or consume a Kafka topic continuously and write the report periodically
+    #[arg(long, value_enum, default_value = "file")]
+    pub source: Source,
+
+    /// Comma-separated Kafka bootstrap servers
+    #[arg(long, value_name = "HOSTS", default_value = "localhost:9092")]
+    pub kafka_brokers: String,
+
+    /// Topic of transaction messages, one or more rows per message in --input-format
+    #[arg(long, value_name = "TOPIC", required_if_eq("source", "kafka"))]
+    pub kafka_topic: Option<String>,
+
+    /// Consumer group id, so a restarted consumer carries on from its committed offsets
+    #[arg(long, value_name = "GROUP", default_value = "transactions")]
+    pub kafka_group: String,
+
+    /// Seconds between account report snapshots
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub snapshot_interval: u64,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Args, Debug)]
+pub struct StoreArgs {
+    /// Keep accounts and transaction history in this SQLite file instead of memory, carrying on from
+    /// whatever an earlier run left in it
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["snapshot_in", "snapshot_out", "checkpoint", "audit_log", "outcomes_file", "dedup_seen", "initial_state", "as_of", "velocity", "check_invariants"])]
+    #[cfg_attr(feature = "parallel", arg(conflicts_with = "parallel"))]
+    #[cfg_attr(feature = "kafka", arg(conflicts_with = "source"))]
+    pub database: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckpointArgs {
+    /// Periodically save the engine state and input position to this file, so an interrupted run
+    /// can carry on with --resume. It's removed once the run completes.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["audit_log", "outcomes_file"])]
+    #[cfg_attr(feature = "parallel", arg(conflicts_with = "parallel"))]
+    #[cfg_attr(feature = "kafka", arg(conflicts_with = "source"))]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Input rows between checkpoints
+    #[arg(
+        long,
+        value_name = "ROWS",
+        default_value_t = 1_000_000,
+        requires = "checkpoint"
+    )]
+    pub checkpoint_every: u64,
+
+    /// Carry on from the --checkpoint file instead of starting over (starts over if there is none)
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Format to write: binary, the fixed-width records --input-format binary reads, or csv
+    #[arg(long, default_value = "binary", value_parser = named::<InputFormat>(InputFormat::WRITABLE_NAMES))]
+    pub to: InputFormat,
+
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// What to do with a row that fails to parse: stop, skip it silently, or skip and report it
+    #[arg(long, default_value = "abort", value_parser = named::<OnError>(OnError::NAMES))]
+    pub on_error: OnError,
+}
+
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// Spread the rows over clients 1 to this
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(ClientId).range(1..))]
+    pub clients: ClientId,
+
+    /// How many rows to write; digits may be grouped with underscores, e.g. 10_000_000
+    #[arg(long, default_value = "100_000", value_parser = count)]
+    pub rows: u64,
+
+    /// Share of rows that are withdrawals, some of them more than the client has
+    #[arg(long, default_value_t = 0.2, value_parser = share)]
+    pub withdrawal_rate: f64,
+
+    /// Share of rows that dispute an earlier deposit or withdrawal; about as many more settle
+    /// open disputes
+    #[arg(long, default_value_t = 0.01, value_parser = share)]
+    pub dispute_rate: f64,
+
+    /// Share of settled disputes that end in a chargeback rather than a resolve
+    #[arg(long, default_value_t = 0.1, value_parser = share)]
+    pub chargeback_rate: f64,
+
+    /// Share of rows that are deposits reusing an earlier tx id, which the engine must refuse
+    #[arg(long, default_value_t = 0.0, value_parser = share)]
+    pub duplicate_rate: f64,
+
+    /// The same seed and options always give the same rows
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Write to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ReconcileArgs {
+    /// The first CSV account report, e.g. the golden one
+    pub a: PathBuf,
+
+    /// The report to compare with it
+    pub b: PathBuf,
+
+    /// How far apart available or held balances may be and still match, e.g. 0.01 for reports
+    /// written with --precision 2
+    #[arg(long, default_value = "0")]
+    pub tolerance: Decimal,
+
+    /// Write the differing accounts to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// The earlier state, from --snapshot-out
+    #[arg(long, value_name = "PATH")]
+    pub before: PathBuf,
+
+    /// The later state, from --snapshot-out
+    #[arg(long, value_name = "PATH")]
+    pub after: PathBuf,
+
+    /// Write the changed accounts to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+// serve needs at least one listener
+#[cfg(feature = "server")]
+const SERVE_ADDRS: &[&str] = &[
+    #[cfg(feature = "http")]
+    "http",
+    #[cfg(feature = "grpc")]
+    "grpc",
+];
+
+#[cfg(feature = "server")]
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Accept newline-delimited transactions and `query <client>` commands on this TCP address
+    #[arg(long, value_name = "ADDR", required_unless_present_any = SERVE_ADDRS)]
+    pub tcp: Option<std::net::SocketAddr>,
+
+    /// Serve the REST API (POST /transactions, GET /clients/{id}, GET /report) on this address
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "ADDR")]
+    pub http: Option<std::net::SocketAddr>,
+
+    /// Serve only the Prometheus metrics (GET /metrics) on this address; --http serves them too
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "ADDR")]
+    pub metrics: Option<std::net::SocketAddr>,
+
+    /// Serve the gRPC TransactionService (see proto/transactions.proto) on this address
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "ADDR")]
+    pub grpc: Option<std::net::SocketAddr>,
+
+    /// Format of the transactions sent by clients
+    #[arg(long, default_value = "csv", value_parser = named::<InputFormat>(InputFormat::LINE_NAMES))]
+    pub input_format: InputFormat,
+
+    /// Log every transaction to this file before applying it, replaying what's already there on
+    /// startup to recover the state from before a crash
+    #[arg(long, value_name = "PATH")]
+    pub wal: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub engine: EngineArgs,
+}
+
+// The --clients list: ids and inclusive ranges of them
+#[derive(Debug, Clone)]
+pub struct ClientIds(Vec<RangeInclusive<ClientId>>);
+
+impl ClientIds {
+    pub fn contains(&self, client: ClientId) -> bool {
+        self.0.iter().any(|range| range.contains(&client))
+    }
+}
+
+impl FromStr for ClientIds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ClientIds, String> {
+        let id = |s: &str| {
+            s.trim()
+                .parse::<ClientId>()
+                .map_err(|_| format!("invalid client id '{}'", s.trim()))
+        };
+        s.split(',')
+            .map(|part| match part.split_once('-') {
+                Some((first, last)) if id(first)? <= id(last)? => Ok(id(first)?..=id(last)?),
+                Some(_) => Err(format!("client range '{}' is backwards", part.trim())),
+                None => Ok(id(part)?..=id(part)?),
+            })
+            .collect::<Result<_, _>>()
+            .map(ClientIds)
+    }
+}
+
+fn delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s != "\"" => Ok(s.as_bytes()[0]),
+        _ => Err(format!(
+            "'{}' isn't a single ASCII character other than a quote",
+            s
+        )),
+    }
+}
+
+fn count(s: &str) -> Result<u64, String> {
+    s.replace('_', "")
+        .parse()
+        .map_err(|_| format!("'{}' isn't a whole number", s))
+}
+
+fn share(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        _ => Err(format!("'{}' isn't a number from 0 to 1", s)),
+    }
+}
+
+// Value parser for the library's named enums, so --help can list the accepted values
+fn named<T>(names: &'static [&'static str]) -> impl TypedValueParser<Value = T>
+where
+    T: FromStr<Err = String> + Clone + Send + Sync + 'static,
+{
+    PossibleValuesParser::new(names).try_map(|s| s.parse::<T>())
+}
@@ -0,0 +1,134 @@
+// Transactions out of binary formats whose fields are typed values rather than text, Parquet and
+// Avro: the values of a row's STANDARD_COLUMNS, converted the way the CSV and JSON inputs would
+// parse them.
+
+use crate::amount::Amount;
+use crate::bank::{ClientId, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::error::Error;
+use crate::policy::PrecisionPolicy;
+use crate::source::STANDARD_COLUMNS;
+use rust_decimal::Decimal;
+use serde::{de::IntoDeserializer, Deserialize};
+use std::{str::FromStr, sync::Arc};
+
+// One decoded value. Dictionary-encoded strings share the dictionary's copy.
+#[derive(Clone, Debug)]
+pub(crate) enum Datum {
+    Null,
+    // booleans too, as 0 or 1
+    Int(i64),
+    Float(f64),
+    Bytes(Arc<[u8]>),
+}
+
+// A row's values for each of the STANDARD_COLUMNS, Null for those the input doesn't have. scale is
+// the amount's decimal places if it's stored as a scaled integer, per_second how many of the
+// timestamp's units make a second, and precision what to do with an amount past four places.
+pub(crate) fn transaction(
+    row: [&Datum; 9],
+    scale: Option<u32>,
+    per_second: Option<i64>,
+    precision: PrecisionPolicy,
+) -> Result<Transaction, Error> {
+    let required = |f: usize| match row[f] {
+        Datum::Null => Err(Error::Value(format!("missing {}", STANDARD_COLUMNS[f]))),
+        datum => Ok(datum),
+    };
+    let tx_type = text(required(0)?, STANDARD_COLUMNS[0])?;
+    let tx_type = TransactionType::deserialize(tx_type.trim().into_deserializer())
+        .map_err(|err: serde::de::value::Error| Error::Value(err.to_string()))?;
+    let to = match row[4] {
+        Datum::Null => None,
+        datum => Some(id::<ClientId>(datum, STANDARD_COLUMNS[4])?),
+    };
+    let timestamp = match row[5] {
+        Datum::Null => None,
+        Datum::Int(v) => Some(v.div_euclid(per_second.unwrap_or(1))),
+        _ => return Err(Error::Value("timestamp isn't an integer".to_string())),
+    };
+    Ok(Transaction {
+        tx_type,
+        client: id::<ClientId>(required(1)?, STANDARD_COLUMNS[1])?,
+        tx: id::<TxId>(required(2)?, STANDARD_COLUMNS[2])?,
+        amount: amount(row[3], scale, precision)?,
+        to,
+        timestamp,
+        currency: currency(row[6])?,
+        to_currency: currency(row[7])?,
+        effective_date: date(row[8])?,
+    })
+}
+
+fn text<'a>(datum: &'a Datum, field: &str) -> Result<&'a str, Error> {
+    match datum {
+        Datum::Bytes(bytes) => std::str::from_utf8(bytes)
+            .map_err(|_| Error::Value(format!("{} isn't valid UTF-8", field))),
+        _ => Err(Error::Value(format!("{} isn't a string", field))),
+    }
+}
+
+// A client or tx id, from an integer column or a string one
+fn id<T: TryFrom<i64> + FromStr>(datum: &Datum, field: &str) -> Result<T, Error> {
+    let out_of_range = || Error::Value(format!("{} id out of range", field));
+    match datum {
+        Datum::Int(v) => T::try_from(*v).map_err(|_| out_of_range()),
+        datum => text(datum, field)?
+            .trim()
+            .parse()
+            .map_err(|_| out_of_range()),
+    }
+}
+
+fn amount(datum: &Datum, scale: Option<u32>, precision: PrecisionPolicy) -> Result<Amount, Error> {
+    let invalid = |err: &dyn std::fmt::Display| Error::Value(format!("amount: {}", err));
+    let decimal = |mantissa: i128| {
+        let value = Decimal::try_from_i128_with_scale(mantissa, scale.unwrap_or(0))
+            .map_err(|err| invalid(&err))?;
+        Amount::from_decimal(value, precision).map_err(|err| invalid(&err))
+    };
+    match datum {
+        Datum::Null => Ok(Amount::ZERO),
+        Datum::Int(v) => decimal((*v).into()),
+        // a DECIMAL's unscaled value as a big-endian two's complement integer
+        Datum::Bytes(bytes) if scale.is_some() => {
+            if bytes.len() > 16 {
+                return Err(Error::Value("amount too large".to_string()));
+            }
+            let fill = if bytes.first().is_some_and(|b| *b & 0x80 != 0) {
+                0xff
+            } else {
+                0
+            };
+            let mut be = [fill; 16];
+            be[16 - bytes.len()..].copy_from_slice(bytes);
+            decimal(i128::from_be_bytes(be))
+        }
+        Datum::Float(v) if v.is_finite() => {
+            Amount::parse(&v.to_string(), precision).map_err(|err| invalid(&err))
+        }
+        Datum::Bytes(_) => {
+            Amount::parse(text(datum, "amount")?, precision).map_err(|err| invalid(&err))
+        }
+        _ => Err(Error::Value("amount isn't a number".to_string())),
+    }
+}
+
+fn date(datum: &Datum) -> Result<Option<Date>, Error> {
+    match datum {
+        Datum::Null => Ok(None),
+        datum => Date::from_str(text(datum, "effective_date")?)
+            .map(Some)
+            .map_err(Error::Value),
+    }
+}
+
+fn currency(datum: &Datum) -> Result<Option<Currency>, Error> {
+    match datum {
+        Datum::Null => Ok(None),
+        datum => Currency::from_str(text(datum, "currency")?)
+            .map(Some)
+            .map_err(Error::Value),
+    }
+}
@@ -0,0 +1,141 @@
+use crate::cli::Cli;
+use clap::{error::ErrorKind, Arg, Command, CommandFactory, FromArgMatches};
+use std::{collections::BTreeMap, env, ffi::OsString, fs, path::Path};
+use toml_edit::{Document, Item, Value};
+
+// A --config file sets option defaults, so a run's settings can be kept in version control
+// instead of on the command line. Its keys are the long option names, e.g.
+//
+//     withdrawal-policy = "overdraft"
+//     on-error = "skip"
+//     precision = 2
+//     output-format = "json"
+//
+// optionally grouped under tables of any name, which only serve to organize them. Values are
+// checked as they would be on the command line, and options given there override the file.
+//
+// In between, every option can also be set by an environment variable named after it, e.g.
+// TXN_OUTPUT_FORMAT for --output-format, for deployments where flags are awkward to pass. Those
+// override the file and are overridden by the command line. TXN_CONFIG names the file.
+pub fn parse() -> Cli {
+    let args: Vec<OsString> = env::args_os().collect();
+    let mut command = with_all_args(Cli::command(), &|arg| {
+        let var = arg.get_long().map(env_var);
+        match var {
+            Some(var) => arg.env(var),
+            None => arg,
+        }
+    });
+    if let Some(path) = config_path(&args) {
+        let defaults = match load(Path::new(&path)) {
+            Ok(defaults) => defaults,
+            Err(msg) => command.error(ErrorKind::Io, msg).exit(),
+        };
+        command = with_all_args(command, &|arg| with_default(arg, &defaults));
+        if let Some(key) = defaults.keys().find(|key| !has_option(&command, key)) {
+            let msg = format!("unknown option '{}' in {}", key, path.to_string_lossy());
+            command.error(ErrorKind::UnknownArgument, msg).exit();
+        }
+    }
+    let matches = command.get_matches_from(args);
+    Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit())
+}
+
+// The variable setting the --long option
+fn env_var(long: &str) -> String {
+    format!("TXN_{}", long.to_ascii_uppercase().replace('-', "_"))
+}
+
+// The --config value, found before clap parses anything since it changes what clap is given
+fn config_path(args: &[OsString]) -> Option<OsString> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().cloned();
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(path.into());
+        }
+    }
+    env::var_os(env_var("config"))
+}
+
+// Every key in the file, with its values as they'd be written on the command line
+fn load(path: &Path) -> Result<BTreeMap<String, Vec<String>>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("can't read config {}: {}", path.display(), err))?;
+    let doc = Document::parse(text)
+        .map_err(|err| format!("invalid config {}: {}", path.display(), err))?;
+    let mut defaults = BTreeMap::new();
+    collect(doc.as_table(), &mut defaults)
+        .map_err(|msg| format!("invalid config {}: {}", path.display(), msg))?;
+    Ok(defaults)
+}
+
+fn collect(
+    table: &toml_edit::Table,
+    defaults: &mut BTreeMap<String, Vec<String>>,
+) -> Result<(), String> {
+    for (key, item) in table.iter() {
+        let values = match item {
+            Item::Table(table) => {
+                collect(table, defaults)?;
+                continue;
+            }
+            Item::Value(Value::Array(array)) => array
+                .iter()
+                .map(|value| scalar(key, value))
+                .collect::<Result<_, _>>()?,
+            Item::Value(value) => vec![scalar(key, value)?],
+            _ => return Err(format!("'{}' must be a value", key)),
+        };
+        if defaults.insert(key.to_string(), values).is_some() {
+            return Err(format!("'{}' is set more than once", key));
+        }
+    }
+    Ok(())
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.value().clone()),
+        Value::Integer(n) => Ok(n.value().to_string()),
+        Value::Float(x) => Ok(x.value().to_string()),
+        Value::Boolean(b) => Ok(b.value().to_string()),
+        _ => Err(format!(
+            "'{}' must be a string, number or boolean, or an array of them",
+            key
+        )),
+    }
+}
+
+// command with f applied to every option, in every subcommand
+fn with_all_args(command: Command, f: &impl Fn(Arg) -> Arg) -> Command {
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    let mut command = command.mut_args(f);
+    for name in names {
+        command = command.mut_subcommand(name, |sub| with_all_args(sub, f));
+    }
+    command
+}
+
+// arg with the file's values as its default, if it names it
+fn with_default(arg: Arg, defaults: &BTreeMap<String, Vec<String>>) -> Arg {
+    match arg.get_long().and_then(|long| defaults.get(long)) {
+        Some(values) => arg.default_values(values.clone()),
+        None => arg,
+    }
+}
+
+fn has_option(command: &Command, key: &str) -> bool {
+    command
+        .get_arguments()
+        .any(|arg| arg.get_long() == Some(key))
+        || command.get_subcommands().any(|sub| has_option(sub, key))
+}
@@ -0,0 +1,142 @@
+use crate::amount::Amount;
+use serde::Deserialize;
+use std::fmt;
+
+// Batch control totals in CSV input, as bank batch files carry them. A batch_trailer row closes
+// a batch, giving in its count and amount columns how many transaction rows the batch has and
+// what their amounts add up to; a batch_header row opens one and may declare the same. A batch
+// runs from its header, or without one from the start of the input or the last trailer, and
+// neither row is a transaction of its own.
+//
+//   type,client,tx,amount,count
+//   batch_header,,,,
+//   deposit,1,1,100.00,
+//   withdrawal,1,2,25.00,
+//   batch_trailer,,,125.00,2
+//
+// The amount total is of the amount column as written, whatever the rows' types, and a
+// recurring row counts as one row with its own amount. Rows that fail to parse aren't counted,
+// so a batch with a malformed row doesn't match its totals. The totals are checked as each batch
+// ends, by its trailer, the next header or the end of the input, and a batch that doesn't match
+// is reported in SourceStats::control_mismatches; whether that fails the run is up to the
+// caller, see ControlPolicy.
+
+// A control row's declared totals, either of which can be left empty
+#[derive(Deserialize, Debug, Default, Copy, Clone)]
+pub(crate) struct Declared {
+    #[serde(default)]
+    count: Option<u64>,
+    #[serde(default)]
+    amount: Option<Amount>,
+}
+
+impl Declared {
+    pub(crate) fn new(count: Option<u64>, amount: Option<Amount>) -> Declared {
+        Declared { count, amount }
+    }
+}
+
+// A batch whose rows didn't add up to what its control rows declared
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ControlMismatch {
+    // the line of the row the batch ended at, or of its header if the input ended first
+    pub line: u64,
+    pub expected_count: Option<u64>,
+    pub count: u64,
+    pub expected_amount: Option<Amount>,
+    pub amount: Amount,
+}
+
+impl fmt::Display for ControlMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: batch control totals don't match:", self.line)?;
+        if let Some(expected) = self.expected_count {
+            write!(f, " expected {} rows, found {}", expected, self.count)?;
+        }
+        if let Some(expected) = self.expected_amount {
+            if self.expected_count.is_some() {
+                f.write_str(",")?;
+            }
+            write!(
+                f,
+                " expected a total of {}, found {}",
+                expected, self.amount
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The batch a source is in the middle of
+#[derive(Debug, Default)]
+pub(crate) struct Batch {
+    // the line of the header that opened it, with what that declared
+    header: Option<(u64, Declared)>,
+    count: u64,
+    amount: Amount,
+    // some of the batch was skipped over when resuming from a checkpoint, so its totals so far
+    // aren't known and aren't checked
+    resumed: bool,
+    // CSV control rows read and batches that didn't match, since the source last handed them
+    // over
+    pub(crate) rows: u64,
+    pub(crate) mismatches: Vec<ControlMismatch>,
+}
+
+impl Batch {
+    // A transaction row of the batch
+    pub(crate) fn add(&mut self, amount: Amount) {
+        self.count += 1;
+        // a total past what an Amount holds can't match any declared one anyway
+        self.amount = self.amount.saturating_add(amount);
+    }
+
+    pub(crate) fn header(&mut self, line: u64, declared: Declared) {
+        self.finish();
+        self.header = Some((line, declared));
+    }
+
+    pub(crate) fn trailer(&mut self, line: u64, declared: Declared) {
+        // what the trailer leaves empty, the header may still have declared
+        let header = self.header.map(|(_, header)| header).unwrap_or_default();
+        let declared = Declared {
+            count: declared.count.or(header.count),
+            amount: declared.amount.or(header.amount),
+        };
+        self.check(line, declared);
+        self.reset();
+    }
+
+    // Ends the batch without a trailer, checking what its header declared
+    pub(crate) fn finish(&mut self) {
+        if let Some((line, declared)) = self.header {
+            self.check(line, declared);
+        }
+        self.reset();
+    }
+
+    pub(crate) fn skipped(&mut self) {
+        self.resumed = true;
+    }
+
+    fn check(&mut self, line: u64, declared: Declared) {
+        let counted = declared.count.is_some_and(|count| count != self.count);
+        let added = declared.amount.is_some_and(|amount| amount != self.amount);
+        if !self.resumed && (counted || added) {
+            self.mismatches.push(ControlMismatch {
+                line,
+                expected_count: declared.count,
+                count: self.count,
+                expected_amount: declared.amount,
+                amount: self.amount,
+            });
+        }
+    }
+
+    fn reset(&mut self) {
+        self.header = None;
+        self.count = 0;
+        self.amount = Amount::ZERO;
+        self.resumed = false;
+    }
+}
@@ -0,0 +1,175 @@
+use crate::amount::Amount;
+use crate::bank::{Client, ClientRecord, Transaction, TransactionType};
+use crate::ledger::Ledger;
+use crate::outcome::TxnError;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, mem, str::FromStr};
+
+// A transaction can name its currency in an optional `currency` column, as a three-letter code
+// like EUR. Each client has a separate available and held balance per currency, so amounts in
+// one are never added to another. Rows without a currency use the account's unlabelled balance,
+// which is the only one there is for inputs that never give a currency.
+//
+// A dispute, resolve or chargeback applies to the balance of the transaction it refers to, and
+// is refused if it names a different currency. A transfer moves funds between the two clients'
+// balances in its currency. Locking is per client: a chargeback in any currency locks the whole
+// account.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    // The code's ASCII letters, as stored in fixed-size records
+    pub(crate) fn bytes(&self) -> [u8; 3] {
+        self.0
+    }
+
+    pub(crate) fn from_bytes(bytes: [u8; 3]) -> Option<Currency> {
+        bytes
+            .iter()
+            .all(u8::is_ascii_uppercase)
+            .then_some(Currency(bytes))
+    }
+}
+
+// Codes are case-insensitive and kept in upper case
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Currency, String> {
+        let code = s.trim().to_ascii_uppercase();
+        code.as_bytes()
+            .try_into()
+            .ok()
+            .and_then(Currency::from_bytes)
+            .ok_or_else(|| format!("invalid currency '{}', expected a three-letter code", s))
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // from_bytes only accepts ASCII letters
+        f.write_str(std::str::from_utf8(&self.0).expect("currency codes are ASCII"))
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Currency, D::Error> {
+        String::deserialize(de)?.parse().map_err(D::Error::custom)
+    }
+}
+
+// A client's funds in one currency
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub(crate) struct Balance {
+    pub(crate) available: Amount,
+    pub(crate) held: Amount,
+    // all its withdrawals added up, see limits.rs
+    pub(crate) withdrawn: Amount,
+    pub(crate) ledger: Ledger,
+}
+
+impl Balance {
+    // Both balances added up, None if a sum overflows
+    pub(crate) fn checked_add(self, other: Balance) -> Option<Balance> {
+        Some(Balance {
+            available: self.available.checked_add(other.available)?,
+            held: self.held.checked_add(other.held)?,
+            withdrawn: self.withdrawn.saturating_add(other.withdrawn),
+            ledger: self.ledger.checked_add(other.ledger)?,
+        })
+    }
+}
+
+impl Client {
+    // The currency whose balance txn applies to: its own, or for a dispute, resolve, chargeback,
+    // refund or release that of the transaction it refers to
+    pub(crate) fn currency_of(&self, txn: &Transaction) -> Result<Option<Currency>, TxnError> {
+        let referenced = match txn.tx_type {
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Chargeback
+            | TransactionType::Refund
+            | TransactionType::Release => self.txns.get(&txn.tx)?.map(|record| record.currency),
+            _ => None,
+        };
+        match (referenced, txn.currency) {
+            (Some(referenced), Some(own)) if referenced != Some(own) => {
+                Err(TxnError::CurrencyMismatch)
+            }
+            (Some(referenced), _) => Ok(referenced),
+            (None, own) => Ok(own),
+        }
+    }
+
+    // Run f with the balance in currency as the account's available and held, so the processing
+    // code only ever deals with one balance. None is the unlabelled balance, which is where they
+    // normally are. A currency's balance is only kept if it already existed or f changed it.
+    pub(crate) fn in_currency<T>(
+        &mut self,
+        currency: Option<Currency>,
+        f: impl FnOnce(&mut Client) -> T,
+    ) -> T {
+        let Some(currency) = currency else {
+            return f(self);
+        };
+        let existing = self.currencies.remove(&currency);
+        let balance = existing.unwrap_or_default();
+        let unlabelled = self.swap_balance(balance);
+        let result = f(self);
+        let updated = self.swap_balance(unlabelled);
+        if existing.is_some() || updated != Balance::default() {
+            self.currencies.insert(currency, updated);
+        }
+        result
+    }
+
+    // The balance in currency, zero if the account has never had one in it
+    pub(crate) fn balance(&self, currency: Option<Currency>) -> Balance {
+        match currency {
+            None => Balance {
+                available: self.available,
+                held: self.held,
+                withdrawn: self.withdrawn,
+                ledger: self.ledger,
+            },
+            Some(currency) => self.currencies.get(&currency).copied().unwrap_or_default(),
+        }
+    }
+
+    // Sets the balance in_currency is working on, returning what it was
+    pub(crate) fn swap_balance(&mut self, balance: Balance) -> Balance {
+        Balance {
+            available: mem::replace(&mut self.available, balance.available),
+            held: mem::replace(&mut self.held, balance.held),
+            withdrawn: mem::replace(&mut self.withdrawn, balance.withdrawn),
+            ledger: mem::replace(&mut self.ledger, balance.ledger),
+        }
+    }
+
+    // The account's report rows: the unlabelled balance, then one per currency in code order.
+    // An account with only currency balances has no unlabelled row unless it holds something.
+    pub fn records(&self) -> impl Iterator<Item = ClientRecord> + '_ {
+        let unlabelled =
+            self.currencies.is_empty() || !self.available.is_zero() || !self.held.is_zero();
+        let currencies = self
+            .currencies
+            .iter()
+            .map(|(currency, balance)| ClientRecord {
+                currency: Some(*currency),
+                available: balance.available,
+                held: balance.held,
+                total: balance.available + balance.held,
+                ..self.record()
+            });
+        unlabelled
+            .then(|| self.record())
+            .into_iter()
+            .chain(currencies)
+    }
+}
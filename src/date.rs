@@ -0,0 +1,125 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+// A calendar day, written YYYY-MM-DD, kept as the number of days since 1970-01-01. Days are
+// counted in the proleptic Gregorian calendar, without time zones: a date is whatever day the
+// file says it is.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone)]
+pub struct Date(i64);
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+impl Date {
+    // None if there's no such day, e.g. February 30th
+    pub fn from_ymd(year: i64, month: u32, day: u32) -> Option<Date> {
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        // Howard Hinnant's days_from_civil, with the year starting in March so that the leap
+        // day comes last
+        let (month, day) = (i64::from(month), i64::from(day));
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        Some(Date(era * 146_097 + day_of_era - 719_468))
+    }
+
+    // The day a Unix timestamp falls on, in UTC
+    pub fn of_timestamp(seconds: i64) -> Date {
+        Date(seconds.div_euclid(SECONDS_PER_DAY))
+    }
+
+    // The Unix timestamp of the day's first second, in UTC
+    pub fn timestamp(self) -> i64 {
+        self.0 * SECONDS_PER_DAY
+    }
+
+    pub fn days_since_epoch(self) -> i64 {
+        self.0
+    }
+
+    pub fn add_days(self, days: i64) -> Date {
+        Date(self.0 + days)
+    }
+
+    // The same day of the month that many months on, or the month's last day if it's shorter,
+    // so a month after January 31st is February 28th or 29th
+    pub fn add_months(self, months: i64) -> Date {
+        let (year, month, day) = self.ymd();
+        let index = year * 12 + i64::from(month) - 1 + months;
+        let (year, month) = (index.div_euclid(12), index.rem_euclid(12) as u32 + 1);
+        let day = day.min(days_in_month(year, month));
+        Date::from_ymd(year, month, day).expect("clamped to the month")
+    }
+
+    // (year, month, day)
+    pub fn ymd(self) -> (i64, u32, u32) {
+        let days = self.0 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        (year, month as u32, day as u32)
+    }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Date, String> {
+        let invalid = || format!("invalid date '{}', expected YYYY-MM-DD", s);
+        let mut parts = s.splitn(3, '-');
+        let (Some(year), Some(month), Some(day)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let digits =
+            |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+        if !(digits(year, 4) && digits(month, 2) && digits(day, 2)) {
+            return Err(invalid());
+        }
+        let parse = |part: &str| part.parse::<i64>().map_err(|_| invalid());
+        Date::from_ymd(parse(year)?, parse(month)? as u32, parse(day)? as u32).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Date, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.trim().parse().map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,152 @@
+use crate::bank::Transaction;
+use crate::control::ControlMismatch;
+use crate::outcome::TxnError;
+use std::{error, fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+    Pattern(glob::PatternError),
+    #[cfg(feature = "kafka")]
+    Kafka(rdkafka::error::KafkaError),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    // a field holding something other than its kind of value, e.g. a Parquet column of the
+    // wrong type
+    Value(String),
+    // a batch whose rows don't add up to its control totals, under ControlPolicy::Fail
+    Control(ControlMismatch),
+    // an input or other file that isn't what it should be as a whole, e.g. one not in its
+    // format or a limits file listing a client twice, see snapshot::invalid
+    Invalid(String),
+    // an OFX or QIF entry given a tx id that's already another's, see imports.rs
+    Collision(String),
+    // a row whose amount has places past four under PrecisionPolicy::Reject, with the amount
+    // rounded; reading a source refuses it with TxnError::TooPrecise rather than failing, see
+    // source::decided
+    TooPrecise(Transaction),
+    // an error tagged with the input line it came from
+    Line(u64, Box<Error>),
+    // an error tagged with the input file it came from
+    File(PathBuf, Box<Error>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Csv(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "{}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Pattern(err) => write!(f, "invalid glob pattern: {}", err),
+            #[cfg(feature = "kafka")]
+            Error::Kafka(err) => write!(f, "kafka: {}", err),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(err) => write!(f, "sqlite: {}", err),
+            Error::Value(msg) | Error::Invalid(msg) | Error::Collision(msg) => write!(f, "{}", msg),
+            Error::Control(mismatch) => write!(f, "{}", mismatch),
+            Error::TooPrecise(_) => write!(f, "{}", TxnError::TooPrecise),
+            Error::Line(line, err) => write!(f, "line {}: {}", line, err),
+            Error::File(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Csv(err) => Some(err),
+            Error::Json(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Pattern(err) => Some(err),
+            #[cfg(feature = "kafka")]
+            Error::Kafka(err) => Some(err),
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(err) => Some(err),
+            Error::Line(_, err) | Error::File(_, err) => Some(err.as_ref()),
+            Error::Value(_)
+            | Error::Invalid(_)
+            | Error::Control(_)
+            | Error::Collision(_)
+            | Error::TooPrecise(_) => None,
+        }
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Error {
+        Error::Csv(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<glob::PatternError> for Error {
+    fn from(err: glob::PatternError) -> Error {
+        Error::Pattern(err)
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl From<rdkafka::error::KafkaError> for Error {
+    fn from(err: rdkafka::error::KafkaError) -> Error {
+        Error::Kafka(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Sqlite(err)
+    }
+}
+
+impl Error {
+    pub fn in_file(self, path: impl Into<PathBuf>) -> Error {
+        Error::File(path.into(), Box::new(self))
+    }
+
+    pub fn at_line(self, line: u64) -> Error {
+        Error::Line(line, Box::new(self))
+    }
+
+    // The rounded row of an Error::TooPrecise, however it's tagged, or the error itself
+    pub(crate) fn too_precise(self) -> Result<Transaction, Error> {
+        match self {
+            Error::TooPrecise(txn) => Ok(txn),
+            Error::Line(line, err) => err.too_precise().map_err(|err| err.at_line(line)),
+            Error::File(path, err) => err.too_precise().map_err(|err| err.in_file(path)),
+            err => Err(err),
+        }
+    }
+
+    // A bad row can be skipped, but a failing reader would just keep failing
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            Error::Csv(err) => !matches!(err.kind(), csv::ErrorKind::Io(_)),
+            Error::Json(err) => !err.is_io(),
+            Error::Line(_, err) | Error::File(_, err) => err.is_recoverable(),
+            Error::Value(_) | Error::TooPrecise(_) => true,
+            Error::Io(_)
+            | Error::Invalid(_)
+            | Error::Pattern(_)
+            | Error::Control(_)
+            | Error::Collision(_) => false,
+            #[cfg(feature = "kafka")]
+            Error::Kafka(_) => false,
+            #[cfg(feature = "sqlite")]
+            Error::Sqlite(_) => false,
+        }
+    }
+}
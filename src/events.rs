@@ -0,0 +1,687 @@
+use crate::amount::Amount;
+use crate::bank::{
+    Bank, Client, ClientId, DisputeState, Transaction, TransactionType, TxId, TxnRecord,
+};
+use crate::currency::{Balance, Currency};
+use crate::error::Error;
+use crate::ledger::Account;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::policy::{Policy, RedisputePolicy};
+use crate::snapshot::invalid;
+use crate::transfer;
+use serde::{Deserialize, Serialize};
+
+// What happened to the accounts when a transaction was applied, for code that reacts to the
+// engine's decisions (notifications, analytics, an external store) without working them out
+// again from the outcome and the balances. Amounts are what moved: for a dispute, resolve or
+// chargeback the part of the transaction held or released, which for a partial dispute is less
+// than the transaction's amount. Currency is None for the unlabelled balance. Events carry what
+// Bank::replay needs to rebuild the state from them.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Copy, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    DepositApplied {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    WithdrawalApplied {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    TransferApplied {
+        client: ClientId,
+        to: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    FeeCharged {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    InterestAccrued {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    Converted {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Currency,
+        converted: Amount,
+        to_currency: Currency,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    // a withdrawal credited back, `amount` being all of it
+    Refunded {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+    },
+    // an admin's hold, moving `amount` from available into held
+    FundsHeld {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    // the hold `tx` given back, `amount` being all of it
+    FundsReleased {
+        client: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+    },
+    // `holder` is the account the funds are held in: the client's own, or the recipient's for
+    // a transfer
+    DisputeOpened {
+        client: ClientId,
+        holder: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+    },
+    DisputeResolved {
+        client: ClientId,
+        holder: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+    },
+    ChargedBack {
+        client: ClientId,
+        holder: ClientId,
+        tx: TxId,
+        amount: Amount,
+        currency: Option<Currency>,
+    },
+    // always follows a ChargedBack, for the holder's account
+    AccountLocked {
+        client: ClientId,
+    },
+    AccountUnlocked {
+        client: ClientId,
+        tx: TxId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<i64>,
+    },
+    AccountClosed {
+        client: ClientId,
+    },
+    // everything client had now belongs to `to`, and client is closed
+    AccountsMerged {
+        client: ClientId,
+        to: ClientId,
+    },
+    // follows the events of a transaction a flagging VelocityRule let through
+    VelocityAlert {
+        client: ClientId,
+        tx: TxId,
+    },
+}
+
+// The balances a transaction's events are worked out from, taken before it's applied
+struct Before {
+    currency: Option<Currency>,
+    holder: ClientId,
+    held: Amount,
+    available: Amount,
+    // the client's balance in the target currency of a conversion
+    converted: Amount,
+}
+
+impl Client {
+    // process_txn, returning what it did as events
+    pub fn process_txn_events(
+        &mut self,
+        txn: Transaction,
+        policy: &Policy,
+    ) -> Result<Vec<Event>, TxnError> {
+        let before = before(Some(self), self.client, &txn);
+        let outcome = self.process_txn(txn, policy)?;
+        Ok(events(&txn, outcome, before, |_, currency| {
+            self.balance(currency)
+        }))
+    }
+}
+
+impl Bank {
+    // insert_txn, returning what it did as events
+    pub fn insert_txn_events(&mut self, txn: Transaction) -> Result<Vec<Event>, TxnError> {
+        let sender = self.bank.get(&txn.client);
+        let holder = match sender {
+            Some(sender) if transfer::is_cross_client(Some(sender), &txn).unwrap_or(false) => {
+                transfer::recipient(sender, &txn).unwrap_or(txn.client)
+            }
+            _ => txn.client,
+        };
+        let mut before = before(sender, holder, &txn);
+        if holder != txn.client {
+            let balance = self.balance_of(holder, before.currency);
+            before.held = balance.held;
+            before.available = balance.available;
+        }
+        let outcome = self.insert_txn(txn)?;
+        let mut events = events(&txn, outcome, before, |client, currency| {
+            self.balance_of(client, currency)
+        });
+        if self.velocity.alerted {
+            events.push(Event::VelocityAlert {
+                client: txn.client,
+                tx: txn.tx,
+            });
+        }
+        Ok(events)
+    }
+
+    fn balance_of(&self, client: ClientId, currency: Option<Currency>) -> Balance {
+        self.bank
+            .get(&client)
+            .map(|account| account.balance(currency))
+            .unwrap_or_default()
+    }
+}
+
+// The balance before txn from client's account (None if it has none yet), held by holder
+fn before(client: Option<&Client>, holder: ClientId, txn: &Transaction) -> Before {
+    let currency = client
+        .map_or(Ok(txn.currency), |client| client.currency_of(txn))
+        .unwrap_or(txn.currency);
+    let balance = client
+        .map(|client| client.balance(currency))
+        .unwrap_or_default();
+    let converted = client
+        .zip(txn.to_currency)
+        .map(|(client, to)| client.balance(Some(to)).available)
+        .unwrap_or_default();
+    Before {
+        currency,
+        holder,
+        held: balance.held,
+        available: balance.available,
+        converted,
+    }
+}
+
+// The events of an accepted txn, given the balances before it and a way to look them up after
+fn events(
+    txn: &Transaction,
+    outcome: TxnOutcome,
+    before: Before,
+    balance: impl Fn(ClientId, Option<Currency>) -> Balance,
+) -> Vec<Event> {
+    let (client, tx, timestamp) = (txn.client, txn.tx, txn.timestamp);
+    let (currency, holder) = (before.currency, before.holder);
+    let after = balance(holder, currency);
+    // the portion disputed or settled; a disputed withdrawal moves it out of held, not into it
+    let held = after.held - before.held;
+    let portion = if held.is_negative() { -held } else { held };
+    let event = match outcome {
+        TxnOutcome::Deposited => Event::DepositApplied {
+            client,
+            tx,
+            amount: txn.amount,
+            currency,
+            timestamp,
+        },
+        TxnOutcome::Held => {
+            return vec![
+                Event::DepositApplied {
+                    client,
+                    tx,
+                    amount: txn.amount,
+                    currency,
+                    timestamp,
+                },
+                Event::DisputeOpened {
+                    client,
+                    holder,
+                    tx,
+                    amount: txn.amount,
+                    currency,
+                },
+            ]
+        }
+        TxnOutcome::Withdrawn => Event::WithdrawalApplied {
+            client,
+            tx,
+            amount: txn.amount,
+            currency,
+            timestamp,
+        },
+        TxnOutcome::Transferred => Event::TransferApplied {
+            client,
+            to: holder,
+            tx,
+            amount: txn.amount,
+            currency,
+            timestamp,
+        },
+        TxnOutcome::FeeCharged => Event::FeeCharged {
+            client,
+            tx,
+            amount: txn.amount,
+            currency,
+            timestamp,
+        },
+        TxnOutcome::InterestAccrued => Event::InterestAccrued {
+            client,
+            tx,
+            amount: after.available - before.available,
+            currency,
+            timestamp,
+        },
+        TxnOutcome::Converted => {
+            // convert only succeeds with both currencies
+            let (Some(from), Some(to)) = (txn.currency, txn.to_currency) else {
+                unreachable!("conversion without both currencies");
+            };
+            Event::Converted {
+                client,
+                tx,
+                amount: txn.amount,
+                currency: from,
+                converted: balance(client, Some(to)).available - before.converted,
+                to_currency: to,
+                timestamp,
+            }
+        }
+        TxnOutcome::Refunded => Event::Refunded {
+            client,
+            tx,
+            amount: after.available - before.available,
+            currency,
+        },
+        // nothing has happened to the accounts yet
+        TxnOutcome::Pending => return Vec::new(),
+        TxnOutcome::HoldPlaced => Event::FundsHeld {
+            client,
+            tx,
+            amount: txn.amount,
+            currency,
+            timestamp,
+        },
+        TxnOutcome::Released => Event::FundsReleased {
+            client,
+            tx,
+            amount: portion,
+            currency,
+        },
+        TxnOutcome::Disputed => Event::DisputeOpened {
+            client,
+            holder,
+            tx,
+            amount: portion,
+            currency,
+        },
+        TxnOutcome::Resolved => Event::DisputeResolved {
+            client,
+            holder,
+            tx,
+            amount: portion,
+            currency,
+        },
+        TxnOutcome::ChargedBack => {
+            return vec![
+                Event::ChargedBack {
+                    client,
+                    holder,
+                    tx,
+                    amount: portion,
+                    currency,
+                },
+                Event::AccountLocked { client: holder },
+            ]
+        }
+        TxnOutcome::Unlocked => Event::AccountUnlocked {
+            client,
+            tx,
+            timestamp,
+        },
+        TxnOutcome::Closed => Event::AccountClosed { client },
+        TxnOutcome::Merged => Event::AccountsMerged { client, to: holder },
+    };
+    vec![event]
+}
+
+// Event sourcing: replay applies a captured stream of events straight to the accounts, without
+// the policy checks that decided them, so a bank that starts where the original did ends up in
+// the same state. The stream has to be complete and in order; an event that doesn't fit the
+// state (a dispute of an unknown tx, a deposit reusing a tx id) is an error. Only accepted
+// transactions have events, so what refused ones leave behind isn't restored: the list of
+// withdrawals turned away, and accounts only opened by a refused transaction.
+impl Bank {
+    pub fn replay(&mut self, events: impl IntoIterator<Item = Event>) -> Result<(), Error> {
+        for (index, event) in events.into_iter().enumerate() {
+            self.replay_event(event)
+                .map_err(|err| invalid(format!("event {}: {}", index + 1, err)))?;
+        }
+        Ok(())
+    }
+
+    fn replay_event(&mut self, event: Event) -> Result<(), TxnError> {
+        match event {
+            Event::DepositApplied {
+                client,
+                tx,
+                amount,
+                currency,
+                timestamp,
+            } => {
+                let txn = replayed(TransactionType::Deposit, client, tx, amount, timestamp);
+                self.replay_new(Transaction { currency, ..txn }, |account| {
+                    account.post(Account::Settlement, Account::Available, amount)
+                })
+            }
+            Event::WithdrawalApplied {
+                client,
+                tx,
+                amount,
+                currency,
+                timestamp,
+            } => {
+                let txn = replayed(TransactionType::Withdrawal, client, tx, amount, timestamp);
+                self.replay_new(Transaction { currency, ..txn }, |account| {
+                    account.withdrawn = account.withdrawn.saturating_add(amount);
+                    account.post(Account::Available, Account::Settlement, amount)
+                })
+            }
+            Event::FeeCharged {
+                client,
+                tx,
+                amount,
+                currency,
+                timestamp,
+            } => {
+                let txn = replayed(TransactionType::Fee, client, tx, amount, timestamp);
+                self.replay_new(Transaction { currency, ..txn }, |account| {
+                    account.post(Account::Available, Account::Fees, amount)
+                })
+            }
+            // the accrual's record has the interest paid as its amount
+            Event::InterestAccrued {
+                client,
+                tx,
+                amount,
+                currency,
+                timestamp,
+            } => {
+                let txn = replayed(TransactionType::Accrue, client, tx, amount, timestamp);
+                self.replay_new(Transaction { currency, ..txn }, |account| {
+                    account.post(Account::Interest, Account::Available, amount)
+                })
+            }
+            Event::TransferApplied {
+                client,
+                to,
+                tx,
+                amount,
+                currency,
+                timestamp,
+            } => {
+                let txn = replayed(TransactionType::Transfer, client, tx, amount, timestamp);
+                self.add_client(to);
+                self.replay_new(
+                    Transaction {
+                        to: Some(to),
+                        currency,
+                        ..txn
+                    },
+                    |account| account.post(Account::Available, Account::Transfers, amount),
+                )?;
+                self.replay_balance(to, currency, |account| {
+                    account.post(Account::Transfers, Account::Available, amount)
+                })
+            }
+            Event::Converted {
+                client,
+                tx,
+                amount,
+                currency,
+                converted,
+                to_currency,
+                timestamp,
+            } => {
+                let txn = replayed(TransactionType::Convert, client, tx, amount, timestamp);
+                let txn = Transaction {
+                    currency: Some(currency),
+                    to_currency: Some(to_currency),
+                    ..txn
+                };
+                self.replay_new(txn, |account| {
+                    account.post(Account::Available, Account::Exchange, amount)
+                })?;
+                self.replay_balance(client, Some(to_currency), |account| {
+                    account.post(Account::Exchange, Account::Available, converted)
+                })
+            }
+            Event::Refunded {
+                client,
+                tx,
+                amount,
+                currency,
+            } => {
+                self.replay_record(client, tx, |record| {
+                    record.state = record.state.transition(DisputeState::Refunded)?;
+                    Ok(())
+                })?;
+                self.replay_balance(client, currency, |account| {
+                    account.post(Account::Settlement, Account::Available, amount)
+                })
+            }
+            Event::FundsHeld {
+                client,
+                tx,
+                amount,
+                currency,
+                timestamp,
+            } => {
+                let txn = replayed(TransactionType::Hold, client, tx, amount, timestamp);
+                self.replay_new(Transaction { currency, ..txn }, |account| {
+                    account.post(Account::Available, Account::Held, amount)
+                })
+            }
+            Event::FundsReleased {
+                client,
+                tx,
+                amount,
+                currency,
+            } => {
+                self.replay_record(client, tx, |record| {
+                    record.state = record.state.transition(DisputeState::Released)?;
+                    Ok(())
+                })?;
+                self.replay_balance(client, currency, |account| {
+                    account.post(Account::Held, Account::Available, amount)
+                })
+            }
+            Event::DisputeOpened {
+                client,
+                holder,
+                tx,
+                amount,
+                currency,
+            } => {
+                let record = self.replay_record(client, tx, |record| {
+                    record.open_dispute(amount, RedisputePolicy::Unlimited)
+                })?;
+                let moved = record.signed(amount);
+                self.replay_balance(holder, currency, |account| {
+                    account.post(Account::Available, Account::Held, moved)
+                })
+            }
+            Event::DisputeResolved {
+                client,
+                holder,
+                tx,
+                amount,
+                currency,
+            } => {
+                let record = self.replay_settle(client, tx, DisputeState::Resolved)?;
+                let moved = record.signed(amount);
+                self.replay_balance(holder, currency, |account| {
+                    account.post(Account::Held, Account::Available, moved)
+                })
+            }
+            // the recipient of a transfer loses the held funds to the sender
+            Event::ChargedBack {
+                client,
+                holder,
+                tx,
+                amount,
+                currency,
+            } => {
+                let record = self.replay_settle(client, tx, DisputeState::ChargedBack)?;
+                let moved = record.signed(amount);
+                // the held funds go back outside the bank, or for a transfer to its sender
+                let to = if holder == client {
+                    Account::Chargebacks
+                } else {
+                    Account::Transfers
+                };
+                self.replay_balance(holder, currency, |account| {
+                    account.post(Account::Held, to, moved)
+                })?;
+                if holder != client {
+                    self.replay_balance(client, currency, |account| {
+                        account.post(Account::Transfers, Account::Available, amount)
+                    })?;
+                }
+                Ok(())
+            }
+            Event::AccountLocked { client } => {
+                let account = self.bank.get_mut(&client).ok_or(TxnError::UnknownClient)?;
+                account.locked = true;
+                Ok(())
+            }
+            Event::AccountUnlocked {
+                client,
+                tx,
+                timestamp,
+            } => {
+                let account = self.bank.get_mut(&client).ok_or(TxnError::UnknownClient)?;
+                if !account.locked {
+                    return Err(TxnError::NotLocked);
+                }
+                account.locked = false;
+                let txn = replayed(TransactionType::Unlock, client, tx, Amount::ZERO, timestamp);
+                account.unlocks.push(txn);
+                Ok(())
+            }
+            Event::AccountClosed { client } => {
+                let account = self.bank.get_mut(&client).ok_or(TxnError::UnknownClient)?;
+                if account.closed {
+                    return Err(TxnError::AccountClosed);
+                }
+                account.closed = true;
+                Ok(())
+            }
+            Event::AccountsMerged { client, to } => self.merge(client, to).map(|_| ()),
+            // it changed nothing
+            Event::VelocityAlert { .. } => Ok(()),
+        }
+    }
+
+    // Record txn in its client's history, opening the account if need be, after applying f to
+    // the balance in its currency
+    fn replay_new(
+        &mut self,
+        txn: Transaction,
+        f: impl FnOnce(&mut Client) -> Result<(), TxnError>,
+    ) -> Result<(), TxnError> {
+        self.add_client(txn.client);
+        if self.owns(txn.client, txn.tx)? {
+            return Err(TxnError::DuplicateTx);
+        }
+        self.replay_balance(txn.client, txn.currency, f)?;
+        let mut record = TxnRecord::new(&txn);
+        if txn.tx_type == TransactionType::Accrue {
+            record.amount = txn.amount;
+        }
+        if let Some(account) = self.bank.get_mut(&txn.client) {
+            account.txns.insert(txn.tx, record);
+        }
+        self.tx_ids.insert(txn.tx);
+        self.recorded(txn.client);
+        Ok(())
+    }
+
+    fn replay_balance(
+        &mut self,
+        client: ClientId,
+        currency: Option<Currency>,
+        f: impl FnOnce(&mut Client) -> Result<(), TxnError>,
+    ) -> Result<(), TxnError> {
+        let account = self.bank.get_mut(&client).ok_or(TxnError::UnknownClient)?;
+        account.in_currency(currency, f)
+    }
+
+    // Update client's record of tx with f, returning the record as it's stored
+    fn replay_record<T>(
+        &mut self,
+        client: ClientId,
+        tx: TxId,
+        f: impl FnOnce(&mut TxnRecord) -> Result<T, TxnError>,
+    ) -> Result<TxnRecord, TxnError> {
+        let account = self.bank.get_mut(&client).ok_or(TxnError::UnknownClient)?;
+        let mut record = account.txns.get(&tx)?.ok_or(TxnError::TxNotFound)?;
+        f(&mut record)?;
+        account.txns.insert(tx, record);
+        Ok(record)
+    }
+
+    fn replay_settle(
+        &mut self,
+        client: ClientId,
+        tx: TxId,
+        next: DisputeState,
+    ) -> Result<TxnRecord, TxnError> {
+        let account = self.bank.get_mut(&client).ok_or(TxnError::UnknownClient)?;
+        let (record, _) = account.settle(tx, next)?;
+        account.txns.insert(tx, record);
+        Ok(record)
+    }
+}
+
+// The transaction an event was made from, as far as its record needs it
+fn replayed(
+    tx_type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    amount: Amount,
+    timestamp: Option<i64>,
+) -> Transaction {
+    Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+        to: None,
+        timestamp,
+        currency: None,
+        to_currency: None,
+        effective_date: None,
+    }
+}
@@ -0,0 +1,56 @@
+use std::{error::Error, io};
+use transactions::SourceStats;
+
+// Exit statuses, one per class of outcome, for scripts and orchestration to branch on
+pub const SUCCESS: i32 = 0;
+// a failure none of the below covers, e.g. an unwritable output
+pub const FAILURE: i32 = 1;
+// bad arguments or options, as clap exits with
+pub const USAGE: i32 = 2;
+// an input, or another file named on the command line, doesn't exist
+pub const NOT_FOUND: i32 = 3;
+// a malformed row or file stopped the run
+pub const PARSE: i32 = 4;
+// the run completed, but some rows were skipped as malformed, or under --partial-on-rejects
+// some transactions were refused
+pub const PARTIAL: i32 = 5;
+// reconcile found accounts that differ between the reports
+pub const MISMATCH: i32 = 6;
+// a batch's rows didn't add up to its control totals, under --control-totals fail
+pub const CONTROL: i32 = 7;
+
+// The status of a run that completed. A refused transaction is the engine doing its job, as
+// with a dispute of an unknown tx, so it only counts against the run when rejects says so.
+pub fn of_stats(stats: &SourceStats, rejects: bool) -> i32 {
+    if stats.skipped > 0 || rejects && stats.rejected > 0 {
+        PARTIAL
+    } else {
+        SUCCESS
+    }
+}
+
+// The status of a run that failed, going by the innermost error that says what kind of failure
+// it was
+pub fn of_error(err: &(dyn Error + 'static)) -> i32 {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if let Some(err) = err.downcast_ref::<transactions::Error>() {
+            if err.is_recoverable() {
+                return PARSE;
+            }
+            match err {
+                transactions::Error::Invalid(_) => return PARSE,
+                transactions::Error::Pattern(_) => return USAGE,
+                transactions::Error::Control(_) => return CONTROL,
+                _ => {}
+            }
+        }
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            if err.kind() == io::ErrorKind::NotFound {
+                return NOT_FOUND;
+            }
+        }
+        next = err.source();
+    }
+    FAILURE
+}
@@ -0,0 +1,199 @@
+// The engine for C and C++ callers, e.g. a settlement system that links it in rather than running
+// the binary. Building with the ffi feature writes the declarations to include/transactions.h.
+//
+// A bank is created with tx_bank_new, fed one transaction at a time with tx_bank_submit, asked
+// for a client's balances with tx_bank_balance, and given back with tx_bank_free. A bank isn't
+// safe to use from two threads at once. Amounts cross the boundary as whole ten-thousandths, the
+// four places the engine keeps.
+
+// the unsafe functions' requirements are under "# Safety" in their comments, which clippy only
+// looks for in doc comments
+#![allow(clippy::missing_safety_doc)]
+
+use crate::amount::Amount;
+use crate::bank::{Bank, ClientId, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+};
+
+// TxTransaction::tx_type, the codes of TransactionType::code
+pub const TX_WITHDRAWAL: u8 = 1;
+pub const TX_DEPOSIT: u8 = 2;
+pub const TX_DISPUTE: u8 = 3;
+pub const TX_RESOLVE: u8 = 4;
+pub const TX_CHARGEBACK: u8 = 5;
+pub const TX_UNLOCK: u8 = 6;
+pub const TX_TRANSFER: u8 = 7;
+pub const TX_FEE: u8 = 8;
+pub const TX_ACCRUE: u8 = 9;
+pub const TX_CONVERT: u8 = 10;
+pub const TX_REFUND: u8 = 11;
+pub const TX_CLOSE: u8 = 12;
+pub const TX_MERGE: u8 = 13;
+pub const TX_HOLD: u8 = 14;
+pub const TX_RELEASE: u8 = 15;
+
+// bits of TxTransaction::flags, which of its optional fields are set
+pub const TX_HAS_TO: u8 = 1;
+pub const TX_HAS_TIMESTAMP: u8 = 1 << 1;
+pub const TX_HAS_CURRENCY: u8 = 1 << 2;
+pub const TX_HAS_TO_CURRENCY: u8 = 1 << 3;
+
+// what tx_bank_submit returns
+pub const TX_APPLIED: i32 = 0;
+// the engine refused it, for the reason written to *reason
+pub const TX_REJECTED: i32 = 1;
+// a null pointer, an unknown type code or flag, or a currency that isn't three capital letters
+pub const TX_INVALID: i32 = -1;
+
+// A bank and the C strings of the reasons it has given, which live as long as it does
+pub struct TxBank {
+    bank: Bank,
+    reasons: HashMap<&'static str, CString>,
+}
+
+// One transaction, the fields set as they would be in a CSV row
+#[repr(C)]
+pub struct TxTransaction {
+    pub tx_type: u8,
+    pub flags: u8,
+    pub client: ClientId,
+    pub tx: TxId,
+    // in ten-thousandths, so 1.5 is 15000
+    pub amount: i64,
+    pub to: ClientId,
+    // seconds since the Unix epoch
+    pub timestamp: i64,
+    // ASCII codes, e.g. {'E', 'U', 'R'}
+    pub currency: [u8; 3],
+    pub to_currency: [u8; 3],
+}
+
+// A client's balances in one currency, amounts in ten-thousandths
+#[repr(C)]
+pub struct TxBalance {
+    pub available: i64,
+    pub held: i64,
+    pub total: i64,
+    pub locked: bool,
+    pub closed: bool,
+}
+
+// A bank with no clients and the default policies, to be given back with tx_bank_free
+#[no_mangle]
+pub extern "C" fn tx_bank_new() -> *mut TxBank {
+    Box::into_raw(Box::new(TxBank {
+        bank: Bank::new(),
+        reasons: HashMap::new(),
+    }))
+}
+
+// # Safety
+// bank must have come from tx_bank_new and not been freed already; null is ignored
+#[no_mangle]
+pub unsafe extern "C" fn tx_bank_free(bank: *mut TxBank) {
+    if !bank.is_null() {
+        drop(Box::from_raw(bank));
+    }
+}
+
+// Applies a transaction, returning TX_APPLIED, TX_REJECTED or TX_INVALID. When it's rejected and
+// reason isn't null, *reason is set to the rejects report's reason code, e.g. "insufficient_funds",
+// valid until the bank is freed.
+//
+// # Safety
+// bank must be a live bank from tx_bank_new, txn must point to a TxTransaction, and reason must be
+// null or point to writable storage for a pointer
+#[no_mangle]
+pub unsafe extern "C" fn tx_bank_submit(
+    bank: *mut TxBank,
+    txn: *const TxTransaction,
+    reason: *mut *const c_char,
+) -> i32 {
+    let (Some(bank), Some(txn)) = (bank.as_mut(), txn.as_ref()) else {
+        return TX_INVALID;
+    };
+    let Some(txn) = transaction(txn) else {
+        return TX_INVALID;
+    };
+    match bank.bank.insert_txn(txn) {
+        Ok(_) => TX_APPLIED,
+        Err(err) => {
+            if !reason.is_null() {
+                let code = err.code();
+                let text = bank
+                    .reasons
+                    .entry(code)
+                    .or_insert_with(|| CString::new(code).expect("reason codes have no NUL bytes"));
+                *reason = text.as_ptr();
+            }
+            TX_REJECTED
+        }
+    }
+}
+
+// Writes a client's balances to *out and returns true, or returns false if the client has no
+// account or its total is past an i64 of ten-thousandths. currency is a NUL-terminated code like "EUR", or null for the unlabelled balance.
+//
+// # Safety
+// bank must be a live bank from tx_bank_new, currency null or a NUL-terminated string, and out
+// must point to a TxBalance
+#[no_mangle]
+pub unsafe extern "C" fn tx_bank_balance(
+    bank: *const TxBank,
+    client: ClientId,
+    currency: *const c_char,
+    out: *mut TxBalance,
+) -> bool {
+    let (Some(bank), Some(out)) = (bank.as_ref(), out.as_mut()) else {
+        return false;
+    };
+    let currency = if currency.is_null() {
+        None
+    } else {
+        let code = CStr::from_ptr(currency).to_str().ok();
+        match code.and_then(|code| code.parse::<Currency>().ok()) {
+            Some(currency) => Some(currency),
+            None => return false,
+        }
+    };
+    let Some(client) = bank.bank.get_client(client) else {
+        return false;
+    };
+    let balance = client.balance(currency);
+    let Some(total) = balance.available.checked_add(balance.held) else {
+        return false;
+    };
+    *out = TxBalance {
+        available: balance.available.units(),
+        held: balance.held.units(),
+        total: total.units(),
+        locked: client.locked(),
+        closed: client.closed(),
+    };
+    true
+}
+
+fn transaction(txn: &TxTransaction) -> Option<Transaction> {
+    if txn.flags & !(TX_HAS_TO | TX_HAS_TIMESTAMP | TX_HAS_CURRENCY | TX_HAS_TO_CURRENCY) != 0 {
+        return None;
+    }
+    let currency = |bit: u8, bytes: [u8; 3]| match txn.flags & bit {
+        0 => Some(None),
+        _ => Currency::from_bytes(bytes).map(Some),
+    };
+    Some(Transaction {
+        tx_type: TransactionType::from_code(txn.tx_type)?,
+        client: txn.client,
+        tx: txn.tx,
+        amount: Amount::from_units(txn.amount),
+        to: (txn.flags & TX_HAS_TO != 0).then_some(txn.to),
+        timestamp: (txn.flags & TX_HAS_TIMESTAMP != 0).then_some(txn.timestamp),
+        currency: currency(TX_HAS_CURRENCY, txn.currency)?,
+        to_currency: currency(TX_HAS_TO_CURRENCY, txn.to_currency)?,
+        effective_date: None,
+    })
+}
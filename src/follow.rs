@@ -0,0 +1,92 @@
+use crate::bank::{Bank, Transaction};
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::source::{take_row, CsvOptions, InputFormat, LineParser, SourceStats};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+// How long to wait before looking for more at the end of the file
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// A file that's still being appended to, e.g. a day's batch drops
+#[derive(Debug, Clone)]
+pub struct FollowSource {
+    pub path: PathBuf,
+    pub format: InputFormat,
+    pub csv: CsvOptions,
+    // how often on_report is handed the current state, while rows keep arriving
+    pub report_interval: Duration,
+}
+
+impl Bank {
+    // Apply the file's rows, then keep it open and apply rows as they're appended, like
+    // `tail -f`. A line is only parsed once its newline is written, so a row caught half-written
+    // is read whole later. Malformed rows are handled per the bank's OnError policy, refused
+    // transactions go to on_reject, and the bank is passed to on_report with the running stats
+    // once the rows already in the file are applied, then every report_interval in which more
+    // arrived. An audit log is flushed before each report.
+    // This only returns when reading the file or one of the callbacks fails.
+    pub fn follow_file<F, S>(
+        &mut self,
+        source: &FollowSource,
+        mut on_reject: F,
+        mut on_report: S,
+    ) -> Result<SourceStats, Error>
+    where
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+        S: FnMut(&Bank, &mut SourceStats) -> Result<(), Error>,
+    {
+        let mut reader = BufReader::new(File::open(&source.path)?);
+        let mut parser = LineParser::new(source.format)
+            .with_csv_options(source.csv)
+            .with_precision(self.policy.precision);
+        let mut stats = SourceStats::default();
+        // the line being read, which may take more than one read to arrive
+        let mut line = Vec::new();
+        let (mut caught_up, mut unreported) = (false, false);
+        let mut last_report = Instant::now();
+        loop {
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                if !caught_up || unreported && last_report.elapsed() >= source.report_interval {
+                    self.flush_audit()?;
+                    on_report(self, &mut stats)?;
+                    (caught_up, unreported) = (true, false);
+                    last_report = Instant::now();
+                }
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            if !line.ends_with(b"\n") {
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line);
+            let result = parser
+                .parse(text.trim_end_matches(['\n', '\r']))
+                .transpose();
+            line.clear();
+            unreported = true;
+            let Some(result) = result else {
+                continue;
+            };
+            if let Some(txn) = take_row(result, self.policy.on_error, &mut stats)? {
+                let result = self.insert_read(txn, parser.refusal());
+                self.check_spill()?;
+                if let Err(err) = result {
+                    stats.reject(&txn, &err, parser.line());
+                    on_reject(&txn, &err)?;
+                }
+            }
+            if caught_up && last_report.elapsed() >= source.report_interval {
+                self.flush_audit()?;
+                on_report(self, &mut stats)?;
+                unreported = false;
+                last_report = Instant::now();
+            }
+        }
+    }
+}
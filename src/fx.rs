@@ -0,0 +1,164 @@
+use crate::amount::Amount;
+use crate::bank::{Client, ClientRecord, Transaction, TxnRecord};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::ledger::Account;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::snapshot::invalid;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, io, path::Path, str::FromStr};
+
+// Exchange rates between currencies, read from a CSV file with from,to,rate columns: one unit
+// of `from` is worth `rate` units of `to`. A pair given only one way round is also used the
+// other way, at the inverse rate. There's no going through a third currency, so every pair that
+// is converted between has to be listed.
+#[derive(Debug, Default)]
+pub struct Rates {
+    rates: HashMap<(Currency, Currency), Decimal>,
+}
+
+#[derive(Deserialize)]
+struct RateRow {
+    from: Currency,
+    to: Currency,
+    rate: String,
+}
+
+impl Rates {
+    pub fn from_reader<R: io::Read>(r: R) -> Result<Rates, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(r);
+        let mut rates = Rates::default();
+        for row in rdr.deserialize() {
+            let row: RateRow = row?;
+            let rate = Decimal::from_str(row.rate.trim())
+                .ok()
+                .filter(|rate| *rate > Decimal::ZERO)
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "rate from {} to {} must be a positive number, not '{}'",
+                        row.from, row.to, row.rate
+                    ))
+                })?;
+            rates.rates.insert((row.from, row.to), rate);
+        }
+        Ok(rates)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Rates, Error> {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(Error::from)
+            .and_then(Rates::from_reader)
+            .map_err(|err| err.in_file(path))
+    }
+
+    // What one unit of from is worth in to, if the table says
+    pub fn rate(&self, from: Currency, to: Currency) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+        self.rates.get(&(from, to)).copied().or_else(|| {
+            self.rates
+                .get(&(to, from))
+                .and_then(|rate| Decimal::ONE.checked_div(*rate))
+        })
+    }
+
+    // amount in from, converted to to and rounded to four places like every other amount
+    pub fn convert(&self, amount: Amount, from: Currency, to: Currency) -> Option<Amount> {
+        self.rate(from, to)
+            .and_then(|rate| amount.as_decimal().checked_mul(rate))
+            .and_then(Amount::new)
+    }
+}
+
+// A `convert` row moves its amount out of the client's balance in `currency` into the one in
+// `to_currency`, at the rate in the bank's table (Policy::rates). Both currencies have to be
+// given. Like a transfer it never overdraws the balance it takes from, whatever the
+// WithdrawalPolicy, and it can't be disputed. It's recorded in the client's history under the
+// source currency, with the amount taken from it.
+impl Client {
+    pub(crate) fn convert(
+        &mut self,
+        txn: Transaction,
+        rates: &Rates,
+    ) -> Result<TxnOutcome, TxnError> {
+        self.check_new(&txn)?;
+        let (Some(from), Some(to)) = (txn.currency, txn.to_currency) else {
+            return Err(TxnError::InvalidConversion);
+        };
+        if from == to {
+            return Err(TxnError::InvalidConversion);
+        }
+        let converted = rates
+            .convert(txn.amount, from, to)
+            .ok_or(TxnError::NoExchangeRate)?;
+        if converted.is_too_large() {
+            return Err(TxnError::AmountTooLarge);
+        }
+        if txn.amount > self.available {
+            return Err(TxnError::InsufficientFunds);
+        }
+        // this runs in_currency(from), so the target is still among the other balances
+        self.post(Account::Available, Account::Exchange, txn.amount)?;
+        let credited = self.in_currency(Some(to), |client| {
+            client.post(Account::Exchange, Account::Available, converted)
+        });
+        if let Err(err) = credited {
+            self.unpost(Account::Available, Account::Exchange, txn.amount);
+            return Err(err);
+        }
+        self.txns.insert(txn.tx, TxnRecord::new(&txn));
+        Ok(TxnOutcome::Converted)
+    }
+}
+
+// The records with each client's balances added up in base, one record per client. The unlabelled
+// balance is taken to be in base already. Each client's records have to be next to each other,
+// as Bank::records and sorted_records give them.
+pub fn convert_records(
+    records: impl Iterator<Item = ClientRecord>,
+    rates: &Rates,
+    base: Currency,
+) -> Result<Vec<ClientRecord>, Error> {
+    let mut converted: Vec<ClientRecord> = Vec::new();
+    for record in records {
+        let (available, held) = match record.currency {
+            None => (record.available, record.held),
+            Some(currency) => {
+                let convert = |amount| {
+                    rates.convert(amount, currency, base).ok_or_else(|| {
+                        invalid(format!("no exchange rate from {} to {}", currency, base))
+                    })
+                };
+                (convert(record.available)?, convert(record.held)?)
+            }
+        };
+        let sum = |a: Amount, b: Amount| {
+            a.checked_add(b).ok_or_else(|| {
+                invalid(format!(
+                    "client {}'s balances add up to more than an amount holds",
+                    record.client
+                ))
+            })
+        };
+        match converted.last_mut() {
+            Some(last) if last.client == record.client => {
+                last.available = sum(last.available, available)?;
+                last.held = sum(last.held, held)?;
+                last.total = sum(last.available, last.held)?;
+            }
+            _ => converted.push(ClientRecord {
+                currency: Some(base),
+                available,
+                held,
+                total: sum(available, held)?,
+                ..record
+            }),
+        }
+    }
+    Ok(converted)
+}
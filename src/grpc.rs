@@ -0,0 +1,197 @@
+use crate::amount::Amount;
+use crate::bank::{ClientId, ClientRecord, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::policy::PrecisionPolicy;
+use crate::server::{lock, SharedBank};
+use futures_util::{Stream, StreamExt};
+use std::{io, pin::Pin, str::FromStr};
+use tokio::net::TcpListener;
+use tonic::{transport::server::TcpIncoming, Request, Response, Status, Streaming};
+
+// Types and service traits generated from proto/transactions.proto
+pub mod proto {
+    tonic::include_proto!("transactions.v1");
+}
+
+use proto::transaction_service_server::{TransactionService, TransactionServiceServer};
+
+// TransactionService backed by the same shared bank as the other serve listeners
+pub struct GrpcService {
+    bank: SharedBank,
+}
+
+impl GrpcService {
+    pub fn new(bank: SharedBank) -> GrpcService {
+        GrpcService { bank }
+    }
+
+    pub fn into_server(self) -> TransactionServiceServer<GrpcService> {
+        TransactionServiceServer::new(self)
+    }
+}
+
+pub async fn serve_grpc(listener: TcpListener, bank: SharedBank) -> io::Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(GrpcService::new(bank).into_server())
+        .serve_with_incoming(TcpIncoming::from(listener))
+        .await
+        .map_err(io::Error::other)
+}
+
+type ResultStream = Pin<Box<dyn Stream<Item = Result<proto::SubmitResult, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl TransactionService for GrpcService {
+    async fn submit_transaction(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<proto::SubmitResult>, Status> {
+        let mut bank = lock(&self.bank);
+        let (txn, refusal) = read(request.into_inner(), bank.policy().precision)?;
+        let result = bank.insert_read_logged(txn, refusal).map_err(internal)?;
+        Ok(Response::new(submit_result(&txn, result)))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<proto::GetAccountRequest>,
+    ) -> Result<Response<proto::Account>, Status> {
+        let client = request.into_inner().client;
+        let record = ClientId::try_from(client)
+            .ok()
+            .and_then(|client_id| lock(&self.bank).record(client_id))
+            .ok_or_else(|| Status::not_found(format!("unknown client {}", client)))?;
+        Ok(Response::new(account(record)))
+    }
+
+    type StreamTransactionsStream = ResultStream;
+
+    async fn stream_transactions(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<ResultStream>, Status> {
+        let bank = self.bank.clone();
+        // each message is applied as it arrives, so the answers come back in submission order
+        let results = request.into_inner().map(move |message| {
+            let mut bank = lock(&bank);
+            let (txn, refusal) = read(message?, bank.policy().precision)?;
+            let result = bank.insert_read_logged(txn, refusal).map_err(internal)?;
+            Ok(submit_result(&txn, result))
+        });
+        Ok(Response::new(Box::pin(results)))
+    }
+}
+
+fn internal(err: crate::Error) -> Status {
+    Status::internal(err.to_string())
+}
+
+// The transaction a message holds, with the refusal reading it decided on: one whose amount is
+// too precise under PrecisionPolicy::Reject is refused as too_precise, not turned away as invalid
+fn read(
+    message: proto::Transaction,
+    precision: PrecisionPolicy,
+) -> Result<(Transaction, Option<TxnError>), Status> {
+    match from_proto(message.clone(), precision) {
+        Err(status) if precision == PrecisionPolicy::Reject => {
+            match from_proto(message, PrecisionPolicy::Round) {
+                Ok(txn) => Ok((txn, Some(TxnError::TooPrecise))),
+                Err(_) => Err(status),
+            }
+        }
+        read => read.map(|txn| (txn, None)),
+    }
+}
+
+fn from_proto(
+    message: proto::Transaction,
+    precision: PrecisionPolicy,
+) -> Result<Transaction, Status> {
+    let tx_type = match message.r#type() {
+        proto::TransactionType::Deposit => TransactionType::Deposit,
+        proto::TransactionType::Withdrawal => TransactionType::Withdrawal,
+        proto::TransactionType::Dispute => TransactionType::Dispute,
+        proto::TransactionType::Resolve => TransactionType::Resolve,
+        proto::TransactionType::Chargeback => TransactionType::Chargeback,
+        proto::TransactionType::Unlock => TransactionType::Unlock,
+        proto::TransactionType::Transfer => TransactionType::Transfer,
+        proto::TransactionType::Fee => TransactionType::Fee,
+        proto::TransactionType::Accrue => TransactionType::Accrue,
+        proto::TransactionType::Convert => TransactionType::Convert,
+        proto::TransactionType::Refund => TransactionType::Refund,
+        proto::TransactionType::Close => TransactionType::Close,
+        proto::TransactionType::Merge => TransactionType::Merge,
+        proto::TransactionType::Hold => TransactionType::Hold,
+        proto::TransactionType::Release => TransactionType::Release,
+        proto::TransactionType::Unspecified => {
+            return Err(Status::invalid_argument("transaction type is required"))
+        }
+    };
+    let client = ClientId::try_from(message.client).map_err(|_| {
+        Status::invalid_argument(format!("client id {} out of range", message.client))
+    })?;
+    let tx = TxId::try_from(message.tx)
+        .map_err(|_| Status::invalid_argument(format!("tx id {} out of range", message.tx)))?;
+    let amount = if message.amount.trim().is_empty() {
+        Amount::ZERO
+    } else {
+        Amount::parse(&message.amount, precision)
+            .map_err(|err| Status::invalid_argument(format!("invalid amount: {}", err)))?
+    };
+    let to = message
+        .to
+        .map(|to| {
+            ClientId::try_from(to)
+                .map_err(|_| Status::invalid_argument(format!("client id {} out of range", to)))
+        })
+        .transpose()?;
+    let currency = |code: &Option<String>| {
+        code.as_deref()
+            .map(Currency::from_str)
+            .transpose()
+            .map_err(Status::invalid_argument)
+    };
+    Ok(Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+        to,
+        timestamp: message.timestamp,
+        currency: currency(&message.currency)?,
+        to_currency: currency(&message.to_currency)?,
+        effective_date: None,
+    })
+}
+
+// the ids' conversions are no-ops with wide ids, which the proto type already fits
+#[cfg_attr(feature = "wide-ids", allow(clippy::useless_conversion))]
+fn submit_result(txn: &Transaction, result: Result<TxnOutcome, TxnError>) -> proto::SubmitResult {
+    let mut reply = proto::SubmitResult {
+        client: u32::from(txn.client),
+        tx: u64::from(txn.tx),
+        ..Default::default()
+    };
+    match result {
+        Ok(outcome) => {
+            reply.accepted = true;
+            reply.outcome = outcome.code().to_string();
+        }
+        Err(err) => reply.reason = err.code().to_string(),
+    }
+    reply
+}
+
+#[cfg_attr(feature = "wide-ids", allow(clippy::useless_conversion))]
+fn account(record: ClientRecord) -> proto::Account {
+    proto::Account {
+        client: u32::from(record.client),
+        available: record.available.to_string(),
+        held: record.held.to_string(),
+        total: record.total.to_string(),
+        locked: record.locked,
+        currency: record.currency.map(|currency| currency.to_string()),
+        closed: record.closed,
+    }
+}
@@ -0,0 +1,152 @@
+use crate::bank::{ClientId, TxId};
+use crate::outcome::TxnError;
+use crate::report::{self, JsonRecord, OutputFormat};
+use crate::server::{lock, SharedBank};
+use crate::source::transaction_from_json;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io;
+use tokio::net::TcpListener;
+
+// REST API over the shared bank:
+//   POST /transactions   one JSON transaction object, or an array of them applied in order
+//   GET  /clients/{id}   a client's report record
+//   GET  /report         every client's record, as JSON unless ?format=csv|ndjson is given
+//   GET  /metrics        Prometheus metrics, see Bank::metrics_text
+pub async fn serve_http(listener: TcpListener, bank: SharedBank) -> io::Result<()> {
+    axum::serve(listener, router(bank)).await
+}
+
+pub fn router(bank: SharedBank) -> Router {
+    Router::new()
+        .route("/transactions", post(post_transactions))
+        .route("/clients/{id}", get(get_client))
+        .route("/report", get(get_report))
+        .route("/metrics", get(get_metrics))
+        .with_state(bank)
+}
+
+// Only GET /metrics, for servers that don't otherwise speak HTTP
+pub async fn serve_metrics(listener: TcpListener, bank: SharedBank) -> io::Result<()> {
+    let router = Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(bank);
+    axum::serve(listener, router).await
+}
+
+async fn get_metrics(State(bank): State<SharedBank>) -> Response {
+    let text = lock(&bank).metrics_text();
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], text).into_response()
+}
+
+// What happened to one submitted transaction
+#[derive(Serialize)]
+struct TxnResult {
+    client: ClientId,
+    tx: TxId,
+    // "ok" or "rejected"
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+}
+
+async fn post_transactions(State(bank): State<SharedBank>, Json(body): Json<Value>) -> Response {
+    let batch = body.is_array();
+    let values = match body {
+        Value::Array(values) => values,
+        value => vec![value],
+    };
+    // parse the whole batch before applying any of it, so a bad entry doesn't leave it half applied
+    let precision = lock(&bank).policy().precision;
+    let txns = match values
+        .into_iter()
+        .map(|value| match transaction_from_json(value, precision) {
+            Ok(txn) => Ok((txn, None)),
+            Err(err) => err
+                .too_precise()
+                .map(|txn| (txn, Some(TxnError::TooPrecise))),
+        })
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(txns) => txns,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    let results = {
+        let mut bank = lock(&bank);
+        txns.into_iter()
+            .map(|(txn, refusal)| {
+                let result = match bank.insert_read_logged(txn, refusal)? {
+                    Ok(outcome) => TxnResult {
+                        client: txn.client,
+                        tx: txn.tx,
+                        status: "ok",
+                        outcome: Some(outcome.code()),
+                        reason: None,
+                    },
+                    Err(err) => TxnResult {
+                        client: txn.client,
+                        tx: txn.tx,
+                        status: "rejected",
+                        outcome: None,
+                        reason: Some(err.code()),
+                    },
+                };
+                Ok(result)
+            })
+            .collect::<Result<Vec<_>, crate::Error>>()
+    };
+    // a transaction that couldn't be logged wasn't applied, and neither were any after it
+    let mut results = match results {
+        Ok(results) => results,
+        Err(err) => return error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    };
+    if batch {
+        Json(results).into_response()
+    } else {
+        Json(results.remove(0)).into_response()
+    }
+}
+
+async fn get_client(State(bank): State<SharedBank>, Path(id): Path<ClientId>) -> Response {
+    match lock(&bank).record(id) {
+        Some(record) => Json(JsonRecord::from(record)).into_response(),
+        None => error(StatusCode::NOT_FOUND, "unknown client".to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    format: Option<String>,
+}
+
+async fn get_report(State(bank): State<SharedBank>, Query(query): Query<ReportQuery>) -> Response {
+    let format = match query.format.as_deref().map(str::parse::<OutputFormat>) {
+        None => OutputFormat::Json,
+        Some(Ok(format)) => format,
+        Some(Err(err)) => return error(StatusCode::BAD_REQUEST, err),
+    };
+    let mut body = Vec::new();
+    if let Err(err) = report::write_report(&lock(&bank), &mut body, format) {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string());
+    }
+    let content_type = match format {
+        OutputFormat::Csv => "text/csv",
+        OutputFormat::Json => "application/json",
+        OutputFormat::Ndjson => "application/x-ndjson",
+        OutputFormat::Parquet => "application/vnd.apache.parquet",
+    };
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
@@ -0,0 +1,162 @@
+use crate::bank::{Bank, ClientId, Transaction, TxId};
+use crate::error::Error;
+use crate::policy::TxIdPolicy;
+use crate::source::{SourceStats, TransactionSource};
+use std::{collections::HashMap, io};
+
+// The entries applied from OFX and QIF files, by the tx id each was given, with its client and
+// what identifies it in the export: the FITID, or for QIF the record's contents (see qif.rs).
+// Those ids are hashes where the export's own aren't numbers, and a hash can give two entries
+// the same id. The strings tell a repeat of an entry, which the bank refuses as a duplicate_tx
+// like any other, from a different entry given an id that's taken, which fails the run instead
+// of being refused as though it were a repeat. Kept in snapshots, so it holds across runs.
+#[derive(Debug, Default)]
+pub(crate) struct Imports(HashMap<TxId, Vec<(ClientId, Box<str>)>>);
+
+impl Imports {
+    pub(crate) fn loaded(entries: impl IntoIterator<Item = (TxId, ClientId, String)>) -> Imports {
+        let mut imports = Imports::default();
+        for (tx, client, key) in entries {
+            imports.0.entry(tx).or_default().push((client, key.into()));
+        }
+        imports
+    }
+
+    // In no particular order
+    pub(crate) fn all(&self) -> impl Iterator<Item = (TxId, ClientId, &str)> + '_ {
+        self.0.iter().flat_map(|(tx, entries)| {
+            entries
+                .iter()
+                .map(|(client, key)| (*tx, *client, key.as_ref()))
+        })
+    }
+}
+
+impl Bank {
+    // The next transaction from source, as TransactionSource::next_txn, failing with
+    // Error::Collision where it's an OFX or QIF entry whose tx id another entry or transaction
+    // already has
+    pub(crate) fn next_from<R: io::Read>(
+        &mut self,
+        source: &mut TransactionSource<R>,
+        stats: &mut SourceStats,
+    ) -> Result<Option<Transaction>, Error> {
+        let Some(txn) = source.next_txn(&self.policy, stats)? else {
+            return Ok(None);
+        };
+        if let Some(key) = source.import_key() {
+            self.check_import(&txn, key)
+                .map_err(|err| err.at_line(source.line()))?;
+        }
+        Ok(Some(txn))
+    }
+
+    fn check_import(&mut self, txn: &Transaction, key: &str) -> Result<(), Error> {
+        let collision = |holder: String| collision(txn, key, holder);
+        let global = self.policy.tx_ids == TxIdPolicy::Global;
+        let owned = self
+            .owns(txn.client, txn.tx)
+            .map_err(|_| self.spill_failure())?;
+        let taken = global && self.tx_ids.contains(&txn.tx);
+        let entries = self.imports.0.entry(txn.tx).or_default();
+        if let Some((_, earlier)) = entries.iter().find(|(client, _)| *client == txn.client) {
+            return if earlier.as_ref() == key {
+                Ok(())
+            } else {
+                Err(collision(format!("the client's '{}'", earlier)))
+            };
+        }
+        if owned {
+            return Err(collision(
+                "another of the client's transactions".to_string(),
+            ));
+        }
+        if let Some((client, earlier)) = entries.first().filter(|_| global) {
+            return Err(collision(format!("client {}'s '{}'", client, earlier)));
+        }
+        if taken {
+            return Err(collision("another client's transaction".to_string()));
+        }
+        entries.push((txn.client, key.into()));
+        Ok(())
+    }
+}
+
+// The error for the entry key, given txn's tx id, which holder already has
+pub(crate) fn collision(txn: &Transaction, key: &str, holder: String) -> Error {
+    Error::Collision(format!(
+        "'{}' for client {} is given tx id {}, which {} already has",
+        key, txn.client, txn.tx, holder
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outcome::TxnError;
+    use crate::policy::Policy;
+
+    // An OFX statement of account for FITIDs, each a deposit of 10
+    fn ofx(account: ClientId, fitids: &[&str]) -> TransactionSource<io::Cursor<String>> {
+        let mut text = format!("<OFX><BANKACCTFROM><ACCTID>{}</BANKACCTFROM>", account);
+        for fitid in fitids {
+            text += &format!(
+                "<STMTTRN><TRNTYPE>CREDIT<TRNAMT>10<FITID>{}</STMTTRN>",
+                fitid
+            );
+        }
+        TransactionSource::ofx(io::Cursor::new(text), None)
+    }
+
+    fn is_collision(err: &Error) -> bool {
+        match err {
+            Error::Line(_, err) => is_collision(err),
+            err => matches!(err, Error::Collision(_)),
+        }
+    }
+
+    #[test]
+    fn repeats_are_refused_as_duplicates() {
+        let mut bank = Bank::new();
+        bank.process_source(ofx(1, &["ABC-1", "ABC-2"])).unwrap();
+        let mut rejected = Vec::new();
+        bank.process_source_with(ofx(1, &["ABC-2", "ABC-3"]), |_, err| {
+            rejected.push(*err);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(rejected, [TxnError::DuplicateTx]);
+        assert_eq!(bank.record(1).unwrap().available.to_string(), "30.0000");
+    }
+
+    #[test]
+    fn another_clients_id_fails_the_run() {
+        let mut bank = Bank::new();
+        bank.process_source(ofx(1, &["ABC-1"])).unwrap();
+        let err = bank.process_source(ofx(2, &["ABC-1"])).unwrap_err();
+        assert!(is_collision(&err), "{}", err);
+
+        let mut bank = Bank::with_policy(Policy {
+            tx_ids: TxIdPolicy::PerClient,
+            ..Default::default()
+        });
+        bank.process_source(ofx(1, &["ABC-1"])).unwrap();
+        bank.process_source(ofx(2, &["ABC-1"])).unwrap();
+    }
+
+    // two FITIDs whose hashes share their top 32 bits
+    #[cfg(not(feature = "wide-ids"))]
+    #[test]
+    fn fitids_hashing_the_same_fail_the_run() {
+        let mut bank = Bank::new();
+        let err = bank
+            .process_source(ofx(1, &["X123493", "X772220"]))
+            .unwrap_err();
+        assert!(is_collision(&err), "{}", err);
+        // a file the bank already has one of fails just the same
+        let mut bank = Bank::new();
+        bank.process_source(ofx(1, &["X123493"])).unwrap();
+        let err = bank.process_source(ofx(1, &["X772220"])).unwrap_err();
+        assert!(is_collision(&err), "{}", err);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::error::Error;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+// Turn the input arguments into the list of files to process.
+// Directories are expanded to the files directly inside them, and arguments containing glob
+// characters are matched against the filesystem; each expansion is sorted lexicographically so
+// dated batch files (e.g. data/2024-*.csv) replay in order. Plain paths and "-" pass through.
+pub fn expand_inputs<S: AsRef<str>>(args: &[S]) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    for arg in args {
+        let arg = arg.as_ref();
+        let path = PathBuf::from(arg);
+        if arg != "-" && path.is_dir() {
+            let mut files = Vec::new();
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    files.push(entry.path());
+                }
+            }
+            files.sort();
+            paths.extend(files);
+        } else if is_glob(arg) {
+            let mut files = Vec::new();
+            for entry in glob::glob(arg)? {
+                let file = entry.map_err(io::Error::from)?;
+                if file.is_file() {
+                    files.push(file);
+                }
+            }
+            // like a shell, a pattern that matches nothing is left as-is and fails when opened
+            if files.is_empty() {
+                files.push(path);
+            }
+            files.sort();
+            paths.extend(files);
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+fn is_glob(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// Open a file (or stdin for "-") for reading, decompressing it on the fly if it is gzip or zstd
+pub fn open_input(path: &Path) -> io::Result<Box<dyn Read>> {
+    if path == Path::new("-") {
+        decompress(io::stdin().lock())
+    } else {
+        decompress(File::open(path)?)
+    }
+}
+
+// Compression is detected from the leading magic bytes rather than the extension, so it also
+// works for piped input. Anything unrecognised is passed through untouched.
+pub fn decompress<R: Read + 'static>(reader: R) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let head = reader.fill_buf()?;
+    if head.starts_with(GZIP_MAGIC) {
+        #[cfg(feature = "gzip")]
+        return Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader)));
+        #[cfg(not(feature = "gzip"))]
+        return Err(unsupported("gzip"));
+    }
+    if head.starts_with(ZSTD_MAGIC) {
+        #[cfg(feature = "zstd")]
+        return Ok(Box::new(zstd::Decoder::with_buffer(reader)?));
+        #[cfg(not(feature = "zstd"))]
+        return Err(unsupported("zstd"));
+    }
+    Ok(Box::new(reader))
+}
+
+#[cfg(not(all(feature = "gzip", feature = "zstd")))]
+fn unsupported(compression: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "input is {} compressed but the {} feature is disabled",
+            compression, compression
+        ),
+    )
+}
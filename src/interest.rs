@@ -0,0 +1,53 @@
+use crate::amount::Amount;
+use crate::bank::{Client, Transaction, TxnRecord};
+use crate::ledger::Account;
+use crate::outcome::{TxnError, TxnOutcome};
+use rust_decimal::Decimal;
+
+// An `accrue` row pays the client interest on their available balance at the bank's interest
+// rate (Policy::interest_rate), once per period. Its amount is the number of periods to pay for,
+// e.g. days since the last accrual, with an empty amount meaning one. Interest is simple, not
+// compounded within a row, and rounded to four places like every other amount.
+//
+// Nothing is paid on a balance that is zero or negative, and funds held by a dispute earn
+// nothing. The posting is recorded in the client's history under the row's own tx id, with the
+// interest paid as its amount; it can't be disputed.
+impl Client {
+    pub(crate) fn accrue(
+        &mut self,
+        txn: Transaction,
+        rate: Decimal,
+    ) -> Result<TxnOutcome, TxnError> {
+        if self.txns.contains_key(&txn.tx)? {
+            return Err(TxnError::DuplicateTx);
+        }
+        let periods = if txn.amount.is_zero() {
+            Decimal::ONE
+        } else if txn.amount.is_negative() {
+            return Err(TxnError::InvalidPeriods);
+        } else {
+            txn.amount.as_decimal()
+        };
+        let interest = interest(self.available, rate, periods)
+            .filter(|interest| !interest.is_too_large())
+            .ok_or(TxnError::AmountTooLarge)?;
+        self.post(Account::Interest, Account::Available, interest)?;
+        let mut record = TxnRecord::new(&txn);
+        record.amount = interest;
+        self.txns.insert(txn.tx, record);
+        Ok(TxnOutcome::InterestAccrued)
+    }
+}
+
+// What balance earns at rate over the given number of periods, rounded to four places, None if
+// that's more than an Amount holds
+pub fn interest(balance: Amount, rate: Decimal, periods: Decimal) -> Option<Amount> {
+    if balance.is_negative() || balance.is_zero() {
+        return Some(Amount::ZERO);
+    }
+    balance
+        .as_decimal()
+        .checked_mul(rate)?
+        .checked_mul(periods)
+        .and_then(Amount::new)
+}
@@ -0,0 +1,189 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, ClientId, Transaction, TxnRecord};
+use crate::currency::Currency;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::policy::{DisputePolicy, NegativeAmountPolicy};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// --check-invariants, for working on the engine itself: after every transaction insert_txn
+// checks the accounts it touched against three things that should always hold, and panics with
+// what it found if one doesn't.
+//
+// - Every balance's total, available + held, is what the postings to its ledger add up to, see
+//   ledger.rs.
+// - No balance holds less than nothing, unless the policy lets disputes of withdrawals or of
+//   negative deposits move funds out of held.
+// - The accounts' totals moved by what the transaction says, worked out from the input alone: a
+//   deposit adds its amount to its currency, a withdrawal or fee takes it away, a conversion
+//   takes it from one currency and adds it at the bank's rate to another, and a transfer, a
+//   dispute, a resolve or a release only moves funds around. Only a chargeback, a refund and an
+//   accrual need the transaction's record, for what was disputed, withdrawn or paid. A refused
+//   transaction moves nothing.
+//
+// It's meant for test inputs rather than production runs: besides the checks, every transaction
+// costs a look at each balance of the accounts it touches.
+
+// The accounts a transaction touches, as they were before it
+pub(crate) struct Before {
+    clients: Vec<ClientId>,
+    currency: Option<Currency>,
+    // the record the transaction refers to
+    record: Option<TxnRecord>,
+    totals: BTreeMap<Option<Currency>, Amount>,
+}
+
+impl Bank {
+    // Check every later transaction with insert_txn, panicking at the first that breaks an
+    // invariant
+    pub fn check_invariants(&mut self) {
+        self.invariants = true;
+    }
+
+    pub(crate) fn before_txn(&self, txn: &Transaction) -> Before {
+        let client = self.bank.get(&txn.client);
+        // a record the spill file fails to give back refuses txn, so there's nothing to check
+        let record = client.and_then(|client| client.txns.get(&txn.tx).ok().flatten());
+        let mut clients = vec![txn.client];
+        for other in [txn.to, record.and_then(|record| record.to)] {
+            if let Some(other) = other.filter(|other| !clients.contains(other)) {
+                clients.push(other);
+            }
+        }
+        Before {
+            currency: client
+                .map_or(Ok(txn.currency), |client| client.currency_of(txn))
+                .unwrap_or(txn.currency),
+            record,
+            totals: self.totals(&clients),
+            clients,
+        }
+    }
+
+    pub(crate) fn check_txn(
+        &self,
+        txn: &Transaction,
+        result: &Result<TxnOutcome, TxnError>,
+        before: Before,
+    ) {
+        let mut broken = Vec::new();
+        for id in &before.clients {
+            let Some(client) = self.bank.get(id) else {
+                continue;
+            };
+            if !client.balanced() {
+                broken.push(format!("client {}'s balances are off their ledger", id));
+            }
+            let negative = self.policy.dispute == DisputePolicy::DepositsAndWithdrawals
+                || self.policy.negative == NegativeAmountPolicy::Allow;
+            if !negative && client.records().any(|record| record.held.is_negative()) {
+                broken.push(format!("client {} holds less than nothing", id));
+            }
+        }
+        let after = self.totals(&before.clients);
+        let expected = self.expected(txn, result, &before);
+        let currencies = before
+            .totals
+            .keys()
+            .chain(after.keys())
+            .chain(expected.keys());
+        for currency in currencies.collect::<BTreeSet<_>>() {
+            let total = |totals: &BTreeMap<Option<Currency>, Amount>| {
+                totals.get(currency).copied().unwrap_or(Amount::ZERO)
+            };
+            let moved = total(&after) - total(&before.totals);
+            if moved != total(&expected) {
+                broken.push(format!(
+                    "{} moved {} where the input moves {}",
+                    currency.map_or("the unlabelled balance".to_string(), |c| c.to_string()),
+                    moved,
+                    total(&expected)
+                ));
+            }
+        }
+        if broken.is_empty() {
+            return;
+        }
+        let mut message = format!(
+            "invariant broken by {} tx {} of client {} ({}):",
+            txn.tx_type,
+            txn.tx,
+            txn.client,
+            match result {
+                Ok(outcome) => outcome.code(),
+                Err(err) => err.code(),
+            }
+        );
+        for what in &broken {
+            let _ = write!(message, "\n  {}", what);
+        }
+        for id in &before.clients {
+            for record in self
+                .bank
+                .get(id)
+                .into_iter()
+                .flat_map(|client| client.records())
+            {
+                let _ = write!(message, "\n  now {}", record);
+            }
+        }
+        panic!("{}", message);
+    }
+
+    // What the accounts' totals in each currency add up to
+    fn totals(&self, clients: &[ClientId]) -> BTreeMap<Option<Currency>, Amount> {
+        let mut totals = BTreeMap::new();
+        let mut add = |currency, total| {
+            let sum: &mut Amount = totals.entry(currency).or_default();
+            *sum = sum.saturating_add(total);
+        };
+        for client in clients.iter().filter_map(|id| self.bank.get(id)) {
+            add(None, client.available + client.held);
+            for (currency, balance) in &client.currencies {
+                add(Some(*currency), balance.available + balance.held);
+            }
+        }
+        totals
+    }
+
+    // How much txn should have moved in each currency
+    fn expected(
+        &self,
+        txn: &Transaction,
+        result: &Result<TxnOutcome, TxnError>,
+        before: &Before,
+    ) -> BTreeMap<Option<Currency>, Amount> {
+        let Ok(outcome) = result else {
+            return BTreeMap::new();
+        };
+        let amount = txn.amount;
+        let record = before.record;
+        let moved = match outcome {
+            TxnOutcome::Deposited | TxnOutcome::Held => amount,
+            TxnOutcome::Withdrawn | TxnOutcome::FeeCharged => -amount,
+            TxnOutcome::Refunded => record.map_or(Amount::ZERO, |record| record.amount),
+            // handed back outside the bank, unless it was a transfer's
+            TxnOutcome::ChargedBack => match record {
+                Some(record) if record.to.is_none() => -record.signed(record.disputed),
+                _ => Amount::ZERO,
+            },
+            TxnOutcome::InterestAccrued => self
+                .bank
+                .get(&txn.client)
+                .and_then(|client| client.txns.get(&txn.tx).ok().flatten())
+                .map_or(Amount::ZERO, |record| record.amount),
+            TxnOutcome::Converted => {
+                let (Some(from), Some(to)) = (txn.currency, txn.to_currency) else {
+                    return BTreeMap::new();
+                };
+                let converted = self.policy.rates.convert(amount, from, to);
+                return BTreeMap::from([
+                    (Some(from), -amount),
+                    (Some(to), converted.unwrap_or(Amount::ZERO)),
+                ]);
+            }
+            _ => Amount::ZERO,
+        };
+        BTreeMap::from([(before.currency, moved)])
+    }
+}
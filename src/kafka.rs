@@ -0,0 +1,87 @@
+use crate::bank::{Bank, Transaction};
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::source::{take_row, InputFormat, LineParser, SourceStats};
+use rdkafka::{
+    config::ClientConfig,
+    consumer::{BaseConsumer, Consumer},
+    error::KafkaError,
+    Message,
+};
+use std::time::{Duration, Instant};
+
+// Where to consume transactions from. Each message payload holds one or more newline-separated
+// transactions in `format`, so CSV producers can batch rows into a single message.
+#[derive(Debug, Clone)]
+pub struct KafkaSource {
+    pub brokers: String,
+    pub topic: String,
+    pub group_id: String,
+    pub format: InputFormat,
+    // how often on_snapshot is handed the current state
+    pub snapshot_interval: Duration,
+}
+
+impl Bank {
+    // Consume the topic continuously, applying transactions as they arrive. Malformed rows are
+    // handled per the bank's OnError policy, refused transactions go to on_reject, and every
+    // snapshot_interval the bank is passed to on_snapshot (e.g. to write the account report) along
+    // with the running stats, so reported parse errors can be drained there.
+    // This only returns when the consumer or one of the callbacks fails.
+    pub fn consume_kafka<F, S>(
+        &mut self,
+        source: &KafkaSource,
+        mut on_reject: F,
+        mut on_snapshot: S,
+    ) -> Result<SourceStats, Error>
+    where
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+        S: FnMut(&Bank, &mut SourceStats) -> Result<(), Error>,
+    {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &source.brokers)
+            .set("group.id", &source.group_id)
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+        consumer.subscribe(&[&source.topic])?;
+
+        let mut parser = LineParser::new(source.format).with_precision(self.policy.precision);
+        let mut stats = SourceStats::default();
+        let mut last_snapshot = Instant::now();
+        let mut last_error = None;
+        loop {
+            match consumer.poll(Duration::from_millis(100)) {
+                None => {}
+                // librdkafka reconnects by itself, so broker outages are reported once, not fatal
+                Some(Err(KafkaError::MessageConsumption(code))) => {
+                    let repeated = last_error.replace(code) == Some(code);
+                    if !repeated {
+                        tracing::warn!("kafka: {}", code);
+                    }
+                }
+                Some(Err(err)) => return Err(err.into()),
+                Some(Ok(message)) => {
+                    last_error = None;
+                    let payload = String::from_utf8_lossy(message.payload().unwrap_or_default());
+                    for line in payload.lines() {
+                        let Some(result) = parser.parse(line).transpose() else {
+                            continue;
+                        };
+                        if let Some(txn) = take_row(result, self.policy.on_error, &mut stats)? {
+                            let result = self.insert_read(txn, parser.refusal());
+                            self.check_spill()?;
+                            if let Err(err) = result {
+                                stats.reject(&txn, &err, parser.line());
+                                on_reject(&txn, &err)?;
+                            }
+                        }
+                    }
+                }
+            }
+            if last_snapshot.elapsed() >= source.snapshot_interval {
+                on_snapshot(self, &mut stats)?;
+                last_snapshot = Instant::now();
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::amount::Amount;
+use crate::bank::Client;
+use crate::outcome::TxnError;
+
+// Double-entry bookkeeping under each balance. Funds never appear in or vanish from an account:
+// every movement is a posting that debits one ledger account and credits another by the same
+// amount. Available and held are two of the accounts; the others are contra accounts standing
+// for where the balance's funds came from or went, so a deposit debits settlement and credits
+// available, a dispute debits available and credits held, and a chargeback debits held and
+// credits chargebacks. Each account is kept as its credits less its debits, which makes all of a
+// balance's accounts add up to zero after any number of postings. One whose accounts don't has had
+// them changed some other way, which is what Client::balanced is there to catch.
+//
+// A transfer or a conversion is a posting on each side, through the transfers or exchange
+// account, so those add up to zero across the bank rather than in each balance. The contra
+// accounts aren't saved with the balances: a bank loaded from a snapshot, a report or the
+// database starts each balance's ledger afresh, with what it holds posted from opening.
+//
+// Every posting is checked: one that would take either account, or the balance's total of
+// available and held, past what an Amount can hold is refused as TxnError::BalanceOverflow,
+// leaving both as they were, and so is the transaction making it. The contra accounts add up a
+// balance's history rather than what it holds, so they're the ones to overflow first.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub(crate) enum Account {
+    Available,
+    Held,
+    // funds deposited from, withdrawn to or refunded from outside the bank
+    Settlement,
+    Fees,
+    Interest,
+    // funds handed back to whoever disputed them
+    Chargebacks,
+    // funds sent to or received from other clients
+    Transfers,
+    // funds converted into or out of other currencies
+    Exchange,
+}
+
+// A balance's contra accounts
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub(crate) struct Ledger {
+    // what the balance held when its ledger was started
+    opening: Amount,
+    settlement: Amount,
+    fees: Amount,
+    interest: Amount,
+    chargebacks: Amount,
+    transfers: Amount,
+    exchange: Amount,
+}
+
+impl Ledger {
+    // The ledger of a balance that already holds total
+    pub(crate) fn opened(total: Amount) -> Ledger {
+        Ledger {
+            opening: -total,
+            ..Ledger::default()
+        }
+    }
+
+    // The contra accounts added up, in ten-thousandths, which is minus what the balance holds
+    // when it's balanced. Each account fits an Amount but partway sums of them needn't.
+    pub(crate) fn total(&self) -> i128 {
+        [
+            self.opening,
+            self.settlement,
+            self.fees,
+            self.interest,
+            self.chargebacks,
+            self.transfers,
+            self.exchange,
+        ]
+        .iter()
+        .map(|amount| i128::from(amount.units()))
+        .sum()
+    }
+
+    // Both ledgers' accounts added up, for merging two balances
+    pub(crate) fn checked_add(self, other: Ledger) -> Option<Ledger> {
+        Some(Ledger {
+            opening: self.opening.checked_add(other.opening)?,
+            settlement: self.settlement.checked_add(other.settlement)?,
+            fees: self.fees.checked_add(other.fees)?,
+            interest: self.interest.checked_add(other.interest)?,
+            chargebacks: self.chargebacks.checked_add(other.chargebacks)?,
+            transfers: self.transfers.checked_add(other.transfers)?,
+            exchange: self.exchange.checked_add(other.exchange)?,
+        })
+    }
+}
+
+impl Client {
+    // Moves amount out of debit into credit, in the balance in_currency is working on
+    pub(crate) fn post(
+        &mut self,
+        debit: Account,
+        credit: Account,
+        amount: Amount,
+    ) -> Result<(), TxnError> {
+        let debited = self.account(debit).checked_sub(amount);
+        let credited = self.account(credit).checked_add(amount);
+        let (Some(debited), Some(credited)) = (debited, credited) else {
+            return Err(TxnError::BalanceOverflow);
+        };
+        let (before_debit, before_credit) = (*self.account(debit), *self.account(credit));
+        *self.account(debit) = debited;
+        *self.account(credit) = credited;
+        if self.available.checked_add(self.held).is_none() {
+            *self.account(debit) = before_debit;
+            *self.account(credit) = before_credit;
+            return Err(TxnError::BalanceOverflow);
+        }
+        Ok(())
+    }
+
+    // Takes back a posting that was made, for when another one it goes with is refused
+    pub(crate) fn unpost(&mut self, debit: Account, credit: Account, amount: Amount) {
+        self.post(credit, debit, amount)
+            .expect("a posting can always be taken back");
+    }
+
+    fn account(&mut self, account: Account) -> &mut Amount {
+        let ledger = &mut self.ledger;
+        match account {
+            Account::Available => &mut self.available,
+            Account::Held => &mut self.held,
+            Account::Settlement => &mut ledger.settlement,
+            Account::Fees => &mut ledger.fees,
+            Account::Interest => &mut ledger.interest,
+            Account::Chargebacks => &mut ledger.chargebacks,
+            Account::Transfers => &mut ledger.transfers,
+            Account::Exchange => &mut ledger.exchange,
+        }
+    }
+
+    // Whether every balance's ledger adds up, as postings always leave it
+    pub(crate) fn balanced(&self) -> bool {
+        let balanced = |available: Amount, held: Amount, ledger: &Ledger| {
+            i128::from(available.units()) + i128::from(held.units()) + ledger.total() == 0
+        };
+        balanced(self.available, self.held, &self.ledger)
+            && self
+                .currencies
+                .values()
+                .all(|balance| balanced(balance.available, balance.held, &balance.ledger))
+    }
+
+    // Starts every balance's ledger afresh, for an account whose balances were set directly
+    pub(crate) fn open_ledger(&mut self) {
+        self.ledger = Ledger::opened(self.available + self.held);
+        for balance in self.currencies.values_mut() {
+            balance.ledger = Ledger::opened(balance.available + balance.held);
+        }
+    }
+}
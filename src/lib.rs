@@ -0,0 +1,99 @@
+pub mod amount;
+mod audit;
+mod avro;
+pub mod bank;
+pub mod binary;
+pub mod camt;
+pub mod checkpoint;
+mod columns;
+pub mod control;
+pub mod currency;
+pub mod date;
+mod error;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod follow;
+pub mod fx;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+mod imports;
+pub mod inputs;
+pub mod interest;
+mod invariants;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+mod ledger;
+pub mod limits;
+mod merge;
+mod metrics;
+mod nacha;
+pub mod observer;
+mod ofx;
+pub mod outcome;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod parquet;
+mod pending;
+pub mod policy;
+mod qif;
+pub mod reconcile;
+pub mod recurring;
+pub mod report;
+pub mod risk;
+pub mod rules;
+mod seen;
+#[cfg(feature = "server")]
+pub mod server;
+mod snappy;
+mod snapshot;
+pub mod source;
+mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod statement;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod summary;
+pub mod testing;
+mod thrift;
+mod transfer;
+pub mod velocity;
+mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use std::io;
+
+pub use crate::amount::{Amount, AmountStyle};
+pub use crate::bank::{
+    Bank, Client, ClientId, ClientRecord, ProcessResult, Transaction, TransactionType, TxId,
+};
+pub use crate::binary::BinaryWriter;
+pub use crate::control::ControlMismatch;
+pub use crate::currency::Currency;
+pub use crate::error::Error;
+pub use crate::events::Event;
+pub use crate::inputs::expand_inputs;
+pub use crate::observer::TxnObserver;
+pub use crate::outcome::{TxnError, TxnOutcome};
+pub use crate::policy::{
+    AccountOpeningPolicy, ControlPolicy, DisputePolicy, LockedPolicy, NegativeAmountPolicy,
+    OnError, Policy, PrecisionPolicy, RedisputePolicy, TxIdPolicy, WithdrawalPolicy,
+};
+pub use crate::report::{OutputFormat, RejectsWriter};
+pub use crate::risk::RiskScorer;
+pub use crate::rules::{RuleResult, ValidationRule};
+pub use crate::source::{
+    csv_row, CsvOptions, Duplicate, InputFormat, LineParser, SourceStats, TransactionSource,
+};
+pub use crate::velocity::VelocityRule;
+
+// Read CSV transactions from any reader and apply them, in order, to a fresh Bank
+pub fn process_transactions<R: io::Read>(reader: R) -> Result<Bank, Error> {
+    let mut bank = Bank::new();
+    bank.process_source(TransactionSource::csv(reader))?;
+    Ok(bank)
+}
@@ -0,0 +1,149 @@
+use crate::amount::Amount;
+use crate::bank::ClientId;
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::snapshot::invalid;
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, io, path::Path};
+
+// Caps on what a client can withdraw: in any one withdrawal, and in all of its withdrawals put
+// together. A withdrawal over either is refused as limit_exceeded, before its funds are looked
+// at, so it's refused whatever the WithdrawalPolicy. Each currency's withdrawals count towards a
+// total of their own.
+//
+// The bank-wide caps apply to every client; a CSV file with client,max_withdrawal,max_total
+// columns sets some clients' own, where an empty column keeps the bank-wide one.
+#[derive(Debug, Default, Clone)]
+pub struct Limits {
+    pub default: Limit,
+    pub clients: HashMap<ClientId, Limit>,
+}
+
+// None for no cap
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct Limit {
+    pub max_withdrawal: Option<Amount>,
+    pub max_total: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+struct LimitRow {
+    client: ClientId,
+    #[serde(default)]
+    max_withdrawal: Option<Amount>,
+    #[serde(default)]
+    max_total: Option<Amount>,
+}
+
+impl Limits {
+    // The per-client caps from r, on top of default
+    pub fn from_reader<R: io::Read>(r: R, default: Limit) -> Result<Limits, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(r);
+        let mut limits = Limits {
+            default,
+            clients: HashMap::new(),
+        };
+        for row in rdr.deserialize() {
+            let row: LimitRow = row?;
+            let limit = Limit {
+                max_withdrawal: row.max_withdrawal.or(default.max_withdrawal),
+                max_total: row.max_total.or(default.max_total),
+            };
+            if limits.clients.insert(row.client, limit).is_some() {
+                return Err(invalid(format!("client {} is listed twice", row.client)));
+            }
+        }
+        Ok(limits)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, default: Limit) -> Result<Limits, Error> {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| Limits::from_reader(file, default))
+            .map_err(|err| err.in_file(path))
+    }
+
+    pub fn get(&self, client: ClientId) -> Limit {
+        self.clients.get(&client).copied().unwrap_or(self.default)
+    }
+}
+
+impl Limit {
+    // Whether a withdrawal of amount is within the caps, after withdrawn already was
+    pub(crate) fn check(&self, amount: Amount, withdrawn: Amount) -> Result<(), TxnError> {
+        let over_single = self.max_withdrawal.is_some_and(|max| amount > max);
+        // a total past what an amount can hold is over any cap
+        let over_total = self.max_total.is_some_and(|max| {
+            withdrawn
+                .checked_add(amount)
+                .is_none_or(|total| total > max)
+        });
+        if over_single || over_total {
+            Err(TxnError::LimitExceeded)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(units: i64) -> Amount {
+        Amount::from_units(units)
+    }
+
+    #[test]
+    fn withdrawals_are_checked_against_both_caps() {
+        let limit = Limit {
+            max_withdrawal: Some(amount(100)),
+            max_total: Some(amount(250)),
+        };
+        assert_eq!(limit.check(amount(100), amount(150)), Ok(()));
+        assert_eq!(
+            limit.check(amount(101), Amount::ZERO),
+            Err(TxnError::LimitExceeded)
+        );
+        assert_eq!(
+            limit.check(amount(100), amount(151)),
+            Err(TxnError::LimitExceeded)
+        );
+        assert_eq!(
+            Limit::default().check(amount(i64::MAX), amount(i64::MAX)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn a_total_that_overflows_is_over_the_cap() {
+        let limit = Limit {
+            max_withdrawal: None,
+            max_total: Some(amount(i64::MAX)),
+        };
+        assert_eq!(limit.check(amount(1), amount(i64::MAX - 1)), Ok(()));
+        assert_eq!(
+            limit.check(amount(2), amount(i64::MAX - 1)),
+            Err(TxnError::LimitExceeded)
+        );
+    }
+
+    #[test]
+    fn clients_listed_get_their_own_caps() {
+        let default = Limit {
+            max_withdrawal: Some(amount(10)),
+            max_total: None,
+        };
+        let csv = "client,max_withdrawal,max_total\n1,,5\n2,3,\n";
+        let limits = Limits::from_reader(csv.as_bytes(), default).unwrap();
+        assert_eq!(limits.get(1).max_withdrawal, Some(amount(10)));
+        assert_eq!(limits.get(1).max_total, Some(amount(50_000)));
+        assert_eq!(limits.get(2).max_withdrawal, Some(amount(30_000)));
+        assert_eq!(limits.get(3), default);
+        let twice = "client,max_withdrawal,max_total\n1,,5\n1,,6\n";
+        assert!(Limits::from_reader(twice.as_bytes(), default).is_err());
+    }
+}
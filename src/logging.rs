@@ -0,0 +1,180 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::{self, Write as _},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span, Event, Level, Metadata, Subscriber,
+};
+
+// Diagnostics go to stderr, one line per event, so stdout only ever has the report on it. A line
+// is the level, the spans it happened in (e.g. the input file) and the message with its fields:
+//
+//     warning: input{path=a.csv}: unknown_client type=deposit client=7 tx=12
+//
+// The default shows errors, warnings and progress, --quiet only errors, -v adds every refused
+// transaction and -vv every accepted one.
+pub fn init(verbose: u8, quiet: bool) {
+    let max = match (quiet, verbose) {
+        (true, _) => Level::ERROR,
+        (false, 0) => Level::INFO,
+        (false, 1) => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let log = StderrLog {
+        max,
+        spans: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+    };
+    // only fails if something already set one, in which case that one is kept
+    let _ = tracing::subscriber::set_global_default(log);
+}
+
+struct StderrLog {
+    max: Level,
+    // each open span's text and how many handles to it there are
+    spans: Mutex<HashMap<u64, (String, usize)>>,
+    next_id: AtomicU64,
+}
+
+thread_local! {
+    // the spans entered on this thread, innermost last
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+// Fields as ` key=value`, with the message (if any) kept apart
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.rest, " {}={}", field.name(), value);
+        }
+    }
+}
+
+impl StderrLog {
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, (String, usize)>> {
+        // a panic while holding it leaves nothing inconsistent, only a half-written span text
+        self.spans
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Subscriber for StderrLog {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.max
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::from_level(self.max))
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+        let text = format!("{}{{{}}}", span.metadata().name(), fields.rest.trim_start());
+        self.spans().insert(id, (text, 1));
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Some((text, _)) = self.spans().get_mut(&span.into_u64()) {
+            text.pop();
+            if !text.ends_with('{') {
+                text.push(' ');
+            }
+            text.push_str(fields.rest.trim_start());
+            text.push('}');
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let level = match *event.metadata().level() {
+            Level::ERROR => "error",
+            Level::WARN => "warning",
+            Level::INFO => "info",
+            Level::DEBUG => "debug",
+            Level::TRACE => "trace",
+        };
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut line = format!("{}: ", level);
+        ENTERED.with(|entered| {
+            let spans = self.spans();
+            for id in entered.borrow().iter() {
+                if let Some((text, _)) = spans.get(id) {
+                    line.push_str(text);
+                    line.push_str(": ");
+                }
+            }
+        });
+        line.push_str(&fields.message);
+        line.push_str(&fields.rest);
+        line.push('\n');
+        let _ = io::stderr().lock().write_all(line.as_bytes());
+    }
+
+    fn enter(&self, span: &span::Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &span::Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(at) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(at);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        if let Some((_, handles)) = self.spans().get_mut(&span.into_u64()) {
+            *handles += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: span::Id) -> bool {
+        let mut spans = self.spans();
+        let id = span.into_u64();
+        match spans.get_mut(&id) {
+            Some((_, handles)) if *handles > 1 => {
+                *handles -= 1;
+                false
+            }
+            Some(_) => {
+                spans.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -1,33 +1,928 @@
-mod bank;
+mod cli;
+mod config;
+mod exit;
+mod logging;
+mod progress;
 
-use std::{env, error::Error, process};
-use crate::bank::{Bank, Transaction};
+use crate::cli::{
+    CheckpointArgs, Command, ConvertArgs, DiffArgs, EngineArgs, GenerateArgs, InputArgs,
+    OutputArgs, ProcessArgs, QueryArgs, ReconcileArgs, ReportCommand, StatementArgs,
+    StatementFormat, ValidateArgs,
+};
+use crate::progress::Progress;
+use std::{
+    cell::RefCell,
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info, info_span, warn};
+use transactions::{
+    camt::Camt053, checkpoint::Position, fx::Rates, summary::Summary, Bank, BinaryWriter,
+    ClientRecord, ControlPolicy, InputFormat, Policy, RejectsWriter, SourceStats, Transaction,
+    TransactionSource, TxnError,
+};
 
-fn read_transactions(filename: &String) -> Result<(), Box<dyn Error>> {
-    let mut bank = Bank::new();
-    let mut rdr = csv::ReaderBuilder::new().trim(csv::Trim::All).flexible(true).from_path(filename)?;
-    for result in rdr.deserialize() {
-        let transaction: Transaction = result?;
-        bank.insert_txn(transaction);
+fn input_paths(input: &InputArgs) -> Result<Vec<PathBuf>, transactions::Error> {
+    if input.files.is_empty() {
+        Ok(vec![PathBuf::from("-")])
+    } else {
+        transactions::expand_inputs(&input.files)
+    }
+}
+
+// An input file (or stdin for "-"), counted towards the progress line if there is one
+fn open_source(
+    path: &Path,
+    input: &InputArgs,
+    progress: Option<&Progress>,
+) -> io::Result<TransactionSource<Box<dyn io::Read>>> {
+    let reader = match progress {
+        None => transactions::inputs::open_input(path)?,
+        Some(progress) => {
+            let raw: Box<dyn io::Read> = if path == Path::new("-") {
+                Box::new(progress.raw(io::stdin().lock()))
+            } else {
+                Box::new(progress.raw(File::open(path)?))
+            };
+            Box::new(progress.lines(transactions::inputs::decompress(raw)?))
+        }
+    };
+    Ok(match input.input_format {
+        InputFormat::Csv => TransactionSource::csv_with(reader, input.csv_options()),
+        InputFormat::Json => TransactionSource::json(reader),
+        InputFormat::Parquet => TransactionSource::parquet(reader),
+        InputFormat::Avro => TransactionSource::avro(reader),
+        InputFormat::Binary => TransactionSource::binary(reader),
+        InputFormat::Nacha => TransactionSource::nacha(reader),
+        InputFormat::Ofx => TransactionSource::ofx(reader, input.account_client),
+        InputFormat::Qif => TransactionSource::qif(reader, input.account_client),
+    })
+}
+
+#[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+fn process_file(
+    bank: &mut Bank,
+    source: TransactionSource<Box<dyn io::Read>>,
+    engine: &EngineArgs,
+    rejects: &mut Option<RejectsWriter<File>>,
+) -> Result<SourceStats, transactions::Error> {
+    let on_reject = |txn: &Transaction, err: &TxnError| reject(rejects, txn, err);
+    #[cfg(feature = "parallel")]
+    if engine.parallel {
+        return bank.process_source_parallel(source, on_reject);
+    }
+    bank.process_source_with(source, on_reject)
+}
+
+// Resuming carries on from the rejects written up to the checkpoint; anything after it is
+// written again
+fn open_rejects(
+    engine: &EngineArgs,
+    resume_at: Option<u64>,
+) -> Result<Option<RejectsWriter<File>>, transactions::Error> {
+    let Some(path) = &engine.rejects_file else {
+        return Ok(None);
+    };
+    match resume_at {
+        Some(len) => {
+            let mut file = OpenOptions::new().write(true).open(path)?;
+            file.set_len(len)?;
+            file.seek(SeekFrom::End(0))?;
+            Ok(Some(RejectsWriter::continuing(file)))
+        }
+        None => Ok(Some(RejectsWriter::new(File::create(path)?)?)),
+    }
+}
+
+// A server appends to its audit log and outcomes file, as it carries on from its write-ahead
+// log; anything else starts them over
+fn open_audit_log(engine: &EngineArgs, bank: &mut Bank, append: bool) -> io::Result<()> {
+    let open = |path| {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+    };
+    if let Some(path) = &engine.audit_log {
+        bank.audit_to(open(path)?);
+    }
+    if let Some(path) = &engine.outcomes_file {
+        let file = open(path)?;
+        // one carried on from already has its header
+        let header = file.metadata()?.len() == 0;
+        bank.outcomes_to(file, header);
     }
-    println!("{}", bank.to_string());
     Ok(())
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        let filename = args.get(1).unwrap();
-        read_file(filename);
+// Record a refused transaction in the rejects file. One for a client with no account is also
+// warned about, as it usually means the feed is missing that client's earlier rows.
+fn reject(
+    rejects: &mut Option<RejectsWriter<File>>,
+    txn: &Transaction,
+    err: &TxnError,
+) -> Result<(), transactions::Error> {
+    if *err == TxnError::UnknownClient {
+        warn!(r#type = %txn.tx_type, client = txn.client, tx = txn.tx, "unknown_client");
+    }
+    match rejects {
+        Some(rejects) => rejects.write(txn, err),
+        None => Ok(()),
+    }
+}
+
+// The end of run summary of rows that weren't applied
+fn report_totals(stats: &SourceStats) {
+    if stats.skipped > 0 {
+        warn!("skipped {} malformed rows", stats.skipped);
+    }
+    if stats.unknown_client > 0 {
+        warn!(
+            "dropped {} transactions for clients with no account",
+            stats.unknown_client
+        );
+    }
+    if !stats.duplicates.is_empty() {
+        warn!(
+            "dropped {} transactions reusing an earlier transaction id",
+            stats.duplicates.len()
+        );
+    }
+    if stats.pending > 0 {
+        info!(
+            "queued {} transactions dated after --as-of as pending",
+            stats.pending
+        );
+    }
+}
+
+// Called in the file's span, which says which file it was
+fn report_file(stats: &SourceStats) {
+    for err in &stats.errors {
+        warn!("skipped {}", err);
+    }
+    for mismatch in &stats.control_mismatches {
+        warn!("{}", mismatch);
+    }
+    for dup in &stats.duplicates {
+        warn!(
+            line = dup.line,
+            client = dup.client,
+            tx = dup.tx,
+            "duplicate transaction id"
+        );
+    }
+}
+
+// Applies the rows of paths[index] from `skip` on, saving a checkpoint every so often
+fn process_file_checkpointed(
+    bank: &mut Bank,
+    paths: &[PathBuf],
+    (index, skip): (usize, u64),
+    mut source: TransactionSource<Box<dyn io::Read>>,
+    checkpoint: &CheckpointArgs,
+    save_to: &Path,
+    rejects: &mut Option<RejectsWriter<File>>,
+) -> Result<SourceStats, transactions::Error> {
+    let path = &paths[index];
+    source.skip_rows(skip)?;
+    // both callbacks write to the rejects file: on_checkpoint flushes it to record its length
+    let rejects = RefCell::new(rejects);
+    let on_reject = |txn: &Transaction, err: &TxnError| reject(*rejects.borrow_mut(), txn, err);
+    let on_checkpoint = |bank: &Bank, read: u64| {
+        let rejects_len = match &mut **rejects.borrow_mut() {
+            Some(rejects) => {
+                rejects.flush()?;
+                Some(rejects.get_ref().metadata()?.len())
+            }
+            None => None,
+        };
+        let position = Position {
+            file: index,
+            path: path.clone(),
+            rows: skip + read,
+            rejects_len,
+        };
+        bank.save_checkpoint(save_to, &position)
+    };
+    bank.process_source_checkpointed(
+        source,
+        checkpoint.checkpoint_every,
+        on_reject,
+        on_checkpoint,
+    )
+}
+
+// The bank and position to carry on from, if resuming from a checkpoint that exists
+fn resume_from(
+    checkpoint: Option<&CheckpointArgs>,
+) -> Result<Option<(Bank, Position)>, transactions::Error> {
+    let Some(path) = checkpoint
+        .filter(|args| args.resume)
+        .and_then(|args| args.checkpoint.as_ref())
+    else {
+        return Ok(None);
+    };
+    if !path.exists() {
+        info!(
+            "no checkpoint at {}, starting from the beginning",
+            path.display()
+        );
+        return Ok(None);
+    }
+    Bank::load_checkpoint(path).map(Some)
+}
+
+fn read_transactions(
+    input: &InputArgs,
+    engine: &EngineArgs,
+    checkpoint: Option<&CheckpointArgs>,
+) -> Result<(Bank, SourceStats), transactions::Error> {
+    let paths = input_paths(input)?;
+    let (mut bank, start) = match resume_from(checkpoint)? {
+        Some((mut bank, position)) => {
+            position.check_inputs(&paths)?;
+            bank.set_policy(engine.policy()?);
+            if let Some(max) = engine.max_txns_in_memory {
+                bank.spill_to_disk(max)?;
+            }
+            if engine.check_invariants {
+                bank.check_invariants();
+            }
+            (bank, Some(position))
+        }
+        None => (engine.bank()?, None),
+    };
+    open_audit_log(engine, &mut bank, false)?;
+    let mut rejects = open_rejects(engine, start.as_ref().and_then(|start| start.rejects_len))?;
+    let save_to = checkpoint.and_then(|args| args.checkpoint.as_deref());
+    let progress = input.progress.then(|| Progress::start(&paths));
+    // every file feeds the same bank, so later files see the state left by earlier ones
+    let mut stats = SourceStats::default();
+    // what earlier runs queued comes first; a resumed run already applied it before the checkpoint
+    if start.is_none() {
+        stats = bank.apply_due(|txn, err| reject(&mut rejects, txn, err))?;
+    }
+    for (index, path) in paths.iter().enumerate() {
+        let _span = info_span!("input", path = %path.display()).entered();
+        let skip = match &start {
+            Some(start) if index < start.file => continue,
+            Some(start) if index == start.file => start.rows,
+            _ => 0,
+        };
+        let source = open_source(path, input, progress.as_ref()).map_err(transactions::Error::from);
+        let file_stats = source
+            .and_then(|source| match (checkpoint, save_to) {
+                (Some(args), Some(save_to)) => process_file_checkpointed(
+                    &mut bank,
+                    &paths,
+                    (index, skip),
+                    source,
+                    args,
+                    save_to,
+                    &mut rejects,
+                ),
+                _ => process_file(&mut bank, source, engine, &mut rejects),
+            })
+            .map_err(|err| err.in_file(path))?;
+        report_file(&file_stats);
+        if let Some(mismatch) = file_stats.control_mismatches.first() {
+            if input.control_totals == ControlPolicy::Fail {
+                return Err(transactions::Error::Control(*mismatch).in_file(path));
+            }
+        }
+        stats.merge(file_stats);
+    }
+    drop(progress);
+    report_totals(&stats);
+    if let Some(rejects) = &mut rejects {
+        rejects.flush()?;
+    }
+    bank.flush_audit()?;
+    Ok((bank, stats))
+}
+
+fn open_output(output: &OutputArgs) -> io::Result<Box<dyn Write>> {
+    match &output.output {
+        Some(path) => Ok(Box::new(File::create(path)?)),
+        None => Ok(Box::new(io::stdout().lock())),
+    }
+}
+
+fn write_report(bank: &Bank, output: &OutputArgs) -> Result<(), transactions::Error> {
+    let records: Box<dyn Iterator<Item = ClientRecord>> = if output.sorted {
+        Box::new(bank.sorted_records().into_iter())
+    } else {
+        Box::new(bank.records())
+    };
+    write_records(records, &bank.policy().rates, output)
+}
+
+fn write_records(
+    records: impl Iterator<Item = ClientRecord>,
+    rates: &Rates,
+    output: &OutputArgs,
+) -> Result<(), transactions::Error> {
+    let clients = output.clients.as_ref();
+    let records = records.filter(|record| clients.is_none_or(|ids| ids.contains(record.client)));
+    let records: Box<dyn Iterator<Item = ClientRecord>> = match output.report_currency {
+        Some(base) => {
+            Box::new(transactions::fx::convert_records(records, rates, base)?.into_iter())
+        }
+        None => Box::new(records),
+    };
+    transactions::report::write_records_styled(
+        records,
+        open_output(output)?,
+        output.output_format,
+        output.amount_style(),
+    )
+}
+
+fn write_summary(
+    stats: &SourceStats,
+    records: impl Iterator<Item = ClientRecord>,
+    started: Instant,
+    output: &OutputArgs,
+) -> Result<(), transactions::Error> {
+    let Some(path) = &output.summary else {
+        return Ok(());
+    };
+    let summary = Summary::new(stats, records, started.elapsed());
+    if path == Path::new("-") {
+        summary.write(io::stderr().lock())
+    } else {
+        summary.write(File::create(path)?)
+    }
+}
+
+// The transactions still queued, as CSV input a later run can be given
+fn write_pending(bank: &Bank, path: &Path) -> io::Result<()> {
+    let mut out = io::BufWriter::new(File::create(path)?);
+    writeln!(
+        out,
+        "type,client,tx,amount,to,timestamp,currency,to_currency,effective_date"
+    )?;
+    for txn in bank.pending() {
+        writeln!(out, "{}", transactions::csv_row(txn))?;
+    }
+    out.flush()
+}
+
+// What a --dry-run reports instead of the accounts, as `name: value` lines like --summary
+fn write_dry_run(stats: &SourceStats, output: &OutputArgs) -> Result<(), transactions::Error> {
+    let mut out = open_output(output)?;
+    writeln!(out, "rows read: {}", stats.rows)?;
+    let accepted = stats.rows - stats.rejected - stats.pending;
+    writeln!(out, "would be accepted: {}", accepted)?;
+    writeln!(out, "would be rejected: {}", stats.rejected)?;
+    for (reason, count) in &stats.rejected_by {
+        writeln!(out, "  {}: {}", reason, count)?;
+    }
+    writeln!(out, "would be pending: {}", stats.pending)?;
+    writeln!(out, "malformed rows skipped: {}", stats.skipped)?;
+    Ok(())
+}
+
+fn run_process(args: &ProcessArgs) -> Result<i32, Box<dyn Error>> {
+    #[cfg(feature = "kafka")]
+    if args.kafka.source == cli::Source::Kafka {
+        return run_kafka(args).map(|()| exit::SUCCESS);
+    }
+    if args.follow.follow {
+        return run_follow(args).map(|()| exit::SUCCESS);
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.store.database {
+        return run_sqlite(args, path);
+    }
+    let started = Instant::now();
+    let (bank, stats) = read_transactions(&args.input, &args.engine, Some(&args.checkpoint))?;
+    if args.output.dry_run {
+        write_dry_run(&stats, &args.output)?;
+        return Ok(exit::of_stats(&stats, args.engine.partial_on_rejects));
+    }
+    write_report(&bank, &args.output)?;
+    write_summary(&stats, bank.records(), started, &args.output)?;
+    if let Some(path) = &args.output.pending_file {
+        write_pending(&bank, path)?;
+    }
+    if let Some(path) = &args.output.snapshot_out {
+        bank.save_snapshot(path)?;
+    }
+    // the run is complete, so there's nothing left to resume
+    if let Some(path) = &args.checkpoint.checkpoint {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(exit::of_stats(&stats, args.engine.partial_on_rejects))
+}
+
+// Consume until the consumer fails, rewriting the report (or appending it to stdout) on every
+// snapshot
+#[cfg(feature = "kafka")]
+fn run_kafka(args: &ProcessArgs) -> Result<(), Box<dyn Error>> {
+    use std::time::Duration;
+    use transactions::kafka::KafkaSource;
+
+    check_line_format(args.input.input_format, "--source kafka");
+    let source = KafkaSource {
+        brokers: args.kafka.kafka_brokers.clone(),
+        topic: args.kafka.kafka_topic.clone().unwrap_or_default(),
+        group_id: args.kafka.kafka_group.clone(),
+        format: args.input.input_format,
+        snapshot_interval: Duration::from_secs(args.kafka.snapshot_interval),
+    };
+    let mut rejects = open_rejects(&args.engine, None)?;
+    let mut bank = args.engine.bank()?;
+    open_audit_log(&args.engine, &mut bank, false)?;
+    let mut reported = SourceStats::default();
+    let on_snapshot = |bank: &Bank, stats: &mut SourceStats| {
+        report_running(&source.topic, stats, &mut reported);
+        write_running(bank, &args.output)
+    };
+    // rejects are written straight through so they survive the consumer being killed
+    let on_reject = |txn: &Transaction, err: &TxnError| {
+        reject(&mut rejects, txn, err)?;
+        match &mut rejects {
+            Some(rejects) => rejects.flush(),
+            None => Ok(()),
+        }
+    };
+    bank.consume_kafka(&source, on_reject, on_snapshot)?;
+    Ok(())
+}
+
+// Sources read a line or message at a time can't take a format that needs the whole file
+fn check_line_format(format: InputFormat, source: &str) {
+    use clap::{error::ErrorKind, CommandFactory};
+
+    if !format.is_line_based() {
+        let msg = format!("--input-format {} can't be used with {}", format, source);
+        cli::Cli::command()
+            .error(ErrorKind::ArgumentConflict, msg)
+            .exit()
+    }
+}
+
+// Follow the file until reading it fails, rewriting the report (or appending it to stdout) once
+// the rows already in it are applied and then as more arrive
+fn run_follow(args: &ProcessArgs) -> Result<(), Box<dyn Error>> {
+    use clap::{error::ErrorKind, CommandFactory};
+    use std::time::Duration;
+    use transactions::follow::FollowSource;
+
+    let path = match args.input.files.as_slice() {
+        [path] if path != "-" => PathBuf::from(path),
+        _ => cli::Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--follow needs exactly one input file",
+            )
+            .exit(),
+    };
+    check_line_format(args.input.input_format, "--follow");
+    let source = FollowSource {
+        path: path.clone(),
+        format: args.input.input_format,
+        csv: args.input.csv_options(),
+        report_interval: Duration::from_secs(args.follow.report_interval),
+    };
+    let mut rejects = open_rejects(&args.engine, None)?;
+    let mut bank = args.engine.bank()?;
+    open_audit_log(&args.engine, &mut bank, false)?;
+    let name = path.display().to_string();
+    let mut reported = SourceStats::default();
+    let on_report = |bank: &Bank, stats: &mut SourceStats| {
+        report_running(&name, stats, &mut reported);
+        write_running(bank, &args.output)
+    };
+    // rejects are written straight through so they survive the run being killed
+    let on_reject = |txn: &Transaction, err: &TxnError| {
+        reject(&mut rejects, txn, err)?;
+        match &mut rejects {
+            Some(rejects) => rejects.flush(),
+            None => Ok(()),
+        }
+    };
+    bank.follow_file(&source, on_reject, on_report)
+        .map_err(|err| err.in_file(&path))?;
+    Ok(())
+}
+
+// What a long-running source skipped or dropped since the last time, with reported holding the
+// counts as of then
+fn report_running(source: &str, stats: &mut SourceStats, reported: &mut SourceStats) {
+    for err in stats.errors.drain(..) {
+        warn!("skipped {}: {}", source, err);
+    }
+    if stats.skipped > reported.skipped {
+        warn!(
+            "skipped {} malformed rows",
+            stats.skipped - reported.skipped
+        );
+        reported.skipped = stats.skipped;
+    }
+    if stats.unknown_client > reported.unknown_client {
+        warn!(
+            "dropped {} transactions for clients with no account",
+            stats.unknown_client - reported.unknown_client
+        );
+        reported.unknown_client = stats.unknown_client;
+    }
+}
+
+// The report, and the snapshot if there's to be one, of a run that's still going
+fn write_running(bank: &Bank, output: &OutputArgs) -> Result<(), transactions::Error> {
+    write_report(bank, output)?;
+    match &output.snapshot_out {
+        Some(path) => bank.save_snapshot(path),
+        None => Ok(()),
+    }
+}
+
+// Same as the in-memory run, with the accounts kept in (and carried on from) a database file
+#[cfg(feature = "sqlite")]
+fn run_sqlite(args: &ProcessArgs, database: &Path) -> Result<i32, Box<dyn Error>> {
+    use transactions::sqlite::SqliteBank;
+
+    let started = Instant::now();
+    let mut bank = SqliteBank::open(database, args.engine.policy()?)?;
+    if args.output.dry_run {
+        bank.dry_run()?;
+    }
+    let mut rejects = open_rejects(&args.engine, None)?;
+    let mut stats = SourceStats::default();
+    let paths = input_paths(&args.input)?;
+    let progress = args.input.progress.then(|| Progress::start(&paths));
+    for path in &paths {
+        let _span = info_span!("input", path = %path.display()).entered();
+        let source =
+            open_source(path, &args.input, progress.as_ref()).map_err(transactions::Error::from);
+        let file_stats = source
+            .and_then(|source| {
+                bank.process_source_with(source, |txn, err| reject(&mut rejects, txn, err))
+            })
+            .map_err(|err| err.in_file(path))?;
+        report_file(&file_stats);
+        stats.merge(file_stats);
+    }
+    drop(progress);
+    report_totals(&stats);
+    if let Some(rejects) = &mut rejects {
+        rejects.flush()?;
+    }
+    if args.output.dry_run {
+        write_dry_run(&stats, &args.output)?;
+        return Ok(exit::of_stats(&stats, args.engine.partial_on_rejects));
+    }
+    write_records(
+        bank.records()?.into_iter(),
+        &bank.policy().rates,
+        &args.output,
+    )?;
+    write_summary(&stats, bank.records()?.into_iter(), started, &args.output)?;
+    Ok(exit::of_stats(&stats, args.engine.partial_on_rejects))
+}
+
+// Built from a sequential in-memory run over the inputs, as `process` would do it
+fn run_statement(args: &StatementArgs) -> Result<i32, Box<dyn Error>> {
+    use clap::{error::ErrorKind, CommandFactory};
+
+    if args.format == StatementFormat::Csv && args.client.is_none() {
+        cli::Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "a csv statement is of one client, given with --client",
+            )
+            .exit()
+    }
+    let mut bank = args.engine.bank()?;
+    open_audit_log(&args.engine, &mut bank, false)?;
+    let mut rejects = open_rejects(&args.engine, None)?;
+    let mut stats = SourceStats::default();
+    let mut lines = Vec::new();
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+    let mut camt = Camt053::open(&bank, created);
+    if let Some(currency) = args.currency {
+        camt.unlabelled = currency;
+    }
+    let paths = input_paths(&args.input)?;
+    let progress = args.input.progress.then(|| Progress::start(&paths));
+    for path in &paths {
+        let _span = info_span!("input", path = %path.display()).entered();
+        let source =
+            open_source(path, &args.input, progress.as_ref()).map_err(transactions::Error::from);
+        let (file_stats, file_lines) = source
+            .and_then(|source| {
+                let on_reject = |txn: &Transaction, err: &TxnError| reject(&mut rejects, txn, err);
+                match args.client {
+                    Some(client) => bank.process_source_statement(source, client, on_reject),
+                    None => bank.process_source_statements(source, on_reject),
+                }
+            })
+            .map_err(|err| err.in_file(path))?;
+        report_file(&file_stats);
+        stats.merge(file_stats);
+        lines.extend(file_lines);
+    }
+    drop(progress);
+    report_totals(&stats);
+    if let Some(rejects) = &mut rejects {
+        rejects.flush()?;
+    }
+    bank.flush_audit()?;
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    match args.format {
+        StatementFormat::Csv => transactions::statement::write_statement(&lines, out)?,
+        StatementFormat::Camt053 => camt.write(&bank, &lines, out)?,
+    }
+    Ok(exit::of_stats(&stats, args.engine.partial_on_rejects))
+}
+
+// The client's records (one per currency) from the whole bank, so transfers to and from other
+// clients are accounted for
+fn run_query(args: &QueryArgs) -> Result<i32, Box<dyn Error>> {
+    let (bank, stats) = if args.input.files.is_empty() && args.engine.snapshot_in.is_some() {
+        (args.engine.bank()?, SourceStats::default())
     } else {
-        println!("No file input parameter");
-        process::exit(1);
+        read_transactions(&args.input, &args.engine, None)?
+    };
+    let records: Vec<ClientRecord> = bank
+        .sorted_records()
+        .into_iter()
+        .filter(|record| record.client == args.client)
+        .collect();
+    if records.is_empty() {
+        return Err(format!("client {} has no account", args.client).into());
     }
+    transactions::report::write_records(
+        records.into_iter(),
+        io::stdout().lock(),
+        args.output_format,
+    )?;
+    Ok(exit::of_stats(&stats, args.engine.partial_on_rejects))
+}
+
+fn run_validate(args: &ValidateArgs) -> Result<i32, Box<dyn Error>> {
+    let (bank, stats) = read_transactions(&args.input, &args.engine, None)?;
+    println!("ok: {} clients", bank.records().count());
+    Ok(exit::of_stats(&stats, args.engine.partial_on_rejects))
 }
 
-fn read_file(filename: &String) {
-    if let Err(err) = read_transactions(filename) {
-        println!("error reading transactions: {}", err);
-        process::exit(1);
+// Where convert writes: the binary format, or CSV under the standard header
+enum Converted {
+    Binary(BinaryWriter<Box<dyn Write>>),
+    Csv(io::BufWriter<Box<dyn Write>>),
+}
+
+impl Converted {
+    fn write(&mut self, txn: &Transaction) -> io::Result<()> {
+        match self {
+            Converted::Binary(out) => out.write(txn),
+            Converted::Csv(out) => writeln!(out, "{}", transactions::csv_row(txn)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Converted::Binary(out) => out.flush(),
+            Converted::Csv(out) => out.flush(),
+        }
+    }
+}
+
+fn run_convert(args: &ConvertArgs) -> Result<i32, Box<dyn Error>> {
+    let paths = input_paths(&args.input)?;
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut out = match args.to {
+        InputFormat::Binary => Converted::Binary(BinaryWriter::new(out)?),
+        _ => {
+            let mut out = io::BufWriter::new(out);
+            writeln!(
+                out,
+                "type,client,tx,amount,to,timestamp,currency,to_currency,effective_date"
+            )?;
+            Converted::Csv(out)
+        }
+    };
+    let progress = args.input.progress.then(|| Progress::start(&paths));
+    // converting applies no transactions, so the only parts of the policy it reads are how to
+    // handle a malformed row and an over-precise amount
+    let policy = Policy {
+        on_error: args.on_error,
+        ..Policy::default()
+    };
+    let mut stats = SourceStats::default();
+    for path in &paths {
+        let _span = info_span!("input", path = %path.display()).entered();
+        let mut source = open_source(path, &args.input, progress.as_ref())
+            .map_err(|err| transactions::Error::from(err).in_file(path))?;
+        let mut file_stats = SourceStats::default();
+        while let Some(txn) = source
+            .next_txn(&policy, &mut file_stats)
+            .map_err(|err| err.in_file(path))?
+        {
+            out.write(&txn)?;
+        }
+        report_file(&file_stats);
+        stats.merge(file_stats);
+    }
+    drop(progress);
+    out.flush()?;
+    report_totals(&stats);
+    info!("converted {} transactions", stats.rows);
+    Ok(exit::of_stats(&stats, false))
+}
+
+fn run_generate(args: &GenerateArgs) -> Result<i32, Box<dyn Error>> {
+    use clap::{error::ErrorKind, CommandFactory};
+    use transactions::testing::{
+        Deposits, Disputes, Duplicates, Mix, Settlements, Stream, Withdrawals,
+    };
+
+    // the rates as weights out of a million, deposits making up the rest
+    let weight = |share: f64| (share * 1e6).round() as u32;
+    let disputes = weight(args.dispute_rate);
+    let others = weight(args.withdrawal_rate) + 2 * disputes + weight(args.duplicate_rate);
+    let Some(deposits) = 1_000_000_u32.checked_sub(others) else {
+        cli::Cli::command()
+            .error(
+                ErrorKind::ValueValidation,
+                "--withdrawal-rate, twice --dispute-rate and --duplicate-rate add up to more than 1",
+            )
+            .exit()
+    };
+    let mix = Mix::new()
+        .with(deposits, Deposits::new(args.clients))
+        .with(weight(args.withdrawal_rate), Withdrawals::new(args.clients))
+        .with(disputes, Disputes)
+        .with(
+            disputes,
+            Settlements {
+                chargeback_rate: args.chargeback_rate,
+            },
+        )
+        .with(weight(args.duplicate_rate), Duplicates);
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut out = io::BufWriter::new(out);
+    writeln!(out, "type,client,tx,amount")?;
+    for txn in Stream::new(args.seed, mix).take(args.rows as usize) {
+        writeln!(out, "{}", transactions::csv_row(&txn))?;
+    }
+    out.flush()?;
+    info!("generated {} transactions", args.rows);
+    Ok(exit::SUCCESS)
+}
+
+fn run_reconcile(args: &ReconcileArgs) -> Result<i32, Box<dyn Error>> {
+    use transactions::reconcile::{reconcile, write_mismatches};
+
+    let a = Bank::load_accounts(&args.a)?.sorted_records();
+    let b = Bank::load_accounts(&args.b)?.sorted_records();
+    let mismatches = reconcile(&a, &b, args.tolerance);
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    write_mismatches(out, &mismatches)?;
+    if mismatches.is_empty() {
+        info!("all {} accounts match", a.len());
+        Ok(exit::SUCCESS)
+    } else {
+        warn!("{} accounts differ", mismatches.len());
+        Ok(exit::MISMATCH)
+    }
+}
+
+fn run_diff(args: &DiffArgs) -> Result<i32, Box<dyn Error>> {
+    use transactions::reconcile::{changes, write_changes};
+
+    let before = Bank::load_snapshot(&args.before)?.sorted_records();
+    let after = Bank::load_snapshot(&args.after)?.sorted_records();
+    let changes = changes(&before, &after)?;
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    write_changes(out, &changes)?;
+    let locked = changes
+        .iter()
+        .filter(|change| change.locked == Some(true))
+        .count();
+    info!(
+        "{} accounts changed, {} of them newly locked",
+        changes.len(),
+        locked
+    );
+    Ok(exit::SUCCESS)
+}
+
+#[cfg(feature = "server")]
+fn run_serve(args: &cli::ServeArgs) -> Result<(), Box<dyn Error>> {
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    let mut bank = args.engine.bank()?;
+    if let Some(path) = &args.wal {
+        let replayed = bank.open_wal(path)?;
+        info!("replayed {} transactions from {}", replayed, path.display());
+    }
+    // after the replay, which is already in the log
+    open_audit_log(&args.engine, &mut bank, true)?;
+    bank.enable_metrics();
+    let bank = Arc::new(Mutex::new(bank));
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        // every listener runs until it fails, sharing the one bank
+        let mut servers = Vec::new();
+        if let Some(addr) = args.tcp {
+            let listener = TcpListener::bind(addr).await?;
+            info!("listening for tcp on {}", listener.local_addr()?);
+            let server = transactions::server::serve_tcp(listener, bank.clone(), args.input_format);
+            servers.push(tokio::spawn(server));
+        }
+        #[cfg(feature = "http")]
+        if let Some(addr) = args.http {
+            let listener = TcpListener::bind(addr).await?;
+            info!("listening for http on {}", listener.local_addr()?);
+            servers.push(tokio::spawn(transactions::http::serve_http(
+                listener,
+                bank.clone(),
+            )));
+        }
+        #[cfg(feature = "http")]
+        if let Some(addr) = args.metrics {
+            let listener = TcpListener::bind(addr).await?;
+            info!("serving metrics on {}", listener.local_addr()?);
+            servers.push(tokio::spawn(transactions::http::serve_metrics(
+                listener,
+                bank.clone(),
+            )));
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(addr) = args.grpc {
+            let listener = TcpListener::bind(addr).await?;
+            info!("listening for grpc on {}", listener.local_addr()?);
+            servers.push(tokio::spawn(transactions::grpc::serve_grpc(
+                listener,
+                bank.clone(),
+            )));
+        }
+        for server in servers {
+            server.await??;
+        }
+        Ok::<(), Box<dyn Error>>(())
+    })?;
+    Ok(())
+}
+
+fn main() {
+    let cli = config::parse();
+    logging::init(cli.verbose, cli.quiet);
+    // the long-running ones only return if they fail; doing is what failed, for the message
+    let (doing, result) = match &cli.command {
+        None => ("reading transactions", run_process(&cli.process)),
+        Some(Command::Process(args)) => ("reading transactions", run_process(args)),
+        Some(Command::Validate(args)) => ("validating transactions", run_validate(args)),
+        Some(Command::Report(ReportCommand::Accounts(args))) => {
+            ("reading transactions", run_process(args))
+        }
+        Some(Command::Report(ReportCommand::Statement(args))) => {
+            ("reading transactions", run_statement(args))
+        }
+        Some(Command::Query(args)) => ("querying the account", run_query(args)),
+        Some(Command::Convert(args)) => ("converting transactions", run_convert(args)),
+        Some(Command::Generate(args)) => ("generating transactions", run_generate(args)),
+        Some(Command::Reconcile(args)) => ("reconciling reports", run_reconcile(args)),
+        Some(Command::Diff(args)) => ("comparing snapshots", run_diff(args)),
+        #[cfg(feature = "server")]
+        Some(Command::Serve(args)) => ("serving", run_serve(args).map(|()| exit::SUCCESS)),
+    };
+    match result {
+        Ok(status) => process::exit(status),
+        Err(err) => {
+            error!("{}: {}", doing, err);
+            process::exit(exit::of_error(err.as_ref()));
+        }
     }
 }
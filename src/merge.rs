@@ -0,0 +1,208 @@
+use crate::bank::{Bank, Client, ClientId, TransactionType, TxId, TxnRecord};
+use crate::currency::Balance;
+use crate::outcome::{TxnError, TxnOutcome};
+
+// Folding one client's account into another's, for a customer who ended up with two client ids.
+// Everything moves to the surviving account: the balances in every currency, the transaction
+// history with any disputes still open (and the funds they hold), the rejected withdrawals and
+// the unlocks. The merged account is left empty and closed, so whatever still arrives for it is
+// refused rather than opening it again. Transfers other clients made to it name the surviving
+// account as their recipient from then on, so disputing one holds the funds where they now are;
+// one the surviving client made to the merged one becomes one to itself, which can't be disputed.
+impl Bank {
+    pub fn merge(&mut self, from: ClientId, to: ClientId) -> Result<TxnOutcome, TxnError> {
+        if !self.bank.contains_key(&from) {
+            return Err(TxnError::UnknownClient);
+        }
+        if to == from {
+            return Err(TxnError::InvalidRecipient);
+        }
+        let [Some(source), Some(target)] = self.bank.get_disjoint_mut([&from, &to]) else {
+            return Err(TxnError::InvalidRecipient);
+        };
+        check(source, target)?;
+        let records = source.txns.all()?;
+        for (tx, _) in &records {
+            if target.txns.contains_key(tx)? {
+                return Err(TxnError::DuplicateTx);
+            }
+        }
+        // found before anything moves, so a spill file that can't be read leaves the merge undone
+        let transfers = self.transfers_to(from)?;
+        let [Some(source), Some(target)] = self.bank.get_disjoint_mut([&from, &to]) else {
+            unreachable!("both looked up above");
+        };
+        move_balances(source, target)?;
+        source.txns.clear();
+        for (tx, record) in records {
+            target.txns.insert(tx, record);
+        }
+        target
+            .rejected_withdrawals
+            .append(&mut source.rejected_withdrawals);
+        target.unlocks.append(&mut source.unlocks);
+        for (sender, tx, mut record) in transfers {
+            record.to = Some(to);
+            if let Some(client) = self.bank.get_mut(&sender) {
+                client.txns.insert(tx, record);
+            }
+        }
+        // the records brought in may put the bank over its in-memory allowance
+        self.spill_if_over();
+        Ok(TxnOutcome::Merged)
+    }
+
+    // The records of the transfers made to client, by sender. A spilled record since brought back
+    // into memory comes after its stale copy on disk.
+    fn transfers_to(&self, client: ClientId) -> Result<Vec<(ClientId, TxId, TxnRecord)>, TxnError> {
+        let spilled = self.spilled().map_err(|_| TxnError::SpillFailed)?;
+        let mut transfers = Vec::new();
+        for sender in self.bank.values() {
+            let on_disk = spilled.get(&sender.client).into_iter().flatten().copied();
+            for (tx, record) in on_disk.chain(sender.txns.hot()) {
+                if record.kind == TransactionType::Transfer && record.to == Some(client) {
+                    transfers.push((sender.client, tx, record));
+                }
+            }
+        }
+        Ok(transfers)
+    }
+}
+
+// Whether source can be merged into target, as far as the accounts themselves go
+pub(crate) fn check(source: &Client, target: &Client) -> Result<(), TxnError> {
+    if source.locked || target.locked {
+        Err(TxnError::AccountLocked)
+    } else if source.closed || target.closed {
+        Err(TxnError::AccountClosed)
+    } else {
+        Ok(())
+    }
+}
+
+// Add source's balances to target's, leaving source empty and closed, or if one of the sums
+// overflows, leaving both as they were
+pub(crate) fn move_balances(source: &mut Client, target: &mut Client) -> Result<(), TxnError> {
+    let overflow = || TxnError::BalanceOverflow;
+    let unlabelled = target
+        .balance(None)
+        .checked_add(source.balance(None))
+        .ok_or_else(overflow)?;
+    let mut currencies = target.currencies.clone();
+    for (currency, balance) in &source.currencies {
+        let into = currencies.entry(*currency).or_default();
+        *into = into.checked_add(*balance).ok_or_else(overflow)?;
+    }
+    target.swap_balance(unlabelled);
+    target.currencies = currencies;
+    source.swap_balance(Balance::default());
+    source.currencies.clear();
+    source.closed = true;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Transaction;
+    use crate::policy::{Policy, TxIdPolicy};
+
+    fn txn(tx_type: TransactionType, client: ClientId, tx: TxId, amount: &str) -> Transaction {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount: amount.parse().unwrap(),
+            to: None,
+            timestamp: None,
+            currency: None,
+            to_currency: None,
+            effective_date: None,
+        }
+    }
+
+    fn to(txn: Transaction, to: ClientId) -> Transaction {
+        Transaction {
+            to: Some(to),
+            ..txn
+        }
+    }
+
+    fn balance(bank: &Bank, client: ClientId) -> (String, String) {
+        let record = bank.record(client).unwrap();
+        (record.available.to_string(), record.held.to_string())
+    }
+
+    #[test]
+    fn merging_moves_everything_to_the_surviving_client() {
+        let mut bank = Bank::new();
+        bank.insert_txn(txn(TransactionType::Deposit, 1, 1, "5"))
+            .unwrap();
+        bank.insert_txn(txn(TransactionType::Deposit, 2, 2, "3"))
+            .unwrap();
+        let merge = to(txn(TransactionType::Merge, 2, 3, "0"), 1);
+        assert_eq!(bank.insert_txn(merge), Ok(TxnOutcome::Merged));
+        assert_eq!(balance(&bank, 1), ("8.0000".into(), "0.0000".into()));
+        assert_eq!(balance(&bank, 2), ("0.0000".into(), "0.0000".into()));
+        assert!(bank.record(2).unwrap().closed);
+        // client 2's deposit is client 1's to dispute now
+        let dispute = txn(TransactionType::Dispute, 1, 2, "0");
+        assert_eq!(bank.insert_txn(dispute), Ok(TxnOutcome::Disputed));
+        assert_eq!(balance(&bank, 1), ("5.0000".into(), "3.0000".into()));
+        let deposit = txn(TransactionType::Deposit, 2, 4, "1");
+        assert_eq!(bank.insert_txn(deposit), Err(TxnError::AccountClosed));
+    }
+
+    #[test]
+    fn a_transfer_to_the_merged_client_is_disputed_against_the_survivor() {
+        let mut bank = Bank::new();
+        bank.insert_txn(txn(TransactionType::Deposit, 1, 1, "5"))
+            .unwrap();
+        bank.insert_txn(txn(TransactionType::Deposit, 2, 5, "1"))
+            .unwrap();
+        bank.insert_txn(txn(TransactionType::Deposit, 3, 2, "1"))
+            .unwrap();
+        let transfer = to(txn(TransactionType::Transfer, 1, 3, "2"), 2);
+        assert_eq!(bank.insert_txn(transfer), Ok(TxnOutcome::Transferred));
+        let merge = to(txn(TransactionType::Merge, 2, 4, "0"), 3);
+        assert_eq!(bank.insert_txn(merge), Ok(TxnOutcome::Merged));
+        assert_eq!(balance(&bank, 3), ("4.0000".into(), "0.0000".into()));
+
+        let dispute = txn(TransactionType::Dispute, 1, 3, "0");
+        assert_eq!(bank.insert_txn(dispute), Ok(TxnOutcome::Disputed));
+        assert_eq!(balance(&bank, 3), ("2.0000".into(), "2.0000".into()));
+        assert_eq!(balance(&bank, 2), ("0.0000".into(), "0.0000".into()));
+        let chargeback = txn(TransactionType::Chargeback, 1, 3, "0");
+        assert_eq!(bank.insert_txn(chargeback), Ok(TxnOutcome::ChargedBack));
+        assert_eq!(balance(&bank, 1), ("5.0000".into(), "0.0000".into()));
+        assert_eq!(balance(&bank, 3), ("2.0000".into(), "0.0000".into()));
+        assert!(bank.record(3).unwrap().locked);
+    }
+
+    #[test]
+    fn merges_that_cant_go_through_change_nothing() {
+        let mut bank = Bank::with_policy(Policy {
+            tx_ids: TxIdPolicy::PerClient,
+            ..Policy::default()
+        });
+        bank.insert_txn(txn(TransactionType::Deposit, 1, 1, "5"))
+            .unwrap();
+        bank.insert_txn(txn(TransactionType::Deposit, 2, 2, "3"))
+            .unwrap();
+        let merge = |from, into| to(txn(TransactionType::Merge, from, 9, "0"), into);
+        assert_eq!(bank.insert_txn(merge(3, 1)), Err(TxnError::UnknownClient));
+        assert_eq!(
+            bank.insert_txn(merge(2, 2)),
+            Err(TxnError::InvalidRecipient)
+        );
+        assert_eq!(
+            bank.insert_txn(merge(2, 4)),
+            Err(TxnError::InvalidRecipient)
+        );
+        bank.insert_txn(txn(TransactionType::Deposit, 2, 1, "1"))
+            .unwrap();
+        assert_eq!(bank.insert_txn(merge(2, 1)), Err(TxnError::DuplicateTx));
+        assert_eq!(balance(&bank, 1), ("5.0000".into(), "0.0000".into()));
+        assert_eq!(balance(&bank, 2), ("4.0000".into(), "0.0000".into()));
+    }
+}
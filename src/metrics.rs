@@ -0,0 +1,129 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, Transaction};
+use crate::currency::Currency;
+use crate::outcome::{TxnError, TxnOutcome};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+    time::Duration,
+};
+
+// Upper bounds of the latency histogram's buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_001, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05,
+];
+
+// Counters a long-lived server keeps for monitoring, rendered in the Prometheus text format by
+// Bank::metrics_text. Only insert_logged (the servers' way in) counts; the gauges are worked
+// out from the accounts when they're scraped.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    processed: BTreeMap<&'static str, u64>,
+    rejected: BTreeMap<(&'static str, &'static str), u64>,
+    // per bucket of LATENCY_BUCKETS, not cumulative
+    latency: [u64; LATENCY_BUCKETS.len()],
+    latency_count: u64,
+    latency_sum: f64,
+}
+
+impl Bank {
+    // Count every later insert_logged, see Bank::metrics_text
+    pub fn enable_metrics(&mut self) {
+        self.metrics.get_or_insert_with(Metrics::default);
+    }
+
+    pub(crate) fn measured(
+        &mut self,
+        txn: &Transaction,
+        result: &Result<TxnOutcome, TxnError>,
+        elapsed: Duration,
+    ) {
+        let Some(metrics) = &mut self.metrics else {
+            return;
+        };
+        let tx_type = txn.tx_type.name();
+        match result {
+            Ok(_) => *metrics.processed.entry(tx_type).or_default() += 1,
+            Err(err) => *metrics.rejected.entry((tx_type, err.code())).or_default() += 1,
+        }
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            metrics.latency[bucket] += 1;
+        }
+        metrics.latency_count += 1;
+        metrics.latency_sum += seconds;
+    }
+
+    // The metrics in the Prometheus text exposition format, empty unless enable_metrics was
+    // called
+    pub fn metrics_text(&self) -> String {
+        let Some(metrics) = &self.metrics else {
+            return String::new();
+        };
+        let (mut clients, mut locked) = (HashSet::new(), HashSet::new());
+        let mut held: BTreeMap<Option<Currency>, Amount> = BTreeMap::new();
+        for record in self.records() {
+            clients.insert(record.client);
+            if record.locked {
+                locked.insert(record.client);
+            }
+            let held = held.entry(record.currency).or_default();
+            *held = held.saturating_add(record.held);
+        }
+
+        // writing to a String can't fail
+        let mut out = String::new();
+        let name = "transactions_processed_total";
+        header(&mut out, name, "counter", "Transactions applied, by type");
+        for (tx_type, count) in &metrics.processed {
+            let _ = writeln!(out, "{}{{type=\"{}\"}} {}", name, tx_type, count);
+        }
+        let name = "transactions_rejected_total";
+        let help = "Transactions refused, by type and reason";
+        header(&mut out, name, "counter", help);
+        for ((tx_type, reason), count) in &metrics.rejected {
+            let _ = writeln!(
+                out,
+                "{}{{type=\"{}\",reason=\"{}\"}} {}",
+                name, tx_type, reason, count
+            );
+        }
+        let name = "transactions_clients";
+        header(&mut out, name, "gauge", "Accounts in the bank");
+        let _ = writeln!(out, "{} {}", name, clients.len());
+        let name = "transactions_locked_clients";
+        header(&mut out, name, "gauge", "Accounts locked by a chargeback");
+        let _ = writeln!(out, "{} {}", name, locked.len());
+        let name = "transactions_held_funds";
+        let help = "Funds held by open disputes across every account, by currency";
+        header(&mut out, name, "gauge", help);
+        for (currency, amount) in &held {
+            match currency {
+                Some(currency) => {
+                    let _ = writeln!(out, "{}{{currency=\"{}\"}} {}", name, currency, amount);
+                }
+                None => {
+                    let _ = writeln!(out, "{} {}", name, amount);
+                }
+            }
+        }
+        let name = "transactions_processing_seconds";
+        let help = "Time taken to log and apply a transaction";
+        header(&mut out, name, "histogram", help);
+        let mut cumulative = 0;
+        for (le, count) in LATENCY_BUCKETS.iter().zip(metrics.latency) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+        }
+        let count = metrics.latency_count;
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, metrics.latency_sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
@@ -0,0 +1,235 @@
+// NACHA files, the fixed-width format U.S. ACH batches come in, read with --input-format nacha.
+// A file is 94-character records, with or without line breaks between them, each starting with
+// its type: a file header (1), then batches of a batch header (5), entry details (6) with their
+// addenda (7) and a batch control (8), then the file control (9) and any padding of 9s.
+//
+// Each entry detail is a transaction: a credit to a checking or savings account (transaction
+// code 22 or 32) a deposit, and a debit (27 or 37) a withdrawal. The entry's individual
+// identification number is the client id and the last seven digits of its trace number, the
+// entry's sequence number in its originator's file, the tx id; the amount is in cents. The
+// batch header's effective entry date is every entry's effective_date, so with an as_of policy a
+// forward-dated batch waits as pending. Prenotes and zero-dollar entries (codes ending 3, 4, 8
+// or 9) carry no funds and are passed over, and returns, notifications of change and the
+// general ledger and loan codes aren't supported, each being an error in its own record.
+//
+// Every batch control's entry/addenda count and total of its debits and credits are checked
+// against the batch's records, like a CSV batch trailer's control totals, see control.rs. The
+// entry hash and the file control aren't.
+
+use crate::amount::Amount;
+use crate::bank::{ClientId, Transaction, TransactionType, TxId};
+use crate::control::{Batch, Declared};
+use crate::date::Date;
+use crate::error::Error;
+use crate::snapshot::invalid;
+use std::io::{self, BufRead, BufReader, Read};
+
+const RECORD: usize = 94;
+
+// Transactions from a NACHA file, read a record at a time
+pub(crate) struct NachaRows<R: Read> {
+    reader: BufReader<R>,
+    record: [u8; RECORD],
+    // records read, counting from 1
+    row: u64,
+    // the effective entry date of the batch being read
+    effective_date: Option<Date>,
+    done: bool,
+}
+
+impl<R: Read> NachaRows<R> {
+    pub(crate) fn new(reader: R) -> NachaRows<R> {
+        NachaRows {
+            reader: BufReader::new(reader),
+            record: [b' '; RECORD],
+            row: 0,
+            effective_date: None,
+            done: false,
+        }
+    }
+
+    // The number of the last record read, counting from 1
+    pub(crate) fn row(&self) -> u64 {
+        self.row
+    }
+
+    // Ok(false) at the end of the file. A line shorter than a record, as editors leave them by
+    // trimming trailing blanks, is padded out with them.
+    fn read_record(&mut self) -> Result<bool, Error> {
+        let mut read = 0;
+        while read < RECORD {
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(Error::from(err)),
+            };
+            if buf.is_empty() {
+                if read == 0 {
+                    return Ok(false);
+                }
+                return Err(invalid(format!(
+                    "NACHA file ends partway through record {}",
+                    self.row + 1
+                )));
+            }
+            let mut used = 0;
+            for &byte in buf {
+                used += 1;
+                if byte == b'\r' || byte == b'\n' {
+                    if read == 0 {
+                        continue;
+                    }
+                    self.record[read..].fill(b' ');
+                    read = RECORD;
+                } else {
+                    self.record[read] = byte;
+                    read += 1;
+                }
+                if read == RECORD {
+                    break;
+                }
+            }
+            self.reader.consume(used);
+        }
+        Ok(true)
+    }
+
+    // The next entry that moves funds, counting every record of the batch it's in towards
+    // batch's totals
+    pub(crate) fn next(&mut self, batch: &mut Batch) -> Option<Result<Transaction, Error>> {
+        loop {
+            if self.done {
+                return None;
+            }
+            match self.read_record() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.done = true;
+                    batch.finish();
+                    return None;
+                }
+                // a torn record means the rest can't be found
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+            self.row += 1;
+            let record = &self.record;
+            let parsed = match record[0] {
+                b'1' | b'9' => Ok(None),
+                b'5' => date(&record[69..75]).map(|date| {
+                    self.effective_date = date;
+                    batch.header(self.row, Declared::default());
+                    None
+                }),
+                b'6' => number(&record[29..39]).and_then(|cents| {
+                    let amount = Amount::from_units(cents as i64 * 100);
+                    batch.add(amount);
+                    entry(record, amount, self.effective_date)
+                }),
+                b'7' => {
+                    batch.add(Amount::ZERO);
+                    Ok(None)
+                }
+                b'8' => number(&record[4..10]).and_then(|count| {
+                    let debits = number(&record[20..32])?;
+                    let credits = number(&record[32..44])?;
+                    let total = Amount::from_units((debits + credits) as i64 * 100);
+                    batch.trailer(self.row, Declared::new(Some(count), Some(total)));
+                    Ok(None)
+                }),
+                kind => Err(Error::Value(format!(
+                    "unknown NACHA record type '{}'",
+                    char::from(kind)
+                ))),
+            };
+            match parsed {
+                Ok(Some(txn)) => return Some(Ok(txn)),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err.at_line(self.row))),
+            }
+        }
+    }
+}
+
+// The transaction an entry detail record stands for, None for one that carries no funds
+fn entry(
+    record: &[u8; RECORD],
+    amount: Amount,
+    effective_date: Option<Date>,
+) -> Result<Option<Transaction>, Error> {
+    let code = text(&record[1..3])?;
+    let tx_type = match code.as_bytes() {
+        [b'2' | b'3', b'2'] => TransactionType::Deposit,
+        [b'2' | b'3', b'7'] => TransactionType::Withdrawal,
+        [b'2' | b'3', b'3' | b'4' | b'8' | b'9'] => return Ok(None),
+        _ => {
+            return Err(Error::Value(format!(
+                "unsupported ACH transaction code {}",
+                code
+            )))
+        }
+    };
+    let individual = text(&record[39..54])?;
+    let client: ClientId = individual.parse().map_err(|_| {
+        Error::Value(format!(
+            "individual identification number '{}' isn't a client id",
+            individual
+        ))
+    })?;
+    let sequence = text(&record[87..94])?;
+    let tx: TxId = sequence
+        .parse()
+        .map_err(|_| Error::Value(format!("invalid trace number sequence '{}'", sequence)))?;
+    Ok(Some(Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+        to: None,
+        timestamp: None,
+        currency: None,
+        to_currency: None,
+        effective_date,
+    }))
+}
+
+// A field's text without its padding
+fn text(field: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(field)
+        .map(str::trim)
+        .map_err(|_| Error::Value("NACHA record isn't ASCII".to_string()))
+}
+
+// A zero-padded numeric field
+fn number(field: &[u8]) -> Result<u64, Error> {
+    let digits = text(field)?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::Value(format!("invalid NACHA number '{}'", digits)));
+    }
+    digits
+        .parse()
+        .map_err(|_| Error::Value(format!("invalid NACHA number '{}'", digits)))
+}
+
+// A YYMMDD date, None if the field is blank
+fn date(field: &[u8]) -> Result<Option<Date>, Error> {
+    let digits = text(field)?;
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || Error::Value(format!("invalid NACHA date '{}'", digits));
+    let value = number(field).map_err(|_| invalid())?;
+    if digits.len() != 6 {
+        return Err(invalid());
+    }
+    let (year, month, day) = (
+        value / 10_000,
+        (value / 100 % 100) as u32,
+        (value % 100) as u32,
+    );
+    Date::from_ymd(2000 + year as i64, month, day)
+        .map(Some)
+        .ok_or_else(invalid)
+}
@@ -0,0 +1,72 @@
+use crate::bank::{Bank, ClientId, Transaction};
+use crate::outcome::{TxnError, TxnOutcome};
+use std::fmt;
+
+// Callbacks for every decision the bank makes, for embedders to hang metrics, alerting or their
+// own persistence off without replacing the processing loop. Each is called once insert_txn has
+// applied (or refused) the transaction, so the bank's state already reflects it. Every method
+// does nothing by default.
+pub trait TxnObserver: Send {
+    fn on_accepted(&mut self, _txn: &Transaction, _outcome: TxnOutcome) {}
+
+    fn on_rejected(&mut self, _txn: &Transaction, _err: &TxnError) {}
+
+    // a chargeback locked client's account: its own, or the recipient's for a transfer
+    fn on_lock(&mut self, _client: ClientId, _txn: &Transaction) {}
+
+    // a flagging VelocityRule let txn through, see Bank::with_velocity_rule
+    fn on_alert(&mut self, _txn: &Transaction) {}
+}
+
+#[derive(Default)]
+pub(crate) struct Observers(Vec<Box<dyn TxnObserver>>);
+
+impl Observers {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl Bank {
+    // The bank, also telling observer about everything it does from now on. Observers are
+    // called in the order they were added.
+    pub fn with_observer(mut self, observer: impl TxnObserver + 'static) -> Bank {
+        self.observers.0.push(Box::new(observer));
+        self
+    }
+
+    pub(crate) fn notify(&mut self, txn: &Transaction, result: &Result<TxnOutcome, TxnError>) {
+        if self.observers.is_empty() {
+            return;
+        }
+        // the chargeback's record still names the recipient of a transfer
+        let locked = match result {
+            Ok(TxnOutcome::ChargedBack) => Some(
+                self.bank
+                    .get(&txn.client)
+                    .and_then(|client| client.txns.get(&txn.tx).ok().flatten())
+                    .and_then(|record| record.to)
+                    .unwrap_or(txn.client),
+            ),
+            _ => None,
+        };
+        for observer in &mut self.observers.0 {
+            match result {
+                Ok(outcome) => observer.on_accepted(txn, *outcome),
+                Err(err) => observer.on_rejected(txn, err),
+            }
+            if let Some(client) = locked {
+                observer.on_lock(client, txn);
+            }
+            if self.velocity.alerted {
+                observer.on_alert(txn);
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// OFX statements, as consumer banks export them for personal finance software, read with
+// --input-format ofx. Both the SGML form of OFX 1.x, whose leaf elements aren't closed, and the
+// XML of 2.x are read, by their tags alone: each STMTTRN is a transaction of the account whose
+// ACCTID came before it.
+//
+// TRNAMT is signed from the account holder's side, so a positive amount is a deposit and a
+// negative one a withdrawal of its size, or a fee for the FEE and SRVCHG types; TRNTYPE is
+// otherwise only a description. A zero amount moves nothing and is passed over. The client is
+// the one given with the source, or failing that the ACCTID, if it's a client id. DTPOSTED, if
+// there is one, is the timestamp, shifted to UTC by its [offset:zone] suffix. CURDEF isn't
+// carried over: amounts go to the account's unlabelled balance like any input's without a
+// currency column.
+//
+// The tx id is the FITID, if it's a number that fits, or else a hash of it, so exports of the
+// same account give an entry the same id each time. Repeats are told by the FITID itself: one
+// the file has already had for the account is dropped as the repeat it is, and a bank that has
+// already applied an entry, from an earlier export overlapping this one, refuses it again as a
+// duplicate_tx. An entry whose id is already another's, be it one with a different FITID that
+// hashed the same or, under TxIdPolicy::Global, another client's transaction, fails the run,
+// see imports.rs. With the default 32-bit tx ids hashes get likely to meet somewhere past tens
+// of thousands of hashed entries; the wide-ids feature makes that a non-issue.
+
+use crate::amount::{Amount, AmountError};
+use crate::bank::{ClientId, Transaction, TransactionType, TxId};
+use crate::date::Date;
+use crate::error::Error;
+use crate::policy::PrecisionPolicy;
+use crate::source::decided;
+use std::{
+    collections::{HashSet, VecDeque},
+    io::Read,
+};
+use tracing::debug;
+
+// A transaction of an OFX or QIF file, with where in the file it was and what identifies it in
+// the export, or why it isn't one
+pub(crate) type Entry = (u64, Result<(Transaction, String), Error>);
+
+// Transactions from an OFX file, which is read whole before the first
+pub(crate) struct OfxRows<R: Read> {
+    reader: Option<R>,
+    client: Option<ClientId>,
+    // each with the STMTTRN it came from, counting from 1, and its FITID
+    rows: VecDeque<Entry>,
+    row: u64,
+    key: Option<String>,
+}
+
+impl<R: Read> OfxRows<R> {
+    pub(crate) fn new(reader: R, client: Option<ClientId>) -> OfxRows<R> {
+        OfxRows {
+            reader: Some(reader),
+            client,
+            rows: VecDeque::new(),
+            row: 0,
+            key: None,
+        }
+    }
+
+    // The STMTTRN the last row returned was, counting from 1
+    pub(crate) fn row(&self) -> u64 {
+        self.row
+    }
+
+    // The FITID of the last row returned
+    pub(crate) fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+}
+
+impl<R: Read> OfxRows<R> {
+    // The next row, with its amount parsed as precision says
+    pub(crate) fn next(
+        &mut self,
+        precision: PrecisionPolicy,
+    ) -> Option<Result<Transaction, Error>> {
+        if let Some(mut reader) = self.reader.take() {
+            let mut bytes = Vec::new();
+            if let Err(err) = reader.read_to_end(&mut bytes) {
+                return Some(Err(Error::from(err)));
+            }
+            // 1.x files are often in a Windows code page rather than UTF-8, but only the names
+            // and memos, which aren't used, would be anything but ASCII
+            self.rows = parse(&String::from_utf8_lossy(&bytes), self.client, precision);
+        }
+        let (row, txn) = self.rows.pop_front()?;
+        self.row = row;
+        self.key = None;
+        Some(match txn {
+            Ok((txn, key)) => {
+                self.key = Some(key);
+                Ok(txn)
+            }
+            Err(err) => Err(err.at_line(row)),
+        })
+    }
+}
+
+// Every STMTTRN that moves funds, in order
+fn parse(text: &str, client: Option<ClientId>, precision: PrecisionPolicy) -> VecDeque<Entry> {
+    let mut rows = VecDeque::new();
+    let mut account = None;
+    let mut fields: Option<Vec<(String, &str)>> = None;
+    let mut fitids = HashSet::new();
+    let mut row = 0;
+    for token in text.split('<').skip(1) {
+        let (tag, value) = token.split_once('>').unwrap_or((token, ""));
+        let (tag, value) = (tag.trim().to_ascii_uppercase(), value.trim());
+        match tag.as_str() {
+            "STMTTRN" => fields = Some(Vec::new()),
+            "/STMTTRN" => {
+                let Some(fields) = fields.take() else {
+                    continue;
+                };
+                row += 1;
+                let field = |name: &str| {
+                    fields
+                        .iter()
+                        .find(|(tag, _)| tag == name)
+                        .map(|(_, value)| *value)
+                };
+                let fitid = field("FITID").unwrap_or_default();
+                if !fitid.is_empty() && !fitids.insert((account, fitid.to_string())) {
+                    debug!(fitid, "dropped a repeat of an OFX entry");
+                    continue;
+                }
+                let txn = decided(
+                    precision,
+                    |precision| transaction(&field, account, client, precision),
+                    |txn| txn,
+                );
+                match txn {
+                    Ok(Some(txn)) => rows.push_back((row, Ok((txn, fitid.to_string())))),
+                    Ok(None) => {}
+                    Err(err) => rows.push_back((row, Err(err))),
+                }
+            }
+            "ACCTID" if fields.is_none() => account = Some(value),
+            _ => {
+                if let Some(fields) = &mut fields {
+                    if !tag.starts_with('/') {
+                        fields.push((tag, value));
+                    }
+                }
+            }
+        }
+    }
+    rows
+}
+
+// The transaction a STMTTRN stands for, None for one of no amount
+fn transaction<'a>(
+    field: &impl Fn(&str) -> Option<&'a str>,
+    account: Option<&str>,
+    client: Option<ClientId>,
+    precision: PrecisionPolicy,
+) -> Result<Option<Transaction>, Error> {
+    let required = |name: &str| {
+        field(name)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| Error::Value(format!("OFX transaction has no {}", name)))
+    };
+    let amount = required("TRNAMT")?;
+    // OFX allows a comma as the decimal point, and has no thousands separators
+    let amount = Amount::parse(&amount.replace(',', "."), precision).map_err(|err| match err {
+        AmountError::Invalid => Error::Value(format!("invalid OFX amount '{}'", amount)),
+        err => Error::Value(err.to_string()),
+    })?;
+    if amount.is_zero() {
+        return Ok(None);
+    }
+    let tx_type = match field("TRNTYPE") {
+        _ if !amount.is_negative() => TransactionType::Deposit,
+        Some("FEE" | "SRVCHG") => TransactionType::Fee,
+        _ => TransactionType::Withdrawal,
+    };
+    let client = match client {
+        Some(client) => client,
+        None => {
+            let account = account.unwrap_or_default();
+            account.parse().map_err(|_| {
+                Error::Value(format!(
+                    "OFX account '{}' isn't a client id; say which client the statement is for",
+                    account
+                ))
+            })?
+        }
+    };
+    let timestamp = field("DTPOSTED")
+        .filter(|value| !value.is_empty())
+        .map(timestamp)
+        .transpose()?;
+    Ok(Some(Transaction {
+        tx_type,
+        client,
+        tx: tx_id(required("FITID")?),
+        amount: if amount.is_negative() {
+            -amount
+        } else {
+            amount
+        },
+        to: None,
+        timestamp,
+        currency: None,
+        to_currency: None,
+        effective_date: None,
+    }))
+}
+
+// The tx id for an entry identified by id: the number itself if it is one that fits, otherwise
+// the top bits of its 64-bit FNV-1a hash
+pub(crate) fn tx_id(id: &str) -> TxId {
+    if let Ok(tx) = id.parse() {
+        return tx;
+    }
+    let hash = id.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash >> (64 - TxId::BITS)) as TxId
+}
+
+// An OFX datetime, YYYYMMDD then optionally HHMMSS, milliseconds and a [hours:zone] offset from
+// UTC, as seconds since the Unix epoch
+fn timestamp(value: &str) -> Result<i64, Error> {
+    let invalid = || Error::Value(format!("invalid OFX date '{}'", value));
+    let (datetime, offset) = match value.split_once('[') {
+        Some((datetime, zone)) => (datetime, zone.trim_end_matches(']')),
+        None => (value, ""),
+    };
+    let digits = datetime.split('.').next().unwrap_or_default();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) || !matches!(digits.len(), 8 | 12 | 14) {
+        return Err(invalid());
+    }
+    let number = |range: std::ops::Range<usize>| digits.get(range).map_or(Ok(0), str::parse);
+    let (year, month, day) = (number(0..4), number(4..6), number(6..8));
+    let date = match (year, month, day) {
+        (Ok(year), Ok(month), Ok(day)) => Date::from_ymd(year, month as u32, day as u32),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    let (hour, minute, second) = (number(8..10), number(10..12), number(12..14));
+    let (Ok(hour), Ok(minute), Ok(second)) = (hour, minute, second) else {
+        return Err(invalid());
+    };
+    let hours = offset.split(':').next().unwrap_or_default().trim();
+    let offset = if hours.is_empty() {
+        0
+    } else {
+        let hours: f64 = hours.parse().map_err(|_| invalid())?;
+        (hours * 3600.0).round() as i64
+    };
+    Ok(date.timestamp() + hour * 3600 + minute * 60 + second - offset)
+}
@@ -0,0 +1,232 @@
+use std::{error, fmt};
+
+// What the engine did with a transaction it accepted
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum TxnOutcome {
+    Deposited,
+    // deposited into held rather than available, see Bank::with_risk_scorer
+    Held,
+    Withdrawn,
+    Disputed,
+    Resolved,
+    // the disputed funds were reversed and the account is now locked
+    ChargedBack,
+    // a locked account was reinstated
+    Unlocked,
+    // funds moved to another client
+    Transferred,
+    FeeCharged,
+    InterestAccrued,
+    // funds exchanged between two of the client's currencies
+    Converted,
+    // a withdrawal's amount credited back
+    Refunded,
+    // the account takes no more deposits or withdrawals
+    Closed,
+    // the account was folded into another client's
+    Merged,
+    // forward-dated, so queued rather than applied, see pending.rs
+    Pending,
+    // funds moved into held by an admin, until a release
+    HoldPlaced,
+    Released,
+}
+
+impl TxnOutcome {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TxnOutcome::Deposited => "deposited",
+            TxnOutcome::Held => "held",
+            TxnOutcome::Withdrawn => "withdrawn",
+            TxnOutcome::Disputed => "disputed",
+            TxnOutcome::Resolved => "resolved",
+            TxnOutcome::ChargedBack => "charged_back",
+            TxnOutcome::Unlocked => "unlocked",
+            TxnOutcome::Transferred => "transferred",
+            TxnOutcome::FeeCharged => "fee_charged",
+            TxnOutcome::InterestAccrued => "interest_accrued",
+            TxnOutcome::Converted => "converted",
+            TxnOutcome::Refunded => "refunded",
+            TxnOutcome::Closed => "closed",
+            TxnOutcome::Merged => "merged",
+            TxnOutcome::Pending => "pending",
+            TxnOutcome::HoldPlaced => "hold_placed",
+            TxnOutcome::Released => "released",
+        }
+    }
+}
+
+// Why the engine refused to apply a transaction
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum TxnError {
+    // the client has no account and this transaction can't open one
+    UnknownClient,
+    // a deposit, withdrawal, transfer or fee reused a tx id already recorded for the client, or a
+    // merge would give the recipient two transactions with the same tx id
+    DuplicateTx,
+    // a deposit, withdrawal, transfer or fee reused a tx id already recorded for another client, under TxIdPolicy::Global
+    DuplicateTxOtherClient,
+    // a deposit, withdrawal, transfer or fee for 0
+    ZeroAmount,
+    // the account was locked by a chargeback
+    AccountLocked,
+    // a deposit, withdrawal or other movement of funds for a closed account, including a transfer
+    // to one, or a close of an account that's already closed
+    AccountClosed,
+    // a withdrawal or fee larger than the available funds under
+    // WithdrawalPolicy::RejectIfInsufficient, or any transfer larger than them
+    InsufficientFunds,
+    // a dispute or refund referencing a tx the client doesn't have
+    TxNotFound,
+    // a dispute referencing a tx type the DisputePolicy doesn't allow disputing, a fee or an accrual
+    NotDisputable,
+    // a resolve or chargeback for a tx with no open dispute
+    NotDisputed,
+    // a dispute of a tx that's already wholly under dispute
+    AlreadyDisputed,
+    // a partial dispute for a negative amount, or more than is left undisputed
+    InvalidDisputeAmount,
+    // a resolve or chargeback of a dispute that was resolved
+    AlreadyResolved,
+    // a dispute of a resolved tx that the RedisputePolicy doesn't allow disputing again
+    RedisputeNotAllowed,
+    // a dispute, resolve, chargeback or refund naming another currency than the referenced tx's
+    CurrencyMismatch,
+    // a conversion missing either currency, or from one to itself
+    InvalidConversion,
+    // a conversion between currencies the rates table has no rate for
+    NoExchangeRate,
+    // any dispute, resolve or chargeback of a tx that was charged back
+    AlreadyChargedBack,
+    // an unlock for an account that isn't locked
+    NotLocked,
+    // a transfer or merge with no recipient, to the sending client itself, or to a client with no
+    // account
+    InvalidRecipient,
+    // an accrual for a negative number of periods
+    InvalidPeriods,
+    // a dispute raised later after the transaction than Policy::dispute_window_days allows
+    DisputeWindowExpired,
+    // refused by one of the bank's validation rules, with the rule's reason code
+    RuleRejected(&'static str),
+    // a row a run up to the bank's snapshot already processed, see Bank::dedup_seen
+    AlreadySeen,
+    // an amount with more than four decimal places, under PrecisionPolicy::Reject
+    TooPrecise,
+    // a deposit or withdrawal for a negative amount, under NegativeAmountPolicy::Reject
+    NegativeAmount,
+    // a refund referencing anything but a withdrawal, or for an amount other than the whole of it
+    NotRefundable,
+    // any dispute, resolve, chargeback or refund of a withdrawal that was refunded
+    AlreadyRefunded,
+    // a release referencing anything but a hold, or for an amount other than the whole of it
+    NotReleasable,
+    // a release of a hold that was already released
+    AlreadyReleased,
+    // a withdrawal over one of the client's Limits
+    LimitExceeded,
+    // a transaction that would take its client over a blocking VelocityRule
+    VelocityExceeded,
+    // an amount of more than a trillion either way, or an accrual or conversion that would
+    // credit one
+    AmountTooLarge,
+    // a transaction that would take one of the balances or ledger accounts it posts to past the
+    // largest amount it can hold, see ledger.rs
+    BalanceOverflow,
+    // a transaction needing a record that couldn't be read back from the spill file, see spill.rs
+    SpillFailed,
+}
+
+impl TxnError {
+    // Stable, machine-readable reason code used in the rejects report
+    pub fn code(&self) -> &'static str {
+        match self {
+            TxnError::UnknownClient => "unknown_client",
+            TxnError::DuplicateTx => "duplicate_tx",
+            TxnError::DuplicateTxOtherClient => "duplicate_tx_other_client",
+            TxnError::ZeroAmount => "zero_amount",
+            TxnError::AccountLocked => "account_locked",
+            TxnError::AccountClosed => "account_closed",
+            TxnError::InsufficientFunds => "insufficient_funds",
+            TxnError::TxNotFound => "tx_not_found",
+            TxnError::NotDisputable => "not_disputable",
+            TxnError::NotDisputed => "not_disputed",
+            TxnError::AlreadyDisputed => "already_disputed",
+            TxnError::InvalidDisputeAmount => "invalid_dispute_amount",
+            TxnError::AlreadyResolved => "already_resolved",
+            TxnError::RedisputeNotAllowed => "redispute_not_allowed",
+            TxnError::CurrencyMismatch => "currency_mismatch",
+            TxnError::InvalidConversion => "invalid_conversion",
+            TxnError::NoExchangeRate => "no_exchange_rate",
+            TxnError::AlreadyChargedBack => "already_charged_back",
+            TxnError::NotLocked => "not_locked",
+            TxnError::InvalidRecipient => "invalid_recipient",
+            TxnError::InvalidPeriods => "invalid_periods",
+            TxnError::DisputeWindowExpired => "dispute_window_expired",
+            TxnError::RuleRejected(code) => code,
+            TxnError::AlreadySeen => "already_seen",
+            TxnError::TooPrecise => "too_precise",
+            TxnError::NegativeAmount => "negative_amount",
+            TxnError::NotRefundable => "not_refundable",
+            TxnError::AlreadyRefunded => "already_refunded",
+            TxnError::NotReleasable => "not_releasable",
+            TxnError::AlreadyReleased => "already_released",
+            TxnError::LimitExceeded => "limit_exceeded",
+            TxnError::VelocityExceeded => "velocity_exceeded",
+            TxnError::AmountTooLarge => "amount_too_large",
+            TxnError::BalanceOverflow => "balance_overflow",
+            TxnError::SpillFailed => "spill_failed",
+        }
+    }
+}
+
+impl fmt::Display for TxnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            TxnError::UnknownClient => "client has no account",
+            TxnError::DuplicateTx => "duplicate transaction id",
+            TxnError::DuplicateTxOtherClient => "transaction id already used by another client",
+            TxnError::ZeroAmount => "amount is zero",
+            TxnError::AccountLocked => "account is locked",
+            TxnError::AccountClosed => "account is closed",
+            TxnError::InsufficientFunds => "insufficient available funds",
+            TxnError::TxNotFound => "referenced transaction not found",
+            TxnError::NotDisputable => "referenced transaction can't be disputed",
+            TxnError::NotDisputed => "referenced transaction is not under dispute",
+            TxnError::AlreadyDisputed => "referenced transaction is already under dispute",
+            TxnError::InvalidDisputeAmount => {
+                "dispute amount is negative or more than is left to dispute"
+            }
+            TxnError::AlreadyResolved => {
+                "dispute of the referenced transaction was already resolved"
+            }
+            TxnError::RedisputeNotAllowed => "referenced transaction can't be disputed again",
+            TxnError::CurrencyMismatch => "currency differs from the referenced transaction's",
+            TxnError::InvalidConversion => "conversion needs two different currencies",
+            TxnError::NoExchangeRate => "no exchange rate between the currencies",
+            TxnError::AlreadyChargedBack => "referenced transaction was already charged back",
+            TxnError::NotLocked => "account is not locked",
+            TxnError::InvalidRecipient => "transfer has no valid recipient",
+            TxnError::InvalidPeriods => "number of periods to accrue is negative",
+            TxnError::DisputeWindowExpired => "too late to dispute the referenced transaction",
+            TxnError::RuleRejected(code) => {
+                return write!(f, "refused by validation rule ({})", code);
+            }
+            TxnError::AlreadySeen => "already processed by an earlier run",
+            TxnError::TooPrecise => "amount has more than four decimal places",
+            TxnError::NegativeAmount => "amount is negative",
+            TxnError::NotRefundable => "referenced transaction can't be refunded",
+            TxnError::AlreadyRefunded => "referenced transaction was already refunded",
+            TxnError::NotReleasable => "referenced transaction isn't a hold that can be released",
+            TxnError::AlreadyReleased => "referenced hold was already released",
+            TxnError::LimitExceeded => "withdrawal is over the client's limit",
+            TxnError::VelocityExceeded => "too many or too much within the client's window",
+            TxnError::AmountTooLarge => "amount is too large",
+            TxnError::BalanceOverflow => "balance would overflow",
+            TxnError::SpillFailed => "reading the transaction spill file failed",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl error::Error for TxnError {}
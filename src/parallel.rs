@@ -0,0 +1,226 @@
+use crate::bank::{Bank, Client, ClientId, Transaction, TransactionType, TxId};
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::policy::TxIdPolicy;
+use crate::source::{SourceStats, TransactionSource};
+use rayon::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    io, mem,
+};
+
+// One client's slice of the input: its account (if it has one yet) and its transactions in order,
+// each with the line it was read from
+struct Partition {
+    client_id: ClientId,
+    client: Option<Client>,
+    txns: Vec<(u64, Transaction)>,
+    rejects: Vec<(u64, Transaction, TxnError)>,
+    accepted_ids: Vec<TxId>,
+}
+
+impl Bank {
+    // Like process_source_with, but applies each client's transactions on a rayon worker.
+    // Clients are independent, so only the order within a client matters and that is preserved.
+    // The whole source is read into memory first to partition it.
+    //
+    // Under TxIdPolicy::Global the cross-client check can't see the other workers, so a
+    // deposit/withdrawal reusing the tx id of another client's transaction not yet applied waits
+    // for it: everything before it is applied first and then it's applied on its own, refused
+    // only if that transaction was accepted, as it would be in input order.
+    //
+    // A transfer, or a dispute of one, touches two clients, so it's applied the same way.
+    //
+    // Rejects are reported after processing, grouped by client rather than in input order.
+    // A bank with an audit log or outcome stream, observers, validation or velocity rules, a risk
+    // scorer, an as_of day, dedup_seen or invariant checks applies the source sequentially
+    // instead, so they see the input order.
+    pub fn process_source_parallel<R, F>(
+        &mut self,
+        mut source: TransactionSource<R>,
+        mut on_reject: F,
+    ) -> Result<SourceStats, Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        if self.audit.is_some()
+            || self.outcomes.is_some()
+            || !self.observers.is_empty()
+            || !self.rules.is_empty()
+            || !self.velocity.is_empty()
+            || !self.risk.is_empty()
+            || self.policy.as_of.is_some()
+            || self.seen.is_enabled()
+            || self.invariants
+        {
+            return self.process_source_with(source, on_reject);
+        }
+        let mut stats = SourceStats::default();
+        let mut rejects = Vec::new();
+        // which client has each deposit/withdrawal tx id among the transactions not yet applied
+        let mut owners: HashMap<TxId, ClientId> = HashMap::new();
+        let mut partitions: HashMap<ClientId, Vec<(u64, Transaction)>> = HashMap::new();
+        // (client, tx) of the transfers read so far, to spot disputes of them
+        let mut transfers: HashSet<(ClientId, TxId)> = HashSet::new();
+        while let Some(txn) = self.next_from(&mut source, &mut stats)? {
+            let line = source.line();
+            if let Some(err) = source.refusal() {
+                rejects.push((line, txn, err));
+                continue;
+            }
+            let global = txn.tx_type.moves_funds() && self.policy.tx_ids == TxIdPolicy::Global;
+            if global
+                && self.tx_ids.contains(&txn.tx)
+                && !self
+                    .owns(txn.client, txn.tx)
+                    .map_err(|_| self.spill_failure())?
+            {
+                rejects.push((line, txn, TxnError::DuplicateTxOtherClient));
+                continue;
+            }
+            let contested = global && *owners.entry(txn.tx).or_insert(txn.client) != txn.client;
+            if txn.tx_type == TransactionType::Transfer {
+                transfers.insert((txn.client, txn.tx));
+            }
+            // one the spill file can't tell about goes to insert_txn, which refuses it
+            if contested
+                || transfers.contains(&(txn.client, txn.tx))
+                || self.is_cross_client(&txn).unwrap_or(true)
+            {
+                self.apply_partitions(mem::take(&mut partitions), &mut rejects);
+                owners.clear();
+                if let Err(err) = self.insert_txn(txn) {
+                    rejects.push((line, txn, err));
+                }
+                self.check_spill()?;
+                continue;
+            }
+            partitions.entry(txn.client).or_default().push((line, txn));
+        }
+        self.apply_partitions(partitions, &mut rejects);
+
+        // workers don't spill as they go, so catch up once they're done
+        self.spill_if_over();
+        self.check_spill()?;
+        for (line, txn, err) in &rejects {
+            stats.reject(txn, err, *line);
+            on_reject(txn, err)?;
+        }
+        Ok(stats)
+    }
+
+    fn apply_partitions(
+        &mut self,
+        partitions: HashMap<ClientId, Vec<(u64, Transaction)>>,
+        rejects: &mut Vec<(u64, Transaction, TxnError)>,
+    ) {
+        let mut work: Vec<Partition> = partitions
+            .into_iter()
+            .map(|(client_id, txns)| Partition {
+                client_id,
+                client: self.bank.remove(&client_id),
+                txns,
+                rejects: Vec::new(),
+                accepted_ids: Vec::new(),
+            })
+            .collect();
+
+        let policy = &self.policy;
+        work.par_iter_mut().for_each(|part| {
+            for (line, txn) in part.txns.drain(..) {
+                if part.client.is_none() && policy.opening.opens(txn.tx_type) {
+                    part.client = Some(Client::new(part.client_id));
+                }
+                let result = match &mut part.client {
+                    Some(client) => client.process_txn(txn, policy),
+                    None => Err(TxnError::UnknownClient),
+                };
+                match result {
+                    Ok(_) if txn.tx_type.moves_funds() => part.accepted_ids.push(txn.tx),
+                    Ok(_) => {}
+                    Err(err) => part.rejects.push((line, txn, err)),
+                }
+            }
+        });
+
+        for part in work {
+            if let Some(client) = part.client {
+                self.bank.insert(part.client_id, client);
+            }
+            self.tx_ids.extend(part.accepted_ids);
+            rejects.extend(part.rejects);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The report and rejects from applying csv sequentially and in parallel
+    fn both(csv: &str) -> [(Vec<String>, Vec<String>); 2] {
+        [false, true].map(|parallel| {
+            let mut bank = Bank::new();
+            let mut rejects = Vec::new();
+            let source = TransactionSource::csv(csv.as_bytes());
+            let on_reject = |txn: &Transaction, err: &TxnError| {
+                rejects.push(format!("{} {} {}", txn.client, txn.tx, err.code()));
+                Ok(())
+            };
+            if parallel {
+                bank.process_source_parallel(source, on_reject).unwrap();
+            } else {
+                bank.process_source_with(source, on_reject).unwrap();
+            }
+            rejects.sort();
+            let records = bank
+                .sorted_records()
+                .iter()
+                .map(|r| r.to_string())
+                .collect();
+            (records, rejects)
+        })
+    }
+
+    #[test]
+    fn a_refused_transactions_tx_id_is_free_for_another_client() {
+        let csv = "type,client,tx,amount\n\
+                   deposit,1,1,10\n\
+                   dispute,1,1,\n\
+                   chargeback,1,1,\n\
+                   deposit,1,2,5\n\
+                   deposit,2,2,7\n\
+                   withdrawal,3,3,1\n\
+                   deposit,3,4,4\n\
+                   deposit,4,4,1\n\
+                   deposit,3,3,2\n";
+        let [sequential, parallel] = both(csv);
+        assert_eq!(sequential, parallel);
+        assert_eq!(
+            sequential.1,
+            [
+                "1 2 account_locked",
+                "3 3 unknown_client",
+                "4 4 duplicate_tx_other_client"
+            ]
+        );
+    }
+
+    #[test]
+    fn clients_apply_the_same_as_in_input_order() {
+        let mut csv = "type,client,tx,amount\n".to_string();
+        for tx in 1..400 {
+            let client = tx % 7 + 1;
+            csv += &match tx % 5 {
+                0 => format!("dispute,{},{},\n", client, tx - 5),
+                1 => format!("withdrawal,{},{},3\n", client, tx),
+                // reusing an id another client may or may not have had accepted
+                2 => format!("deposit,{},{},2\n", client % 3 + 1, tx - 1),
+                _ => format!("deposit,{},{},{}.5\n", client, tx, tx % 4),
+            };
+        }
+        let [sequential, parallel] = both(&csv);
+        assert_eq!(sequential, parallel);
+    }
+}
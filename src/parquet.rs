@@ -0,0 +1,1052 @@
+// Parquet files, read and written by hand rather than pulling in the arrow stack for a handful
+// of flat columns.
+//
+// The account report is written as one uncompressed, PLAIN-encoded page per column per row group
+// plus the Thrift-encoded footer. client is an INT64, the balances DECIMAL(38, precision) stored as
+// 16-byte big-endian integers, locked a BOOLEAN, currency an optional UTF8 string and closed a BOOLEAN.
+//
+// Transactions are read from files as Spark, DuckDB or pyarrow write them: flat columns named like
+// the CSV headers, PLAIN or dictionary encoded, uncompressed or compressed with Snappy, gzip or
+// zstd, in v1 or v2 data pages.
+
+use crate::amount::{Amount, AmountStyle};
+use crate::bank::{ClientRecord, Transaction};
+use crate::columns::{self, Datum};
+use crate::error::Error;
+use crate::policy::PrecisionPolicy;
+use crate::report::REPORT_HEADERS;
+use crate::snappy;
+use crate::snapshot::invalid;
+use crate::source::{decided, STANDARD_COLUMNS};
+use crate::thrift::{self, Compact, Value, T_BINARY, T_I32, T_STRUCT};
+use rust_decimal::RoundingStrategy;
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+const MAGIC: &[u8] = b"PAR1";
+// clients per row group, which bounds what's held in memory and the size of each page
+const ROW_GROUP: usize = 1 << 20;
+
+// physical types
+const BOOLEAN: i32 = 0;
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const INT96: i32 = 3;
+const FLOAT: i32 = 4;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+const FIXED_LEN_BYTE_ARRAY: i32 = 7;
+// converted types
+const UTF8: i32 = 0;
+const DECIMAL: i32 = 5;
+const TIMESTAMP_MILLIS: i32 = 9;
+const TIMESTAMP_MICROS: i32 = 10;
+// repetition types
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+const REPEATED: i32 = 2;
+// encodings
+const PLAIN: i32 = 0;
+const PLAIN_DICTIONARY: i32 = 2;
+const RLE: i32 = 3;
+const DELTA_BINARY_PACKED: i32 = 5;
+const RLE_DICTIONARY: i32 = 8;
+// page types
+const DATA_PAGE: i32 = 0;
+const DICTIONARY_PAGE: i32 = 2;
+const DATA_PAGE_V2: i32 = 3;
+// compression codecs
+const UNCOMPRESSED: i32 = 0;
+const SNAPPY: i32 = 1;
+const GZIP: i32 = 2;
+const ZSTD: i32 = 6;
+
+pub(crate) fn write_parquet<W: io::Write>(
+    records: impl Iterator<Item = ClientRecord>,
+    w: W,
+    style: AmountStyle,
+) -> Result<(), Error> {
+    let mut w = io::BufWriter::new(w);
+    let scale = style.precision.min(28);
+    w.write_all(MAGIC)?;
+    let mut offset = MAGIC.len() as i64;
+    let mut groups = Vec::new();
+    let mut records = records.peekable();
+    while records.peek().is_some() {
+        let chunk: Vec<ClientRecord> = records.by_ref().take(ROW_GROUP).collect();
+        let mut columns = Vec::new();
+        for (column, page) in pages(&chunk, scale) {
+            let header = page_header(chunk.len(), page.len());
+            w.write_all(&header)?;
+            w.write_all(&page)?;
+            let size = (header.len() + page.len()) as i64;
+            columns.push(ColumnChunk {
+                column,
+                offset,
+                size,
+            });
+            offset += size;
+        }
+        groups.push((chunk.len(), columns));
+    }
+    let footer = file_metadata(&groups, scale);
+    w.write_all(&footer)?;
+    w.write_all(&(footer.len() as u32).to_le_bytes())?;
+    w.write_all(MAGIC)?;
+    w.flush()?;
+    Ok(())
+}
+
+// A column of the schema, in REPORT_HEADERS order
+#[derive(Copy, Clone)]
+struct Column {
+    name: &'static str,
+    physical: i32,
+    repetition: i32,
+    // FIXED_LEN_BYTE_ARRAY width
+    length: Option<i32>,
+    converted: Option<i32>,
+    decimal: bool,
+}
+
+const fn column(name: &'static str, physical: i32) -> Column {
+    Column {
+        name,
+        physical,
+        repetition: REQUIRED,
+        length: None,
+        converted: None,
+        decimal: false,
+    }
+}
+
+const AMOUNT: Column = Column {
+    length: Some(16),
+    converted: Some(DECIMAL),
+    decimal: true,
+    ..column("", FIXED_LEN_BYTE_ARRAY)
+};
+
+const COLUMNS: [Column; 7] = [
+    column(REPORT_HEADERS[0], INT64),
+    Column {
+        name: REPORT_HEADERS[1],
+        ..AMOUNT
+    },
+    Column {
+        name: REPORT_HEADERS[2],
+        ..AMOUNT
+    },
+    Column {
+        name: REPORT_HEADERS[3],
+        ..AMOUNT
+    },
+    column(REPORT_HEADERS[4], BOOLEAN),
+    Column {
+        repetition: OPTIONAL,
+        converted: Some(UTF8),
+        ..column(REPORT_HEADERS[5], BYTE_ARRAY)
+    },
+    column(REPORT_HEADERS[6], BOOLEAN),
+];
+
+struct ColumnChunk {
+    column: Column,
+    // where its page header starts, and its length including that header
+    offset: i64,
+    size: i64,
+}
+
+// Each column's page of values for the records
+fn pages(records: &[ClientRecord], scale: u32) -> Vec<(Column, Vec<u8>)> {
+    let mut client = Vec::with_capacity(records.len() * 8);
+    let mut amounts = [(); 3].map(|_| Vec::with_capacity(records.len() * 16));
+    let mut locked = vec![0; records.len().div_ceil(8)];
+    let mut closed = vec![0; records.len().div_ceil(8)];
+    let mut levels = Vec::with_capacity(records.len());
+    let mut currencies = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        client.extend_from_slice(&i64::from(record.client).to_le_bytes());
+        for (column, amount) in
+            amounts
+                .iter_mut()
+                .zip([record.available, record.held, record.total])
+        {
+            column.extend_from_slice(&unscaled(amount, scale).to_be_bytes());
+        }
+        if record.locked {
+            locked[i / 8] |= 1 << (i % 8);
+        }
+        if record.closed {
+            closed[i / 8] |= 1 << (i % 8);
+        }
+        levels.push(record.currency.is_some() as u8);
+        if let Some(currency) = record.currency {
+            let code = currency.to_string();
+            currencies.extend_from_slice(&(code.len() as u32).to_le_bytes());
+            currencies.extend_from_slice(code.as_bytes());
+        }
+    }
+    // a page of an optional column starts with the definition levels saying which rows have a value
+    let mut currency = rle_levels(&levels);
+    currency.extend_from_slice(&currencies);
+    let [available, held, total] = amounts;
+    COLUMNS
+        .into_iter()
+        .zip([client, available, held, total, locked, currency, closed])
+        .collect()
+}
+
+// The amount as an integer count of 10^-scale units, e.g. 1.5 at scale 4 is 15000
+fn unscaled(amount: Amount, scale: u32) -> i128 {
+    let mut decimal = amount
+        .as_decimal()
+        .round_dp_with_strategy(scale, RoundingStrategy::MidpointAwayFromZero);
+    decimal.rescale(scale);
+    decimal.mantissa()
+}
+
+// Levels of bit width 1 in the RLE/bit-packing hybrid, as runs of repeated values, with the
+// length prefix a data page puts before them
+fn rle_levels(levels: &[u8]) -> Vec<u8> {
+    let mut runs = Vec::new();
+    for run in levels.chunk_by(|a, b| a == b) {
+        thrift::varint(&mut runs, (run.len() as u64) << 1);
+        runs.push(run[0]);
+    }
+    let mut bytes = (runs.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(&runs);
+    bytes
+}
+
+fn page_header(rows: usize, size: usize) -> Vec<u8> {
+    let mut t = Compact::default();
+    t.begin();
+    // DATA_PAGE, uncompressed so both sizes are the same
+    t.i32(1, 0);
+    t.i32(2, size as i32);
+    t.i32(3, size as i32);
+    t.struct_field(5);
+    t.i32(1, rows as i32);
+    t.i32(2, PLAIN);
+    t.i32(3, RLE);
+    t.i32(4, RLE);
+    t.end();
+    t.end();
+    t.buf
+}
+
+fn file_metadata(groups: &[(usize, Vec<ColumnChunk>)], scale: u32) -> Vec<u8> {
+    let rows: usize = groups.iter().map(|(rows, _)| rows).sum();
+    let mut t = Compact::default();
+    t.begin();
+    t.i32(1, 1);
+    // the schema, flattened: the root then its columns
+    t.list(2, T_STRUCT, COLUMNS.len() + 1);
+    t.begin();
+    t.binary(4, b"schema");
+    t.i32(5, COLUMNS.len() as i32);
+    t.end();
+    for column in COLUMNS {
+        t.begin();
+        t.i32(1, column.physical);
+        if let Some(length) = column.length {
+            t.i32(2, length);
+        }
+        t.i32(3, column.repetition);
+        t.binary(4, column.name.as_bytes());
+        if let Some(converted) = column.converted {
+            t.i32(6, converted);
+        }
+        if column.decimal {
+            t.i32(7, scale as i32);
+            t.i32(8, 38);
+        }
+        t.end();
+    }
+    t.i64(3, rows as i64);
+    t.list(4, T_STRUCT, groups.len());
+    for (rows, columns) in groups {
+        t.begin();
+        t.list(1, T_STRUCT, columns.len());
+        for chunk in columns {
+            t.begin();
+            t.i64(2, chunk.offset);
+            t.struct_field(3);
+            t.i32(1, chunk.column.physical);
+            t.list(2, T_I32, 2);
+            t.element_i32(PLAIN);
+            t.element_i32(RLE);
+            t.list(3, T_BINARY, 1);
+            t.element_binary(chunk.column.name.as_bytes());
+            // UNCOMPRESSED
+            t.i32(4, 0);
+            t.i64(5, *rows as i64);
+            t.i64(6, chunk.size);
+            t.i64(7, chunk.size);
+            t.i64(9, chunk.offset);
+            t.end();
+            t.end();
+        }
+        t.i64(2, columns.iter().map(|chunk| chunk.size).sum());
+        t.i64(3, *rows as i64);
+        t.end();
+    }
+    t.binary(
+        6,
+        concat!("transactions version ", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    t.end();
+    t.buf
+}
+
+// Transactions from a Parquet file held in memory, a row group decoded at a time
+pub(crate) struct ParquetRows {
+    file: Vec<u8>,
+    leaves: Vec<Leaf>,
+    // which leaf holds each of the STANDARD_COLUMNS, if any does
+    fields: [Option<usize>; 9],
+    groups: Vec<RowGroup>,
+    next_group: usize,
+    // the decoded values of the current row group, for each of the STANDARD_COLUMNS
+    columns: [Vec<Datum>; 9],
+    // rows of the current group, and how many of them have been returned
+    group_rows: usize,
+    at: usize,
+    // rows returned from the whole file
+    row: u64,
+}
+
+// A column as the file's schema describes it
+struct Leaf {
+    name: String,
+    physical: i32,
+    // a FIXED_LEN_BYTE_ARRAY's width
+    length: usize,
+    optional: bool,
+    // decimal places of a DECIMAL column
+    scale: Option<u32>,
+    // how many of a timestamp column's units make a second
+    per_second: Option<i64>,
+}
+
+struct RowGroup {
+    rows: usize,
+    // for each leaf
+    chunks: Vec<Chunk>,
+}
+
+// Where a column chunk's pages are in the file
+struct Chunk {
+    codec: i32,
+    values: usize,
+    start: usize,
+    end: usize,
+}
+
+impl ParquetRows {
+    pub(crate) fn new(file: Vec<u8>) -> Result<ParquetRows, Error> {
+        let len = file.len();
+        if len < 12 || &file[..4] != MAGIC || &file[len - 4..] != MAGIC {
+            return Err(invalid("not a Parquet file".to_string()));
+        }
+        let footer_len = u32::from_le_bytes(file[len - 8..len - 4].try_into().expect("4 bytes"));
+        let footer = (len - 8)
+            .checked_sub(footer_len as usize)
+            .filter(|start| *start >= 4)
+            .map(|start| &file[start..len - 8])
+            .ok_or_else(|| {
+                invalid("Parquet footer length past the start of the file".to_string())
+            })?;
+        let metadata = thrift::Reader::new(footer).read_struct()?;
+        let leaves = metadata
+            .list(2)
+            .iter()
+            .skip(1)
+            .map(leaf)
+            .collect::<Result<Vec<_>, _>>()?;
+        let fields = STANDARD_COLUMNS.map(|name| leaves.iter().position(|leaf| leaf.name == name));
+        if let Some(missing) = (0..4).find(|f| fields[*f].is_none()) {
+            return Err(invalid(format!(
+                "Parquet file has no '{}' column",
+                STANDARD_COLUMNS[missing]
+            )));
+        }
+        let groups = metadata
+            .list(4)
+            .iter()
+            .map(|group| row_group(group, leaves.len(), len))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ParquetRows {
+            file,
+            leaves,
+            fields,
+            groups,
+            next_group: 0,
+            columns: Default::default(),
+            group_rows: 0,
+            at: 0,
+            row: 0,
+        })
+    }
+
+    // The row number of the last row returned, counting from 1
+    pub(crate) fn row(&self) -> u64 {
+        self.row
+    }
+
+    fn decode_group(&mut self) -> Result<(), Error> {
+        let group = &self.groups[self.next_group];
+        for (column, field) in self.columns.iter_mut().zip(self.fields) {
+            *column = match field {
+                Some(leaf) => read_chunk(&self.file, &group.chunks[leaf], &self.leaves[leaf])?,
+                None => Vec::new(),
+            };
+            if field.is_some() && column.len() != group.rows {
+                return Err(invalid(format!(
+                    "Parquet column chunk holds {} values for {} rows",
+                    column.len(),
+                    group.rows
+                )));
+            }
+        }
+        self.group_rows = group.rows;
+        self.at = 0;
+        Ok(())
+    }
+
+    fn transaction(&self, i: usize, precision: PrecisionPolicy) -> Result<Transaction, Error> {
+        let row = std::array::from_fn(|f| self.columns[f].get(i).unwrap_or(&Datum::Null));
+        let leaf = |f: usize| self.fields[f].map(|leaf| &self.leaves[leaf]);
+        columns::transaction(
+            row,
+            leaf(3).and_then(|leaf| leaf.scale),
+            leaf(5).and_then(|leaf| leaf.per_second),
+            precision,
+        )
+    }
+}
+
+impl ParquetRows {
+    // The next row, with its amount parsed as precision says
+    pub(crate) fn next(
+        &mut self,
+        precision: PrecisionPolicy,
+    ) -> Option<Result<Transaction, Error>> {
+        while self.at == self.group_rows {
+            if self.next_group == self.groups.len() {
+                return None;
+            }
+            let decoded = self.decode_group();
+            self.next_group += 1;
+            if let Err(err) = decoded {
+                // a corrupt row group means a corrupt file, so there's no carrying on past it
+                self.next_group = self.groups.len();
+                return Some(Err(err));
+            }
+        }
+        let txn = decided(
+            precision,
+            |precision| self.transaction(self.at, precision),
+            Some,
+        );
+        self.at += 1;
+        self.row += 1;
+        Some(txn.map_err(|err| err.at_line(self.row)))
+    }
+}
+
+fn leaf(element: &Value) -> Result<Leaf, Error> {
+    let element = element
+        .as_struct()
+        .ok_or_else(|| invalid("Parquet schema element isn't a struct".to_string()))?;
+    let name = String::from_utf8_lossy(element.binary(4).unwrap_or_default()).into_owned();
+    let repetition = element.i32(3).unwrap_or(REQUIRED);
+    if element.i64(5).is_some_and(|children| children > 0) || repetition == REPEATED {
+        return Err(invalid(format!(
+            "Parquet column '{}' is nested or repeated; only flat columns can be read",
+            name
+        )));
+    }
+    let logical = element.structure(10);
+    let converted = element.i32(6);
+    let scale = match converted {
+        Some(DECIMAL) => element.i32(7),
+        _ => logical
+            .and_then(|logical| logical.structure(5))
+            .and_then(|d| d.i32(1)),
+    };
+    let per_second = match converted {
+        Some(TIMESTAMP_MILLIS) => Some(1_000),
+        Some(TIMESTAMP_MICROS) => Some(1_000_000),
+        // a TimestampType's unit is a union of MILLIS, MICROS and NANOS
+        _ => logical
+            .and_then(|logical| logical.structure(8))
+            .and_then(|timestamp| timestamp.structure(2))
+            .and_then(|unit| unit.variant())
+            .and_then(|(unit, _)| match unit {
+                1 => Some(1_000),
+                2 => Some(1_000_000),
+                3 => Some(1_000_000_000),
+                _ => None,
+            }),
+    };
+    Ok(Leaf {
+        physical: element
+            .i32(1)
+            .ok_or_else(|| invalid(format!("Parquet column '{}' has no type", name)))?,
+        length: element.i32(2).unwrap_or(0).max(0) as usize,
+        optional: repetition == OPTIONAL,
+        scale: scale
+            .map(|scale| u32::try_from(scale).ok().filter(|scale| *scale <= 28))
+            .map(|scale| {
+                scale.ok_or_else(|| {
+                    invalid(format!(
+                        "Parquet column '{}' has too many decimal places",
+                        name
+                    ))
+                })
+            })
+            .transpose()?,
+        per_second,
+        name,
+    })
+}
+
+fn row_group(group: &Value, leaves: usize, file_len: usize) -> Result<RowGroup, Error> {
+    let bad = || invalid("corrupt Parquet row group metadata".to_string());
+    let group = group.as_struct().ok_or_else(bad)?;
+    let chunks = group
+        .list(1)
+        .iter()
+        .map(|chunk| {
+            let chunk = chunk.as_struct().ok_or_else(bad)?;
+            if chunk.binary(1).is_some() {
+                return Err(invalid(
+                    "Parquet columns stored in other files aren't supported".to_string(),
+                ));
+            }
+            let meta = chunk.structure(3).ok_or_else(bad)?;
+            let offset = |id| meta.i64(id).and_then(|v| usize::try_from(v).ok());
+            let data = offset(9).ok_or_else(bad)?;
+            // the dictionary page, if there is one, comes first
+            let start = offset(11)
+                .filter(|dict| *dict > 0 && *dict < data)
+                .unwrap_or(data);
+            let end = offset(7)
+                .and_then(|size| start.checked_add(size))
+                .filter(|end| *end <= file_len)
+                .ok_or_else(bad)?;
+            Ok(Chunk {
+                codec: meta.i32(4).unwrap_or(UNCOMPRESSED),
+                values: offset(5).ok_or_else(bad)?,
+                start,
+                end,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if chunks.len() != leaves {
+        return Err(bad());
+    }
+    let rows = group
+        .i64(3)
+        .and_then(|v| usize::try_from(v).ok())
+        .ok_or_else(bad)?;
+    Ok(RowGroup { rows, chunks })
+}
+
+// Every value in the column chunk, with a Null for each one not defined
+fn read_chunk(file: &[u8], chunk: &Chunk, leaf: &Leaf) -> Result<Vec<Datum>, Error> {
+    let bad = || invalid(format!("corrupt Parquet page in column '{}'", leaf.name));
+    let mut pages = &file[chunk.start..chunk.end];
+    let mut dictionary = None;
+    let mut values = Vec::with_capacity(chunk.values.min(ROW_GROUP));
+    while values.len() < chunk.values {
+        let mut reader = thrift::Reader::new(pages);
+        let header = reader.read_struct()?;
+        let size = header
+            .i32(3)
+            .and_then(|v| usize::try_from(v).ok())
+            .ok_or_else(bad)?;
+        let uncompressed = header
+            .i32(2)
+            .and_then(|v| usize::try_from(v).ok())
+            .ok_or_else(bad)?;
+        let body = pages[reader.position()..].get(..size).ok_or_else(bad)?;
+        pages = &pages[reader.position() + size..];
+        match header.i32(1) {
+            Some(DICTIONARY_PAGE) => {
+                let n = header.structure(7).and_then(|h| h.i64(1)).ok_or_else(bad)?;
+                let data = decompress(chunk.codec, body, uncompressed, leaf)?;
+                dictionary = Some(plain(&data, leaf, n as usize)?);
+            }
+            Some(DATA_PAGE) => {
+                let page = header.structure(5).ok_or_else(bad)?;
+                let n = page
+                    .i32(1)
+                    .and_then(|v| usize::try_from(v).ok())
+                    .ok_or_else(bad)?;
+                let data = decompress(chunk.codec, body, uncompressed, leaf)?;
+                let (levels, data) = if leaf.optional {
+                    let len = data.get(..4).ok_or_else(bad)?;
+                    let len = u32::from_le_bytes(len.try_into().expect("4 bytes")) as usize;
+                    let levels = data.get(4..4 + len).ok_or_else(bad)?;
+                    (Some(rle(levels, 1, n).ok_or_else(bad)?), &data[4 + len..])
+                } else {
+                    (None, &data[..])
+                };
+                let encoding = page.i32(2).ok_or_else(bad)?;
+                page_values(&mut values, n, levels, data, encoding, leaf, &dictionary)?;
+            }
+            Some(DATA_PAGE_V2) => {
+                let page = header.structure(8).ok_or_else(bad)?;
+                let count = |id| {
+                    page.i32(id)
+                        .and_then(|v| usize::try_from(v).ok())
+                        .ok_or_else(bad)
+                };
+                let n = count(1)?;
+                let (repeats, defines) = (count(6)?, count(5)?);
+                // the levels are never compressed, and have no length before them
+                let levels = body.get(repeats..repeats + defines).ok_or_else(bad)?;
+                let data = &body[repeats + defines..];
+                let data = if page.bool(7).unwrap_or(true) {
+                    let len = uncompressed
+                        .checked_sub(repeats + defines)
+                        .ok_or_else(bad)?;
+                    decompress(chunk.codec, data, len, leaf)?
+                } else {
+                    Cow::Borrowed(data)
+                };
+                let levels = match leaf.optional {
+                    true => Some(rle(levels, 1, n).ok_or_else(bad)?),
+                    false => None,
+                };
+                let encoding = page.i32(4).ok_or_else(bad)?;
+                page_values(&mut values, n, levels, &data, encoding, leaf, &dictionary)?;
+            }
+            // index pages and anything newer carry no values
+            _ => {}
+        }
+        if pages.is_empty() && values.len() < chunk.values {
+            return Err(bad());
+        }
+    }
+    Ok(values)
+}
+
+fn decompress<'a>(
+    codec: i32,
+    data: &'a [u8],
+    len: usize,
+    leaf: &Leaf,
+) -> Result<Cow<'a, [u8]>, Error> {
+    let out = match codec {
+        UNCOMPRESSED => return Ok(Cow::Borrowed(data)),
+        SNAPPY => snappy::decompress(data)?,
+        #[cfg(feature = "gzip")]
+        GZIP => {
+            let mut out = Vec::with_capacity(len.min(ROW_GROUP * 16));
+            io::Read::read_to_end(&mut flate2::read::MultiGzDecoder::new(data), &mut out)?;
+            out
+        }
+        #[cfg(feature = "zstd")]
+        ZSTD => {
+            let mut out = Vec::with_capacity(len.min(ROW_GROUP * 16));
+            io::Read::read_to_end(&mut zstd::Decoder::with_buffer(data)?, &mut out)?;
+            out
+        }
+        _ => {
+            let name = match codec {
+                GZIP => "gzip",
+                3 => "LZO",
+                4 => "Brotli",
+                5 | 7 => "LZ4",
+                ZSTD => "zstd",
+                _ => "an unknown codec",
+            };
+            return Err(invalid(format!(
+                "Parquet column '{}' is compressed with {}, which this build can't read",
+                leaf.name, name
+            )));
+        }
+    };
+    if out.len() != len {
+        return Err(invalid(format!(
+            "Parquet column '{}' has a page that decompresses to {} bytes rather than {}",
+            leaf.name,
+            out.len(),
+            len
+        )));
+    }
+    Ok(Cow::Owned(out))
+}
+
+// Decodes a data page's n values, Nulls included, onto the end of values
+fn page_values(
+    values: &mut Vec<Datum>,
+    n: usize,
+    levels: Option<Vec<u32>>,
+    data: &[u8],
+    encoding: i32,
+    leaf: &Leaf,
+    dictionary: &Option<Vec<Datum>>,
+) -> Result<(), Error> {
+    let bad = || invalid(format!("corrupt Parquet page in column '{}'", leaf.name));
+    let present = match &levels {
+        Some(levels) => levels.iter().filter(|level| **level > 0).count(),
+        None => n,
+    };
+    let decoded = match encoding {
+        PLAIN => plain(data, leaf, present)?,
+        PLAIN_DICTIONARY | RLE_DICTIONARY => {
+            let dictionary = dictionary.as_ref().ok_or_else(bad)?;
+            let (width, indices) = data.split_first().ok_or_else(bad)?;
+            rle(indices, *width, present)
+                .ok_or_else(bad)?
+                .into_iter()
+                .map(|i| dictionary.get(i as usize).cloned().ok_or_else(bad))
+                .collect::<Result<_, _>>()?
+        }
+        DELTA_BINARY_PACKED => delta(data, present)
+            .ok_or_else(bad)?
+            .into_iter()
+            .map(Datum::Int)
+            .collect(),
+        _ => {
+            return Err(invalid(format!(
+                "Parquet column '{}' uses encoding {}, which isn't supported",
+                leaf.name, encoding
+            )))
+        }
+    };
+    match levels {
+        None => values.extend(decoded),
+        Some(levels) => {
+            let mut decoded = decoded.into_iter();
+            for level in levels {
+                values.push(match level {
+                    0 => Datum::Null,
+                    _ => decoded.next().unwrap_or(Datum::Null),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn plain(data: &[u8], leaf: &Leaf, n: usize) -> Result<Vec<Datum>, Error> {
+    let bad = || invalid(format!("corrupt Parquet page in column '{}'", leaf.name));
+    let mut values = Vec::with_capacity(n.min(data.len() * 8));
+    let mut rest = data;
+    let mut take = |len: usize| {
+        let (bytes, after) = rest.split_at_checked(len).ok_or_else(bad)?;
+        rest = after;
+        Ok::<_, Error>(bytes)
+    };
+    for i in 0..n {
+        values.push(match leaf.physical {
+            BOOLEAN => Datum::Int((data.get(i / 8).ok_or_else(bad)? >> (i % 8) & 1).into()),
+            INT32 => Datum::Int(i32::from_le_bytes(take(4)?.try_into().expect("4 bytes")).into()),
+            INT64 => Datum::Int(i64::from_le_bytes(take(8)?.try_into().expect("8 bytes"))),
+            // the legacy timestamp: nanoseconds into the day, then the Julian day number
+            INT96 => {
+                let bytes = take(12)?;
+                let nanos = i64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"));
+                let day = i64::from(u32::from_le_bytes(bytes[8..].try_into().expect("4 bytes")));
+                Datum::Int((day - 2_440_588) * 86_400 + nanos / 1_000_000_000)
+            }
+            // by way of the shortest string that reads back the same, so 0.1 stays 0.1
+            FLOAT => {
+                let v = f32::from_le_bytes(take(4)?.try_into().expect("4 bytes"));
+                Datum::Float(v.to_string().parse().unwrap_or(f64::NAN))
+            }
+            DOUBLE => Datum::Float(f64::from_le_bytes(take(8)?.try_into().expect("8 bytes"))),
+            BYTE_ARRAY => {
+                let len = u32::from_le_bytes(take(4)?.try_into().expect("4 bytes"));
+                Datum::Bytes(take(len as usize)?.into())
+            }
+            FIXED_LEN_BYTE_ARRAY => Datum::Bytes(take(leaf.length)?.into()),
+            other => {
+                return Err(invalid(format!(
+                    "unknown Parquet type {} in column '{}'",
+                    other, leaf.name
+                )))
+            }
+        });
+    }
+    Ok(values)
+}
+
+// n values of the RLE/bit-packing hybrid, each width bits wide; None if the data runs out first
+fn rle(mut data: &[u8], width: u8, n: usize) -> Option<Vec<u32>> {
+    if width > 32 {
+        return None;
+    }
+    let width = width as usize;
+    let mut values = Vec::with_capacity(n);
+    while values.len() < n {
+        let header = uleb(&mut data)?;
+        let count = usize::try_from(header >> 1).ok()?;
+        if header & 1 == 0 {
+            let (bytes, rest) = data.split_at_checked(width.div_ceil(8))?;
+            data = rest;
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0u32, |v, byte| v << 8 | u32::from(*byte));
+            values.extend(std::iter::repeat_n(value, count.min(n - values.len())));
+        } else {
+            // groups of eight values, packed from the lowest bit up
+            let (packed, rest) = data.split_at_checked(count.checked_mul(width)?)?;
+            data = rest;
+            for i in 0..count.saturating_mul(8).min(n - values.len()) {
+                values.push(unpack(packed, i * width, width));
+            }
+        }
+    }
+    Some(values)
+}
+
+// n integers of DELTA_BINARY_PACKED: blocks of miniblocks, each bit-packed differences from the
+// previous value over the block's minimum
+fn delta(mut data: &[u8], n: usize) -> Option<Vec<i64>> {
+    let block = usize::try_from(uleb(&mut data)?).ok()?;
+    let miniblocks = usize::try_from(uleb(&mut data)?).ok()?;
+    let _total = uleb(&mut data)?;
+    let mut last = zigzag(uleb(&mut data)?);
+    if miniblocks == 0 || block % miniblocks != 0 {
+        return None;
+    }
+    let per_miniblock = block / miniblocks;
+    let mut values = Vec::with_capacity(n);
+    if n > 0 {
+        values.push(last);
+    }
+    while values.len() < n {
+        let min = zigzag(uleb(&mut data)?);
+        let (widths, rest) = data.split_at_checked(miniblocks)?;
+        data = rest;
+        for width in widths {
+            let width = *width as usize;
+            if width > 64 {
+                return None;
+            }
+            let (packed, rest) = data.split_at_checked(per_miniblock.checked_mul(width)? / 8)?;
+            data = rest;
+            for i in 0..per_miniblock {
+                if values.len() == n {
+                    break;
+                }
+                last = last
+                    .wrapping_add(min)
+                    .wrapping_add(unpack64(packed, i * width, width) as i64);
+                values.push(last);
+            }
+            if values.len() == n {
+                break;
+            }
+        }
+    }
+    Some(values)
+}
+
+// The width-bit value starting at the given bit, lowest bits first; zero past the end
+fn unpack(packed: &[u8], bit: usize, width: usize) -> u32 {
+    unpack64(packed, bit, width) as u32
+}
+
+fn unpack64(packed: &[u8], bit: usize, width: usize) -> u64 {
+    let mut v = 0u128;
+    for (i, byte) in packed
+        .iter()
+        .skip(bit / 8)
+        .take((bit % 8 + width).div_ceil(8))
+        .enumerate()
+    {
+        v |= u128::from(*byte) << (8 * i);
+    }
+    let mask = if width == 64 {
+        u64::MAX
+    } else {
+        (1u64 << width) - 1
+    };
+    (v >> (bit % 8)) as u64 & mask
+}
+
+fn uleb(data: &mut &[u8]) -> Option<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = data.split_first()?;
+        *data = rest;
+        v |= u64::from(byte & 0x7f) << shift;
+        if *byte < 0x80 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+fn zigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::csv_row;
+
+    // The files tests/fixtures/parquet/make.py writes, each laid out as one of the writers this
+    // reads from does
+    const DICTIONARY_SNAPPY: &[u8] =
+        include_bytes!("../tests/fixtures/parquet/dictionary_snappy.parquet");
+    const SPARK_INT96: &[u8] = include_bytes!("../tests/fixtures/parquet/spark_int96.parquet");
+    const V2_DELTA_ZSTD: &[u8] = include_bytes!("../tests/fixtures/parquet/v2_delta_zstd.parquet");
+    const PLAIN_GZIP: &[u8] = include_bytes!("../tests/fixtures/parquet/plain_gzip.parquet");
+
+    fn read(file: &[u8]) -> Result<Vec<String>, Error> {
+        let mut rows = ParquetRows::new(file.to_vec())?;
+        std::iter::from_fn(|| rows.next(PrecisionPolicy::Round))
+            .map(|txn| txn.map(|txn| csv_row(&txn)))
+            .collect()
+    }
+
+    #[test]
+    fn reads_dictionary_pages_over_row_groups() {
+        assert_eq!(
+            read(DICTIONARY_SNAPPY).unwrap(),
+            [
+                "deposit,1,1,10.5000,,1700000000",
+                "deposit,2,2,2.2500,,1700000060",
+                "withdrawal,1,3,1.5000",
+                "dispute,1,1,,,1700000180",
+                "deposit,1,4,0.1000,,1700000240",
+                "deposit,2,5,7.1250,,1700000300",
+                "deposit,1,6,3.0000,,1700000360",
+                "resolve,1,1,",
+                "withdrawal,2,7,1.2500,,1700000480",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_int96_timestamps_and_a_chunk_falling_back_to_plain() {
+        assert_eq!(
+            read(SPARK_INT96).unwrap(),
+            [
+                "deposit,1,1,100.5000,,1700000000",
+                "deposit,2,2,20.1234,,1700000060",
+                "withdrawal,1,3,0.5000,,1700000120",
+                "dispute,2,2,,,1700000180",
+                "chargeback,2,2,,,1700000240",
+                "deposit,3,4,1.0000,,1700000300",
+                "deposit,3,5,2.0000,,1700000360",
+                "withdrawal,3,6,2.5000,,1700000420",
+                "dispute,3,5,,,1700000480",
+                "deposit,4,7,12.3400,,1700000540,EUR",
+                "deposit,4,8,0.0001,,1700000600,EUR",
+                "withdrawal,4,9,5.0000,,1700000660,EUR",
+            ]
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn reads_v2_pages_of_delta_encoded_integers() {
+        // the rows make.py's v2_rows writes
+        let expected = (1..=300u32).map(|i| {
+            let client = i % 5 + 1;
+            let transfer = i % 10 == 0;
+            let amount = i * 12_345 % 1_000_000;
+            let mut row = vec![
+                if transfer { "transfer" } else { "deposit" }.to_string(),
+                client.to_string(),
+                i.to_string(),
+                format!("{}.{:04}", amount / 10_000, amount % 10_000),
+                if transfer {
+                    (client % 5 + 1).to_string()
+                } else {
+                    String::new()
+                },
+                if i % 7 != 0 {
+                    (1_700_000_000 + i * 3 / 2).to_string()
+                } else {
+                    String::new()
+                },
+                String::new(),
+                String::new(),
+                if i % 3 == 0 {
+                    format!("2024-01-{:02}", i % 28 + 1)
+                } else {
+                    String::new()
+                },
+            ];
+            while row.len() > 4 && row.last().is_some_and(String::is_empty) {
+                row.pop();
+            }
+            row.join(",")
+        });
+        assert_eq!(read(V2_DELTA_ZSTD).unwrap(), expected.collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn finds_columns_by_name() {
+        assert_eq!(
+            read(PLAIN_GZIP).unwrap(),
+            [
+                "deposit,7,100,1.5000",
+                "deposit,8,101,999999.9900",
+                "withdrawal,7,102,0.2500",
+                "dispute,8,101,",
+                "resolve,8,101,",
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_what_it_cant_read() {
+        let err = read(include_bytes!("../tests/fixtures/parquet/brotli.parquet")).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("column 'amount' is compressed with Brotli"),
+            "{}",
+            err
+        );
+        let err = read(include_bytes!("../tests/fixtures/parquet/nested.parquet")).unwrap_err();
+        assert!(
+            err.to_string().contains("column 'meta' is nested"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn reads_the_hybrid_encoding() {
+        // a run of five 3s, then one group of eight bit-packed 3-bit values, 0 to 7
+        let data = [5 << 1, 3, 1 << 1 | 1, 0b1000_1000, 0b1100_0110, 0b1111_1010];
+        assert_eq!(rle(&data, 3, 7), Some(vec![3, 3, 3, 3, 3, 0, 1]));
+        assert_eq!(
+            rle(&data, 3, 13),
+            Some(vec![3, 3, 3, 3, 3, 0, 1, 2, 3, 4, 5, 6, 7])
+        );
+        assert_eq!(rle(&data, 3, 14), None);
+        // zero-width values take no bytes, however many a run claims
+        let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(rle(&huge, 0, 3), Some(vec![0, 0, 0]));
+    }
+
+    // A corrupt file fails to read rather than panicking, wherever it's corrupt
+    #[test]
+    fn survives_corruption() {
+        for file in [DICTIONARY_SNAPPY, SPARK_INT96, V2_DELTA_ZSTD, PLAIN_GZIP] {
+            for at in 0..file.len() {
+                let mut file = file.to_vec();
+                file[at] ^= 0xff;
+                let _ = read(&file);
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::bank::{Bank, Transaction};
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::source::SourceStats;
+
+// Forward-dated transactions. One whose effective_date is after the policy's as_of day isn't
+// applied yet: insert_txn queues it and reports it as pending, and it waits in the bank (and its
+// snapshots) until a run whose as_of has reached it applies it with apply_due. Without an as_of
+// every transaction applies when it arrives, whatever its date.
+impl Bank {
+    // The queued transactions, in the order they arrived
+    pub fn pending(&self) -> &[Transaction] {
+        &self.pending
+    }
+
+    // Queues txn if it isn't due yet, returning whether it did
+    pub(crate) fn queue_if_pending(&mut self, txn: &Transaction) -> bool {
+        let Some(as_of) = self.policy.as_of else {
+            return false;
+        };
+        let pending = txn.effective_date.is_some_and(|date| date > as_of);
+        if pending {
+            self.pending.push(*txn);
+        }
+        pending
+    }
+
+    // Applies the queued transactions as_of has reached, in the order they were queued, calling
+    // on_reject for each the engine refuses. The rest stay queued.
+    pub fn apply_due<F>(&mut self, mut on_reject: F) -> Result<SourceStats, Error>
+    where
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        let as_of = self.policy.as_of;
+        let (due, waiting) =
+            std::mem::take(&mut self.pending)
+                .into_iter()
+                .partition(|txn: &Transaction| {
+                    as_of.is_none_or(|as_of| txn.effective_date.is_none_or(|date| date <= as_of))
+                });
+        self.pending = waiting;
+        let mut stats = SourceStats::default();
+        for (line, txn) in (1..).zip(due) {
+            stats.rows += 1;
+            let result = self.insert_txn(txn);
+            self.check_spill()?;
+            if let Err(err) = result {
+                stats.reject(&txn, &err, line);
+                on_reject(&txn, &err)?;
+            }
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::bank::{TransactionType, TxId};
+    use crate::date::Date;
+    use crate::outcome::TxnOutcome;
+    use crate::policy::Policy;
+
+    fn txn(tx_type: TransactionType, tx: TxId, amount: &str, date: Option<&str>) -> Transaction {
+        Transaction {
+            tx_type,
+            client: 1,
+            tx,
+            amount: amount.parse().unwrap(),
+            to: None,
+            timestamp: None,
+            currency: None,
+            to_currency: None,
+            effective_date: date.map(|date| date.parse().unwrap()),
+        }
+    }
+
+    fn as_of(date: &str) -> Policy {
+        Policy {
+            as_of: Some(date.parse::<Date>().unwrap()),
+            ..Policy::default()
+        }
+    }
+
+    fn available(bank: &Bank) -> Amount {
+        bank.get_client(1).unwrap().available()
+    }
+
+    #[test]
+    fn forward_dated_transactions_wait_until_they_are_due() {
+        let mut bank = Bank::with_policy(as_of("2026-03-01"));
+        let due = txn(TransactionType::Deposit, 1, "5", Some("2026-03-01"));
+        let later = txn(TransactionType::Withdrawal, 2, "2", Some("2026-03-02"));
+        assert_eq!(bank.insert_txn(due), Ok(TxnOutcome::Deposited));
+        assert_eq!(bank.insert_txn(later), Ok(TxnOutcome::Pending));
+        assert_eq!(
+            bank.pending().iter().map(|txn| txn.tx).collect::<Vec<_>>(),
+            [2]
+        );
+        assert_eq!(available(&bank), "5".parse().unwrap());
+
+        // not due yet, so it stays queued
+        let stats = bank.apply_due(|_, _| Ok(())).unwrap();
+        assert_eq!((stats.rows, bank.pending().len()), (0, 1));
+
+        bank.set_policy(as_of("2026-03-02"));
+        let stats = bank.apply_due(|_, _| Ok(())).unwrap();
+        assert_eq!((stats.rows, stats.rejected), (1, 0));
+        assert!(bank.pending().is_empty());
+        assert_eq!(available(&bank), "3".parse().unwrap());
+    }
+
+    #[test]
+    fn a_due_transaction_the_engine_refuses_is_reported() {
+        let mut bank = Bank::with_policy(as_of("2026-03-01"));
+        bank.insert_txn(txn(TransactionType::Deposit, 1, "1", None))
+            .unwrap();
+        let later = txn(TransactionType::Withdrawal, 2, "2", Some("2026-04-01"));
+        assert_eq!(bank.insert_txn(later), Ok(TxnOutcome::Pending));
+
+        bank.set_policy(Policy::default());
+        let mut refused = Vec::new();
+        let stats = bank
+            .apply_due(|txn, err| {
+                refused.push((txn.tx, *err));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(refused, [(2, TxnError::InsufficientFunds)]);
+        assert_eq!(available(&bank), "1".parse().unwrap());
+    }
+}
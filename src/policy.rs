@@ -0,0 +1,306 @@
+// Knobs controlling how the engine treats transactions where the spec leaves room for interpretation
+
+use crate::bank::{Transaction, TransactionType};
+use crate::date::Date;
+use crate::fx::Rates;
+use crate::limits::Limits;
+use crate::outcome::TxnError;
+use rust_decimal::Decimal;
+use std::{fmt, str::FromStr, sync::Arc};
+
+// Policies are named on the command line by these kebab-case strings
+macro_rules! policy_names {
+    ($ty:ident { $($variant:ident => $name:literal),+ $(,)? }) => {
+        impl $ty {
+            pub const NAMES: &'static [&'static str] = &[$($name),+];
+        }
+
+        impl FromStr for $ty {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<$ty, String> {
+                match s {
+                    $($name => Ok($ty::$variant),)+
+                    _ => Err(format!("unknown {} '{}'", stringify!($ty), s)),
+                }
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $($ty::$variant => f.write_str($name),)+
+                }
+            }
+        }
+    };
+}
+
+// Also applies to fees
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum WithdrawalPolicy {
+    // A withdrawal larger than the available funds is rejected and leaves the account untouched
+    #[default]
+    RejectIfInsufficient,
+    // Withdrawals always go through, letting available go negative
+    AllowOverdraft,
+}
+
+policy_names!(WithdrawalPolicy {
+    RejectIfInsufficient => "reject",
+    AllowOverdraft => "overdraft",
+});
+
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum DisputePolicy {
+    // Disputes referencing anything but a deposit are ignored
+    #[default]
+    DepositsOnly,
+    // Withdrawals can be disputed too. The withdrawn funds are credited back to available while
+    // held goes negative by the same amount, so total is unchanged until the dispute settles.
+    DepositsAndWithdrawals,
+}
+
+policy_names!(DisputePolicy {
+    DepositsOnly => "deposits",
+    DepositsAndWithdrawals => "deposits-and-withdrawals",
+});
+
+// How far a deposit/withdrawal tx id has to be unique
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum TxIdPolicy {
+    // No two deposits/withdrawals may share a tx id, whichever clients they belong to
+    #[default]
+    Global,
+    // Ids only have to be unique within each client
+    PerClient,
+}
+
+policy_names!(TxIdPolicy {
+    Global => "global",
+    PerClient => "per-client",
+});
+
+// Which transactions for a client with no account open one
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum AccountOpeningPolicy {
+    // Only a deposit; anything else is refused as UnknownClient
+    #[default]
+    DepositOnly,
+    // Any transaction that carries its own amount: a deposit, withdrawal, transfer, fee or accrual
+    AnyFunding,
+    // Every transaction, even a dispute that can't refer to anything yet. Also opens the
+    // recipient of a transfer.
+    Always,
+}
+
+policy_names!(AccountOpeningPolicy {
+    DepositOnly => "deposit",
+    AnyFunding => "funding",
+    Always => "always",
+});
+
+impl AccountOpeningPolicy {
+    pub fn opens(&self, tx_type: TransactionType) -> bool {
+        match self {
+            AccountOpeningPolicy::DepositOnly => tx_type == TransactionType::Deposit,
+            // a hold only ever moves funds the account already has
+            AccountOpeningPolicy::AnyFunding => {
+                tx_type.moves_funds() && tx_type != TransactionType::Hold
+            }
+            AccountOpeningPolicy::Always => true,
+        }
+    }
+}
+
+// What a locked account still takes, besides the unlock that reinstates it and a close
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum LockedPolicy {
+    // Nothing else, refusing it as AccountLocked
+    #[default]
+    Frozen,
+    // Disputes, resolves and chargebacks, so a dispute still open when another's chargeback
+    // locked the account can run its course; nothing that moves funds in or out
+    Disputes,
+}
+
+policy_names!(LockedPolicy {
+    Frozen => "frozen",
+    Disputes => "disputes",
+});
+
+impl LockedPolicy {
+    // Whether a locked account takes a transaction of tx_type
+    pub fn allows(&self, tx_type: TransactionType) -> bool {
+        let admin = matches!(tx_type, TransactionType::Unlock | TransactionType::Close);
+        let dispute = matches!(
+            tx_type,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+        );
+        admin || (*self == LockedPolicy::Disputes && dispute)
+    }
+}
+
+// How many times a transaction can be disputed again after a dispute of it is resolved
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum RedisputePolicy {
+    #[default]
+    Unlimited,
+    // Once resolved, a transaction can't be disputed again
+    Forbid,
+    // Up to this many disputes after the first one is resolved
+    Times(u32),
+}
+
+impl RedisputePolicy {
+    // Whether a transaction whose disputes were resolved this many times can be disputed again
+    pub fn allows(&self, resolutions: u32) -> bool {
+        match self {
+            RedisputePolicy::Unlimited => true,
+            RedisputePolicy::Forbid => false,
+            RedisputePolicy::Times(n) => resolutions <= *n,
+        }
+    }
+}
+
+// Named "unlimited", "forbid" or "once", or given as a number of times
+impl FromStr for RedisputePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RedisputePolicy, String> {
+        match s {
+            "unlimited" => Ok(RedisputePolicy::Unlimited),
+            "forbid" => Ok(RedisputePolicy::Forbid),
+            "once" => Ok(RedisputePolicy::Times(1)),
+            _ => s
+                .parse()
+                .map(RedisputePolicy::Times)
+                .map_err(|_| format!("unknown RedisputePolicy '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for RedisputePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedisputePolicy::Unlimited => f.write_str("unlimited"),
+            RedisputePolicy::Forbid => f.write_str("forbid"),
+            RedisputePolicy::Times(1) => f.write_str("once"),
+            RedisputePolicy::Times(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+// What to do with an input row that fails to parse
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum OnError {
+    // Stop processing and return the error
+    #[default]
+    Abort,
+    // Skip the row, only counting it
+    Skip,
+    // Skip the row, keeping the error so it can be shown to the operator
+    Report,
+}
+
+policy_names!(OnError {
+    Abort => "abort",
+    Skip => "skip",
+    Report => "report",
+});
+
+// What to do once a run has found batches whose rows don't match their control totals, see
+// control.rs. Either way the rows have been applied; the run's output is what this decides.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum ControlPolicy {
+    // Fail the run with Error::Control rather than report the balances
+    #[default]
+    Fail,
+    // Warn about each batch and carry on
+    Warn,
+}
+
+policy_names!(ControlPolicy {
+    Fail => "fail",
+    Warn => "warn",
+});
+
+// What to do with a transaction amount given to more than amount::SCALE decimal places. It's
+// decided as the amount is parsed, since an Amount can't hold the places past SCALE.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum PrecisionPolicy {
+    // Round it half away from zero to four places and apply it
+    #[default]
+    Round,
+    // Refuse the transaction with TxnError::TooPrecise, recorded like any other refusal
+    Reject,
+}
+
+policy_names!(PrecisionPolicy {
+    Round => "round",
+    Reject => "reject",
+});
+
+// Whether a deposit or withdrawal may carry a negative amount, reversing its effect. Some
+// adjustment-style feeds correct earlier rows this way. The other transactions that carry an
+// amount of their own, fees, transfers, conversions and holds, are refused one whatever the
+// policy: reversed, they'd credit the account or move funds out of the wrong one.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum NegativeAmountPolicy {
+    // Refuse it with TxnError::NegativeAmount
+    #[default]
+    Reject,
+    // Apply it as given: a negative deposit debits the account, a negative withdrawal credits it
+    Allow,
+}
+
+policy_names!(NegativeAmountPolicy {
+    Reject => "reject",
+    Allow => "allow",
+});
+
+impl NegativeAmountPolicy {
+    pub(crate) fn check(self, txn: &Transaction) -> Result<(), TxnError> {
+        let refused = match txn.tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                self == NegativeAmountPolicy::Reject
+            }
+            TransactionType::Fee
+            | TransactionType::Transfer
+            | TransactionType::Convert
+            | TransactionType::Hold => true,
+            // an accrual's amount is a number of periods, the rest refer to an earlier
+            // transaction's and check theirs against it
+            _ => false,
+        };
+        if refused && txn.amount.is_negative() {
+            Err(TxnError::NegativeAmount)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub withdrawal: WithdrawalPolicy,
+    pub dispute: DisputePolicy,
+    pub tx_ids: TxIdPolicy,
+    pub on_error: OnError,
+    pub opening: AccountOpeningPolicy,
+    pub locked: LockedPolicy,
+    // how many days after a transaction it can still be disputed, unlimited if None. Only
+    // enforced when both the transaction and the dispute have a timestamp.
+    pub dispute_window_days: Option<u32>,
+    pub redispute: RedisputePolicy,
+    // interest paid per period by accrue rows, e.g. 0.0001 for 0.01% a day; zero pays none
+    pub interest_rate: Decimal,
+    // exchange rates for convert rows, shared by every copy of the policy
+    pub rates: Arc<Rates>,
+    pub precision: PrecisionPolicy,
+    pub negative: NegativeAmountPolicy,
+    // caps on withdrawals, shared like the rates
+    pub limits: Arc<Limits>,
+    // the day the run is for: transactions taking effect after it wait, see pending.rs
+    pub as_of: Option<Date>,
+}
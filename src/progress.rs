@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// A status line on stderr, redrawn every second while the inputs are read:
+//
+//     1200000 lines, 410000 lines/s, 38%, about 5s left
+//
+// Lines are counted after decompression and bytes before, so the percentage and estimate, which
+// go by the inputs' sizes on disk, hold for compressed files too. Reading from stdin there's no
+// telling how much is left, so only the count and rate are shown.
+pub struct Progress {
+    counts: Arc<Counts>,
+    stop: Option<mpsc::Sender<()>>,
+    printer: Option<thread::JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Counts {
+    lines: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Progress {
+    pub fn start(paths: &[PathBuf]) -> Progress {
+        let total = paths
+            .iter()
+            .map(|path| match path.to_str() {
+                Some("-") => None,
+                _ => fs::metadata(path).ok().map(|meta| meta.len()),
+            })
+            .sum::<Option<u64>>();
+        let counts = Arc::new(Counts::default());
+        let (stop, stopped) = mpsc::channel();
+        let printer = {
+            let counts = counts.clone();
+            let started = Instant::now();
+            thread::spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(Duration::from_secs(1))
+                {
+                    print_status(&counts, started, total, false);
+                }
+                print_status(&counts, started, total, true);
+            })
+        };
+        Progress {
+            counts,
+            stop: Some(stop),
+            printer: Some(printer),
+        }
+    }
+
+    // raw as read from disk, counting its bytes
+    pub fn raw<R: Read>(&self, raw: R) -> Counted<R> {
+        Counted {
+            inner: raw,
+            counts: self.counts.clone(),
+            lines: false,
+        }
+    }
+
+    // decompressed as parsed, counting its lines
+    pub fn lines<R: Read>(&self, decompressed: R) -> Counted<R> {
+        Counted {
+            inner: decompressed,
+            counts: self.counts.clone(),
+            lines: true,
+        }
+    }
+}
+
+// The last line stays up, ended so what follows starts on a line of its own
+impl Drop for Progress {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(printer) = self.printer.take() {
+            let _ = printer.join();
+        }
+    }
+}
+
+pub struct Counted<R> {
+    inner: R,
+    counts: Arc<Counts>,
+    lines: bool,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if self.lines {
+            let lines = buf[..n].iter().filter(|b| **b == b'\n').count();
+            self.counts.lines.fetch_add(lines as u64, Ordering::Relaxed);
+        } else {
+            self.counts.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        Ok(n)
+    }
+}
+
+fn print_status(counts: &Counts, started: Instant, total: Option<u64>, last: bool) {
+    let lines = counts.lines.load(Ordering::Relaxed);
+    let bytes = counts.bytes.load(Ordering::Relaxed);
+    let elapsed = started.elapsed().as_secs_f64();
+    let mut status = format!("{} lines, {:.0} lines/s", lines, lines as f64 / elapsed);
+    if let Some(total) = total.filter(|total| *total > 0) {
+        let done = (bytes as f64 / total as f64).min(1.0);
+        status.push_str(&format!(", {:.0}%", done * 100.0));
+        if !last && done > 0.0 {
+            let left = elapsed / done - elapsed;
+            status.push_str(&format!(", about {:.0}s left", left));
+        }
+    }
+    // the escape clears what's left of a longer earlier line
+    let end = if last { "\n" } else { "" };
+    let _ = write!(io::stderr().lock(), "\r{}\x1b[K{}", status, end);
+}
@@ -0,0 +1,239 @@
+// QIF exports, the older line-based format personal finance software reads, with
+// --input-format qif. A record is a line per field, each starting with its code, ending at a
+// line of ^:
+//
+//   !Type:Bank
+//   D10/14'26
+//   T-25.00
+//   PGrocer
+//   ^
+//
+// Only the records of bank, cash, credit card and other asset or liability sections are
+// transactions; investment, category, class and memorized records are passed over, as are the
+// account list's. T (or U) is the signed amount from the account holder's side, so a positive
+// one is a deposit and a negative one a withdrawal of its size, and a zero one is passed over. D
+// is the date, month first, which is the timestamp at its midnight UTC; a year written after an
+// apostrophe, or in two digits below 70, is in the 2000s.
+//
+// QIF has no account number and no ids, so the client has to be given with the source, and the
+// tx id is a hash of the record's date, amount, payee and check number, along with how many
+// identical records came before it in the file, see ofx::tx_id. Exports of the same account give
+// an entry the same id each time, so a bank that has already applied an entry, from an earlier
+// export overlapping this one, refuses it again as a duplicate_tx. Like OFX's, an entry whose id
+// is already another's fails the run, see imports.rs.
+
+use crate::amount::{Amount, AmountError};
+use crate::bank::{ClientId, Transaction, TransactionType};
+use crate::date::Date;
+use crate::error::Error;
+use crate::ofx::{tx_id, Entry};
+use crate::policy::PrecisionPolicy;
+use crate::snapshot::invalid;
+use crate::source::decided;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Read,
+};
+
+// Transactions from a QIF file, which is read whole before the first
+pub(crate) struct QifRows<R: Read> {
+    reader: Option<R>,
+    client: Option<ClientId>,
+    // each with the line its record started on, and what identifies it (see transaction)
+    rows: VecDeque<Entry>,
+    line: u64,
+    key: Option<String>,
+}
+
+impl<R: Read> QifRows<R> {
+    pub(crate) fn new(reader: R, client: Option<ClientId>) -> QifRows<R> {
+        QifRows {
+            reader: Some(reader),
+            client,
+            rows: VecDeque::new(),
+            line: 0,
+            key: None,
+        }
+    }
+
+    // The line the last row returned started on, counting from 1
+    pub(crate) fn line(&self) -> u64 {
+        self.line
+    }
+
+    // What identifies the last row returned, that its tx id is a hash of
+    pub(crate) fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+}
+
+impl<R: Read> QifRows<R> {
+    // The next row, with its amount parsed as precision says
+    pub(crate) fn next(
+        &mut self,
+        precision: PrecisionPolicy,
+    ) -> Option<Result<Transaction, Error>> {
+        if let Some(mut reader) = self.reader.take() {
+            let Some(client) = self.client else {
+                return Some(Err(invalid(
+                    "QIF files don't say whose account they're for, so the client has to be given"
+                        .to_string(),
+                )));
+            };
+            let mut bytes = Vec::new();
+            if let Err(err) = reader.read_to_end(&mut bytes) {
+                return Some(Err(Error::from(err)));
+            }
+            self.rows = parse(&String::from_utf8_lossy(&bytes), client, precision);
+        }
+        let (line, txn) = self.rows.pop_front()?;
+        self.line = line;
+        self.key = None;
+        Some(match txn {
+            Ok((txn, key)) => {
+                self.key = Some(key);
+                Ok(txn)
+            }
+            Err(err) => Err(err.at_line(line)),
+        })
+    }
+}
+
+// The fields of a record, by code
+#[derive(Default)]
+struct Record<'a> {
+    start: u64,
+    date: Option<&'a str>,
+    amount: Option<&'a str>,
+    payee: &'a str,
+    number: &'a str,
+}
+
+// Every record that moves funds, in order
+fn parse(text: &str, client: ClientId, precision: PrecisionPolicy) -> VecDeque<Entry> {
+    let mut rows = VecDeque::new();
+    let mut record: Option<Record> = None;
+    // a file without a !Type line is taken to be a bank account's
+    let mut transactions = true;
+    let mut identical = HashMap::new();
+    let mut end = |record: Option<Record>, transactions: bool| {
+        let Some(record) = record.filter(|_| transactions) else {
+            return;
+        };
+        // a refused amount fails before identical is counted in, so it's counted in just once
+        let txn = decided(
+            precision,
+            |precision| transaction(&record, client, &mut identical, precision),
+            |txn| txn.map(|(txn, _)| txn),
+        );
+        match txn {
+            Ok(Some(txn)) => rows.push_back((record.start, Ok(txn))),
+            Ok(None) => {}
+            Err(err) => rows.push_back((record.start, Err(err))),
+        }
+    };
+    for (line, text) in (1..).zip(text.lines()) {
+        let text = text.trim();
+        let Some(code) = text.chars().next() else {
+            continue;
+        };
+        let value = text[code.len_utf8()..].trim();
+        match code {
+            '!' => {
+                end(record.take(), transactions);
+                let header = value.to_ascii_lowercase();
+                if let Some(section) = header.strip_prefix("type:") {
+                    transactions = matches!(
+                        section.trim(),
+                        "bank" | "cash" | "ccard" | "oth a" | "oth l"
+                    );
+                } else if header == "account" {
+                    transactions = false;
+                }
+            }
+            '^' => end(record.take(), transactions),
+            _ => {
+                let record = record.get_or_insert_with(|| Record {
+                    start: line,
+                    ..Record::default()
+                });
+                match code {
+                    'D' => record.date = Some(value),
+                    'T' => record.amount = Some(value),
+                    'U' => record.amount = record.amount.or(Some(value)),
+                    'P' => record.payee = value,
+                    'N' => record.number = value,
+                    _ => {}
+                }
+            }
+        }
+    }
+    end(record.take(), transactions);
+    rows
+}
+
+// The transaction a record stands for, None for one of no amount
+fn transaction(
+    record: &Record,
+    client: ClientId,
+    identical: &mut HashMap<String, u32>,
+    precision: PrecisionPolicy,
+) -> Result<Option<(Transaction, String)>, Error> {
+    let written = record
+        .amount
+        .filter(|amount| !amount.is_empty())
+        .ok_or_else(|| Error::Value("QIF record has no amount".to_string()))?;
+    let amount = Amount::parse(&written.replace(',', ""), precision).map_err(|err| match err {
+        AmountError::Invalid => Error::Value(format!("invalid QIF amount '{}'", written)),
+        err => Error::Value(err.to_string()),
+    })?;
+    if amount.is_zero() {
+        return Ok(None);
+    }
+    let date = record
+        .date
+        .ok_or_else(|| Error::Value("QIF record has no date".to_string()))?;
+    let date = parse_date(date)?;
+    let key = format!("{}|{}|{}|{}", date, written, record.payee, record.number);
+    let seen = identical.entry(key.clone()).or_insert(0);
+    *seen += 1;
+    let (tx_type, amount) = if amount.is_negative() {
+        (TransactionType::Withdrawal, -amount)
+    } else {
+        (TransactionType::Deposit, amount)
+    };
+    let key = format!("{}#{}", key, seen);
+    let txn = Transaction {
+        tx_type,
+        client,
+        tx: tx_id(&key),
+        amount,
+        to: None,
+        timestamp: Some(date.timestamp()),
+        currency: None,
+        to_currency: None,
+        effective_date: None,
+    };
+    Ok(Some((txn, key)))
+}
+
+// A month-first date such as 10/14/2026, 10/14/26, 10/14'26 or 10-14-2026, or a YYYY-MM-DD one
+fn parse_date(s: &str) -> Result<Date, Error> {
+    let invalid = || Error::Value(format!("invalid QIF date '{}'", s));
+    if let Ok(date) = s.parse() {
+        return Ok(date);
+    }
+    let parts: Vec<&str> = s.split(['/', '-', '.', '\'']).map(str::trim).collect();
+    let [month, day, year] = parts[..] else {
+        return Err(invalid());
+    };
+    let number = |part: &str| part.parse::<u32>().map_err(|_| invalid());
+    let (month, day, written) = (number(month)?, number(day)?, number(year)?);
+    let year = match year.len() {
+        4 => i64::from(written),
+        2 if s.contains('\'') || written < 70 => 2000 + i64::from(written),
+        2 => 1900 + i64::from(written),
+        _ => return Err(invalid()),
+    };
+    Date::from_ymd(year, month, day).ok_or_else(invalid)
+}
@@ -0,0 +1,240 @@
+// Comparing two sets of account records. reconcile checks two account reports against each other,
+// e.g. this build's against a golden output kept from the last release, each read with
+// Bank::read_accounts. Accounts are matched by client and currency; they differ if either report
+// lacks one, if their locked flags differ, or if their available or held balances are further
+// apart than the tolerance, which allows for reports written at different precisions.
+//
+// changes is for two states of the same bank, e.g. the snapshots before and after a batch: how
+// much each account's balances moved, and which accounts were locked or unlocked along the way.
+
+use crate::amount::Amount;
+use crate::bank::{ClientId, ClientRecord};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::snapshot::invalid;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::{collections::BTreeMap, io};
+
+// An account that isn't the same in both reports, None on the side that lacks it
+#[derive(Debug, Clone, Copy)]
+pub struct Mismatch {
+    pub client: ClientId,
+    pub currency: Option<Currency>,
+    pub a: Option<ClientRecord>,
+    pub b: Option<ClientRecord>,
+}
+
+// The accounts that differ between a and b, in client and then currency order
+pub fn reconcile(a: &[ClientRecord], b: &[ClientRecord], tolerance: Decimal) -> Vec<Mismatch> {
+    let near = |x: Amount, y: Amount| (x.as_decimal() - y.as_decimal()).abs() <= tolerance;
+    paired(a, b)
+        .into_iter()
+        .filter(|(_, pair)| match pair {
+            (Some(a), Some(b)) => {
+                a.locked != b.locked || !near(a.available, b.available) || !near(a.held, b.held)
+            }
+            _ => true,
+        })
+        .map(|((client, currency), (a, b))| Mismatch {
+            client,
+            currency,
+            a,
+            b,
+        })
+        .collect()
+}
+
+// Each account in either a or b, with its record from each side that has one
+fn paired(a: &[ClientRecord], b: &[ClientRecord]) -> BTreeMap<Account, Pair> {
+    let mut accounts: BTreeMap<Account, Pair> = BTreeMap::new();
+    for record in a {
+        accounts
+            .entry((record.client, record.currency))
+            .or_default()
+            .0 = Some(*record);
+    }
+    for record in b {
+        accounts
+            .entry((record.client, record.currency))
+            .or_default()
+            .1 = Some(*record);
+    }
+    accounts
+}
+
+type Account = (ClientId, Option<Currency>);
+type Pair = (Option<ClientRecord>, Option<ClientRecord>);
+
+#[derive(Serialize)]
+struct MismatchRow {
+    client: ClientId,
+    currency: Option<Currency>,
+    a_available: Option<Amount>,
+    b_available: Option<Amount>,
+    a_held: Option<Amount>,
+    b_held: Option<Amount>,
+    a_locked: Option<bool>,
+    b_locked: Option<bool>,
+}
+
+// One CSV row per mismatch, with both reports' values side by side and the missing side's empty
+pub fn write_mismatches<W: io::Write>(w: W, mismatches: &[Mismatch]) -> Result<(), Error> {
+    let mut wtr = csv::Writer::from_writer(w);
+    if mismatches.is_empty() {
+        // serialize writes the header with the first row, and there isn't one
+        wtr.write_record([
+            "client",
+            "currency",
+            "a_available",
+            "b_available",
+            "a_held",
+            "b_held",
+            "a_locked",
+            "b_locked",
+        ])?;
+    }
+    for mismatch in mismatches {
+        let (a, b) = (mismatch.a.as_ref(), mismatch.b.as_ref());
+        wtr.serialize(MismatchRow {
+            client: mismatch.client,
+            currency: mismatch.currency,
+            a_available: a.map(|a| a.available),
+            b_available: b.map(|b| b.available),
+            a_held: a.map(|a| a.held),
+            b_held: b.map(|b| b.held),
+            a_locked: a.map(|a| a.locked),
+            b_locked: b.map(|b| b.locked),
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// How an account moved between two states, amounts as after minus before. An account only in
+// after started from zero, and one only in before went to zero.
+#[derive(Debug, Clone, Copy)]
+pub struct Change {
+    pub client: ClientId,
+    pub currency: Option<Currency>,
+    pub available: Amount,
+    pub held: Amount,
+    // Some(true) if it was locked in between, Some(false) if it was unlocked
+    pub locked: Option<bool>,
+}
+
+impl Change {
+    // An error where the two add up to more than an amount can hold, as they can from balances
+    // read out of report files
+    pub fn total(&self) -> Result<Amount, Error> {
+        self.available
+            .checked_add(self.held)
+            .ok_or_else(|| overflow(self.client))
+    }
+}
+
+fn overflow(client: ClientId) -> Error {
+    invalid(format!(
+        "client {}'s balances change by too much to count",
+        client
+    ))
+}
+
+// The accounts whose balances or locked state differ between before and after, in client and
+// then currency order. An error where an account's balances moved by more than an amount can
+// hold, which records read from report files can make them.
+pub fn changes(before: &[ClientRecord], after: &[ClientRecord]) -> Result<Vec<Change>, Error> {
+    paired(before, after)
+        .into_iter()
+        .map(|((client, currency), (before, after))| {
+            let balances = |record: Option<ClientRecord>| {
+                record.map_or((Amount::ZERO, Amount::ZERO, false), |record| {
+                    (record.available, record.held, record.locked)
+                })
+            };
+            let (available, held, was_locked) = balances(before);
+            let (now_available, now_held, locked) = balances(after);
+            let moved = |now: Amount, was: Amount| now.checked_sub(was).ok_or(overflow(client));
+            Ok(Change {
+                client,
+                currency,
+                available: moved(now_available, available)?,
+                held: moved(now_held, held)?,
+                locked: (locked != was_locked).then_some(locked),
+            })
+        })
+        .filter(|change| {
+            change.as_ref().map_or(true, |change| {
+                !change.available.is_zero() || !change.held.is_zero() || change.locked.is_some()
+            })
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct ChangeRow {
+    client: ClientId,
+    currency: Option<Currency>,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    // "locked" or "unlocked", empty if neither
+    locked: &'static str,
+}
+
+// One CSV row per changed account
+pub fn write_changes<W: io::Write>(w: W, changes: &[Change]) -> Result<(), Error> {
+    let mut wtr = csv::Writer::from_writer(w);
+    if changes.is_empty() {
+        wtr.write_record(["client", "currency", "available", "held", "total", "locked"])?;
+    }
+    for change in changes {
+        wtr.serialize(ChangeRow {
+            client: change.client,
+            currency: change.currency,
+            available: change.available,
+            held: change.held,
+            total: change.total()?,
+            locked: match change.locked {
+                Some(true) => "locked",
+                Some(false) => "unlocked",
+                None => "",
+            },
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(client: ClientId, available: i64, held: i64) -> ClientRecord {
+        ClientRecord {
+            client,
+            currency: None,
+            available: Amount::from_units(available),
+            held: Amount::from_units(held),
+            total: Amount::ZERO,
+            locked: false,
+            closed: false,
+        }
+    }
+
+    #[test]
+    fn balances_that_move_too_far_name_their_client() {
+        let before = [record(1, 5, 0), record(2, -i64::MAX, 0)];
+        let after = [record(1, 7, 1), record(2, i64::MAX, 0)];
+        let err = changes(&before, &after).unwrap_err();
+        assert!(err.to_string().contains("client 2"), "{}", err);
+
+        let changed = changes(&before[..1], &after[..1]).unwrap();
+        assert_eq!(changed[0].total().unwrap(), Amount::from_units(3));
+        let change = Change {
+            available: Amount::from_units(i64::MAX),
+            ..changed[0]
+        };
+        assert!(change.total().unwrap_err().to_string().contains("client 1"));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::amount::Amount;
+use crate::bank::{ClientId, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::error::Error;
+use serde::{de, Deserialize, Deserializer};
+use std::str::FromStr;
+
+// Standing orders in CSV input. A row of type recurring, with interval and count columns, stands
+// for count deposits (or, for a negative amount, withdrawals of its size) an interval apart: the
+// source hands them to the engine one by one, as though each had its own row. The first has the
+// row's tx id and the others the ids after it, and if the row has an effective_date or timestamp
+// each falls an interval after the one before, so with an as_of policy the later ones wait as
+// pending until they're due.
+//
+//   type,client,tx,amount,effective_date,interval,count
+//   recurring,1,100,-25.00,2026-01-15,1m,12
+//
+// is twelve monthly withdrawals of 25, tx ids 100 to 111, from January 15th 2026.
+
+// The most occurrences one row can expand into, so a typo can't flood the engine
+pub const MAX_OCCURRENCES: u32 = 10_000;
+
+// How far apart the occurrences are: a number of days (d), weeks (w) or calendar months (m)
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Interval {
+    Days(u32),
+    Months(u32),
+}
+
+impl Interval {
+    // The day n intervals after date
+    pub fn after(self, date: Date, n: u32) -> Date {
+        match self {
+            Interval::Days(days) => date.add_days(i64::from(days) * i64::from(n)),
+            Interval::Months(months) => date.add_months(i64::from(months) * i64::from(n)),
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Interval, String> {
+        let invalid = || format!("invalid interval '{}', expected e.g. 7d, 2w or 1m", s);
+        let (n, unit) = s.split_at(s.len() - s.chars().last().map_or(0, char::len_utf8));
+        let n: u32 = n.parse().ok().filter(|n| *n > 0).ok_or_else(invalid)?;
+        match unit {
+            "d" => Ok(Interval::Days(n)),
+            "w" => n.checked_mul(7).map(Interval::Days).ok_or_else(invalid),
+            "m" => Ok(Interval::Months(n)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Interval {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Interval, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.trim().parse().map_err(de::Error::custom)
+    }
+}
+
+// A recurring row, under the same header as the rest of the input
+#[derive(Deserialize)]
+pub(crate) struct Recurring {
+    client: ClientId,
+    tx: TxId,
+    amount: Amount,
+    interval: Interval,
+    count: u32,
+    #[serde(default)]
+    timestamp: Option<i64>,
+    #[serde(default)]
+    currency: Option<Currency>,
+    #[serde(default)]
+    effective_date: Option<Date>,
+}
+
+impl Recurring {
+    // The row's amount as written, negative for withdrawals
+    pub(crate) fn amount(&self) -> Amount {
+        self.amount
+    }
+
+    // The transactions the row stands for, in order
+    pub(crate) fn expand(&self) -> Result<Vec<Transaction>, Error> {
+        if self.amount.is_zero() {
+            return Err(Error::Value("recurring amount is zero".to_string()));
+        }
+        if !(1..=MAX_OCCURRENCES).contains(&self.count) {
+            return Err(Error::Value(format!(
+                "recurring count must be from 1 to {}",
+                MAX_OCCURRENCES
+            )));
+        }
+        let (tx_type, amount) = if self.amount.is_negative() {
+            (TransactionType::Withdrawal, -self.amount)
+        } else {
+            (TransactionType::Deposit, self.amount)
+        };
+        (0..self.count)
+            .map(|n| {
+                let tx = self
+                    .tx
+                    .checked_add(TxId::from(n))
+                    .ok_or_else(|| Error::Value("recurring tx ids run out of range".to_string()))?;
+                let timestamp = self
+                    .timestamp
+                    .map(|timestamp| {
+                        let day = Date::of_timestamp(timestamp);
+                        let days =
+                            self.interval.after(day, n).days_since_epoch() - day.days_since_epoch();
+                        days.checked_mul(24 * 60 * 60)
+                            .and_then(|seconds| timestamp.checked_add(seconds))
+                            .ok_or_else(|| {
+                                Error::Value("recurring timestamps run out of range".to_string())
+                            })
+                    })
+                    .transpose()?;
+                Ok(Transaction {
+                    tx_type,
+                    client: self.client,
+                    tx,
+                    amount,
+                    to: None,
+                    timestamp,
+                    currency: self.currency,
+                    to_currency: None,
+                    effective_date: self.effective_date.map(|date| self.interval.after(date, n)),
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,250 @@
+use crate::amount::{Amount, AmountStyle};
+use crate::bank::{Bank, ClientId, ClientRecord, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::parquet;
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+// currency is empty for a record of the unlabelled balance, i.e. always for single-currency input.
+// closed comes last so readers going by position still find the others where they were.
+pub const REPORT_HEADERS: [&str; 7] = [
+    "client",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "currency",
+    "closed",
+];
+
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    // a single JSON array holding every client
+    Json,
+    // one JSON object per line
+    Ndjson,
+    // a typed, columnar file for analytics tools, see parquet.rs
+    Parquet,
+}
+
+impl OutputFormat {
+    pub const NAMES: &'static [&'static str] = &["csv", "json", "ndjson", "parquet"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Ndjson => write!(f, "ndjson"),
+            OutputFormat::Parquet => write!(f, "parquet"),
+        }
+    }
+}
+
+// Clients are written in id order, so the same input always gives the same report
+pub fn write_report<W: io::Write>(bank: &Bank, w: W, format: OutputFormat) -> Result<(), Error> {
+    write_records(bank.sorted_records().into_iter(), w, format)
+}
+
+// Clients in whatever order the bank holds them, skipping the sort
+pub fn write_report_unsorted<W: io::Write>(
+    bank: &Bank,
+    w: W,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    write_records(bank.records(), w, format)
+}
+
+// The report for records from anywhere, e.g. a store other than the in-memory bank
+pub fn write_records<W: io::Write>(
+    records: impl Iterator<Item = ClientRecord>,
+    w: W,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    write_records_styled(records, w, format, AmountStyle::default())
+}
+
+// Like write_records, with amounts written in the given style rather than to four places
+pub fn write_records_styled<W: io::Write>(
+    records: impl Iterator<Item = ClientRecord>,
+    w: W,
+    format: OutputFormat,
+    style: AmountStyle,
+) -> Result<(), Error> {
+    match format {
+        OutputFormat::Csv => write_csv(records, w, style),
+        OutputFormat::Json => write_json(records, w, style),
+        OutputFormat::Ndjson => write_ndjson(records, w, style),
+        OutputFormat::Parquet => parquet::write_parquet(records, w, style),
+    }
+}
+
+fn write_csv<W: io::Write>(
+    records: impl Iterator<Item = ClientRecord>,
+    w: W,
+    style: AmountStyle,
+) -> Result<(), Error> {
+    // headers are written by hand so an empty bank still produces a valid report
+    let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+    wtr.write_record(REPORT_HEADERS)?;
+    for record in records {
+        wtr.write_record([
+            record.client.to_string(),
+            record.available.format(style),
+            record.held.format(style),
+            record.total.format(style),
+            record.locked.to_string(),
+            record.currency.map(|c| c.to_string()).unwrap_or_default(),
+            record.closed.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// The array is written a record at a time rather than collected, same as the CSV path
+fn write_json<W: io::Write>(
+    records: impl Iterator<Item = ClientRecord>,
+    w: W,
+    style: AmountStyle,
+) -> Result<(), Error> {
+    let mut w = io::BufWriter::new(w);
+    w.write_all(b"[")?;
+    for (i, record) in records.enumerate() {
+        if i > 0 {
+            w.write_all(b",")?;
+        }
+        w.write_all(b"\n")?;
+        serde_json::to_writer(&mut w, &JsonRecord::styled(record, style))?;
+    }
+    w.write_all(b"\n]\n")?;
+    w.flush()?;
+    Ok(())
+}
+
+fn write_ndjson<W: io::Write>(
+    records: impl Iterator<Item = ClientRecord>,
+    w: W,
+    style: AmountStyle,
+) -> Result<(), Error> {
+    let mut w = io::BufWriter::new(w);
+    for record in records {
+        serde_json::to_writer(&mut w, &JsonRecord::styled(record, style))?;
+        w.write_all(b"\n")?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+// Amounts go out as JSON numbers (not strings) while keeping their exact digits
+#[derive(Serialize)]
+pub(crate) struct JsonRecord {
+    client: ClientId,
+    available: serde_json::Number,
+    held: serde_json::Number,
+    total: serde_json::Number,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    closed: bool,
+}
+
+impl From<ClientRecord> for JsonRecord {
+    fn from(record: ClientRecord) -> JsonRecord {
+        JsonRecord::styled(record, AmountStyle::default())
+    }
+}
+
+impl JsonRecord {
+    fn styled(record: ClientRecord, style: AmountStyle) -> JsonRecord {
+        let number = |amount: Amount| {
+            serde_json::Number::from_str(&amount.format(style))
+                .expect("amounts always format as valid JSON numbers")
+        };
+        JsonRecord {
+            client: record.client,
+            available: number(record.available),
+            held: number(record.held),
+            total: number(record.total),
+            locked: record.locked,
+            currency: record.currency,
+            closed: record.closed,
+        }
+    }
+}
+
+// CSV of transactions the engine refused, each with its reason code
+pub struct RejectsWriter<W: io::Write> {
+    wtr: csv::Writer<W>,
+}
+
+#[derive(Serialize)]
+struct RejectRecord {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    // left blank for dispute/resolve/chargeback/unlock rows, which carry no amount unless it's a
+    // partial dispute
+    amount: Option<Amount>,
+    reason: &'static str,
+}
+
+impl<W: io::Write> RejectsWriter<W> {
+    pub fn new(w: W) -> Result<RejectsWriter<W>, Error> {
+        let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+        wtr.write_record(["type", "client", "tx", "amount", "reason"])?;
+        Ok(RejectsWriter { wtr })
+    }
+
+    // For adding to a rejects file that already has its header, e.g. when resuming a run
+    pub fn continuing(w: W) -> RejectsWriter<W> {
+        let wtr = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+        RejectsWriter { wtr }
+    }
+
+    // The underlying writer; flush first for it to have everything written so far
+    pub fn get_ref(&self) -> &W {
+        self.wtr.get_ref()
+    }
+
+    pub fn write(&mut self, txn: &Transaction, err: &TxnError) -> Result<(), Error> {
+        let amount = (txn.tx_type.moves_funds() || !txn.amount.is_zero()).then_some(txn.amount);
+        self.wtr.serialize(RejectRecord {
+            tx_type: txn.tx_type,
+            client: txn.client,
+            tx: txn.tx,
+            amount,
+            reason: err.code(),
+        })?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
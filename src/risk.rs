@@ -0,0 +1,151 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, Client, Transaction, TransactionType, TxnRecord};
+use crate::ledger::Account;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::policy::Policy;
+use std::fmt;
+
+// Fraud screening of deposits, by a scorer of the bank's own choosing. Every deposit is scored
+// before it's applied, and one scoring above the threshold is credited to held rather than
+// available, as though the whole of it were disputed as soon as it arrived: a resolve releases
+// the funds and a chargeback reverses the deposit and locks the account, as for any dispute.
+// That resolve counts towards the RedisputePolicy like any other.
+//
+// Only deposits are screened, being the only transactions whose funds the engine can hold back
+// until someone has looked at them.
+pub trait RiskScorer: Send {
+    // client is the deposit's client's account, None if it has none yet
+    fn score(&self, txn: &Transaction, client: Option<&Client>) -> f64;
+}
+
+#[derive(Default)]
+pub(crate) struct Risk {
+    scorer: Option<(Box<dyn RiskScorer>, f64)>,
+}
+
+impl Risk {
+    #[cfg(feature = "parallel")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.scorer.is_none()
+    }
+}
+
+impl fmt::Debug for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.scorer {
+            Some((_, threshold)) => write!(f, "Risk(threshold {})", threshold),
+            None => f.write_str("Risk(none)"),
+        }
+    }
+}
+
+impl Bank {
+    // The bank, holding every deposit scorer scores above threshold from now on. A bank has one
+    // scorer; setting another replaces it.
+    pub fn with_risk_scorer(mut self, scorer: impl RiskScorer + 'static, threshold: f64) -> Bank {
+        self.risk.scorer = Some((Box::new(scorer), threshold));
+        self
+    }
+
+    // Whether txn is a deposit to apply into held, see Client::process_held
+    pub(crate) fn risky(&self, txn: &Transaction) -> bool {
+        match &self.risk.scorer {
+            Some((scorer, threshold)) if txn.tx_type == TransactionType::Deposit => {
+                scorer.score(txn, self.bank.get(&txn.client)) > *threshold
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Client {
+    // Applies the deposit txn with the whole of it disputed at once. The dispute is opened on the
+    // deposit's record before either is applied, so a deposit that can't be held, e.g. a
+    // negative one under NegativeAmountPolicy::Allow, is refused whole rather than left credited
+    // to available.
+    pub(crate) fn process_held(
+        &mut self,
+        txn: Transaction,
+        policy: &Policy,
+    ) -> Result<TxnOutcome, TxnError> {
+        let currency = self.admit(&txn, policy)?;
+        let outcome = self.in_currency(currency, |client| {
+            client.check_new(&txn)?;
+            let mut record = TxnRecord::new(&txn);
+            let portion = record.open_dispute(Amount::ZERO, policy.redispute)?;
+            client.post(Account::Settlement, Account::Available, txn.amount)?;
+            if let Err(err) = client.post(Account::Available, Account::Held, portion) {
+                client.unpost(Account::Settlement, Account::Available, txn.amount);
+                return Err(err);
+            }
+            client.txns.insert(txn.tx, record);
+            Ok(TxnOutcome::Held)
+        });
+        debug_assert!(self.balanced(), "client {} is off balance", self.client);
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{ClientId, TxId};
+    use crate::policy::NegativeAmountPolicy;
+
+    struct Always;
+
+    impl RiskScorer for Always {
+        fn score(&self, _: &Transaction, _: Option<&Client>) -> f64 {
+            1.0
+        }
+    }
+
+    fn deposit(tx: TxId, amount: &str) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx,
+            amount: amount.parse().unwrap(),
+            to: None,
+            timestamp: None,
+            currency: None,
+            to_currency: None,
+            effective_date: None,
+        }
+    }
+
+    fn balance(bank: &Bank, client: ClientId) -> (String, String) {
+        let record = bank.record(client).unwrap();
+        (record.available.to_string(), record.held.to_string())
+    }
+
+    #[test]
+    fn risky_deposits_are_applied_into_held() {
+        let mut bank = Bank::new().with_risk_scorer(Always, 0.5);
+        assert_eq!(bank.insert_txn(deposit(1, "2.5")), Ok(TxnOutcome::Held));
+        assert_eq!(balance(&bank, 1), ("0.0000".into(), "2.5000".into()));
+        let resolve = Transaction {
+            tx_type: TransactionType::Resolve,
+            ..deposit(1, "0")
+        };
+        assert_eq!(bank.insert_txn(resolve), Ok(TxnOutcome::Resolved));
+        assert_eq!(balance(&bank, 1), ("2.5000".into(), "0.0000".into()));
+    }
+
+    #[test]
+    fn a_deposit_that_cant_be_held_is_refused_whole() {
+        let policy = Policy {
+            negative: NegativeAmountPolicy::Allow,
+            ..Policy::default()
+        };
+        let mut bank = Bank::with_policy(policy).with_risk_scorer(Always, 0.5);
+        assert_eq!(bank.insert_txn(deposit(1, "5")), Ok(TxnOutcome::Held));
+        assert_eq!(
+            bank.insert_txn(deposit(2, "-1")),
+            Err(TxnError::InvalidDisputeAmount)
+        );
+        assert_eq!(balance(&bank, 1), ("0.0000".into(), "5.0000".into()));
+        // nothing was recorded, so the id is still free
+        assert_eq!(bank.insert_txn(deposit(2, "1")), Ok(TxnOutcome::Held));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, Client, ClientId, Transaction, TransactionType};
+use crate::outcome::TxnError;
+use std::{collections::HashSet, fmt};
+
+// Checks of a bank's own choosing, run on every transaction before the engine's: a transaction
+// any rule rejects is refused with TxnError::RuleRejected and the rule's reason code, and leaves
+// the bank untouched. Rules run in the order they were added, and the first rejection wins.
+pub trait ValidationRule: Send {
+    // client is the transaction's client's account, None if it has none yet
+    fn check(&self, txn: &Transaction, client: Option<&Client>) -> RuleResult;
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum RuleResult {
+    Pass,
+    // with a stable, machine-readable reason code for the rejects report
+    Reject(&'static str),
+}
+
+// Refuses deposits, withdrawals, transfers, fees and conversions for more than the limit
+#[derive(Debug, Copy, Clone)]
+pub struct MaxAmount(pub Amount);
+
+impl ValidationRule for MaxAmount {
+    fn check(&self, txn: &Transaction, _client: Option<&Client>) -> RuleResult {
+        // an accrual's amount is a number of periods, not funds, and a hold's stays in the account
+        let limited = txn.tx_type.moves_funds()
+            && !matches!(txn.tx_type, TransactionType::Accrue | TransactionType::Hold);
+        if limited && txn.amount > self.0 {
+            RuleResult::Reject("amount_over_limit")
+        } else {
+            RuleResult::Pass
+        }
+    }
+}
+
+// Refuses every transaction from a client not in the set, and transfers to one
+#[derive(Debug, Clone)]
+pub struct AllowedClients(pub HashSet<ClientId>);
+
+impl ValidationRule for AllowedClients {
+    fn check(&self, txn: &Transaction, _client: Option<&Client>) -> RuleResult {
+        let to = txn.to.filter(|_| txn.tx_type == TransactionType::Transfer);
+        if !self.0.contains(&txn.client) || to.is_some_and(|to| !self.0.contains(&to)) {
+            RuleResult::Reject("client_not_allowed")
+        } else {
+            RuleResult::Pass
+        }
+    }
+}
+
+// Refuses transactions timestamped outside start_hour..end_hour UTC, and at weekends when
+// weekdays_only is set. Rows without a timestamp can't be told apart, so they're let through.
+#[derive(Debug, Copy, Clone)]
+pub struct BusinessHours {
+    pub start_hour: u32,
+    pub end_hour: u32,
+    pub weekdays_only: bool,
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+impl ValidationRule for BusinessHours {
+    fn check(&self, txn: &Transaction, _client: Option<&Client>) -> RuleResult {
+        let Some(timestamp) = txn.timestamp else {
+            return RuleResult::Pass;
+        };
+        let hour = timestamp.rem_euclid(SECONDS_PER_DAY) / 3600;
+        // the Unix epoch was a Thursday, so day 0 is weekday 3 counting from Monday
+        let weekday = (timestamp.div_euclid(SECONDS_PER_DAY) + 3).rem_euclid(7);
+        let in_hours = (i64::from(self.start_hour)..i64::from(self.end_hour)).contains(&hour);
+        if !in_hours || (self.weekdays_only && weekday >= 5) {
+            RuleResult::Reject("outside_business_hours")
+        } else {
+            RuleResult::Pass
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Rules(Vec<Box<dyn ValidationRule>>);
+
+impl Rules {
+    #[cfg(feature = "parallel")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rules({})", self.0.len())
+    }
+}
+
+impl Bank {
+    // The bank, also checking every transaction against rule from now on
+    pub fn with_rule(mut self, rule: impl ValidationRule + 'static) -> Bank {
+        self.rules.0.push(Box::new(rule));
+        self
+    }
+
+    pub(crate) fn check_rules(&self, txn: &Transaction) -> Result<(), TxnError> {
+        let client = self.bank.get(&txn.client);
+        for rule in &self.rules.0 {
+            if let RuleResult::Reject(code) = rule.check(txn, client) {
+                return Err(TxnError::RuleRejected(code));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use crate::bank::{Bank, ClientId, Transaction, TransactionType, TxId};
+use crate::outcome::TxnError;
+use std::collections::HashSet;
+
+// A row as far as telling a repeat goes: its type, client and tx id
+pub(crate) type SeenTxn = (TransactionType, ClientId, TxId);
+
+// The rows processed so far, kept in snapshots so a later run fed a file that overlaps the ones
+// before it can tell which of its rows were already handled, see Bank::dedup_seen
+#[derive(Debug, Default)]
+pub(crate) struct Seen {
+    enabled: bool,
+    // from the snapshot the bank was loaded from
+    earlier: HashSet<SeenTxn>,
+    // processed since, while enabled
+    since: HashSet<SeenTxn>,
+}
+
+impl Seen {
+    pub(crate) fn loaded(earlier: impl IntoIterator<Item = SeenTxn>) -> Seen {
+        Seen {
+            earlier: earlier.into_iter().collect(),
+            ..Seen::default()
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // In no particular order; kept even when not enabled, so a run without dedup_seen doesn't
+    // lose what the ones before it recorded
+    pub(crate) fn all(&self) -> impl Iterator<Item = SeenTxn> + '_ {
+        self.earlier.union(&self.since).copied()
+    }
+}
+
+impl Bank {
+    // Refuse rows the runs up to the snapshot this bank was loaded from already processed, with
+    // TxnError::AlreadySeen, and record the rest in later snapshots. Applied or refused, a row
+    // is only handled once across runs. Only earlier runs count: a run's own repeats are dealt
+    // with as usual, as a dispute may legitimately follow a resolve of the same tx.
+    pub fn dedup_seen(&mut self) {
+        self.seen.enabled = true;
+    }
+
+    pub(crate) fn check_seen(&mut self, txn: &Transaction) -> Result<(), TxnError> {
+        if !self.seen.enabled {
+            return Ok(());
+        }
+        let key = (txn.tx_type, txn.client, txn.tx);
+        if self.seen.earlier.contains(&key) {
+            return Err(TxnError::AlreadySeen);
+        }
+        self.seen.since.insert(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::bank::{Bank, ClientId};
+use crate::source::{InputFormat, LineParser};
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task,
+};
+
+// A bank shared by every connection of a long-lived server. Transactions are applied one at a
+// time under the lock, so the ordering guarantees are the same as in batch mode.
+pub type SharedBank = Arc<Mutex<Bank>>;
+
+// Accept connections forever, each speaking the line protocol below against the shared bank.
+//
+// Every line a client sends is either
+//   - a transaction, in the server's input format (a CSV header line is accepted and ignored),
+//     answered with `ok <outcome>`, `rejected <reason>` or `error <message>`
+//   - `query <client>`, answered with the client's report row
+//     (client,available,held,total,locked,currency,closed) or `error unknown client`
+// Blank lines are ignored. A line longer than MAX_LINE bytes is answered with
+// `error line too long` and the connection closed.
+pub async fn serve_tcp(
+    listener: TcpListener,
+    bank: SharedBank,
+    format: InputFormat,
+) -> io::Result<()> {
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        let bank = bank.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(socket, bank, format).await {
+                tracing::warn!("connection from {} failed: {}", peer, err);
+            }
+        });
+    }
+}
+
+// Far longer than any transaction or query, so a client can't make the server buffer without end
+const MAX_LINE: u64 = 64 * 1024;
+
+async fn handle_connection(
+    socket: TcpStream,
+    bank: SharedBank,
+    format: InputFormat,
+) -> io::Result<()> {
+    let (read, mut write) = socket.into_split();
+    let mut read = BufReader::new(read);
+    let precision = lock(&bank).policy().precision;
+    let mut parser = LineParser::new(format).with_precision(precision);
+    loop {
+        let mut buf = Vec::new();
+        if (&mut read)
+            .take(MAX_LINE + 1)
+            .read_until(b'\n', &mut buf)
+            .await?
+            == 0
+        {
+            return Ok(());
+        }
+        if buf.last() != Some(&b'\n') && buf.len() as u64 > MAX_LINE {
+            write.write_all(b"error line too long\n").await?;
+            return Ok(());
+        }
+        let line = String::from_utf8(buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        // applying a transaction may fsync the log while holding the lock, so it's done where
+        // blocking doesn't hold up the other connections
+        let bank = bank.clone();
+        let (reply, handed_back) = task::spawn_blocking(move || {
+            let reply = handle_line(line.trim_end_matches(['\n', '\r']), &mut parser, &bank);
+            (reply, parser)
+        })
+        .await
+        .map_err(io::Error::other)?;
+        parser = handed_back;
+        if let Some(reply) = reply {
+            write.write_all(reply.as_bytes()).await?;
+            write.write_all(b"\n").await?;
+        }
+    }
+}
+
+fn handle_line(line: &str, parser: &mut LineParser, bank: &SharedBank) -> Option<String> {
+    if let Some(client) = line.trim().strip_prefix("query ") {
+        let reply = match client.trim().parse::<ClientId>() {
+            Ok(client_id) => match lock(bank).record(client_id) {
+                Some(record) => record.to_string(),
+                None => "error unknown client".to_string(),
+            },
+            Err(err) => format!("error invalid client id: {}", err),
+        };
+        return Some(reply);
+    }
+    let reply = match parser.parse(line) {
+        Ok(None) => return None,
+        Ok(Some(txn)) => match lock(bank).insert_read_logged(txn, parser.refusal()) {
+            Ok(Ok(outcome)) => format!("ok {}", outcome.code()),
+            Ok(Err(err)) => format!("rejected {}", err.code()),
+            Err(err) => format!("error {}", err),
+        },
+        Err(err) => format!("error {}", err),
+    };
+    Some(reply)
+}
+
+// A panic while holding the lock can't leave a half-applied transaction behind (balances are only
+// written once a transaction is known to be valid), so a poisoned bank is still safe to use.
+pub(crate) fn lock(bank: &SharedBank) -> std::sync::MutexGuard<'_, Bank> {
+    bank.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    // What the server answers to each line of requests, sent over one connection
+    fn replies(requests: &[u8]) -> String {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let bank = SharedBank::default();
+            tokio::spawn(serve_tcp(listener, bank, InputFormat::Csv));
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            socket.write_all(requests).await.unwrap();
+            socket.shutdown().await.unwrap();
+            let mut replies = String::new();
+            socket.read_to_string(&mut replies).await.unwrap();
+            replies
+        })
+    }
+
+    #[test]
+    fn answers_transactions_and_queries() {
+        let requests =
+            b"type,client,tx,amount\r\ndeposit,1,1,2.5\r\n\nwithdrawal,1,2,5\nquery 1\nquery 2";
+        assert_eq!(
+            replies(requests),
+            "ok deposited\nrejected insufficient_funds\n1,2.5000,0.0000,2.5000,false,,false\n\
+             error unknown client\n"
+        );
+    }
+
+    #[test]
+    fn closes_connections_sending_lines_too_long() {
+        let mut requests = b"query 1\n".to_vec();
+        requests.extend(vec![b' '; MAX_LINE as usize + 1]);
+        requests.extend(b"\nquery 1\n");
+        assert_eq!(
+            replies(&requests),
+            "error unknown client\nerror line too long\n"
+        );
+    }
+}
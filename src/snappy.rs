@@ -0,0 +1,150 @@
+// Decompression of raw Snappy blocks, the codec most Parquet writers default to. There's no
+// framing: the uncompressed length, then literals and back-references into what's been
+// decompressed so far.
+
+use std::io;
+
+pub(crate) fn decompress(input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut pos = 0;
+    let len = varint(input, &mut pos)?;
+    // every element decompresses to at most 64 bytes per byte of input, so a length claiming
+    // more is corrupt rather than a reason to allocate it
+    if len > input.len() as u64 * 64 {
+        return Err(corrupt());
+    }
+    let len = len as usize;
+    let mut out = Vec::with_capacity(len);
+    while pos < input.len() {
+        let tag = input[pos];
+        pos += 1;
+        let (copy_len, offset) = match tag & 3 {
+            0 => {
+                let mut n = (tag >> 2) as usize;
+                // lengths past 59 follow the tag in 1 to 4 bytes
+                if n >= 60 {
+                    let bytes = n - 59;
+                    n = le(input.get(pos..pos + bytes).ok_or_else(corrupt)?);
+                    pos += bytes;
+                }
+                let literal = input.get(pos..pos + n + 1).ok_or_else(corrupt)?;
+                if out.len() + literal.len() > len {
+                    return Err(corrupt());
+                }
+                out.extend_from_slice(literal);
+                pos += n + 1;
+                continue;
+            }
+            1 => {
+                let low = *input.get(pos).ok_or_else(corrupt)? as usize;
+                pos += 1;
+                (
+                    4 + ((tag >> 2) & 7) as usize,
+                    ((tag as usize >> 5) << 8) | low,
+                )
+            }
+            2 => {
+                let offset = le(input.get(pos..pos + 2).ok_or_else(corrupt)?);
+                pos += 2;
+                (1 + (tag >> 2) as usize, offset)
+            }
+            _ => {
+                let offset = le(input.get(pos..pos + 4).ok_or_else(corrupt)?);
+                pos += 4;
+                (1 + (tag >> 2) as usize, offset)
+            }
+        };
+        if offset == 0 || offset > out.len() || out.len() + copy_len > len {
+            return Err(corrupt());
+        }
+        // the copy may overlap what it's writing, e.g. an offset of 1 repeats the last byte
+        let start = out.len() - offset;
+        for i in 0..copy_len {
+            out.push(out[start + i]);
+        }
+    }
+    if out.len() != len {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+fn varint(input: &[u8], pos: &mut usize) -> io::Result<u64> {
+    let mut v = 0u64;
+    for shift in (0..35).step_by(7) {
+        let byte = *input.get(*pos).ok_or_else(corrupt)?;
+        *pos += 1;
+        v |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return Ok(v);
+        }
+    }
+    Err(corrupt())
+}
+
+fn le(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |v, byte| v << 8 | *byte as usize)
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt Snappy data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompresses_each_element_kind() {
+        // "abc", then a 1-byte-offset copy of 9 overlapping what it writes
+        assert_eq!(
+            decompress(&[12, 2 << 2, b'a', b'b', b'c', 5 << 2 | 1, 3]).unwrap(),
+            b"abcabcabcabc"
+        );
+        // 2 and 4-byte offsets
+        assert_eq!(
+            decompress(&[
+                6,
+                1 << 2,
+                b'x',
+                b'y',
+                1 << 2 | 2,
+                2,
+                0,
+                1 << 2 | 3,
+                4,
+                0,
+                0,
+                0
+            ])
+            .unwrap(),
+            b"xyxyxy"
+        );
+        // a literal of 100, its length in the byte after the tag
+        let mut input = vec![100, 60 << 2, 99];
+        input.extend([b'z'; 100]);
+        assert_eq!(decompress(&input).unwrap(), [b'z'; 100]);
+    }
+
+    #[test]
+    fn refuses_corrupt_blocks() {
+        let corrupt = [
+            // a copy from before the start, then from nowhere
+            &[4, 0, b'a', 1 << 2 | 1, 2][..],
+            &[4, 0, b'a', 1 << 2 | 1, 0],
+            // more or fewer bytes than the length given
+            &[1, 1 << 2, b'a', b'b'],
+            &[3, 1 << 2, b'a', b'b'],
+            // a literal or offset past the end
+            &[5, 4 << 2, b'a'],
+            &[5, 0, b'a', 2],
+            // a length out of all proportion to the input
+            &[0xff, 0xff, 0xff, 0x0f, 0],
+        ];
+        for input in corrupt {
+            assert!(decompress(input).is_err(), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, Client, ClientId, DisputeState, Transaction, TxId, TxnRecord};
+use crate::currency::{Balance, Currency};
+use crate::error::Error;
+use crate::imports::Imports;
+use crate::seen::{Seen, SeenTxn};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+// Bumped whenever the layout changes, so an old snapshot is refused rather than misread
+const SNAPSHOT_VERSION: u32 = 1;
+
+// The full engine state as JSON: enough to carry on processing in a later run exactly as if
+// the inputs had been one stream. Policies aren't part of it, they come from the run that
+// loads it.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    version: u32,
+    clients: Vec<ClientState>,
+    // the rows processed with Bank::dedup_seen, sorted
+    #[serde(default)]
+    seen: Vec<SeenTxn>,
+    // forward-dated transactions still waiting for their day
+    #[serde(default)]
+    pending: Vec<Transaction>,
+    // tx id, client and FITID (or QIF contents) of the entries applied from OFX and QIF files,
+    // sorted
+    #[serde(default)]
+    imports: Vec<(TxId, ClientId, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ClientState {
+    client: ClientId,
+    available: Amount,
+    held: Amount,
+    // currency, available and held of each balance in a named currency
+    #[serde(default)]
+    currencies: Vec<(Currency, Amount, Amount)>,
+    // all the withdrawals from the unlabelled balance, and from each currency's with any, for
+    // the withdrawal limits; older snapshots didn't keep count
+    #[serde(default)]
+    withdrawn: Amount,
+    #[serde(default)]
+    currency_withdrawn: Vec<(Currency, Amount)>,
+    locked: bool,
+    #[serde(default)]
+    closed: bool,
+    // accepted deposits and withdrawals, by tx id
+    txns: Vec<Transaction>,
+    // tx ids of the transactions currently under dispute
+    disputes: Vec<TxId>,
+    // those of them only partly disputed, with the portion that is
+    #[serde(default)]
+    partial_disputes: Vec<(TxId, Amount)>,
+    // and of those whose dispute has ended, which older snapshots didn't record
+    #[serde(default)]
+    resolved: Vec<TxId>,
+    #[serde(default)]
+    charged_back: Vec<TxId>,
+    // withdrawals a refund credited back
+    #[serde(default)]
+    refunded: Vec<TxId>,
+    // holds a release gave back
+    #[serde(default)]
+    released: Vec<TxId>,
+    // how many disputes of a transaction were resolved, for those with any
+    #[serde(default)]
+    resolutions: Vec<(TxId, u32)>,
+    rejected_withdrawals: Vec<Transaction>,
+    // snapshots from before unlocks existed have none
+    #[serde(default)]
+    unlocks: Vec<Transaction>,
+}
+
+impl ClientState {
+    // spilled: the client's transactions that were moved to disk
+    fn new(client: &Client, spilled: Vec<(TxId, TxnRecord)>) -> ClientState {
+        // a spilled transaction that has been updated since is back in memory, and that copy wins
+        let records: BTreeMap<TxId, TxnRecord> =
+            spilled.into_iter().chain(client.txns.hot()).collect();
+        let txns = records
+            .iter()
+            .map(|(tx, record)| record.transaction(client.client, *tx))
+            .collect();
+        let in_state = |state: DisputeState| {
+            records
+                .iter()
+                .filter(|(_, record)| record.state == state)
+                .map(|(tx, _)| *tx)
+                .collect()
+        };
+        ClientState {
+            client: client.client,
+            available: client.available,
+            held: client.held,
+            currencies: client
+                .currencies
+                .iter()
+                .map(|(currency, balance)| (*currency, balance.available, balance.held))
+                .collect(),
+            withdrawn: client.withdrawn,
+            currency_withdrawn: client
+                .currencies
+                .iter()
+                .filter(|(_, balance)| !balance.withdrawn.is_zero())
+                .map(|(currency, balance)| (*currency, balance.withdrawn))
+                .collect(),
+            locked: client.locked,
+            closed: client.closed,
+            txns,
+            disputes: in_state(DisputeState::Disputed),
+            partial_disputes: records
+                .iter()
+                .filter(|(_, record)| {
+                    record.state == DisputeState::Disputed && record.disputed != record.amount
+                })
+                .map(|(tx, record)| (*tx, record.disputed))
+                .collect(),
+            resolved: in_state(DisputeState::Resolved),
+            charged_back: in_state(DisputeState::ChargedBack),
+            refunded: in_state(DisputeState::Refunded),
+            released: in_state(DisputeState::Released),
+            resolutions: records
+                .iter()
+                .filter(|(_, record)| record.resolutions > 0)
+                .map(|(tx, record)| (*tx, record.resolutions))
+                .collect(),
+            rejected_withdrawals: client.rejected_withdrawals.clone(),
+            unlocks: client.unlocks.clone(),
+        }
+    }
+
+    fn into_client(self) -> Result<Client, Error> {
+        let mut client = Client::new(self.client);
+        client.available = self.available;
+        client.held = self.held;
+        for (currency, available, held) in self.currencies {
+            client.currencies.insert(
+                currency,
+                Balance {
+                    available,
+                    held,
+                    ..Balance::default()
+                },
+            );
+        }
+        client.withdrawn = self.withdrawn;
+        for (currency, withdrawn) in self.currency_withdrawn {
+            client.currencies.entry(currency).or_default().withdrawn = withdrawn;
+        }
+        client.open_ledger();
+        client.locked = self.locked;
+        client.closed = self.closed;
+        for txn in &self.txns {
+            client.txns.insert(txn.tx, TxnRecord::new(txn));
+        }
+        let states = [
+            (self.disputes, DisputeState::Disputed),
+            (self.resolved, DisputeState::Resolved),
+            (self.charged_back, DisputeState::ChargedBack),
+            (self.refunded, DisputeState::Refunded),
+            (self.released, DisputeState::Released),
+        ];
+        for (txs, state) in states {
+            for tx in txs {
+                let mut record = restored(&client, tx).ok_or_else(|| {
+                    invalid(format!(
+                        "client {} has {} unknown tx {}",
+                        self.client,
+                        state.name(),
+                        tx
+                    ))
+                })?;
+                record.state = state;
+                if state == DisputeState::Disputed {
+                    record.disputed = record.amount;
+                }
+                client.txns.insert(tx, record);
+            }
+        }
+        for (tx, portion) in self.partial_disputes {
+            let mut record = restored(&client, tx)
+                .filter(|record| record.state == DisputeState::Disputed)
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "client {} partly disputes tx {} that isn't disputed",
+                        self.client, tx
+                    ))
+                })?;
+            record.disputed = portion;
+            client.txns.insert(tx, record);
+        }
+        for (tx, resolutions) in self.resolutions {
+            let mut record = restored(&client, tx).ok_or_else(|| {
+                invalid(format!(
+                    "client {} has resolutions of unknown tx {}",
+                    self.client, tx
+                ))
+            })?;
+            record.resolutions = resolutions;
+            client.txns.insert(tx, record);
+        }
+        client.rejected_withdrawals = self.rejected_withdrawals;
+        client.unlocks = self.unlocks;
+        Ok(client)
+    }
+}
+
+// A record of a client being restored, which has nothing spilled yet
+fn restored(client: &Client, tx: TxId) -> Option<TxnRecord> {
+    client.txns.get(&tx).ok().flatten()
+}
+
+pub(crate) fn invalid(msg: String) -> Error {
+    Error::Invalid(msg)
+}
+
+impl Bank {
+    // Clients are in id order so snapshots of the same state serialize byte-for-byte equal
+    pub(crate) fn snapshot(&self) -> io::Result<Snapshot> {
+        let mut spilled = self.spilled()?;
+        let mut clients: Vec<ClientState> = self
+            .bank
+            .values()
+            .map(|client| {
+                let spilled = spilled.remove(&client.client).unwrap_or_default();
+                ClientState::new(client, spilled)
+            })
+            .collect();
+        clients.sort_by_key(|state| state.client);
+        let mut seen: Vec<SeenTxn> = self.seen.all().collect();
+        seen.sort_by_key(|(tx_type, client, tx)| (*client, *tx, tx_type.name()));
+        let mut imports: Vec<(TxId, ClientId, String)> = self
+            .imports
+            .all()
+            .map(|(tx, client, key)| (tx, client, key.to_string()))
+            .collect();
+        imports.sort();
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            clients,
+            seen,
+            pending: self.pending.clone(),
+            imports,
+        })
+    }
+
+    // The restored bank has the default policy, see set_policy
+    pub(crate) fn from_snapshot(snapshot: Snapshot) -> Result<Bank, Error> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(invalid(format!(
+                "unsupported snapshot version {}",
+                snapshot.version
+            )));
+        }
+        let mut bank = Bank::new();
+        for state in snapshot.clients {
+            let client = state.into_client()?;
+            bank.tx_ids.extend(client.txns.hot().map(|(tx, _)| tx));
+            bank.bank.insert(client.client, client);
+        }
+        bank.seen = Seen::loaded(snapshot.seen);
+        bank.pending = snapshot.pending;
+        bank.imports = Imports::loaded(snapshot.imports);
+        Ok(bank)
+    }
+
+    pub fn write_snapshot<W: io::Write>(&self, w: W) -> Result<(), Error> {
+        serde_json::to_writer(w, self)?;
+        Ok(())
+    }
+
+    pub fn read_snapshot<R: io::Read>(r: R) -> Result<Bank, Error> {
+        Bank::from_snapshot(serde_json::from_reader(r)?)
+    }
+
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        write_atomically(path.as_ref(), |w| self.write_snapshot(w))
+    }
+
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Bank, Error> {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| Bank::read_snapshot(BufReader::new(file)))
+            .map_err(|err| err.in_file(path))
+    }
+
+    // A bank with the balances of an earlier run's CSV account report
+    // (client,available,held,total,locked and optionally currency columns), for carrying on from
+    // a run whose snapshot wasn't kept. Unlike a snapshot it has no transactions, so nothing
+    // before it can be disputed and funds it has held stay held. The total column is ignored.
+    pub fn read_accounts<R: io::Read>(r: R) -> Result<Bank, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(r);
+        let mut bank = Bank::new();
+        let mut listed = HashSet::new();
+        for row in rdr.deserialize() {
+            let row: AccountRow = row?;
+            // a report only ever holds four places, but one written by hand might not, which
+            // parsing rounds
+            let (available, held) = (row.available, row.held);
+            if !listed.insert((row.client, row.currency)) {
+                let currency = row.currency.map(|c| format!(" {}", c)).unwrap_or_default();
+                return Err(invalid(format!(
+                    "client {}{} is listed twice",
+                    row.client, currency
+                )));
+            }
+            let client = bank
+                .bank
+                .entry(row.client)
+                .or_insert_with(|| Client::new(row.client));
+            client.locked |= row.locked;
+            client.closed |= row.closed;
+            match row.currency {
+                Some(currency) => {
+                    let balance = Balance {
+                        available,
+                        held,
+                        ..Balance::default()
+                    };
+                    client.currencies.insert(currency, balance);
+                }
+                None => {
+                    client.available = available;
+                    client.held = held;
+                }
+            }
+            client.open_ledger();
+        }
+        Ok(bank)
+    }
+
+    pub fn load_accounts<P: AsRef<Path>>(path: P) -> Result<Bank, Error> {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| Bank::read_accounts(BufReader::new(file)))
+            .map_err(|err| err.in_file(path))
+    }
+}
+
+#[derive(Deserialize)]
+struct AccountRow {
+    client: ClientId,
+    available: Amount,
+    held: Amount,
+    locked: bool,
+    #[serde(default)]
+    currency: Option<Currency>,
+    // reports from before accounts could be closed don't have the column
+    #[serde(default)]
+    closed: bool,
+}
+
+// The whole engine state, in the snapshot layout, so it can be read back with
+// Bank::read_snapshot when written as JSON
+impl Serialize for Bank {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+// An account as it appears in a snapshot: its balances, recorded transactions and their dispute
+// states. One with transactions on disk reads the whole spill file to find them.
+impl Serialize for Client {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let spilled = self.txns.spilled().map_err(serde::ser::Error::custom)?;
+        ClientState::new(self, spilled).serialize(serializer)
+    }
+}
+
+// Written to a temporary file next to path and renamed over it, so a crash mid-write leaves the
+// previous contents intact
+pub(crate) fn write_atomically<F>(path: &Path, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), Error>,
+{
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    write(&mut w)?;
+    w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,847 @@
+use crate::amount::{Amount, AmountError};
+use crate::avro::AvroRows;
+use crate::bank::{ClientId, Transaction, TransactionType, TxId};
+use crate::binary::BinaryRows;
+use crate::control::{Batch, ControlMismatch, Declared};
+use crate::currency::Currency;
+use crate::date::Date;
+use crate::error::Error;
+use crate::nacha::NachaRows;
+use crate::ofx::OfxRows;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::parquet::ParquetRows;
+use crate::policy::{OnError, Policy, PrecisionPolicy};
+use crate::qif::QifRows;
+use crate::recurring::Recurring;
+use crate::snapshot::invalid;
+use serde::{de::Error as _, Deserialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    io::{self, BufRead},
+    str::FromStr,
+};
+
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    // newline-delimited JSON, one transaction object per line
+    Json,
+    // columns named like the CSV headers, see parquet.rs
+    Parquet,
+    // an object container file of records with fields named like the CSV headers, see avro.rs
+    Avro,
+    // fixed-width records, see binary.rs
+    Binary,
+    // U.S. ACH files, see nacha.rs
+    Nacha,
+    // bank statement exports, see ofx.rs and qif.rs
+    Ofx,
+    Qif,
+}
+
+impl InputFormat {
+    pub const NAMES: &'static [&'static str] = &[
+        "csv", "json", "parquet", "avro", "binary", "nacha", "ofx", "qif",
+    ];
+    // those that can be parsed a line or message at a time, see LineParser
+    pub const LINE_NAMES: &'static [&'static str] = &["csv", "json"];
+    // those `convert` can write
+    pub const WRITABLE_NAMES: &'static [&'static str] = &["csv", "binary"];
+
+    // Whether the format can be parsed a line or message at a time, see LineParser
+    pub fn is_line_based(self) -> bool {
+        matches!(self, InputFormat::Csv | InputFormat::Json)
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputFormat, String> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "json" | "jsonl" | "ndjson" => Ok(InputFormat::Json),
+            "parquet" => Ok(InputFormat::Parquet),
+            "avro" => Ok(InputFormat::Avro),
+            "binary" => Ok(InputFormat::Binary),
+            "nacha" | "ach" => Ok(InputFormat::Nacha),
+            "ofx" | "qfx" => Ok(InputFormat::Ofx),
+            "qif" => Ok(InputFormat::Qif),
+            _ => Err(format!("unknown input format '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputFormat::Csv => write!(f, "csv"),
+            InputFormat::Json => write!(f, "json"),
+            InputFormat::Parquet => write!(f, "parquet"),
+            InputFormat::Avro => write!(f, "avro"),
+            InputFormat::Binary => write!(f, "binary"),
+            InputFormat::Nacha => write!(f, "nacha"),
+            InputFormat::Ofx => write!(f, "ofx"),
+            InputFormat::Qif => write!(f, "qif"),
+        }
+    }
+}
+
+// How a CSV input is laid out
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct CsvOptions {
+    // whether the first row names the columns; without one they're taken to be in the standard
+    // order, see LineParser
+    pub header: bool,
+    // what separates the fields, e.g. b'\t' or b'|' for exports from older systems
+    pub delimiter: u8,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            header: true,
+            delimiter: b',',
+        }
+    }
+}
+
+// The columns of a CSV input without a header, in order. Only the first four are required.
+pub(crate) const STANDARD_COLUMNS: [&str; 9] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "to",
+    "timestamp",
+    "currency",
+    "to_currency",
+    "effective_date",
+];
+
+// A transaction as a row under the STANDARD_COLUMNS header, without the line ending: the optional
+// columns only as far as the last one that's set, so it parses back to the same transaction
+pub fn csv_row(txn: &Transaction) -> String {
+    // a dispute's amount is only set for a partial one
+    let amount = if !txn.tx_type.moves_funds() && txn.amount.is_zero() {
+        String::new()
+    } else {
+        txn.amount.to_string()
+    };
+    let mut line = format!("{},{},{},{}", txn.tx_type, txn.client, txn.tx, amount);
+    let optional = [
+        txn.to.map(|to| to.to_string()),
+        txn.timestamp.map(|timestamp| timestamp.to_string()),
+        txn.currency.map(|currency| currency.to_string()),
+        txn.to_currency.map(|currency| currency.to_string()),
+        txn.effective_date.map(|date| date.to_string()),
+    ];
+    let set = optional
+        .iter()
+        .rposition(Option::is_some)
+        .map_or(0, |last| last + 1);
+    for column in &optional[..set] {
+        line.push(',');
+        line.push_str(column.as_deref().unwrap_or_default());
+    }
+    line
+}
+
+fn standard_headers() -> csv::StringRecord {
+    csv::StringRecord::from(STANDARD_COLUMNS.to_vec())
+}
+
+// An iterator of transactions parsed out of a reader in one of the supported input formats
+pub struct TransactionSource<R: io::Read> {
+    inner: Inner<R>,
+    // the occurrences of a recurring row still to hand out, see recurring.rs
+    occurrences: VecDeque<Transaction>,
+    // the batch control totals so far, see control.rs
+    batch: Batch,
+    // how amounts past four places are parsed, from the policy of whatever reads the source
+    precision: PrecisionPolicy,
+    // the refusal the last row read already has, see refusal
+    refusal: Option<TxnError>,
+}
+
+enum Inner<R: io::Read> {
+    // read a record at a time rather than through into_deserialize, to know which line each
+    // came from; the headers are read along with the first one
+    Csv {
+        rows: csv::Reader<R>,
+        record: csv::StringRecord,
+        headers: Option<csv::StringRecord>,
+    },
+    // parsed a line at a time so one bad line doesn't poison the rest of the stream
+    Json {
+        lines: io::Lines<io::BufReader<R>>,
+        line: u64,
+    },
+    // a Parquet file's footer is at its end, so the whole input is read in before the first row;
+    // the reader is dropped once it has been
+    Parquet {
+        reader: Option<R>,
+        rows: Option<Box<ParquetRows>>,
+    },
+    // the header, schema included, is read with the first row, then a block at a time
+    Avro(Box<AvroRows<R>>),
+    Binary(BinaryRows<R>),
+    // a record at a time, counting each batch's records towards its control totals
+    Nacha(NachaRows<R>),
+    // read whole, like Parquet, the statement being one document
+    Ofx(OfxRows<R>),
+    Qif(QifRows<R>),
+}
+
+impl<R: io::Read> TransactionSource<R> {
+    fn of(inner: Inner<R>) -> TransactionSource<R> {
+        TransactionSource {
+            inner,
+            occurrences: VecDeque::new(),
+            batch: Batch::default(),
+            precision: PrecisionPolicy::default(),
+            refusal: None,
+        }
+    }
+
+    // Parsing amounts past four places as precision says when iterated directly; next_txn
+    // parses them as its policy says
+    pub fn with_precision(mut self, precision: PrecisionPolicy) -> TransactionSource<R> {
+        self.precision = precision;
+        self
+    }
+
+    pub fn new(reader: R, format: InputFormat) -> TransactionSource<R> {
+        match format {
+            InputFormat::Csv => TransactionSource::csv(reader),
+            InputFormat::Json => TransactionSource::json(reader),
+            InputFormat::Parquet => TransactionSource::parquet(reader),
+            InputFormat::Avro => TransactionSource::avro(reader),
+            InputFormat::Binary => TransactionSource::binary(reader),
+            InputFormat::Nacha => TransactionSource::nacha(reader),
+            InputFormat::Ofx => TransactionSource::ofx(reader, None),
+            InputFormat::Qif => TransactionSource::qif(reader, None),
+        }
+    }
+
+    pub fn csv(reader: R) -> TransactionSource<R> {
+        TransactionSource::csv_with(reader, CsvOptions::default())
+    }
+
+    pub fn csv_with(reader: R, options: CsvOptions) -> TransactionSource<R> {
+        let rdr = csv::ReaderBuilder::new()
+            .has_headers(options.header)
+            .delimiter(options.delimiter)
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(reader);
+        TransactionSource::of(Inner::Csv {
+            rows: rdr,
+            record: csv::StringRecord::new(),
+            headers: (!options.header).then(standard_headers),
+        })
+    }
+
+    pub fn json(reader: R) -> TransactionSource<R> {
+        TransactionSource::of(Inner::Json {
+            lines: io::BufReader::new(reader).lines(),
+            line: 0,
+        })
+    }
+
+    pub fn parquet(reader: R) -> TransactionSource<R> {
+        TransactionSource::of(Inner::Parquet {
+            reader: Some(reader),
+            rows: None,
+        })
+    }
+
+    pub fn avro(reader: R) -> TransactionSource<R> {
+        TransactionSource::of(Inner::Avro(Box::new(AvroRows::new(reader))))
+    }
+
+    pub fn binary(reader: R) -> TransactionSource<R> {
+        TransactionSource::of(Inner::Binary(BinaryRows::new(reader)))
+    }
+
+    pub fn nacha(reader: R) -> TransactionSource<R> {
+        TransactionSource::of(Inner::Nacha(NachaRows::new(reader)))
+    }
+
+    // client is whose account the statement is of; OFX statements without one are taken to be
+    // the client numbered like their ACCTID, and QIF ones fail to read
+    pub fn ofx(reader: R, client: Option<ClientId>) -> TransactionSource<R> {
+        TransactionSource::of(Inner::Ofx(OfxRows::new(reader, client)))
+    }
+
+    pub fn qif(reader: R, client: Option<ClientId>) -> TransactionSource<R> {
+        TransactionSource::of(Inner::Qif(QifRows::new(reader, client)))
+    }
+}
+
+impl<R: io::Read> TransactionSource<R> {
+    // Next transaction that parses, with its amount rounded or refused as policy's
+    // PrecisionPolicy says: one it refuses comes with its amount rounded, and refusal says so.
+    // Malformed rows either fail the whole source or are skipped and counted in stats, depending
+    // on its OnError policy.
+    pub fn next_txn(
+        &mut self,
+        policy: &Policy,
+        stats: &mut SourceStats,
+    ) -> Result<Option<Transaction>, Error> {
+        self.precision = policy.precision;
+        let next = self.next_row(policy.on_error, stats);
+        stats.controls += std::mem::take(&mut self.batch.rows);
+        stats.control_mismatches.append(&mut self.batch.mismatches);
+        next
+    }
+
+    fn next_row(
+        &mut self,
+        on_error: OnError,
+        stats: &mut SourceStats,
+    ) -> Result<Option<Transaction>, Error> {
+        self.refusal = None;
+        if let Some(txn) = self.occurrences.pop_front() {
+            stats.rows += 1;
+            stats.expanded += 1;
+            return Ok(Some(txn));
+        }
+        while let Some(result) = self.next() {
+            let result = result.or_else(|err| {
+                let txn = err.too_precise()?;
+                self.refusal = Some(TxnError::TooPrecise);
+                Ok(txn)
+            });
+            if let Some(txn) = take_row(result, on_error, stats)? {
+                return Ok(Some(txn));
+            }
+        }
+        Ok(None)
+    }
+
+    // Why the engine must refuse the transaction next_txn last returned before applying it, if
+    // reading it decided that already: an amount too precise under PrecisionPolicy::Reject
+    pub fn refusal(&self) -> Option<TxnError> {
+        self.refusal
+    }
+
+    // Whether the last row read was a recurring one with occurrences still to come, which aren't
+    // yet counted as read past
+    pub fn mid_row(&self) -> bool {
+        !self.occurrences.is_empty()
+    }
+
+    // Pass over the next n rows without parsing them, e.g. ones applied before a checkpoint.
+    // Returns how many there were, which is less than n if the input ends first.
+    pub fn skip_rows(&mut self, n: u64) -> Result<u64, Error> {
+        let mut skipped = 0;
+        match &mut self.inner {
+            Inner::Csv { rows, .. } => {
+                let mut record = csv::ByteRecord::new();
+                while skipped < n && rows.read_byte_record(&mut record)? {
+                    skipped += 1;
+                }
+                if skipped > 0 {
+                    self.batch.skipped();
+                }
+            }
+            Inner::Json { lines, line } => {
+                while skipped < n {
+                    let Some(text) = lines.next() else { break };
+                    *line += 1;
+                    if !text?.trim().is_empty() {
+                        skipped += 1;
+                    }
+                }
+            }
+            Inner::Parquet { .. }
+            | Inner::Avro(_)
+            | Inner::Binary(_)
+            | Inner::Nacha(_)
+            | Inner::Ofx(_)
+            | Inner::Qif(_) => {
+                while skipped < n {
+                    match self.next() {
+                        Some(Err(err)) if !err.is_recoverable() => return Err(err),
+                        Some(_) => skipped += 1,
+                        None => break,
+                    }
+                }
+                // reading the rows again kept the batch totals, but the batches that didn't
+                // match were already reported by the run that read them first
+                self.batch.mismatches.clear();
+            }
+        }
+        Ok(skipped)
+    }
+
+    // The line the last row returned started on, counting from 1 with the header
+    pub fn line(&self) -> u64 {
+        match &self.inner {
+            Inner::Csv { rows, record, .. } => {
+                let Some(start) = record.position() else {
+                    return 0;
+                };
+                // csv starts a record where the last one ended, so blank lines in between count as
+                // its own. The reader is now a line past its end, unless it ran to the end of the
+                // input.
+                let newlines: usize = record.iter().map(|field| field.matches('\n').count()).sum();
+                let end = rows.position().line();
+                start.line().max(end.saturating_sub(1 + newlines as u64))
+            }
+            Inner::Json { line, .. } => *line,
+            // the row, since there are no lines
+            Inner::Parquet { rows, .. } => rows.as_ref().map_or(0, |rows| rows.row()),
+            Inner::Avro(rows) => rows.row(),
+            Inner::Binary(rows) => rows.row(),
+            Inner::Nacha(rows) => rows.row(),
+            Inner::Ofx(rows) => rows.row(),
+            Inner::Qif(rows) => rows.line(),
+        }
+    }
+
+    // What tells the last row returned apart from the other entries of its OFX or QIF export,
+    // see imports.rs; None for the other formats, whose tx ids are their own
+    pub(crate) fn import_key(&self) -> Option<&str> {
+        match &self.inner {
+            Inner::Ofx(rows) => rows.key(),
+            Inner::Qif(rows) => rows.key(),
+            _ => None,
+        }
+    }
+}
+
+// What record's type column says
+fn row_type<'r>(record: &'r csv::StringRecord, headers: Option<&csv::StringRecord>) -> &'r str {
+    let column = headers.and_then(|headers| headers.iter().position(|name| name == "type"));
+    record.get(column.unwrap_or(0)).unwrap_or_default()
+}
+
+// Count a parsed row, or deal with a malformed one according to the OnError policy
+pub(crate) fn take_row(
+    result: Result<Transaction, Error>,
+    on_error: OnError,
+    stats: &mut SourceStats,
+) -> Result<Option<Transaction>, Error> {
+    match result {
+        Ok(txn) => {
+            stats.rows += 1;
+            Ok(Some(txn))
+        }
+        Err(err) if on_error == OnError::Abort || !err.is_recoverable() => Err(err),
+        Err(err) => {
+            stats.skipped += 1;
+            if on_error == OnError::Report {
+                stats.errors.push(err);
+            }
+            Ok(None)
+        }
+    }
+}
+
+impl<R: io::Read> Iterator for TransactionSource<R> {
+    type Item = Result<Transaction, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(txn) = self.occurrences.pop_front() {
+            return Some(Ok(txn));
+        }
+        match &mut self.inner {
+            Inner::Csv {
+                rows,
+                record,
+                headers,
+            } => {
+                if headers.is_none() {
+                    match rows.headers() {
+                        Ok(read) => *headers = Some(read.clone()),
+                        // the reader keeps returning a bad header's error, so there's no row
+                        // after it to skip to
+                        Err(err) => return Some(Err(invalid(format!("CSV header: {}", err)))),
+                    }
+                }
+                loop {
+                    match rows.read_record(record) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            self.batch.finish();
+                            return None;
+                        }
+                        Err(err) => return Some(Err(Error::from(err))),
+                    }
+                    let line = record.position().map_or(0, |start| start.line());
+                    let kind = row_type(record, headers.as_ref());
+                    if kind == "batch_header" || kind == "batch_trailer" {
+                        self.batch.rows += 1;
+                        match record.deserialize::<Declared>(headers.as_ref()) {
+                            Ok(declared) if kind == "batch_header" => {
+                                self.batch.header(line, declared)
+                            }
+                            Ok(declared) => self.batch.trailer(line, declared),
+                            Err(err) => return Some(Err(Error::from(err))),
+                        }
+                        continue;
+                    }
+                    let precise = is_precise(record, headers.as_ref(), self.precision);
+                    if kind == "recurring" {
+                        let expanded = record
+                            .deserialize::<Recurring>(headers.as_ref())
+                            .map_err(Error::from)
+                            .and_then(|row| {
+                                let occurrences = row.expand().map_err(|err| err.at_line(line))?;
+                                Ok((row.amount(), occurrences))
+                            });
+                        return match expanded {
+                            // refused as one row, its first occurrence standing for it
+                            Ok((amount, occurrences)) if !precise => {
+                                self.batch.add(amount);
+                                let first = occurrences.into_iter().next()?;
+                                Some(Err(Error::TooPrecise(first).at_line(line)))
+                            }
+                            Ok((amount, occurrences)) => {
+                                self.batch.add(amount);
+                                self.occurrences = occurrences.into();
+                                self.occurrences.pop_front().map(Ok)
+                            }
+                            Err(err) => Some(Err(err)),
+                        };
+                    }
+                    let txn = record.deserialize::<Transaction>(headers.as_ref());
+                    return Some(match txn {
+                        Ok(txn) => {
+                            self.batch.add(txn.amount);
+                            if precise {
+                                Ok(txn)
+                            } else {
+                                Err(Error::TooPrecise(txn).at_line(line))
+                            }
+                        }
+                        Err(err) => Err(Error::from(err)),
+                    });
+                }
+            }
+            Inner::Json { lines, line } => loop {
+                let text = match lines.next()? {
+                    Ok(text) => text,
+                    Err(err) => return Some(Err(Error::from(err))),
+                };
+                *line += 1;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let txn = parse_json(&text, self.precision);
+                return Some(txn.map_err(|err| err.at_line(*line)));
+            },
+            Inner::Parquet { reader, rows } => {
+                if let Some(mut reader) = reader.take() {
+                    let mut file = Vec::new();
+                    if let Err(err) = reader.read_to_end(&mut file) {
+                        return Some(Err(Error::from(err)));
+                    }
+                    match ParquetRows::new(file) {
+                        Ok(read) => *rows = Some(Box::new(read)),
+                        Err(err) => return Some(Err(err)),
+                    }
+                }
+                rows.as_mut()?.next(self.precision)
+            }
+            Inner::Avro(rows) => rows.next(self.precision),
+            Inner::Binary(rows) => rows.next(self.precision),
+            Inner::Nacha(rows) => rows.next(&mut self.batch),
+            Inner::Ofx(rows) => rows.next(self.precision),
+            Inner::Qif(rows) => rows.next(self.precision),
+        }
+    }
+}
+
+// The row decode makes of its input under precision. Where it fails under
+// PrecisionPolicy::Reject but rounding the amount lets it through, the amount is too precise,
+// and that's Error::TooPrecise with the row as rounded, which reading a source refuses with
+// TxnError::TooPrecise rather than failing. row picks the transaction out of what decode gives.
+pub(crate) fn decided<T>(
+    precision: PrecisionPolicy,
+    mut decode: impl FnMut(PrecisionPolicy) -> Result<T, Error>,
+    row: impl FnOnce(T) -> Option<Transaction>,
+) -> Result<T, Error> {
+    match decode(precision) {
+        Err(err) if precision == PrecisionPolicy::Reject => {
+            match decode(PrecisionPolicy::Round).map(row) {
+                Ok(Some(txn)) => Err(Error::TooPrecise(txn)),
+                _ => Err(err),
+            }
+        }
+        decoded => decoded,
+    }
+}
+
+// Whether a CSV row's amount survives precision, which deserializing it, which rounds, doesn't
+// check. Any other problem with the amount is left for deserializing to report.
+fn is_precise(
+    record: &csv::StringRecord,
+    headers: Option<&csv::StringRecord>,
+    precision: PrecisionPolicy,
+) -> bool {
+    if precision == PrecisionPolicy::Round {
+        return true;
+    }
+    let column = headers.and_then(|headers| headers.iter().position(|name| name == "amount"));
+    let written = column.and_then(|column| record.get(column));
+    written.map(|written| Amount::parse(written, precision)) != Some(Err(AmountError::TooPrecise))
+}
+
+// Parses transactions a line at a time, for transports that hand over one line or message at a
+// time instead of a reader: sockets, message queues, async streams.
+// For CSV a header line is optional. A line whose first field is "type" is taken as the header for
+// the lines after it; until one arrives the standard
+// type,client,tx,amount,to,timestamp,currency,to_currency,effective_date order is assumed. The
+// last five columns are optional.
+#[derive(Debug)]
+pub struct LineParser {
+    format: InputFormat,
+    delimiter: u8,
+    headers: csv::StringRecord,
+    line: u64,
+    precision: PrecisionPolicy,
+    // the refusal the last line parsed already has, see refusal
+    refusal: Option<TxnError>,
+}
+
+impl LineParser {
+    pub fn new(format: InputFormat) -> LineParser {
+        LineParser {
+            format,
+            delimiter: b',',
+            headers: standard_headers(),
+            line: 0,
+            precision: PrecisionPolicy::default(),
+            refusal: None,
+        }
+    }
+
+    // Parsing amounts past four places as precision says, which should be the bank's policy
+    pub fn with_precision(mut self, precision: PrecisionPolicy) -> LineParser {
+        self.precision = precision;
+        self
+    }
+
+    // With the options' delimiter between CSV fields. Without a header the standard order is
+    // assumed anyway, so only the delimiter matters here.
+    pub fn with_csv_options(mut self, options: CsvOptions) -> LineParser {
+        self.delimiter = options.delimiter;
+        self
+    }
+
+    // Ok(None) for blank lines and CSV header lines. As with TransactionSource::next_txn, a
+    // transaction the precision refuses comes with its amount rounded, and refusal says so.
+    pub fn parse(&mut self, text: &str) -> Result<Option<Transaction>, Error> {
+        self.line += 1;
+        self.refusal = None;
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        let line = self.line;
+        let parsed = match self.format {
+            InputFormat::Json => parse_json(text, self.precision).map(Some),
+            InputFormat::Csv => self.parse_csv(text),
+            InputFormat::Parquet
+            | InputFormat::Avro
+            | InputFormat::Binary
+            | InputFormat::Nacha
+            | InputFormat::Ofx
+            | InputFormat::Qif => {
+                return Err(invalid(format!(
+                    "{} input can't be read a line at a time",
+                    self.format
+                )))
+            }
+        };
+        match parsed.map_err(Error::too_precise) {
+            Ok(txn) => Ok(txn),
+            Err(Ok(txn)) => {
+                self.refusal = Some(TxnError::TooPrecise);
+                Ok(Some(txn))
+            }
+            Err(Err(err)) => Err(err.at_line(line)),
+        }
+    }
+
+    // Why the engine must refuse the transaction parse last returned, as
+    // TransactionSource::refusal
+    pub fn refusal(&self) -> Option<TxnError> {
+        self.refusal
+    }
+
+    fn parse_csv(&mut self, text: &str) -> Result<Option<Transaction>, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(self.delimiter)
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let mut record = csv::StringRecord::new();
+        if !rdr.read_record(&mut record)? {
+            return Ok(None);
+        }
+        if record.get(0) == Some("type") {
+            self.headers = record;
+            return Ok(None);
+        }
+        let txn = record.deserialize(Some(&self.headers))?;
+        if !is_precise(&record, Some(&self.headers), self.precision) {
+            return Err(Error::TooPrecise(txn));
+        }
+        Ok(Some(txn))
+    }
+
+    // The line number of the last line given to parse
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+}
+
+// Same fields as the CSV columns. JSON amounts may be written as numbers or strings,
+// and like CSV a missing/null/empty amount is treated as 0.
+#[derive(Deserialize)]
+struct JsonTransaction {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    #[serde(default)]
+    amount: Value,
+    #[serde(default)]
+    to: Option<ClientId>,
+    #[serde(default)]
+    timestamp: Option<i64>,
+    #[serde(default)]
+    currency: Option<Currency>,
+    #[serde(default)]
+    to_currency: Option<Currency>,
+    #[serde(default)]
+    effective_date: Option<Date>,
+}
+
+impl JsonTransaction {
+    fn to_transaction(&self, precision: PrecisionPolicy) -> Result<Transaction, Error> {
+        let parse = |s: &str| {
+            Amount::parse(s, precision).map_err(|err| Error::from(serde_json::Error::custom(err)))
+        };
+        let amount = match &self.amount {
+            Value::Null => Amount::ZERO,
+            Value::String(s) if s.trim().is_empty() => Amount::ZERO,
+            Value::String(s) => parse(s)?,
+            // serde_json keeps the original digits with arbitrary_precision, so this is exact
+            Value::Number(n) => parse(&n.to_string())?,
+            other => {
+                let err = serde_json::Error::custom(format!("invalid amount: {}", other));
+                return Err(err.into());
+            }
+        };
+        Ok(Transaction {
+            tx_type: self.tx_type,
+            client: self.client,
+            tx: self.tx,
+            amount,
+            to: self.to,
+            timestamp: self.timestamp,
+            currency: self.currency,
+            to_currency: self.to_currency,
+            effective_date: self.effective_date,
+        })
+    }
+}
+
+fn parse_json(text: &str, precision: PrecisionPolicy) -> Result<Transaction, Error> {
+    let txn = serde_json::from_str::<JsonTransaction>(text)?;
+    decided(precision, |precision| txn.to_transaction(precision), Some)
+}
+
+// A transaction from an already-parsed JSON object, with the same field rules as JSON Lines input
+pub fn transaction_from_json(
+    value: Value,
+    precision: PrecisionPolicy,
+) -> Result<Transaction, Error> {
+    let txn = serde_json::from_value::<JsonTransaction>(value)?;
+    decided(precision, |precision| txn.to_transaction(precision), Some)
+}
+
+// What came out of reading one source
+#[derive(Debug, Default)]
+pub struct SourceStats {
+    // rows that parsed and were handed to the engine
+    pub rows: u64,
+    // rows the engine refused to apply
+    pub rejected: u64,
+    // rows queued until their effective date, see Bank::pending
+    pub pending: u64,
+    // of the rows, the occurrences of recurring rows after each one's first, which the inputs
+    // don't have rows of their own for
+    pub expanded: u64,
+    // batch header and trailer rows, which aren't transactions
+    pub controls: u64,
+    // batches whose rows didn't add up to their control totals, see control.rs
+    pub control_mismatches: Vec<ControlMismatch>,
+    // of those, the ones for a client with no account, which often means a gap in the upstream feed
+    pub unknown_client: u64,
+    // the rejected count per reason code
+    pub rejected_by: BTreeMap<&'static str, u64>,
+    // malformed rows passed over under OnError::Skip / OnError::Report
+    pub skipped: u64,
+    // the parse errors for skipped rows, only kept under OnError::Report
+    pub errors: Vec<Error>,
+    // rejected rows reusing a transaction id, which point at a bug in whatever assigns them
+    pub duplicates: Vec<Duplicate>,
+}
+
+// Where a duplicate id turned up. The line is the input's, or for streams of transactions the
+// position in the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duplicate {
+    pub line: u64,
+    pub client: ClientId,
+    pub tx: TxId,
+}
+
+impl SourceStats {
+    pub(crate) fn applied(&mut self, outcome: TxnOutcome) {
+        if outcome == TxnOutcome::Pending {
+            self.pending += 1;
+        }
+    }
+
+    pub(crate) fn reject(&mut self, txn: &Transaction, err: &TxnError, line: u64) {
+        self.rejected += 1;
+        *self.rejected_by.entry(err.code()).or_default() += 1;
+        match err {
+            TxnError::UnknownClient => self.unknown_client += 1,
+            TxnError::DuplicateTx | TxnError::DuplicateTxOtherClient => {
+                self.duplicates.push(Duplicate {
+                    line,
+                    client: txn.client,
+                    tx: txn.tx,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    pub fn merge(&mut self, other: SourceStats) {
+        self.rows += other.rows;
+        self.rejected += other.rejected;
+        self.pending += other.pending;
+        self.expanded += other.expanded;
+        self.controls += other.controls;
+        self.control_mismatches.extend(other.control_mismatches);
+        self.unknown_client += other.unknown_client;
+        for (reason, count) in other.rejected_by {
+            *self.rejected_by.entry(reason).or_default() += count;
+        }
+        self.skipped += other.skipped;
+        self.errors.extend(other.errors);
+        self.duplicates.extend(other.duplicates);
+    }
+}
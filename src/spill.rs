@@ -0,0 +1,543 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, ClientId, DisputeState, TransactionType, TxId, TxnRecord};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::outcome::TxnError;
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+// A client's recorded deposits and withdrawals. Recently used ones are kept in memory; once the
+// bank spills to disk (see Bank::spill_to_disk) older ones are moved to a file shared by every
+// client and read back from there when a dispute or duplicate check needs them.
+//
+// Failing to read the spill file refuses the transaction that needed it with
+// TxnError::SpillFailed, so nothing is decided on a record that couldn't be looked up, and failing
+// to write it leaves the transactions in memory. Either way the first error is kept for
+// Bank::check_spill, which the processing loops call after every transaction to fail the run.
+#[derive(Debug)]
+pub(crate) struct TxnStore {
+    client: ClientId,
+    hot: HashMap<TxId, TxnRecord>,
+    // set once some of this client's transactions have been moved to disk
+    spill: Option<SharedSpill>,
+    // how many there are there, counting those since brought back into memory
+    spilled: usize,
+    // when the client last had a transaction recorded, for picking which clients to spill
+    last_used: u64,
+}
+
+impl TxnStore {
+    pub(crate) fn new(client: ClientId) -> TxnStore {
+        TxnStore {
+            client,
+            hot: HashMap::new(),
+            spill: None,
+            spilled: 0,
+            last_used: 0,
+        }
+    }
+
+    pub(crate) fn contains_key(&self, tx: &TxId) -> Result<bool, TxnError> {
+        Ok(self.get(tx)?.is_some())
+    }
+
+    pub(crate) fn get(&self, tx: &TxId) -> Result<Option<TxnRecord>, TxnError> {
+        if let Some(record) = self.hot.get(tx) {
+            return Ok(Some(*record));
+        }
+        match &self.spill {
+            Some(spill) => kept(lock(spill), |file| file.get(self.client, *tx)),
+            None => Ok(None),
+        }
+    }
+
+    // Record a transaction, or update one (bringing it back into memory if it was spilled)
+    pub(crate) fn insert(&mut self, tx: TxId, record: TxnRecord) {
+        self.hot.insert(tx, record);
+    }
+
+    // How many transactions are recorded, in memory or not
+    pub(crate) fn len(&self) -> io::Result<usize> {
+        let Some(spill) = &self.spill else {
+            return Ok(self.hot.len());
+        };
+        // those back in memory after an update are on disk too
+        let mut file = lock(spill);
+        let mut back = 0;
+        for tx in self.hot.keys() {
+            if file.get(self.client, *tx)?.is_some() {
+                back += 1;
+            }
+        }
+        Ok(self.spilled + self.hot.len() - back)
+    }
+
+    // the transactions on disk, including stale copies of those since brought back into memory
+    pub(crate) fn spilled(&self) -> io::Result<Vec<(TxId, TxnRecord)>> {
+        let Some(spill) = &self.spill else {
+            return Ok(Vec::new());
+        };
+        let txns = lock(spill).scan()?;
+        Ok(txns
+            .into_iter()
+            .filter(|entry| entry.client == self.client)
+            .map(|entry| (entry.tx, entry.record))
+            .collect())
+    }
+
+    // Every transaction, in memory or not
+    pub(crate) fn all(&self) -> Result<Vec<(TxId, TxnRecord)>, TxnError> {
+        let spilled = match &self.spill {
+            Some(spill) => kept(lock(spill), |file| file.scan())?,
+            None => Vec::new(),
+        };
+        // a spilled transaction that has been updated since is back in memory, and that copy wins
+        let mut records: HashMap<TxId, TxnRecord> = spilled
+            .into_iter()
+            .filter(|entry| entry.client == self.client)
+            .map(|entry| (entry.tx, entry.record))
+            .collect();
+        records.extend(self.hot());
+        Ok(records.into_iter().collect())
+    }
+
+    // Forget every transaction. Those on disk stay in the file, but Bank::spilled no longer
+    // counts them as the client's.
+    pub(crate) fn clear(&mut self) {
+        self.hot.clear();
+        self.spill = None;
+        self.spilled = 0;
+    }
+
+    // the transactions held in memory, spilled ones aren't included
+    pub(crate) fn hot(&self) -> impl Iterator<Item = (TxId, TxnRecord)> + '_ {
+        self.hot.iter().map(|(tx, record)| (*tx, *record))
+    }
+
+    // Moves the transactions in memory to the file. If writing fails they all stay in memory,
+    // those that made it to the file counted as back in memory after an update.
+    fn spill(&mut self, spill: &SharedSpill) {
+        let mut file = lock(spill);
+        let (mut written, mut failed) = (0, false);
+        for (tx, record) in &self.hot {
+            let entry = Entry {
+                client: self.client,
+                tx: *tx,
+                record: *record,
+            };
+            match file.insert(&entry) {
+                Ok(added) => written += usize::from(added),
+                Err(err) => {
+                    file.error.get_or_insert(err);
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        self.spilled += written;
+        if !failed {
+            self.hot.clear();
+            self.spill = Some(spill.clone());
+        }
+    }
+}
+
+type SharedSpill = Arc<Mutex<SpillFile>>;
+
+fn lock(spill: &SharedSpill) -> MutexGuard<'_, SpillFile> {
+    spill
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// What read gets from the file, or TxnError::SpillFailed with the error kept for
+// Bank::check_spill
+fn kept<T>(
+    mut file: MutexGuard<'_, SpillFile>,
+    read: impl FnOnce(&mut SpillFile) -> io::Result<T>,
+) -> Result<T, TxnError> {
+    read(&mut file).map_err(|err| {
+        file.error.get_or_insert(err);
+        TxnError::SpillFailed
+    })
+}
+
+// The bank's side of spilling: the shared file and how much may stay in memory
+#[derive(Debug)]
+pub(crate) struct Spill {
+    file: SharedSpill,
+    max_in_memory: usize,
+    // transactions recorded since the last count, an upper bound on how many were added
+    recorded: usize,
+    in_memory: usize,
+    clock: u64,
+}
+
+impl Bank {
+    // Keep at most about max_in_memory deposits/withdrawals in memory, moving those of the
+    // least recently active clients to a temporary file when there are more. Balances, open
+    // disputes and the tx ids seen so far stay in memory.
+    pub fn spill_to_disk(&mut self, max_in_memory: usize) -> io::Result<()> {
+        let file = SpillFile::create()?;
+        self.spill = Some(Spill {
+            file: Arc::new(Mutex::new(file)),
+            max_in_memory,
+            recorded: 0,
+            in_memory: 0,
+            clock: 0,
+        });
+        self.spill_if_over();
+        Ok(())
+    }
+
+    // Called after a deposit/withdrawal is recorded for client_id
+    pub(crate) fn recorded(&mut self, client_id: ClientId) {
+        let Some(spill) = &mut self.spill else {
+            return;
+        };
+        spill.clock += 1;
+        spill.recorded += 1;
+        if let Some(client) = self.bank.get_mut(&client_id) {
+            client.txns.last_used = spill.clock;
+        }
+        if spill.in_memory + spill.recorded > spill.max_in_memory {
+            self.spill_if_over();
+        }
+    }
+
+    // Spill the least recently active clients until only half the allowance is in memory, so
+    // this doesn't run again on the very next transaction
+    pub(crate) fn spill_if_over(&mut self) {
+        let Some(spill) = &mut self.spill else {
+            return;
+        };
+        let mut in_memory: usize = self.bank.values().map(|client| client.txns.hot.len()).sum();
+        if in_memory > spill.max_in_memory {
+            let mut clients: Vec<(u64, ClientId)> = self
+                .bank
+                .values()
+                .filter(|client| !client.txns.hot.is_empty())
+                .map(|client| (client.txns.last_used, client.client))
+                .collect();
+            clients.sort_unstable();
+            for (_, client_id) in clients {
+                if in_memory <= spill.max_in_memory / 2 {
+                    break;
+                }
+                let txns = &mut self.bank.get_mut(&client_id).expect("listed above").txns;
+                in_memory -= txns.hot.len();
+                txns.spill(&spill.file);
+            }
+        }
+        spill.in_memory = in_memory;
+        spill.recorded = 0;
+    }
+
+    // The first error reading or writing the spill file since the last call, if there was one.
+    // The transactions it refused with TxnError::SpillFailed weren't applied.
+    pub fn check_spill(&mut self) -> io::Result<()> {
+        match &self.spill {
+            Some(spill) => lock(&spill.file).error.take().map_or(Ok(()), Err),
+            None => Ok(()),
+        }
+    }
+
+    // The error behind a TxnError::SpillFailed, where it fails the run instead of refusing the
+    // transaction
+    pub(crate) fn spill_failure(&mut self) -> Error {
+        match self.check_spill() {
+            Err(err) => err.into(),
+            Ok(()) => io::Error::other(TxnError::SpillFailed.to_string()).into(),
+        }
+    }
+
+    // Every spilled transaction, by client
+    pub(crate) fn spilled(&self) -> io::Result<HashMap<ClientId, Vec<(TxId, TxnRecord)>>> {
+        let mut spilled: HashMap<ClientId, Vec<(TxId, TxnRecord)>> = HashMap::new();
+        if let Some(spill) = &self.spill {
+            let txns = lock(&spill.file).scan()?;
+            for entry in txns {
+                // a client merged into another has none there any more
+                let owned = self.bank.get(&entry.client);
+                if owned.is_none_or(|client| client.txns.spill.is_none()) {
+                    continue;
+                }
+                let client = spilled.entry(entry.client).or_default();
+                client.push((entry.tx, entry.record));
+            }
+        }
+        Ok(spilled)
+    }
+}
+
+// An on-disk hash table of transactions keyed by client and tx id, with open addressing and
+// linear probing over fixed-size slots. It's only ever added to. The file is removed on drop.
+#[derive(Debug)]
+struct SpillFile {
+    file: File,
+    path: PathBuf,
+    // number of slots, always a power of two
+    capacity: u64,
+    len: u64,
+    // the first error reading or writing it that Bank::check_spill hasn't returned yet
+    error: Option<io::Error>,
+}
+
+// Where each field of an encoded Entry starts; the ids take as many bytes as their type
+const CLIENT: usize = 2;
+const TX: usize = CLIENT + mem::size_of::<ClientId>();
+const AMOUNT: usize = TX + mem::size_of::<TxId>();
+const TO: usize = AMOUNT + 8;
+const TIMESTAMP: usize = TO + mem::size_of::<ClientId>();
+const DISPUTED: usize = TIMESTAMP + 8;
+const RESOLUTIONS: usize = DISPUTED + 8;
+const CURRENCY: usize = RESOLUTIONS + 4;
+// one encoded Entry
+const SLOT: usize = CURRENCY + 3;
+const INITIAL_SLOTS: u64 = 1 << 16;
+// slots read per probe, so a run of collisions is one read rather than one per slot
+const PROBE_SLOTS: u64 = 64;
+
+static SPILL_FILES: AtomicU64 = AtomicU64::new(0);
+
+impl SpillFile {
+    fn create() -> io::Result<SpillFile> {
+        SpillFile::with_capacity(INITIAL_SLOTS)
+    }
+
+    fn with_capacity(capacity: u64) -> io::Result<SpillFile> {
+        let n = SPILL_FILES.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("transactions-spill-{}-{}", process::id(), n));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        // unused slots read back as zeroes
+        file.set_len(capacity * SLOT as u64)?;
+        Ok(SpillFile {
+            file,
+            path,
+            capacity,
+            len: 0,
+            error: None,
+        })
+    }
+
+    fn get(&mut self, client: ClientId, tx: TxId) -> io::Result<Option<TxnRecord>> {
+        let (_, found) = self.probe(client, tx)?;
+        Ok(found.map(|entry| entry.record))
+    }
+
+    // Adds the entry, or overwrites the stale copy of one that was spilled before. Returns
+    // whether it was added.
+    fn insert(&mut self, entry: &Entry) -> io::Result<bool> {
+        // kept at most half full so probe runs stay short
+        if (self.len + 1) * 2 > self.capacity {
+            self.grow()?;
+        }
+        let (slot, found) = self.probe(entry.client, entry.tx)?;
+        self.file.seek(SeekFrom::Start(slot * SLOT as u64))?;
+        self.file.write_all(&encode(entry))?;
+        if found.is_none() {
+            self.len += 1;
+        }
+        Ok(found.is_none())
+    }
+
+    // The slot holding (client, tx) and its entry, or the empty slot where it would go
+    fn probe(&mut self, client: ClientId, tx: TxId) -> io::Result<(u64, Option<Entry>)> {
+        let mut slot = hash(client, tx) & (self.capacity - 1);
+        let mut buf = [0; SLOT * PROBE_SLOTS as usize];
+        loop {
+            let count = PROBE_SLOTS.min(self.capacity - slot);
+            let chunk = &mut buf[..count as usize * SLOT];
+            self.file.seek(SeekFrom::Start(slot * SLOT as u64))?;
+            self.file.read_exact(chunk)?;
+            for (i, bytes) in chunk.chunks_exact(SLOT).enumerate() {
+                match decode(bytes) {
+                    None => return Ok((slot + i as u64, None)),
+                    Some(entry) if entry.client == client && entry.tx == tx => {
+                        return Ok((slot + i as u64, Some(entry)))
+                    }
+                    Some(_) => {}
+                }
+            }
+            slot = (slot + count) & (self.capacity - 1);
+        }
+    }
+
+    fn grow(&mut self) -> io::Result<()> {
+        let mut bigger = SpillFile::with_capacity(self.capacity * 2)?;
+        for entry in self.scan()? {
+            bigger.insert(&entry)?;
+        }
+        bigger.error = self.error.take();
+        *self = bigger;
+        Ok(())
+    }
+
+    fn scan(&mut self) -> io::Result<Vec<Entry>> {
+        let mut entries = Vec::with_capacity(self.len as usize);
+        let mut buf = vec![0; SLOT * 4096];
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut remaining = self.capacity * SLOT as u64;
+        while remaining > 0 {
+            let chunk = &mut buf[..remaining.min((SLOT * 4096) as u64) as usize];
+            self.file.read_exact(chunk)?;
+            entries.extend(chunk.chunks_exact(SLOT).filter_map(decode));
+            remaining -= chunk.len() as u64;
+        }
+        Ok(entries)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg_attr(feature = "wide-ids", allow(clippy::useless_conversion))]
+fn hash(client: ClientId, tx: TxId) -> u64 {
+    // splitmix64's finalizer, so neighbouring ids land far apart
+    let mut x = u64::from(client).rotate_left(32) ^ u64::from(tx);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[derive(Debug)]
+struct Entry {
+    client: ClientId,
+    tx: TxId,
+    record: TxnRecord,
+}
+
+// stands in for a missing timestamp, far enough in the past not to be a real one
+const NO_TIMESTAMP: i64 = i64::MIN;
+
+// The kind's code (zero marks an unused slot), the dispute state, client, tx, the
+// amount in ten-thousandths, a transfer's recipient, the timestamp, the disputed portion,
+// how many disputes were resolved, then the currency code (zeros for none)
+
+fn encode(entry: &Entry) -> [u8; SLOT] {
+    let mut bytes = [0; SLOT];
+    bytes[0] = entry.record.kind.code();
+    bytes[1] = match entry.record.state {
+        DisputeState::Undisputed => 0,
+        DisputeState::Disputed => 1,
+        DisputeState::Resolved => 2,
+        DisputeState::ChargedBack => 3,
+        DisputeState::Refunded => 4,
+        DisputeState::Released => 5,
+    };
+    bytes[CLIENT..TX].copy_from_slice(&entry.client.to_le_bytes());
+    bytes[TX..AMOUNT].copy_from_slice(&entry.tx.to_le_bytes());
+    bytes[AMOUNT..TO].copy_from_slice(&entry.record.amount.units().to_le_bytes());
+    bytes[TO..TIMESTAMP].copy_from_slice(&entry.record.to.unwrap_or(0).to_le_bytes());
+    let timestamp = entry.record.timestamp.unwrap_or(NO_TIMESTAMP);
+    bytes[TIMESTAMP..DISPUTED].copy_from_slice(&timestamp.to_le_bytes());
+    bytes[DISPUTED..RESOLUTIONS].copy_from_slice(&entry.record.disputed.units().to_le_bytes());
+    bytes[RESOLUTIONS..CURRENCY].copy_from_slice(&entry.record.resolutions.to_le_bytes());
+    if let Some(currency) = entry.record.currency {
+        bytes[CURRENCY..SLOT].copy_from_slice(&currency.bytes());
+    }
+    bytes
+}
+
+// The N bytes of the field starting at start
+fn field<const N: usize>(bytes: &[u8], start: usize) -> [u8; N] {
+    bytes[start..start + N].try_into().expect("slot layout")
+}
+
+fn decode(bytes: &[u8]) -> Option<Entry> {
+    let kind = TransactionType::from_code(bytes[0])?;
+    let to = ClientId::from_le_bytes(field(bytes, TO));
+    let timestamp = i64::from_le_bytes(field(bytes, TIMESTAMP));
+    Some(Entry {
+        client: ClientId::from_le_bytes(field(bytes, CLIENT)),
+        tx: TxId::from_le_bytes(field(bytes, TX)),
+        record: TxnRecord {
+            kind,
+            amount: Amount::from_units(i64::from_le_bytes(field(bytes, AMOUNT))),
+            state: match bytes[1] {
+                0 => DisputeState::Undisputed,
+                1 => DisputeState::Disputed,
+                2 => DisputeState::Resolved,
+                3 => DisputeState::ChargedBack,
+                4 => DisputeState::Refunded,
+                _ => DisputeState::Released,
+            },
+            to: (kind == TransactionType::Transfer).then_some(to),
+            timestamp: (timestamp != NO_TIMESTAMP).then_some(timestamp),
+            disputed: Amount::from_units(i64::from_le_bytes(field(bytes, DISPUTED))),
+            resolutions: u32::from_le_bytes(field(bytes, RESOLUTIONS)),
+            currency: Currency::from_bytes(field(bytes, CURRENCY)),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::TransactionSource;
+
+    fn process(bank: &mut Bank, rows: &str) -> Result<(), Error> {
+        let csv = format!("type,client,tx,amount\n{}", rows);
+        bank.process_source(TransactionSource::csv(csv.as_bytes()))
+            .map(|_| ())
+    }
+
+    // Swaps the spill file's handle for one opened only for writing or only for reading
+    fn reopen(bank: &Bank, options: &OpenOptions) {
+        let mut file = lock(&bank.spill.as_ref().unwrap().file);
+        file.file = options.open(&file.path).unwrap();
+    }
+
+    #[test]
+    fn failing_reads_refuse_the_transaction_and_fail_the_run() {
+        let mut bank = Bank::new();
+        bank.spill_to_disk(1).unwrap();
+        process(&mut bank, "deposit,1,1,5\ndeposit,2,2,5\n").unwrap();
+        assert!(bank.bank[&1].txns.spill.is_some());
+
+        reopen(&bank, OpenOptions::new().write(true));
+        let dispute = "dispute,1,1,\n";
+        assert!(matches!(process(&mut bank, dispute), Err(Error::Io(_))));
+        bank.check_spill().unwrap();
+        assert_eq!(bank.record(1).unwrap().held.to_string(), "0.0000");
+
+        reopen(&bank, OpenOptions::new().read(true).write(true));
+        process(&mut bank, dispute).unwrap();
+        assert_eq!(bank.record(1).unwrap().held.to_string(), "5.0000");
+    }
+
+    #[test]
+    fn failing_writes_leave_the_transactions_in_memory() {
+        let mut bank = Bank::new();
+        bank.spill_to_disk(1).unwrap();
+        reopen(&bank, OpenOptions::new().read(true));
+        assert!(matches!(
+            process(&mut bank, "deposit,1,1,5\ndeposit,2,2,5\n"),
+            Err(Error::Io(_))
+        ));
+        assert_eq!(bank.bank[&1].txns.hot.len(), 1);
+        assert!(bank.bank[&1].txns.spill.is_none());
+
+        reopen(&bank, OpenOptions::new().read(true).write(true));
+        process(&mut bank, "dispute,1,1,\n").unwrap();
+        assert_eq!(bank.record(1).unwrap().held.to_string(), "5.0000");
+    }
+}
@@ -0,0 +1,606 @@
+use crate::amount::Amount;
+use crate::bank::{
+    Client, ClientId, ClientRecord, DisputeState, Transaction, TransactionType, TxnRecord,
+};
+use crate::currency::{Balance, Currency};
+use crate::error::Error;
+use crate::imports;
+use crate::merge;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::policy::{AccountOpeningPolicy, Policy, TxIdPolicy};
+use crate::report::{self, OutputFormat};
+use crate::source::{SourceStats, TransactionSource};
+use crate::transfer;
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef},
+    Connection, OptionalExtension,
+};
+use std::{io, path::Path};
+
+const SCHEMA: &str = "
+    -- with each client's unlabelled balance
+    CREATE TABLE IF NOT EXISTS clients (
+        client INTEGER PRIMARY KEY,
+        available TEXT NOT NULL,
+        held TEXT NOT NULL,
+        locked INTEGER NOT NULL,
+        closed INTEGER NOT NULL DEFAULT 0,
+        -- all the withdrawals from it, for the withdrawal limits
+        withdrawn TEXT NOT NULL DEFAULT '0'
+    );
+    -- accepted deposits, withdrawals, transfers, fees, interest postings and conversions
+    CREATE TABLE IF NOT EXISTS txns (
+        client INTEGER NOT NULL,
+        tx INTEGER NOT NULL,
+        type TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        state TEXT NOT NULL DEFAULT 'undisputed',
+        -- how much of amount the open dispute covers
+        disputed TEXT NOT NULL DEFAULT '0',
+        -- how many disputes of it were resolved
+        resolutions INTEGER NOT NULL DEFAULT 0,
+        -- a transfer's recipient
+        recipient INTEGER,
+        -- seconds since the Unix epoch, if the input gave one
+        timestamp INTEGER,
+        currency TEXT,
+        PRIMARY KEY (client, tx)
+    );
+    CREATE INDEX IF NOT EXISTS txns_by_tx ON txns (tx);
+    CREATE TABLE IF NOT EXISTS rejected_withdrawals (
+        client INTEGER NOT NULL,
+        tx INTEGER NOT NULL,
+        amount TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS unlocks (
+        client INTEGER NOT NULL,
+        tx INTEGER NOT NULL
+    );
+    -- balances in a named currency
+    CREATE TABLE IF NOT EXISTS balances (
+        client INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        available TEXT NOT NULL,
+        held TEXT NOT NULL,
+        withdrawn TEXT NOT NULL DEFAULT '0',
+        PRIMARY KEY (client, currency)
+    );
+    -- the FITID or QIF contents of each entry applied from an OFX or QIF file, see imports.rs
+    CREATE TABLE IF NOT EXISTS imports (
+        tx INTEGER NOT NULL,
+        client INTEGER NOT NULL,
+        key TEXT NOT NULL,
+        PRIMARY KEY (tx, client)
+    );
+";
+
+// Columns added to the tables since they were first created, for files from before then
+const ADDED_COLUMNS: [(&str, &str, &str); 3] = [
+    ("clients", "closed", "INTEGER NOT NULL DEFAULT 0"),
+    ("clients", "withdrawn", "TEXT NOT NULL DEFAULT '0'"),
+    ("balances", "withdrawn", "TEXT NOT NULL DEFAULT '0'"),
+];
+
+// A bank whose accounts and transaction history live in a SQLite file rather than in memory,
+// for inputs whose history is too big to hold. The file persists, so a later run carries on
+// from the state left by earlier ones.
+//
+// Only the rows a transaction touches are read: the client's balances plus the referenced tx, and
+// the recipient's balances for a transfer. They're loaded into a Client, which processes the
+// transaction exactly as in memory, and the changes are written back.
+pub struct SqliteBank {
+    conn: Connection,
+    policy: Policy,
+}
+
+impl SqliteBank {
+    pub fn open<P: AsRef<Path>>(path: P, policy: Policy) -> Result<SqliteBank, Error> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        for (table, column, definition) in ADDED_COLUMNS {
+            if conn
+                .prepare(&format!("SELECT {} FROM {}", column, table))
+                .is_err()
+            {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, definition
+                ))?;
+            }
+        }
+        Ok(SqliteBank { conn, policy })
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    // Same as Bank::insert_txn. The outer error is a database failure, the inner one the
+    // engine refusing the transaction.
+    pub fn insert_txn(&mut self, txn: Transaction) -> Result<Result<TxnOutcome, TxnError>, Error> {
+        let moves_funds = txn.tx_type.moves_funds();
+        if moves_funds && self.policy.tx_ids == TxIdPolicy::Global && self.owned_elsewhere(&txn)? {
+            return Ok(Err(TxnError::DuplicateTxOtherClient));
+        }
+        if txn.tx_type == TransactionType::Merge {
+            self.conn.execute_batch("SAVEPOINT merge")?;
+            let result = self.insert_merge(txn);
+            match result {
+                Ok(_) => self.conn.execute_batch("RELEASE merge")?,
+                Err(_) => self
+                    .conn
+                    .execute_batch("ROLLBACK TO merge; RELEASE merge")?,
+            }
+            return result;
+        }
+        // an account that's opened stays open even if the transaction opening it is refused
+        let (mut client, opened) = match self.load_client(&txn)? {
+            Some(client) => (client, false),
+            None if self.policy.opening.opens(txn.tx_type) => (Client::new(txn.client), true),
+            None => return Ok(Err(TxnError::UnknownClient)),
+        };
+        match transfer::is_cross_client(Some(&client), &txn) {
+            Ok(true) => return self.insert_transfer(client, opened, txn),
+            Ok(false) => {}
+            Err(err) => return Ok(Err(err)),
+        }
+        let rejected_before = client.rejected_withdrawals.len();
+        let unlocks_before = client.unlocks.len();
+        let result = client.process_txn(txn, &self.policy);
+        for rejected in &client.rejected_withdrawals[rejected_before..] {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO rejected_withdrawals (client, tx, amount) VALUES (?1, ?2, ?3)",
+                )?
+                .execute(params![rejected.client, rejected.tx, rejected.amount])?;
+        }
+        if result.is_ok() || opened {
+            self.store_client(&client, &txn)?;
+        }
+        for unlock in &client.unlocks[unlocks_before..] {
+            self.conn
+                .prepare_cached("INSERT INTO unlocks (client, tx) VALUES (?1, ?2)")?
+                .execute(params![unlock.client, unlock.tx])?;
+        }
+        Ok(result)
+    }
+
+    // Like Bank::merge, moving the merged client's rows over to the recipient
+    fn insert_merge(&mut self, txn: Transaction) -> Result<Result<TxnOutcome, TxnError>, Error> {
+        let Some(mut source) = self.load_balances(txn.client)? else {
+            return Ok(Err(TxnError::UnknownClient));
+        };
+        let to = txn.to.filter(|to| *to != txn.client);
+        let Some(mut target) = to.map(|to| self.load_balances(to)).transpose()?.flatten() else {
+            return Ok(Err(TxnError::InvalidRecipient));
+        };
+        if let Err(err) = merge::check(&source, &target) {
+            return Ok(Err(err));
+        }
+        let shared: bool = self.conn.query_row(
+            "SELECT EXISTS (
+                SELECT 1 FROM txns mine JOIN txns theirs ON theirs.tx = mine.tx
+                WHERE mine.client = ?1 AND theirs.client = ?2
+             )",
+            params![source.client, target.client],
+            |row| row.get(0),
+        )?;
+        if shared {
+            return Ok(Err(TxnError::DuplicateTx));
+        }
+        if let Err(err) = merge::move_balances(&mut source, &mut target) {
+            return Ok(Err(err));
+        }
+        for table in ["txns", "rejected_withdrawals", "unlocks"] {
+            self.conn.execute(
+                &format!("UPDATE {} SET client = ?2 WHERE client = ?1", table),
+                params![source.client, target.client],
+            )?;
+        }
+        self.conn.execute(
+            "UPDATE txns SET recipient = ?2 WHERE recipient = ?1",
+            params![source.client, target.client],
+        )?;
+        // store_client only writes the currencies an account still has
+        self.conn.execute(
+            "DELETE FROM balances WHERE client = ?1",
+            params![source.client],
+        )?;
+        self.store_client(&source, &txn)?;
+        self.store_client(&target, &txn)?;
+        Ok(Ok(TxnOutcome::Merged))
+    }
+
+    // Like Bank::process_transfer
+    fn insert_transfer(
+        &mut self,
+        mut sender: Client,
+        sender_opened: bool,
+        txn: Transaction,
+    ) -> Result<Result<TxnOutcome, TxnError>, Error> {
+        let mut recipient = match transfer::recipient(&sender, &txn) {
+            Ok(recipient) => self.load_recipient(recipient)?,
+            Err(err) => Err(err),
+        };
+        let result = match &mut recipient {
+            Ok((recipient, _)) => transfer::transfer(&mut sender, recipient, txn, &self.policy),
+            Err(err) => Err(*err),
+        };
+        if result.is_ok() || sender_opened {
+            self.store_client(&sender, &txn)?;
+        }
+        if let Ok((recipient, opened)) = &recipient {
+            if result.is_ok() || *opened {
+                self.store_client(recipient, &txn)?;
+            }
+        }
+        Ok(result)
+    }
+
+    // A transfer recipient's balances, and whether the account was just opened for it
+    fn load_recipient(
+        &self,
+        client_id: ClientId,
+    ) -> Result<Result<(Client, bool), TxnError>, Error> {
+        Ok(match self.load_balances(client_id)? {
+            Some(client) => Ok((client, false)),
+            None if self.policy.opening == AccountOpeningPolicy::Always => {
+                Ok((Client::new(client_id), true))
+            }
+            None => Err(TxnError::InvalidRecipient),
+        })
+    }
+
+    // As Bank::next_from does for the bank in memory
+    fn check_import(&self, txn: &Transaction, key: &str) -> Result<(), Error> {
+        let collision = |holder: String| imports::collision(txn, key, holder);
+        let earlier: Option<String> = self
+            .conn
+            .prepare_cached("SELECT key FROM imports WHERE tx = ?1 AND client = ?2")?
+            .query_row(params![txn.tx, txn.client], |row| row.get(0))
+            .optional()?;
+        if let Some(earlier) = earlier {
+            return if earlier == key {
+                Ok(())
+            } else {
+                Err(collision(format!("the client's '{}'", earlier)))
+            };
+        }
+        let owned = self
+            .conn
+            .prepare_cached("SELECT 1 FROM txns WHERE tx = ?1 AND client = ?2")?
+            .query_row(params![txn.tx, txn.client], |_| Ok(()))
+            .optional()?;
+        if owned.is_some() {
+            return Err(collision(
+                "another of the client's transactions".to_string(),
+            ));
+        }
+        if self.policy.tx_ids == TxIdPolicy::Global {
+            let other: Option<(ClientId, String)> = self
+                .conn
+                .prepare_cached("SELECT client, key FROM imports WHERE tx = ?1 LIMIT 1")?
+                .query_row(params![txn.tx], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?;
+            if let Some((client, earlier)) = other {
+                return Err(collision(format!("client {}'s '{}'", client, earlier)));
+            }
+            if self.owned_elsewhere(txn)? {
+                return Err(collision("another client's transaction".to_string()));
+            }
+        }
+        self.conn
+            .prepare_cached("INSERT INTO imports (tx, client, key) VALUES (?1, ?2, ?3)")?
+            .execute(params![txn.tx, txn.client, key])?;
+        Ok(())
+    }
+
+    // whether another client already recorded a deposit/withdrawal with this tx id
+    fn owned_elsewhere(&self, txn: &Transaction) -> Result<bool, Error> {
+        let found = self
+            .conn
+            .prepare_cached("SELECT 1 FROM txns WHERE tx = ?1 AND client != ?2 LIMIT 1")?
+            .query_row(params![txn.tx, txn.client], |_| Ok(()))
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    // The client's balances, in every currency, with no history
+    fn load_balances(&self, client_id: ClientId) -> Result<Option<Client>, Error> {
+        let client = self
+            .conn
+            .prepare_cached(
+                "SELECT available, held, locked, closed, withdrawn FROM clients WHERE client = ?1",
+            )?
+            .query_row(params![client_id], |row| {
+                let mut client = Client::new(client_id);
+                client.available = row.get(0)?;
+                client.held = row.get(1)?;
+                client.locked = row.get(2)?;
+                client.closed = row.get(3)?;
+                client.withdrawn = row.get(4)?;
+                Ok(client)
+            })
+            .optional()?;
+        let Some(mut client) = client else {
+            return Ok(None);
+        };
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT currency, available, held, withdrawn FROM balances WHERE client = ?1",
+        )?;
+        let balances = stmt.query_map(params![client_id], |row| {
+            let balance = Balance {
+                available: row.get(1)?,
+                held: row.get(2)?,
+                withdrawn: row.get(3)?,
+                ..Balance::default()
+            };
+            Ok((row.get(0)?, balance))
+        })?;
+        for balance in balances {
+            let (currency, balance) = balance?;
+            client.currencies.insert(currency, balance);
+        }
+        client.open_ledger();
+        Ok(Some(client))
+    }
+
+    // The client's balances, with only the transaction txn refers to in its history
+    fn load_client(&self, txn: &Transaction) -> Result<Option<Client>, Error> {
+        let Some(mut client) = self.load_balances(txn.client)? else {
+            return Ok(None);
+        };
+        let stored = self
+            .conn
+            .prepare_cached(
+                "SELECT type, amount, state, disputed, resolutions, recipient, timestamp, currency
+                 FROM txns
+                 WHERE client = ?1 AND tx = ?2",
+            )?
+            .query_row(params![txn.client, txn.tx], |row| {
+                Ok(TxnRecord {
+                    kind: row.get(0)?,
+                    amount: row.get(1)?,
+                    state: row.get(2)?,
+                    disputed: row.get(3)?,
+                    resolutions: row.get(4)?,
+                    to: row.get(5)?,
+                    timestamp: row.get(6)?,
+                    currency: row.get(7)?,
+                })
+            })
+            .optional()?;
+        if let Some(record) = stored {
+            client.txns.insert(txn.tx, record);
+        }
+        Ok(Some(client))
+    }
+
+    // Write back what processing txn can have changed: the balances and the referenced tx
+    fn store_client(&self, client: &Client, txn: &Transaction) -> Result<(), Error> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO clients (client, available, held, locked, closed, withdrawn)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (client) DO UPDATE SET
+                    available = excluded.available, held = excluded.held,
+                    locked = excluded.locked, closed = excluded.closed,
+                    withdrawn = excluded.withdrawn",
+            )?
+            .execute(params![
+                client.client,
+                client.available,
+                client.held,
+                client.locked,
+                client.closed,
+                client.withdrawn
+            ])?;
+        for (currency, balance) in &client.currencies {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO balances (client, currency, available, held, withdrawn)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (client, currency) DO UPDATE SET
+                        available = excluded.available, held = excluded.held,
+                        withdrawn = excluded.withdrawn",
+                )?
+                .execute(params![
+                    client.client,
+                    currency,
+                    balance.available,
+                    balance.held,
+                    balance.withdrawn
+                ])?;
+        }
+        // a client loaded for a transaction never spills, so its records are all in memory
+        if let Ok(Some(record)) = client.txns.get(&txn.tx) {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO txns
+                        (client, tx, type, amount, state, disputed, resolutions, recipient, timestamp,
+                         currency)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                     ON CONFLICT (client, tx) DO UPDATE SET state = excluded.state,
+                        disputed = excluded.disputed, resolutions = excluded.resolutions",
+                )?
+                .execute(params![
+                    client.client,
+                    txn.tx,
+                    record.kind,
+                    record.amount,
+                    record.state,
+                    record.disputed,
+                    record.resolutions,
+                    record.to,
+                    record.timestamp,
+                    record.currency
+                ])?;
+        }
+        Ok(())
+    }
+
+    // Leave the file as it is: everything from now on is applied in a database transaction
+    // that's never committed, so it's rolled back when the bank is dropped
+    pub fn dry_run(&mut self) -> Result<(), Error> {
+        self.conn.execute_batch("BEGIN")?;
+        Ok(())
+    }
+
+    // Like Bank::process_source_with. The whole source is applied in one database
+    // transaction (a savepoint, so it nests in a dry run's), so a run that fails part way
+    // leaves the file as it was.
+    pub fn process_source_with<R, F>(
+        &mut self,
+        source: TransactionSource<R>,
+        mut on_reject: F,
+    ) -> Result<SourceStats, Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        self.conn.execute_batch("SAVEPOINT source")?;
+        let result = self.apply_source(source, &mut on_reject);
+        match result {
+            Ok(_) => self.conn.execute_batch("RELEASE source")?,
+            Err(_) => self
+                .conn
+                .execute_batch("ROLLBACK TO source; RELEASE source")?,
+        }
+        result
+    }
+
+    fn apply_source<R, F>(
+        &mut self,
+        mut source: TransactionSource<R>,
+        on_reject: &mut F,
+    ) -> Result<SourceStats, Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        let mut stats = SourceStats::default();
+        while let Some(txn) = source.next_txn(&self.policy, &mut stats)? {
+            if let Some(key) = source.import_key() {
+                self.check_import(&txn, key)
+                    .map_err(|err| err.at_line(source.line()))?;
+            }
+            let result = match source.refusal() {
+                Some(err) => Err(err),
+                None => self.insert_txn(txn)?,
+            };
+            if let Err(err) = result {
+                stats.reject(&txn, &err, source.line());
+                on_reject(&txn, &err)?;
+            }
+        }
+        Ok(stats)
+    }
+
+    // The record of the client's unlabelled balance, as Bank::record
+    pub fn record(&self, client_id: ClientId) -> Result<Option<ClientRecord>, Error> {
+        Ok(self.load_balances(client_id)?.map(|client| client.record()))
+    }
+
+    // Every client's report records, in client id order and then currency order
+    pub fn records(&self) -> Result<Vec<ClientRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT client FROM clients ORDER BY client")?;
+        let ids = stmt
+            .query_map([], |row| row.get::<_, ClientId>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut records = Vec::new();
+        for id in ids {
+            if let Some(client) = self.load_balances(id)? {
+                records.extend(client.records());
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn write_report_as<W: io::Write>(&self, w: W, format: OutputFormat) -> Result<(), Error> {
+        report::write_records(self.records()?.into_iter(), w, format)
+    }
+}
+
+// Amounts are stored as their decimal text so no precision is lost
+impl ToSql for Amount {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for Amount {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Amount> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err| FromSqlError::Other(Box::new(err)))
+    }
+}
+
+impl ToSql for TransactionType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for TransactionType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<TransactionType> {
+        match value.as_str()? {
+            "withdrawal" => Ok(TransactionType::Withdrawal),
+            "deposit" => Ok(TransactionType::Deposit),
+            "dispute" => Ok(TransactionType::Dispute),
+            "resolve" => Ok(TransactionType::Resolve),
+            "chargeback" => Ok(TransactionType::Chargeback),
+            "unlock" => Ok(TransactionType::Unlock),
+            "transfer" => Ok(TransactionType::Transfer),
+            "fee" => Ok(TransactionType::Fee),
+            "accrue" => Ok(TransactionType::Accrue),
+            "convert" => Ok(TransactionType::Convert),
+            "refund" => Ok(TransactionType::Refund),
+            "close" => Ok(TransactionType::Close),
+            "merge" => Ok(TransactionType::Merge),
+            "hold" => Ok(TransactionType::Hold),
+            "release" => Ok(TransactionType::Release),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for DisputeState {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.name()))
+    }
+}
+
+impl FromSql for DisputeState {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<DisputeState> {
+        match value.as_str()? {
+            "undisputed" => Ok(DisputeState::Undisputed),
+            "disputed" => Ok(DisputeState::Disputed),
+            "resolved" => Ok(DisputeState::Resolved),
+            "charged_back" => Ok(DisputeState::ChargedBack),
+            "refunded" => Ok(DisputeState::Refunded),
+            "released" => Ok(DisputeState::Released),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+// Currencies are stored as their code
+impl ToSql for Currency {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for Currency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Currency> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err: String| FromSqlError::Other(err.into()))
+    }
+}
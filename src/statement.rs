@@ -0,0 +1,166 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, ClientId, Transaction, TransactionType, TxId};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::source::{SourceStats, TransactionSource};
+use crate::transfer;
+use serde::Serialize;
+use std::io;
+
+// A client's statement is every accepted transaction that touched their account, in the order
+// it was applied, each with the balance it left. Transfers to the client and disputes of those
+// transfers are included, with the sender as their client. The balance is the one in the
+// transaction's currency, i.e. the referenced transaction's for a dispute, resolve or chargeback.
+#[derive(Serialize, Debug, Copy, Clone)]
+pub struct StatementLine {
+    // the client whose statement the line is on, the recipient's for a transfer paid to them
+    #[serde(skip)]
+    pub account: ClientId,
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    pub client: ClientId,
+    pub tx: TxId,
+    // blank for rows that carry no amount
+    pub amount: Option<Amount>,
+    pub currency: Option<Currency>,
+    pub outcome: &'static str,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    #[serde(skip)]
+    pub timestamp: Option<i64>,
+}
+
+impl Bank {
+    // Like process_source_with, also returning the statement lines of client
+    pub fn process_source_statement<R, F>(
+        &mut self,
+        source: TransactionSource<R>,
+        client: ClientId,
+        on_reject: F,
+    ) -> Result<(SourceStats, Vec<StatementLine>), Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        self.statement_lines(source, Some(client), on_reject)
+    }
+
+    // Like process_source_statement, for every client's statement at once, each line with the
+    // account it's on
+    pub fn process_source_statements<R, F>(
+        &mut self,
+        source: TransactionSource<R>,
+        on_reject: F,
+    ) -> Result<(SourceStats, Vec<StatementLine>), Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        self.statement_lines(source, None, on_reject)
+    }
+
+    // The lines of client's statement, or of everyone's for None
+    fn statement_lines<R, F>(
+        &mut self,
+        source: TransactionSource<R>,
+        client: Option<ClientId>,
+        mut on_reject: F,
+    ) -> Result<(SourceStats, Vec<StatementLine>), Error>
+    where
+        R: io::Read,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        let mut stats = SourceStats::default();
+        let mut lines = Vec::new();
+        let mut source = source;
+        while let Some(txn) = self.next_from(&mut source, &mut stats)? {
+            let recipient = self.recipient(&txn);
+            let accounts = [Some(txn.client), recipient]
+                .into_iter()
+                .flatten()
+                .filter(|account| client.is_none_or(|client| client == *account));
+            let accounts: Vec<ClientId> = accounts.collect();
+            // the balance it applies to, worked out as the engine will
+            let currency = self
+                .bank
+                .get(&txn.client)
+                .map_or(Ok(txn.currency), |sender| sender.currency_of(&txn))
+                .unwrap_or(txn.currency);
+            let result = self.insert_read(txn, source.refusal());
+            self.check_spill()?;
+            match result {
+                Ok(outcome) => {
+                    for account in accounts {
+                        lines.push(self.statement_line(&txn, outcome, account, currency));
+                    }
+                }
+                Err(err) => {
+                    stats.reject(&txn, &err, source.line());
+                    on_reject(&txn, &err)?;
+                }
+            }
+        }
+        Ok((stats, lines))
+    }
+
+    // The client txn pays, if it's a transfer (or dispute of one) to another client's account
+    fn recipient(&self, txn: &Transaction) -> Option<ClientId> {
+        let Some(sender) = self.bank.get(&txn.client) else {
+            return txn.to.filter(|_| txn.tx_type == TransactionType::Transfer);
+        };
+        if !transfer::is_cross_client(Some(sender), txn).unwrap_or(false) {
+            return None;
+        }
+        transfer::recipient(sender, txn).ok()
+    }
+
+    fn statement_line(
+        &self,
+        txn: &Transaction,
+        outcome: TxnOutcome,
+        client: ClientId,
+        currency: Option<Currency>,
+    ) -> StatementLine {
+        let balance = self
+            .bank
+            .get(&client)
+            .map(|account| account.balance(currency))
+            .unwrap_or_default();
+        StatementLine {
+            account: client,
+            tx_type: txn.tx_type,
+            client: txn.client,
+            tx: txn.tx,
+            amount: (txn.tx_type.moves_funds() || !txn.amount.is_zero()).then_some(txn.amount),
+            currency,
+            outcome: outcome.code(),
+            available: balance.available,
+            held: balance.held,
+            total: balance.available + balance.held,
+            timestamp: txn.timestamp,
+        }
+    }
+}
+
+pub fn write_statement<W: io::Write>(lines: &[StatementLine], w: W) -> Result<(), Error> {
+    let mut wtr = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+    // written by hand so an empty statement still has its header
+    wtr.write_record([
+        "type",
+        "client",
+        "tx",
+        "amount",
+        "currency",
+        "outcome",
+        "available",
+        "held",
+        "total",
+    ])?;
+    for line in lines {
+        wtr.serialize(line)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::bank::{Bank, Transaction};
+use crate::error::Error;
+use crate::outcome::TxnError;
+use crate::source::{take_row, InputFormat, LineParser, SourceStats};
+use futures_util::{stream, Stream, StreamExt};
+use std::pin::pin;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+impl Bank {
+    // Apply transactions as they arrive on an async stream, e.g. from a socket or message queue.
+    // Only waits while the stream has nothing ready, so no thread is tied up per connection.
+    // Failing to read the spill file refuses the transactions it affects; Bank::check_spill
+    // says whether it happened.
+    pub async fn process_stream<S>(&mut self, stream: S) -> SourceStats
+    where
+        S: Stream<Item = Transaction>,
+    {
+        let mut stats = SourceStats::default();
+        let mut stream = pin!(stream);
+        while let Some(txn) = stream.next().await {
+            stats.rows += 1;
+            if let Err(err) = self.insert_txn(txn) {
+                stats.reject(&txn, &err, stats.rows);
+            }
+        }
+        stats
+    }
+
+    // Like process_stream, calling on_reject for every transaction the engine refuses
+    pub async fn process_stream_with<S, F>(
+        &mut self,
+        stream: S,
+        mut on_reject: F,
+    ) -> Result<SourceStats, Error>
+    where
+        S: Stream<Item = Transaction>,
+        F: FnMut(&Transaction, &TxnError) -> Result<(), Error>,
+    {
+        let mut stats = SourceStats::default();
+        let mut stream = pin!(stream);
+        while let Some(txn) = stream.next().await {
+            stats.rows += 1;
+            let result = self.insert_txn(txn);
+            self.check_spill()?;
+            if let Err(err) = result {
+                stats.reject(&txn, &err, stats.rows);
+                on_reject(&txn, &err)?;
+            }
+        }
+        Ok(stats)
+    }
+
+    // The async counterpart of process_source: malformed items abort or are skipped per the
+    // bank's OnError policy
+    pub async fn process_try_stream<S>(&mut self, stream: S) -> Result<SourceStats, Error>
+    where
+        S: Stream<Item = Result<Transaction, Error>>,
+    {
+        let mut stats = SourceStats::default();
+        let mut stream = pin!(stream);
+        while let Some(result) = stream.next().await {
+            if let Some(txn) = take_row(result, self.policy.on_error, &mut stats)? {
+                let result = self.insert_txn(txn);
+                self.check_spill()?;
+                if let Err(err) = result {
+                    stats.reject(&txn, &err, stats.rows);
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+// Parse transactions a line at a time from an async reader
+pub fn transaction_stream<R>(
+    reader: R,
+    format: InputFormat,
+) -> impl Stream<Item = Result<Transaction, Error>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let state = (reader.lines(), LineParser::new(format));
+    stream::unfold(state, |(mut lines, mut parser)| async move {
+        loop {
+            let text = match lines.next_line().await {
+                Ok(Some(text)) => text,
+                Ok(None) => return None,
+                Err(err) => return Some((Err(Error::from(err)), (lines, parser))),
+            };
+            match parser.parse(&text) {
+                Ok(None) => continue,
+                Ok(Some(txn)) => return Some((Ok(txn), (lines, parser))),
+                Err(err) => return Some((Err(err), (lines, parser))),
+            }
+        }
+    })
+}
@@ -0,0 +1,98 @@
+use crate::amount::Amount;
+use crate::bank::ClientRecord;
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::source::{Duplicate, SourceStats};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    time::Duration,
+};
+
+// Totals for a whole run, for an operator to check at a glance that it did what they expected
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub rows: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub rejected_by: BTreeMap<&'static str, u64>,
+    pub skipped: u64,
+    // rows queued until their effective date, which aren't counted as accepted
+    pub pending: u64,
+    // the rejected rows that reused a transaction id, in the order they were read
+    pub duplicates: Vec<Duplicate>,
+    pub clients: usize,
+    pub locked: usize,
+    // available and held across every account, per currency (None for unlabelled balances)
+    pub totals: BTreeMap<Option<Currency>, (Amount, Amount)>,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    // From the stats of every source read and the final report records
+    pub fn new(
+        stats: &SourceStats,
+        records: impl Iterator<Item = ClientRecord>,
+        elapsed: Duration,
+    ) -> Summary {
+        let mut summary = Summary {
+            rows: stats.rows,
+            accepted: stats.rows - stats.rejected - stats.pending,
+            rejected: stats.rejected,
+            rejected_by: stats.rejected_by.clone(),
+            skipped: stats.skipped,
+            pending: stats.pending,
+            duplicates: stats.duplicates.clone(),
+            elapsed,
+            ..Summary::default()
+        };
+        let (mut clients, mut locked) = (HashSet::new(), HashSet::new());
+        for record in records {
+            clients.insert(record.client);
+            if record.locked {
+                locked.insert(record.client);
+            }
+            let totals = summary.totals.entry(record.currency).or_default();
+            // the bank's totals can be past what an Amount holds, where each client's can't
+            totals.0 = totals.0.saturating_add(record.available);
+            totals.1 = totals.1.saturating_add(record.held);
+        }
+        summary.clients = clients.len();
+        summary.locked = locked.len();
+        summary
+    }
+
+    // As `name: value` lines, with the rejections broken down by reason code and the duplicate
+    // ids' lines listed
+    pub fn write<W: io::Write>(&self, mut w: W) -> Result<(), Error> {
+        writeln!(w, "rows read: {}", self.rows)?;
+        writeln!(w, "accepted: {}", self.accepted)?;
+        writeln!(w, "rejected: {}", self.rejected)?;
+        for (reason, count) in &self.rejected_by {
+            writeln!(w, "  {}: {}", reason, count)?;
+        }
+        writeln!(w, "pending: {}", self.pending)?;
+        writeln!(w, "malformed rows skipped: {}", self.skipped)?;
+        writeln!(w, "duplicate ids: {}", self.duplicates.len())?;
+        for dup in &self.duplicates {
+            writeln!(
+                w,
+                "  line {}: client {}, tx {}",
+                dup.line, dup.client, dup.tx
+            )?;
+        }
+        writeln!(w, "clients: {}", self.clients)?;
+        writeln!(w, "locked: {}", self.locked)?;
+        if self.totals.is_empty() {
+            writeln!(w, "total available: {}", Amount::ZERO)?;
+            writeln!(w, "total held: {}", Amount::ZERO)?;
+        }
+        for (currency, (available, held)) in &self.totals {
+            let suffix = currency.map(|c| format!(" {}", c)).unwrap_or_default();
+            writeln!(w, "total available{}: {}", suffix, available)?;
+            writeln!(w, "total held{}: {}", suffix, held)?;
+        }
+        writeln!(w, "elapsed: {:.3}s", self.elapsed.as_secs_f64())?;
+        Ok(())
+    }
+}
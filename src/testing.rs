@@ -0,0 +1,587 @@
+// Streams of made-up transactions, for property-testing code that feeds or consumes the engine.
+// A stream is built from generators, each making one kind of row, mixed by weight:
+//
+//   let rows = Stream::new(42, Mix::new()
+//       .with(90, Deposits::new(1000))
+//       .with(8, Disputes)
+//       .with(2, Duplicates));
+//
+// The same seed always gives the same rows. Generators see what the stream has produced so far,
+// so disputes reference earlier deposits and withdrawals of the same client, and resolves and
+// chargebacks reference open disputes.
+
+use crate::amount::Amount;
+use crate::bank::{ClientId, Transaction, TransactionType, TxId};
+use std::collections::HashMap;
+
+// A small, fast pseudo-random generator (splitmix64); not for anything needing real randomness
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut x = self.0;
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+        x ^ (x >> 31)
+    }
+
+    // Uniform in 0..n, for n above 0
+    pub fn below(&mut self, n: u64) -> u64 {
+        // the widening multiply's top half, which has no bias worth speaking of at these sizes
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+
+    // true with probability p
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    // A client from 1 to clients
+    pub fn client(&mut self, clients: ClientId) -> ClientId {
+        (self.below(u64::from(clients).max(1)) + 1) as ClientId
+    }
+
+    // An amount above zero and no more than max, to four places
+    pub fn amount(&mut self, max: Amount) -> Amount {
+        let units = max.units().max(1);
+        Amount::from_units(self.below(units as u64) as i64 + 1)
+    }
+}
+
+// What a stream has produced so far, as far as generators need to know
+#[derive(Debug, Default)]
+pub struct History {
+    rows: u64,
+    next_tx: TxId,
+    // every deposit and withdrawal
+    funds: Vec<(ClientId, TxId)>,
+    // disputes not yet resolved or charged back
+    disputed: Vec<(ClientId, TxId)>,
+    // where each of them is in disputed
+    open: HashMap<(ClientId, TxId), usize>,
+}
+
+impl History {
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    // A tx id no row has used yet
+    pub fn fresh_tx(&self) -> TxId {
+        self.next_tx
+    }
+
+    pub fn funds(&self) -> &[(ClientId, TxId)] {
+        &self.funds
+    }
+
+    pub fn disputed(&self) -> &[(ClientId, TxId)] {
+        &self.disputed
+    }
+
+    fn record(&mut self, txn: &Transaction) {
+        self.rows += 1;
+        if txn.tx >= self.next_tx {
+            self.next_tx = txn.tx.wrapping_add(1);
+        }
+        let key = (txn.client, txn.tx);
+        match txn.tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => self.funds.push(key),
+            TransactionType::Dispute if !self.open.contains_key(&key) => {
+                self.open.insert(key, self.disputed.len());
+                self.disputed.push(key);
+            }
+            TransactionType::Resolve | TransactionType::Chargeback => {
+                if let Some(at) = self.open.remove(&key) {
+                    self.disputed.swap_remove(at);
+                    if let Some(moved) = self.disputed.get(at) {
+                        self.open.insert(*moved, at);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Makes one kind of row. None means it has nothing to make yet, e.g. a dispute before any
+// deposit; a Mix then asks another of its generators.
+pub trait Generator {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction>;
+}
+
+impl<G: Generator + ?Sized> Generator for Box<G> {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction> {
+        (**self).generate(rng, history)
+    }
+}
+
+fn row(tx_type: TransactionType, client: ClientId, tx: TxId, amount: Amount) -> Transaction {
+    Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+        to: None,
+        timestamp: None,
+        currency: None,
+        to_currency: None,
+        effective_date: None,
+    }
+}
+
+// Deposits with fresh tx ids, for clients 1 to clients
+#[derive(Clone, Debug)]
+pub struct Deposits {
+    pub clients: ClientId,
+    pub max_amount: Amount,
+}
+
+impl Deposits {
+    pub fn new(clients: ClientId) -> Deposits {
+        Deposits {
+            clients,
+            max_amount: Amount::from_units(1000 * 10_000),
+        }
+    }
+}
+
+impl Generator for Deposits {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction> {
+        let client = rng.client(self.clients);
+        let amount = rng.amount(self.max_amount);
+        Some(row(
+            TransactionType::Deposit,
+            client,
+            history.fresh_tx(),
+            amount,
+        ))
+    }
+}
+
+// Withdrawals with fresh tx ids, some of which will be more than the client has
+#[derive(Clone, Debug)]
+pub struct Withdrawals {
+    pub clients: ClientId,
+    pub max_amount: Amount,
+}
+
+impl Withdrawals {
+    pub fn new(clients: ClientId) -> Withdrawals {
+        Withdrawals {
+            clients,
+            max_amount: Amount::from_units(500 * 10_000),
+        }
+    }
+}
+
+impl Generator for Withdrawals {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction> {
+        let client = rng.client(self.clients);
+        let amount = rng.amount(self.max_amount);
+        Some(row(
+            TransactionType::Withdrawal,
+            client,
+            history.fresh_tx(),
+            amount,
+        ))
+    }
+}
+
+// Disputes of earlier deposits and withdrawals, by the client that made them; now and then one
+// that's already under dispute
+#[derive(Clone, Copy, Debug)]
+pub struct Disputes;
+
+impl Generator for Disputes {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction> {
+        let funds = history.funds();
+        if funds.is_empty() {
+            return None;
+        }
+        let (client, tx) = funds[rng.below(funds.len() as u64) as usize];
+        Some(row(TransactionType::Dispute, client, tx, Amount::ZERO))
+    }
+}
+
+// Resolves or chargebacks of open disputes, a chargeback with the given probability
+#[derive(Clone, Copy, Debug)]
+pub struct Settlements {
+    pub chargeback_rate: f64,
+}
+
+impl Generator for Settlements {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction> {
+        let disputed = history.disputed();
+        if disputed.is_empty() {
+            return None;
+        }
+        let (client, tx) = disputed[rng.below(disputed.len() as u64) as usize];
+        let tx_type = if rng.chance(self.chargeback_rate) {
+            TransactionType::Chargeback
+        } else {
+            TransactionType::Resolve
+        };
+        Some(row(tx_type, client, tx, Amount::ZERO))
+    }
+}
+
+// Adversarial deposits reusing an earlier deposit's or withdrawal's tx id, half the time for the
+// same client and half for another, which the engine must refuse
+#[derive(Clone, Copy, Debug)]
+pub struct Duplicates;
+
+impl Generator for Duplicates {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction> {
+        let funds = history.funds();
+        if funds.is_empty() {
+            return None;
+        }
+        let (client, tx) = funds[rng.below(funds.len() as u64) as usize];
+        let client = if rng.chance(0.5) {
+            client
+        } else {
+            client.wrapping_add(1)
+        };
+        Some(row(
+            TransactionType::Deposit,
+            client,
+            tx,
+            Amount::from_units(10_000),
+        ))
+    }
+}
+
+// Generators picked between by weight. One with nothing to make passes to the next in the
+// order they were added, so early in a stream a mix of deposits and disputes is all deposits.
+#[derive(Default)]
+pub struct Mix {
+    generators: Vec<(u32, Box<dyn Generator>)>,
+    total: u64,
+}
+
+impl Mix {
+    pub fn new() -> Mix {
+        Mix::default()
+    }
+
+    pub fn with<G: Generator + 'static>(mut self, weight: u32, generator: G) -> Mix {
+        self.total += u64::from(weight);
+        self.generators.push((weight, Box::new(generator)));
+        self
+    }
+}
+
+impl Generator for Mix {
+    fn generate(&mut self, rng: &mut Rng, history: &History) -> Option<Transaction> {
+        if self.total == 0 {
+            return None;
+        }
+        let mut pick = rng.below(self.total);
+        let first = self
+            .generators
+            .iter()
+            .position(|(weight, _)| match pick.checked_sub(u64::from(*weight)) {
+                Some(rest) => {
+                    pick = rest;
+                    false
+                }
+                None => true,
+            })
+            .unwrap_or(0);
+        let n = self.generators.len();
+        (0..n).find_map(|i| self.generators[(first + i) % n].1.generate(rng, history))
+    }
+}
+
+// An endless, reproducible stream of a generator's rows, ending only if it has nothing to make
+pub struct Stream<G> {
+    rng: Rng,
+    history: History,
+    generator: G,
+}
+
+impl<G: Generator> Stream<G> {
+    pub fn new(seed: u64, generator: G) -> Stream<G> {
+        Stream {
+            rng: Rng::new(seed),
+            history: History::default(),
+            generator,
+        }
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+}
+
+impl<G: Generator> Iterator for Stream<G> {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Transaction> {
+        let txn = self.generator.generate(&mut self.rng, &self.history)?;
+        self.history.record(&txn);
+        Some(txn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::outcome::{TxnError, TxnOutcome};
+    use crate::policy::{DisputePolicy, Policy, RedisputePolicy};
+    use crate::source::csv_row;
+    use std::collections::HashSet;
+
+    const CLIENTS: ClientId = 20;
+
+    fn mix() -> Mix {
+        Mix::new()
+            .with(50, Deposits::new(CLIENTS))
+            .with(15, Withdrawals::new(CLIENTS))
+            .with(15, Disputes)
+            .with(
+                12,
+                Settlements {
+                    chargeback_rate: 0.2,
+                },
+            )
+            .with(8, Duplicates)
+    }
+
+    fn rows(seed: u64, n: usize) -> Vec<String> {
+        Stream::new(seed, mix())
+            .take(n)
+            .map(|txn| csv_row(&txn))
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_rows() {
+        assert_eq!(rows(7, 2000), rows(7, 2000));
+        assert_ne!(rows(7, 2000), rows(8, 2000));
+    }
+
+    #[test]
+    fn rows_reference_what_came_before() {
+        let mut stream = Stream::new(3, mix());
+        let mut kinds = HashSet::new();
+        for _ in 0..5000 {
+            let history = stream.history();
+            let (fresh, funds, disputed) = (
+                history.fresh_tx(),
+                history.funds().to_vec(),
+                history.disputed().to_vec(),
+            );
+            let txn = stream.next().unwrap();
+            let key = (txn.client, txn.tx);
+            match txn.tx_type {
+                TransactionType::Deposit | TransactionType::Withdrawal if txn.tx == fresh => {
+                    assert!((1..=CLIENTS).contains(&txn.client), "{:?}", txn);
+                    assert!(txn.amount > Amount::ZERO, "{:?}", txn);
+                    assert!(txn.amount <= Amount::from_units(1000 * 10_000), "{:?}", txn);
+                    kinds.insert("fresh");
+                }
+                // a duplicate
+                TransactionType::Deposit => {
+                    assert!(funds.iter().any(|(_, tx)| *tx == txn.tx), "{:?}", txn);
+                    kinds.insert("duplicate");
+                }
+                TransactionType::Dispute => {
+                    assert!(funds.contains(&key), "{:?}", txn);
+                    if disputed.contains(&key) {
+                        kinds.insert("dispute of a disputed one");
+                    }
+                }
+                TransactionType::Resolve | TransactionType::Chargeback => {
+                    assert!(disputed.contains(&key), "{:?}", txn);
+                    kinds.insert(txn.tx_type.name());
+                }
+                _ => panic!("{:?}", txn),
+            }
+        }
+        assert_eq!(kinds.len(), 5, "{:?}", kinds);
+        assert_eq!(stream.history().rows(), 5000);
+    }
+
+    #[test]
+    fn mixes_pass_over_generators_with_nothing_to_make() {
+        let mut stream = Stream::new(1, Mix::new().with(99, Disputes).with(1, Deposits::new(5)));
+        assert_eq!(stream.next().unwrap().tx_type, TransactionType::Deposit);
+        assert_eq!(stream.next().unwrap().tx_type, TransactionType::Dispute);
+        assert!(Stream::new(1, Disputes).next().is_none());
+        assert!(Stream::new(
+            1,
+            Settlements {
+                chargeback_rate: 1.0
+            }
+        )
+        .next()
+        .is_none());
+        assert!(Stream::new(1, Mix::new()).next().is_none());
+    }
+
+    #[test]
+    fn mixes_pick_by_weight() {
+        let mix = Mix::new()
+            .with(3, Deposits::new(5))
+            .with(1, Withdrawals::new(5));
+        let deposits = Stream::new(5, mix)
+            .take(10_000)
+            .filter(|txn| txn.tx_type == TransactionType::Deposit)
+            .count();
+        assert!((7300..7700).contains(&deposits), "{}", deposits);
+    }
+
+    #[test]
+    fn rng_stays_in_range() {
+        let mut rng = Rng::new(11);
+        for n in [1, 2, 7, 1 << 40] {
+            assert!((0..1000).all(|_| rng.below(n) < n));
+        }
+        assert!((0..1000).all(|_| rng.client(0) == 1));
+        assert!((0..1000).all(|_| (1..=3).contains(&rng.client(3))));
+        assert_eq!(rng.amount(Amount::ZERO), Amount::from_units(1));
+        let max = Amount::from_units(3);
+        assert!((0..1000).all(|_| (Amount::from_units(1)..=max).contains(&rng.amount(max))));
+        assert!((0..1000).all(|_| !rng.chance(0.0) && rng.chance(1.0)));
+    }
+
+    #[test]
+    fn the_engine_refuses_every_duplicate() {
+        let mut bank = Bank::new();
+        let mut stream = Stream::new(
+            9,
+            Mix::new()
+                .with(2, Deposits::new(CLIENTS))
+                .with(1, Duplicates),
+        );
+        for _ in 0..3000 {
+            let fresh = stream.history().fresh_tx();
+            let txn = stream.next().unwrap();
+            match bank.insert_txn(txn) {
+                Ok(_) => assert_eq!(txn.tx, fresh),
+                Err(err) => assert!(
+                    matches!(
+                        err,
+                        TxnError::DuplicateTx | TxnError::DuplicateTxOtherClient
+                    ),
+                    "{:?}",
+                    err
+                ),
+            }
+        }
+    }
+
+    // Applies a row of tx_type, its amount given as in the input
+    fn apply(
+        bank: &mut Bank,
+        tx_type: TransactionType,
+        client: ClientId,
+        tx: TxId,
+        amount: &str,
+    ) -> Result<TxnOutcome, TxnError> {
+        let amount = if amount.is_empty() {
+            Amount::ZERO
+        } else {
+            amount.parse().unwrap()
+        };
+        bank.insert_txn(row(tx_type, client, tx, amount))
+    }
+
+    // A row and what applying it should give
+    type Step = (
+        TransactionType,
+        ClientId,
+        TxId,
+        &'static str,
+        Result<TxnOutcome, TxnError>,
+    );
+
+    #[test]
+    fn disputes_move_through_their_states() {
+        use TransactionType::*;
+        use TxnError::*;
+        use TxnOutcome::*;
+
+        let mut bank = Bank::with_policy(Policy {
+            dispute: DisputePolicy::DepositsAndWithdrawals,
+            ..Default::default()
+        });
+        let mut steps = |steps: &[Step]| {
+            for (i, (tx_type, client, tx, amount, expected)) in steps.iter().enumerate() {
+                let got = apply(&mut bank, *tx_type, *client, *tx, amount);
+                assert_eq!(got, *expected, "step {}: {:?} of {}", i, tx_type, tx);
+            }
+        };
+
+        // undisputed, disputed, resolved, disputed again, charged back
+        steps(&[
+            (Deposit, 1, 1, "10", Ok(Deposited)),
+            (Resolve, 1, 1, "", Err(NotDisputed)),
+            (Chargeback, 1, 1, "", Err(NotDisputed)),
+            (Dispute, 1, 9, "", Err(TxNotFound)),
+            (Resolve, 1, 9, "", Err(NotDisputed)),
+            (Dispute, 1, 1, "", Ok(Disputed)),
+            (Dispute, 1, 1, "", Err(AlreadyDisputed)),
+            (Resolve, 1, 1, "", Ok(Resolved)),
+            (Resolve, 1, 1, "", Err(AlreadyResolved)),
+            (Chargeback, 1, 1, "", Err(AlreadyResolved)),
+            (Dispute, 1, 1, "", Ok(Disputed)),
+            (Chargeback, 1, 1, "", Ok(ChargedBack)),
+            (Dispute, 1, 1, "", Err(AccountLocked)),
+            (Unlock, 1, 10, "", Ok(Unlocked)),
+            (Dispute, 1, 1, "", Err(AlreadyChargedBack)),
+            (Resolve, 1, 1, "", Err(AlreadyChargedBack)),
+        ]);
+        // a withdrawal refunded only outside a dispute, and never disputed after
+        steps(&[
+            (Deposit, 2, 2, "10", Ok(Deposited)),
+            (Withdrawal, 2, 3, "4", Ok(Withdrawn)),
+            (Refund, 2, 2, "", Err(NotRefundable)),
+            (Dispute, 2, 3, "", Ok(Disputed)),
+            (Refund, 2, 3, "", Err(AlreadyDisputed)),
+            (Resolve, 2, 3, "", Ok(Resolved)),
+            (Refund, 2, 3, "", Ok(Refunded)),
+            (Refund, 2, 3, "", Err(AlreadyRefunded)),
+            (Dispute, 2, 3, "", Err(AlreadyRefunded)),
+        ]);
+        // a hold released once, and never disputed
+        steps(&[
+            (Hold, 2, 4, "1", Ok(HoldPlaced)),
+            (Dispute, 2, 4, "", Err(NotDisputable)),
+            (Release, 2, 2, "", Err(NotReleasable)),
+            (Release, 2, 4, "", Ok(Released)),
+            (Release, 2, 4, "", Err(AlreadyReleased)),
+        ]);
+        // partial disputes add up until all of it is disputed
+        steps(&[
+            (Deposit, 3, 5, "10", Ok(Deposited)),
+            (Dispute, 3, 5, "4", Ok(Disputed)),
+            (Dispute, 3, 5, "7", Err(InvalidDisputeAmount)),
+            (Dispute, 3, 5, "", Ok(Disputed)),
+            (Dispute, 3, 5, "", Err(AlreadyDisputed)),
+        ]);
+        assert_eq!(bank.record(3).unwrap().held.to_string(), "10.0000");
+
+        let mut bank = Bank::with_policy(Policy {
+            redispute: RedisputePolicy::Forbid,
+            ..Default::default()
+        });
+        for (tx_type, expected) in [
+            (Deposit, Ok(Deposited)),
+            (Dispute, Ok(Disputed)),
+            (Resolve, Ok(Resolved)),
+            (Dispute, Err(RedisputeNotAllowed)),
+        ] {
+            assert_eq!(apply(&mut bank, tx_type, 1, 1, "10"), expected);
+        }
+    }
+}
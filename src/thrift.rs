@@ -0,0 +1,377 @@
+// Thrift's compact protocol, the encoding of Parquet's page headers and footer: only as much of
+// it as reading and writing those takes
+
+use std::{collections::HashMap, io};
+
+// field types
+const T_TRUE: u8 = 1;
+const T_FALSE: u8 = 2;
+const T_BYTE: u8 = 3;
+const T_I16: u8 = 4;
+pub(crate) const T_I32: u8 = 5;
+pub(crate) const T_I64: u8 = 6;
+const T_DOUBLE: u8 = 7;
+pub(crate) const T_BINARY: u8 = 8;
+pub(crate) const T_LIST: u8 = 9;
+const T_SET: u8 = 10;
+const T_MAP: u8 = 11;
+pub(crate) const T_STRUCT: u8 = 12;
+
+// deeper than anything Parquet nests, so a corrupt footer can't recurse without end
+const MAX_DEPTH: usize = 32;
+
+#[derive(Default)]
+pub(crate) struct Compact {
+    pub(crate) buf: Vec<u8>,
+    // the last field id written in each open struct, which the next one is encoded relative to
+    last: Vec<i16>,
+}
+
+impl Compact {
+    pub(crate) fn begin(&mut self) {
+        self.last.push(0);
+    }
+
+    pub(crate) fn end(&mut self) {
+        self.buf.push(0);
+        self.last.pop();
+    }
+
+    fn field(&mut self, id: i16, ty: u8) {
+        let last = self.last.last_mut().expect("field outside a struct");
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | ty);
+        } else {
+            self.buf.push(ty);
+            varint(&mut self.buf, zigzag(id.into()));
+        }
+        *last = id;
+    }
+
+    pub(crate) fn i32(&mut self, id: i16, v: i32) {
+        self.field(id, T_I32);
+        self.element_i32(v);
+    }
+
+    pub(crate) fn i64(&mut self, id: i16, v: i64) {
+        self.field(id, T_I64);
+        varint(&mut self.buf, zigzag(v));
+    }
+
+    pub(crate) fn binary(&mut self, id: i16, bytes: &[u8]) {
+        self.field(id, T_BINARY);
+        self.element_binary(bytes);
+    }
+
+    pub(crate) fn struct_field(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.begin();
+    }
+
+    // Followed by the n elements, each struct between begin and end
+    pub(crate) fn list(&mut self, id: i16, ty: u8, n: usize) {
+        self.field(id, T_LIST);
+        if n < 15 {
+            self.buf.push((n as u8) << 4 | ty);
+        } else {
+            self.buf.push(0xf0 | ty);
+            varint(&mut self.buf, n as u64);
+        }
+    }
+
+    pub(crate) fn element_i32(&mut self, v: i32) {
+        varint(&mut self.buf, zigzag(v.into()));
+    }
+
+    pub(crate) fn element_binary(&mut self, bytes: &[u8]) {
+        varint(&mut self.buf, bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+pub(crate) fn varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+// A decoded struct's fields by id. Parquet's schema is looked up by field id, and any field it
+// doesn't know about is decoded and ignored the same as the rest.
+#[derive(Debug, Default)]
+pub(crate) struct Struct(HashMap<i16, Value>);
+
+#[derive(Debug)]
+pub(crate) enum Value {
+    Bool(bool),
+    Int(i64),
+    Binary(Vec<u8>),
+    List(Vec<Value>),
+    Struct(Struct),
+    // a double or a map, which Parquet only has in metadata this doesn't read
+    Other,
+}
+
+impl Struct {
+    pub(crate) fn i64(&self, id: i16) -> Option<i64> {
+        match self.0.get(&id) {
+            Some(Value::Int(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    // None if it's missing or out of range
+    pub(crate) fn i32(&self, id: i16) -> Option<i32> {
+        self.i64(id).and_then(|v| i32::try_from(v).ok())
+    }
+
+    pub(crate) fn bool(&self, id: i16) -> Option<bool> {
+        match self.0.get(&id) {
+            Some(Value::Bool(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn binary(&self, id: i16) -> Option<&[u8]> {
+        match self.0.get(&id) {
+            Some(Value::Binary(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn structure(&self, id: i16) -> Option<&Struct> {
+        match self.0.get(&id) {
+            Some(Value::Struct(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    // empty if the field is missing
+    pub(crate) fn list(&self, id: i16) -> &[Value] {
+        match self.0.get(&id) {
+            Some(Value::List(v)) => v,
+            _ => &[],
+        }
+    }
+
+    // The id of the field a union has set, and its value
+    pub(crate) fn variant(&self) -> Option<(i16, &Value)> {
+        self.0.iter().next().map(|(id, value)| (*id, value))
+    }
+}
+
+impl Value {
+    pub(crate) fn as_struct(&self) -> Option<&Struct> {
+        match self {
+            Value::Struct(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+// Decodes from a buffer, keeping track of where it's got to so the bytes after a page header can
+// be found
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+
+    // how many bytes have been decoded
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn read_struct(&mut self) -> io::Result<Struct> {
+        self.structure(0)
+    }
+
+    fn structure(&mut self, depth: usize) -> io::Result<Struct> {
+        if depth > MAX_DEPTH {
+            return Err(corrupt("structs nested too deeply"));
+        }
+        let mut fields = HashMap::new();
+        let mut last = 0i16;
+        loop {
+            let header = self.byte()?;
+            if header == 0 {
+                return Ok(Struct(fields));
+            }
+            let ty = header & 0x0f;
+            let delta = (header >> 4) as i16;
+            let id = if delta == 0 {
+                i16::try_from(self.zigzag()?).map_err(|_| corrupt("field id out of range"))?
+            } else {
+                last.wrapping_add(delta)
+            };
+            last = id;
+            let value = match ty {
+                T_TRUE => Value::Bool(true),
+                T_FALSE => Value::Bool(false),
+                _ => self.value(ty, depth)?,
+            };
+            fields.insert(id, value);
+        }
+    }
+
+    fn value(&mut self, ty: u8, depth: usize) -> io::Result<Value> {
+        Ok(match ty {
+            // a bool outside a field header, i.e. a list element, is a byte of its own
+            T_TRUE | T_FALSE => Value::Bool(self.byte()? == T_TRUE),
+            T_BYTE => Value::Int(self.byte()? as i8 as i64),
+            T_I16 | T_I32 | T_I64 => Value::Int(self.zigzag()?),
+            T_DOUBLE => {
+                self.take(8)?;
+                Value::Other
+            }
+            T_BINARY => {
+                let len = self.len()?;
+                Value::Binary(self.take(len)?.to_vec())
+            }
+            T_LIST | T_SET => {
+                let header = self.byte()?;
+                let n = match header >> 4 {
+                    15 => self.len()?,
+                    n => n as usize,
+                };
+                let elements = (0..n)
+                    .map(|_| self.value(header & 0x0f, depth + 1))
+                    .collect::<io::Result<_>>()?;
+                Value::List(elements)
+            }
+            T_MAP => {
+                let n = self.len()?;
+                if n > 0 {
+                    let types = self.byte()?;
+                    for _ in 0..n {
+                        self.value(types >> 4, depth + 1)?;
+                        self.value(types & 0x0f, depth + 1)?;
+                    }
+                }
+                Value::Other
+            }
+            T_STRUCT => Value::Struct(self.structure(depth + 1)?),
+            _ => return Err(corrupt(&format!("unknown field type {}", ty))),
+        })
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or_else(|| corrupt("truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            v |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(v);
+            }
+        }
+        Err(corrupt("varint too long"))
+    }
+
+    fn zigzag(&mut self) -> io::Result<i64> {
+        let v = self.varint()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    // A length or element count, which can't be more than the bytes left to hold them
+    fn len(&mut self) -> io::Result<usize> {
+        let n = self.varint()?;
+        if n > (self.bytes.len() - self.pos) as u64 {
+            return Err(corrupt("length past the end"));
+        }
+        Ok(n as usize)
+    }
+}
+
+fn corrupt(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt Parquet metadata: {}", what),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let mut t = Compact::default();
+        t.begin();
+        t.i32(1, -7);
+        // a jump of more than 15 ids, which takes the id in full
+        t.i64(40, i64::MIN);
+        t.list(41, T_STRUCT, 20);
+        for i in 0..20 {
+            t.begin();
+            t.binary(1, format!("element {}", i).as_bytes());
+            t.end();
+        }
+        t.end();
+        let decoded = Reader::new(&t.buf).read_struct().unwrap();
+        assert_eq!(decoded.i32(1), Some(-7));
+        assert_eq!(decoded.i64(40), Some(i64::MIN));
+        assert_eq!(decoded.i32(40), None);
+        let list = decoded.list(41);
+        assert_eq!(list.len(), 20);
+        assert_eq!(
+            list[19].as_struct().and_then(|s| s.binary(1)),
+            Some(&b"element 19"[..])
+        );
+    }
+
+    #[test]
+    fn skips_fields_it_has_no_use_for() {
+        // a true, a double, a map of one i32 to a string, and a union with its second field set
+        let bytes = [
+            0x11, 0x17, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f, 0x1b, 1, 0x58, 2, 1, b'a', 0x1c, 0x2c, 0, 0,
+            0,
+        ];
+        let decoded = Reader::new(&bytes).read_struct().unwrap();
+        assert_eq!(decoded.bool(1), Some(true));
+        assert!(matches!(decoded.0.get(&2), Some(Value::Other)));
+        assert!(matches!(decoded.0.get(&3), Some(Value::Other)));
+        let union = decoded.structure(4).unwrap();
+        assert_eq!(union.variant().map(|(id, _)| id), Some(2));
+    }
+
+    #[test]
+    fn refuses_corrupt_structs() {
+        let mut nested = vec![0x1c; MAX_DEPTH + 2];
+        nested.extend(vec![0; MAX_DEPTH + 3]);
+        let corrupt = [
+            // truncated, and a binary longer than what's left
+            &[0x15][..],
+            &[0x18, 10, b'a'],
+            // a list claiming more elements than there are bytes
+            &[0x19, 0xf5, 0xff, 0xff, 0x03],
+            &[0x1d],
+            &nested,
+        ];
+        for input in corrupt {
+            assert!(Reader::new(input).read_struct().is_err(), "{:?}", input);
+        }
+    }
+}
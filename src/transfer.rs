@@ -0,0 +1,262 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, Client, ClientId, DisputeState, Transaction, TransactionType, TxnRecord};
+use crate::ledger::Account;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::policy::{AccountOpeningPolicy, Policy};
+
+// A transfer moves funds from its client to the `to` client. It has its own tx id, recorded in the
+// sender's history like a withdrawal. Unlike a withdrawal it never overdraws the sender, whatever
+// the WithdrawalPolicy, and both accounts must be unlocked, as they must for a dispute of it
+// unless the LockedPolicy says otherwise. The recipient must already have an
+// account unless the AccountOpeningPolicy is Always.
+//
+// Only the sender can dispute a transfer, and every DisputePolicy allows it. The disputed funds
+// are held in the recipient's account, as they would be for a deposit: resolving releases them
+// to the recipient, and a chargeback hands them back to the sender and locks the recipient.
+impl Bank {
+    // Whether txn involves a second client: a transfer, or a dispute, resolve or chargeback of one
+    pub(crate) fn is_cross_client(&self, txn: &Transaction) -> Result<bool, TxnError> {
+        is_cross_client(self.bank.get(&txn.client), txn)
+    }
+
+    // Apply a transaction for which is_cross_client holds to both accounts involved
+    pub(crate) fn process_transfer(&mut self, txn: Transaction) -> Result<TxnOutcome, TxnError> {
+        let sender = self.bank.get(&txn.client).ok_or(TxnError::UnknownClient)?;
+        let recipient = recipient(sender, &txn)?;
+        if self.policy.opening == AccountOpeningPolicy::Always {
+            self.add_client(recipient);
+        }
+        match self.bank.get_disjoint_mut([&txn.client, &recipient]) {
+            [Some(sender), Some(recipient)] => transfer(sender, recipient, txn, &self.policy),
+            _ => Err(TxnError::InvalidRecipient),
+        }
+    }
+}
+
+// Bank::is_cross_client, given txn's client
+pub(crate) fn is_cross_client(
+    client: Option<&Client>,
+    txn: &Transaction,
+) -> Result<bool, TxnError> {
+    Ok(match (txn.tx_type, client) {
+        (TransactionType::Transfer | TransactionType::Merge, _) => true,
+        (
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback,
+            Some(client),
+        ) => client
+            .txns
+            .get(&txn.tx)?
+            .is_some_and(|record| record.kind == TransactionType::Transfer),
+        _ => false,
+    })
+}
+
+// The other client a cross-client txn from sender involves
+pub(crate) fn recipient(sender: &Client, txn: &Transaction) -> Result<ClientId, TxnError> {
+    let to = match txn.tx_type {
+        TransactionType::Transfer | TransactionType::Merge => txn.to,
+        _ => sender.txns.get(&txn.tx)?.and_then(|record| record.to),
+    };
+    to.filter(|to| *to != txn.client)
+        .ok_or(TxnError::InvalidRecipient)
+}
+
+// Apply a transfer, or a dispute, resolve or chargeback of one, between its two accounts
+pub(crate) fn transfer(
+    sender: &mut Client,
+    recipient: &mut Client,
+    txn: Transaction,
+    policy: &Policy,
+) -> Result<TxnOutcome, TxnError> {
+    if txn.amount.is_too_large() {
+        return Err(TxnError::AmountTooLarge);
+    }
+    policy.negative.check(&txn)?;
+    if (sender.locked || recipient.locked) && !policy.locked.allows(txn.tx_type) {
+        return Err(TxnError::AccountLocked);
+    }
+    if txn.tx_type == TransactionType::Transfer && (sender.closed || recipient.closed) {
+        return Err(TxnError::AccountClosed);
+    }
+    // both sides in the transfer's currency
+    let currency = sender.currency_of(&txn)?;
+    let outcome = sender.in_currency(currency, |sender| {
+        recipient.in_currency(currency, |recipient| apply(sender, recipient, txn, policy))
+    });
+    debug_assert!(
+        sender.balanced() && recipient.balanced(),
+        "transfer {} is off balance",
+        txn.tx
+    );
+    outcome
+}
+
+fn apply(
+    sender: &mut Client,
+    recipient: &mut Client,
+    txn: Transaction,
+    policy: &Policy,
+) -> Result<TxnOutcome, TxnError> {
+    match txn.tx_type {
+        TransactionType::Transfer => {
+            sender.check_new(&txn)?;
+            if txn.amount > sender.available {
+                return Err(TxnError::InsufficientFunds);
+            }
+            post_between(sender, recipient, txn.amount)?;
+            sender.txns.insert(txn.tx, TxnRecord::new(&txn));
+            Ok(TxnOutcome::Transferred)
+        }
+        TransactionType::Dispute => {
+            let mut record = sender.txns.get(&txn.tx)?.ok_or(TxnError::TxNotFound)?;
+            let portion = record.open_dispute(txn.amount, policy.redispute)?;
+            record.check_window(&txn, policy)?;
+            recipient.post(Account::Available, Account::Held, portion)?;
+            sender.txns.insert(txn.tx, record);
+            Ok(TxnOutcome::Disputed)
+        }
+        TransactionType::Resolve => {
+            let (record, portion) = sender.settle(txn.tx, DisputeState::Resolved)?;
+            recipient.post(Account::Held, Account::Available, portion)?;
+            sender.txns.insert(txn.tx, record);
+            Ok(TxnOutcome::Resolved)
+        }
+        TransactionType::Chargeback => {
+            let (record, portion) = sender.settle(txn.tx, DisputeState::ChargedBack)?;
+            recipient.post(Account::Held, Account::Transfers, portion)?;
+            if let Err(err) = sender.post(Account::Transfers, Account::Available, portion) {
+                recipient.unpost(Account::Held, Account::Transfers, portion);
+                return Err(err);
+            }
+            recipient.locked = true;
+            sender.txns.insert(txn.tx, record);
+            Ok(TxnOutcome::ChargedBack)
+        }
+        _ => unreachable!("{} only involves its own client", txn.tx_type),
+    }
+}
+
+// Moves amount out of from's available into to's, refusing it without moving anything if either
+// side would overflow
+fn post_between(from: &mut Client, to: &mut Client, amount: Amount) -> Result<(), TxnError> {
+    from.post(Account::Available, Account::Transfers, amount)?;
+    if let Err(err) = to.post(Account::Transfers, Account::Available, amount) {
+        from.unpost(Account::Available, Account::Transfers, amount);
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::TxId;
+    use crate::policy::NegativeAmountPolicy;
+
+    fn txn(tx_type: TransactionType, tx: TxId, amount: &str) -> Transaction {
+        Transaction {
+            tx_type,
+            client: 1,
+            tx,
+            amount: amount.parse().unwrap(),
+            to: (tx_type == TransactionType::Transfer).then_some(2),
+            timestamp: None,
+            currency: None,
+            to_currency: None,
+            effective_date: None,
+        }
+    }
+
+    fn balance(bank: &Bank, client: ClientId) -> (String, String) {
+        let record = bank.record(client).unwrap();
+        (record.available.to_string(), record.held.to_string())
+    }
+
+    // client 1 with 5 and client 2 with 1
+    fn bank(policy: Policy) -> Bank {
+        let mut bank = Bank::with_policy(policy);
+        bank.insert_txn(txn(TransactionType::Deposit, 1, "5"))
+            .unwrap();
+        let deposit = Transaction {
+            client: 2,
+            ..txn(TransactionType::Deposit, 2, "1")
+        };
+        bank.insert_txn(deposit).unwrap();
+        bank
+    }
+
+    #[test]
+    fn transfers_move_funds_between_the_accounts() {
+        let mut bank = bank(Policy::default());
+        let transfer = txn(TransactionType::Transfer, 3, "2");
+        assert_eq!(bank.insert_txn(transfer), Ok(TxnOutcome::Transferred));
+        assert_eq!(balance(&bank, 1), ("3.0000".into(), "0.0000".into()));
+        assert_eq!(balance(&bank, 2), ("3.0000".into(), "0.0000".into()));
+        assert_eq!(bank.insert_txn(transfer), Err(TxnError::DuplicateTx));
+    }
+
+    #[test]
+    fn transfers_that_cant_go_through_move_nothing() {
+        let mut bank = bank(Policy {
+            negative: NegativeAmountPolicy::Allow,
+            ..Policy::default()
+        });
+        let refused = [
+            (
+                txn(TransactionType::Transfer, 3, "6"),
+                TxnError::InsufficientFunds,
+            ),
+            (
+                txn(TransactionType::Transfer, 3, "-1"),
+                TxnError::NegativeAmount,
+            ),
+            (txn(TransactionType::Transfer, 3, "0"), TxnError::ZeroAmount),
+            (
+                Transaction {
+                    to: Some(3),
+                    ..txn(TransactionType::Transfer, 3, "1")
+                },
+                TxnError::InvalidRecipient,
+            ),
+            (
+                Transaction {
+                    to: Some(1),
+                    ..txn(TransactionType::Transfer, 3, "1")
+                },
+                TxnError::InvalidRecipient,
+            ),
+        ];
+        for (transfer, err) in refused {
+            assert_eq!(bank.insert_txn(transfer), Err(err), "{:?}", transfer);
+        }
+        assert_eq!(balance(&bank, 1), ("5.0000".into(), "0.0000".into()));
+        assert_eq!(balance(&bank, 2), ("1.0000".into(), "0.0000".into()));
+    }
+
+    #[test]
+    fn a_disputed_transfer_is_held_in_the_recipients_account() {
+        let mut bank = bank(Policy::default());
+        bank.insert_txn(txn(TransactionType::Transfer, 3, "2"))
+            .unwrap();
+        let dispute = txn(TransactionType::Dispute, 3, "0");
+        // only the sender can dispute it
+        let theirs = Transaction {
+            client: 2,
+            ..dispute
+        };
+        assert_eq!(bank.insert_txn(theirs), Err(TxnError::TxNotFound));
+        assert_eq!(bank.insert_txn(dispute), Ok(TxnOutcome::Disputed));
+        assert_eq!(balance(&bank, 2), ("1.0000".into(), "2.0000".into()));
+        assert_eq!(bank.insert_txn(dispute), Err(TxnError::AlreadyDisputed));
+        let resolve = txn(TransactionType::Resolve, 3, "0");
+        assert_eq!(bank.insert_txn(resolve), Ok(TxnOutcome::Resolved));
+        assert_eq!(balance(&bank, 2), ("3.0000".into(), "0.0000".into()));
+
+        assert_eq!(bank.insert_txn(dispute), Ok(TxnOutcome::Disputed));
+        let chargeback = txn(TransactionType::Chargeback, 3, "0");
+        assert_eq!(bank.insert_txn(chargeback), Ok(TxnOutcome::ChargedBack));
+        assert_eq!(balance(&bank, 1), ("5.0000".into(), "0.0000".into()));
+        assert_eq!(balance(&bank, 2), ("1.0000".into(), "0.0000".into()));
+        assert!(bank.record(2).unwrap().locked);
+    }
+}
@@ -0,0 +1,323 @@
+use crate::amount::Amount;
+use crate::bank::{Bank, ClientId, Transaction, TransactionType};
+use crate::outcome::TxnError;
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+};
+
+// Limits on how fast a client moves funds: no more than max_count transactions, or max_amount in
+// all, within a window of its last so many transactions or the last so many seconds. A rule that
+// flags lets the transaction through and raises an alert, in the audit log, the events and
+// TxnObserver::on_alert; one that blocks refuses it as velocity_exceeded. The window includes
+// the transaction being checked, and only accepted deposits, withdrawals, transfers, fees and
+// conversions count towards it. A row without a timestamp passes every time window.
+//
+// The windows are kept in memory only, so a run resumed from a snapshot starts them empty.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct VelocityRule {
+    pub window: Window,
+    pub max_count: Option<u32>,
+    pub max_amount: Option<Amount>,
+    pub action: VelocityAction,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Window {
+    // the client's last n transactions
+    Transactions(u32),
+    // the client's transactions timestamped up to this many seconds before
+    Seconds(i64),
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum VelocityAction {
+    Flag,
+    Block,
+}
+
+// The transactions each client's windows still reach back to, oldest first
+#[derive(Debug, Default)]
+pub(crate) struct Velocity {
+    rules: Vec<VelocityRule>,
+    recent: HashMap<ClientId, VecDeque<(Option<i64>, Amount)>>,
+    // whether a flagging rule tripped on the transaction insert_txn is applying
+    pub(crate) alerted: bool,
+}
+
+impl Velocity {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+// what counts towards a window; an accrual's amount is a number of periods, not funds, and a
+// hold's stays in the account
+fn counted(txn: &Transaction) -> bool {
+    txn.tx_type.moves_funds()
+        && !matches!(txn.tx_type, TransactionType::Accrue | TransactionType::Hold)
+}
+
+impl VelocityRule {
+    // Whether txn would take its client over the rule, after the transactions in recent
+    fn exceeded(&self, recent: &VecDeque<(Option<i64>, Amount)>, txn: &Transaction) -> bool {
+        let (count, amount) = match self.window {
+            Window::Transactions(n) => recent
+                .iter()
+                .rev()
+                .take((n as usize).saturating_sub(1))
+                .fold((1, txn.amount), |(count, sum), (_, amount)| {
+                    (count + 1, sum.saturating_add(*amount))
+                }),
+            Window::Seconds(seconds) => {
+                let Some(now) = txn.timestamp else {
+                    return false;
+                };
+                recent
+                    .iter()
+                    .filter(|(at, _)| {
+                        at.is_some_and(|at| at > now.saturating_sub(seconds) && at <= now)
+                    })
+                    .fold((1, txn.amount), |(count, sum), (_, amount)| {
+                        (count + 1, sum.saturating_add(*amount))
+                    })
+            }
+        };
+        self.max_count.is_some_and(|max| count > max)
+            || self.max_amount.is_some_and(|max| amount > max)
+    }
+}
+
+impl Bank {
+    // The bank, also holding every client to rule from now on
+    pub fn with_velocity_rule(mut self, rule: VelocityRule) -> Bank {
+        self.velocity.rules.push(rule);
+        self
+    }
+
+    // Refuses txn if a blocking rule would be exceeded, and notes whether a flagging one would
+    pub(crate) fn check_velocity(&mut self, txn: &Transaction) -> Result<(), TxnError> {
+        if self.velocity.is_empty() || !counted(txn) {
+            return Ok(());
+        }
+        let empty = VecDeque::new();
+        let recent = self.velocity.recent.get(&txn.client).unwrap_or(&empty);
+        let mut flagged = false;
+        for rule in &self.velocity.rules {
+            if rule.exceeded(recent, txn) {
+                match rule.action {
+                    VelocityAction::Block => return Err(TxnError::VelocityExceeded),
+                    VelocityAction::Flag => flagged = true,
+                }
+            }
+        }
+        self.velocity.alerted = flagged;
+        Ok(())
+    }
+
+    // Adds an accepted txn to its client's windows, forgetting what no rule reaches back to
+    pub(crate) fn record_velocity(&mut self, txn: &Transaction) {
+        if self.velocity.is_empty() || !counted(txn) {
+            return;
+        }
+        let (mut keep, mut span) = (0, None::<i64>);
+        for rule in &self.velocity.rules {
+            match rule.window {
+                Window::Transactions(n) => keep = keep.max(n as usize),
+                Window::Seconds(seconds) => span = Some(span.map_or(seconds, |s| s.max(seconds))),
+            }
+        }
+        let recent = self.velocity.recent.entry(txn.client).or_default();
+        recent.push_back((txn.timestamp, txn.amount));
+        let latest = recent.iter().filter_map(|(at, _)| *at).max();
+        while recent.len() > keep {
+            let in_span = match (recent.front(), span, latest) {
+                (Some((Some(at), _)), Some(span), Some(latest)) => {
+                    *at > latest.saturating_sub(span)
+                }
+                _ => false,
+            };
+            if in_span {
+                break;
+            }
+            recent.pop_front();
+        }
+    }
+}
+
+// count=N and/or amount=X, then window=Ntx for transactions or a number of seconds with an s,
+// m, h or d suffix, and optionally action=flag (the default) or block, e.g.
+// "count=5,window=1h,action=block"
+impl FromStr for VelocityRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<VelocityRule, String> {
+        let (mut window, mut max_count, mut max_amount) = (None, None, None);
+        let mut action = VelocityAction::Flag;
+        for part in s.split(',') {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", part))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "count" => {
+                    max_count = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid count '{}'", value))?,
+                    )
+                }
+                "amount" => {
+                    max_amount = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid amount '{}'", value))?,
+                    )
+                }
+                "window" => window = Some(value.parse()?),
+                "action" => {
+                    action = match value {
+                        "flag" => VelocityAction::Flag,
+                        "block" => VelocityAction::Block,
+                        _ => return Err(format!("unknown action '{}'", value)),
+                    }
+                }
+                _ => return Err(format!("unknown velocity rule setting '{}'", key)),
+            }
+        }
+        let window = window.ok_or("a velocity rule needs a window")?;
+        if max_count.is_none() && max_amount.is_none() {
+            return Err("a velocity rule needs a count or an amount".to_string());
+        }
+        Ok(VelocityRule {
+            window,
+            max_count,
+            max_amount,
+            action,
+        })
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Window, String> {
+        let invalid = || format!("invalid window '{}'", s);
+        if let Some(n) = s.strip_suffix("tx") {
+            return match n.parse() {
+                Ok(n) if n > 0 => Ok(Window::Transactions(n)),
+                _ => Err(invalid()),
+            };
+        }
+        let (n, unit) = s.split_at(s.len() - s.chars().last().map_or(0, char::len_utf8));
+        let unit = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        match n.parse::<i64>() {
+            Ok(n) if n > 0 => n.checked_mul(unit).map(Window::Seconds).ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::TxId;
+    use crate::outcome::TxnOutcome;
+
+    fn deposit(tx: TxId, amount: &str, timestamp: Option<i64>) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx,
+            amount: amount.parse().unwrap(),
+            to: None,
+            timestamp,
+            currency: None,
+            to_currency: None,
+            effective_date: None,
+        }
+    }
+
+    #[test]
+    fn a_blocking_rule_refuses_what_would_exceed_it() {
+        let rule = "count=2,window=3tx,action=block".parse().unwrap();
+        let mut bank = Bank::new().with_velocity_rule(rule);
+        assert_eq!(
+            bank.insert_txn(deposit(1, "1", None)),
+            Ok(TxnOutcome::Deposited)
+        );
+        assert_eq!(
+            bank.insert_txn(deposit(2, "1", None)),
+            Ok(TxnOutcome::Deposited)
+        );
+        assert_eq!(
+            bank.insert_txn(deposit(3, "1", None)),
+            Err(TxnError::VelocityExceeded)
+        );
+        // a dispute doesn't count towards the window
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            ..deposit(1, "0", None)
+        };
+        assert_eq!(bank.insert_txn(dispute), Ok(TxnOutcome::Disputed));
+        assert_eq!(
+            bank.get_client(1).unwrap().available(),
+            "1".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn a_time_window_only_reaches_back_so_far() {
+        let rule = "amount=10,window=1m,action=block".parse().unwrap();
+        let mut bank = Bank::new().with_velocity_rule(rule);
+        assert_eq!(
+            bank.insert_txn(deposit(1, "6", Some(0))),
+            Ok(TxnOutcome::Deposited)
+        );
+        let refused = bank.insert_txn(deposit(2, "6", Some(59)));
+        assert_eq!(refused, Err(TxnError::VelocityExceeded));
+        assert_eq!(
+            bank.insert_txn(deposit(3, "6", Some(60))),
+            Ok(TxnOutcome::Deposited)
+        );
+        // without a timestamp it passes every time window
+        assert_eq!(
+            bank.insert_txn(deposit(4, "6", None)),
+            Ok(TxnOutcome::Deposited)
+        );
+    }
+
+    #[test]
+    fn a_flagging_rule_lets_the_transaction_through() {
+        let rule = "count=1,window=2tx".parse().unwrap();
+        let mut bank = Bank::new().with_velocity_rule(rule);
+        bank.insert_txn(deposit(1, "1", None)).unwrap();
+        assert!(!bank.velocity.alerted);
+        assert_eq!(
+            bank.insert_txn(deposit(2, "1", None)),
+            Ok(TxnOutcome::Deposited)
+        );
+        assert!(bank.velocity.alerted);
+    }
+
+    #[test]
+    fn rules_need_a_window_and_a_limit() {
+        let rule: VelocityRule = "count=5,amount=2.5,window=1h,action=block".parse().unwrap();
+        assert_eq!(rule.window, Window::Seconds(3600));
+        assert_eq!(rule.max_amount, Some("2.5".parse().unwrap()));
+        for bad in [
+            "count=5",
+            "window=1h",
+            "count=5,window=0tx",
+            "count=5,window=1w",
+        ] {
+            assert!(bad.parse::<VelocityRule>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::bank::{Bank, Transaction};
+use crate::error::Error;
+use crate::outcome::{TxnError, TxnOutcome};
+use crate::source::{csv_row, InputFormat, LineParser};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::Path,
+    time::Instant,
+};
+
+// Append-only log of the transactions handed to a bank, one CSV row (type,client,tx,amount, then
+// the recipient, timestamp and currencies when there are any) per line. Each row is on disk before the transaction is applied, so after a crash replaying the
+// log rebuilds exactly the state that had been reached: the engine is deterministic, so rows it
+// refused the first time are refused again.
+//
+// The log holds everything since the bank's starting state (empty, or the snapshot it was loaded
+// from), so it has to be replayed onto that same state.
+#[derive(Debug)]
+pub struct Wal {
+    file: File,
+}
+
+impl Wal {
+    fn append(&mut self, txn: &Transaction) -> io::Result<()> {
+        let mut line = csv_row(txn);
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()
+    }
+}
+
+impl Bank {
+    // Replay the log at path (creating it if needed) and log every later insert_logged to it.
+    // A torn last line, left by a crash mid-append, was never applied and is cut off.
+    // Returns the number of transactions replayed.
+    pub fn open_wal<P: AsRef<Path>>(&mut self, path: P) -> Result<u64, Error> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let (replayed, end) = self.replay_wal(&file).map_err(|err| err.in_file(path))?;
+        if end < file.metadata()?.len() {
+            file.set_len(end)?;
+            file.seek(SeekFrom::End(0))?;
+        }
+        self.wal = Some(Wal { file });
+        Ok(replayed)
+    }
+
+    // The count of replayed rows, and the length of the log up to the last complete line
+    fn replay_wal(&mut self, file: &File) -> Result<(u64, u64), Error> {
+        let mut reader = BufReader::new(file);
+        let mut parser = LineParser::new(InputFormat::Csv);
+        let mut line = String::new();
+        let (mut replayed, mut end) = (0, 0);
+        for number in 1.. {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            end += read as u64;
+            if let Some(txn) = parser.parse(&line).map_err(|err| err.at_line(number))? {
+                // refusals are part of the replayed history, not errors
+                let _ = self.insert_txn(txn);
+                self.check_spill()?;
+                replayed += 1;
+            }
+        }
+        Ok((replayed, end))
+    }
+
+    // insert_txn, writing the transaction to the log first if there is one. The outer error is
+    // a failure to log it, in which case the transaction isn't applied, or to write out its
+    // audit log entry.
+    pub fn insert_logged(
+        &mut self,
+        txn: Transaction,
+    ) -> Result<Result<TxnOutcome, TxnError>, Error> {
+        self.insert_read_logged(txn, None)
+    }
+
+    // insert_logged for a transaction as read, as Bank::insert_read. One already refused isn't
+    // logged, since replaying the log couldn't tell it apart from one to apply.
+    pub(crate) fn insert_read_logged(
+        &mut self,
+        txn: Transaction,
+        refusal: Option<TxnError>,
+    ) -> Result<Result<TxnOutcome, TxnError>, Error> {
+        let started = Instant::now();
+        if let Some(wal) = self.wal.as_mut().filter(|_| refusal.is_none()) {
+            wal.append(&txn)?;
+        }
+        let result = self.insert_read(txn, refusal);
+        self.measured(&txn, &result, started.elapsed());
+        self.check_spill()?;
+        self.flush_audit()?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,59 @@
+// The engine for JavaScript, so a browser-based tool can check and preview a transaction file
+// before it's uploaded. Build with wasm-pack, leaving out the default features (rayon's thread
+// pool has no threads to run on in a browser):
+//
+//   wasm-pack build --target web --no-default-features --features wasm
+//
+// and call `process_csv(new Uint8Array(await file.arrayBuffer()))`.
+
+use crate::bank::Bank;
+use crate::error::Error;
+use crate::policy::{OnError, Policy};
+use crate::report::JsonRecord;
+use crate::source::TransactionSource;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+#[derive(Serialize)]
+struct Preview {
+    // the report, as --output-format json writes it
+    accounts: Vec<JsonRecord>,
+    rows: u64,
+    rejected: u64,
+    rejected_by: BTreeMap<&'static str, u64>,
+    skipped: u64,
+    // what was wrong with each malformed row, with its line
+    errors: Vec<String>,
+}
+
+// Applies a CSV file to an empty bank with the default policies and returns the report as JSON,
+// along with how many rows were rejected and why. Malformed rows are skipped and listed so one
+// bad line doesn't hide the rest of the preview; only an unreadable file is an error.
+#[wasm_bindgen]
+pub fn process_csv(bytes: &[u8]) -> Result<String, JsError> {
+    report_json(bytes).map_err(|err| JsError::new(&err.to_string()))
+}
+
+// process_csv's result, for callers outside a browser
+pub fn report_json(bytes: &[u8]) -> Result<String, Error> {
+    let mut bank = Bank::new();
+    bank.set_policy(Policy {
+        on_error: OnError::Report,
+        ..Policy::default()
+    });
+    let stats = bank.process_source(TransactionSource::csv(bytes))?;
+    let preview = Preview {
+        accounts: bank
+            .sorted_records()
+            .into_iter()
+            .map(JsonRecord::from)
+            .collect(),
+        rows: stats.rows,
+        rejected: stats.rejected,
+        rejected_by: stats.rejected_by,
+        skipped: stats.skipped,
+        errors: stats.errors.iter().map(Error::to_string).collect(),
+    };
+    Ok(serde_json::to_string(&preview)?)
+}
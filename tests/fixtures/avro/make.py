@@ -0,0 +1,236 @@
+#!/usr/bin/env python3
+# Writes the Avro container files avro.rs's tests read, laid out from the Avro specification
+# (avro.apache.org/docs/current/specification) with the standard library, the zstd command line
+# tool and the Snappy compressor in ../parquet/make.py, independently of the Rust code: no Avro
+# library is at hand to write them. Each follows what the Java and Python writers put out for
+# its codec:
+#
+#   null.avro       a namespaced record with an enum type, a decimal in bytes, millisecond
+#                   timestamps, nullable unions, and fields the reader skips: a map, an array,
+#                   a nested record and a reference back to it by name; two blocks
+#   deflate.avro    raw deflate blocks, a double amount, nullable to and effective_date
+#   snappy.avro     Snappy blocks each followed by the CRC-32 of what they hold, a decimal in a
+#                   fixed, microsecond timestamps, the metadata in a block with a byte size
+#   zstandard.avro  zstd blocks, a string amount
+#   bzip2.avro      a codec the reader doesn't know
+#   bad_enum.avro   a type enum with a symbol that isn't a transaction type
+#
+# Run it from this directory to write them again: python3 make.py
+
+import json
+import os
+import struct
+import subprocess
+import sys
+import zlib
+
+sys.path.insert(0, os.path.join(os.path.dirname(os.path.abspath(__file__)), "..", "parquet"))
+from make import snappy  # noqa: E402
+
+SYNC = bytes(range(0x10, 0x20))
+
+
+def long(v):
+    v = (v << 1) ^ (v >> 63)
+    out = bytearray()
+    while v >= 0x80:
+        out.append(v & 0x7F | 0x80)
+        v >>= 7
+    out.append(v)
+    return bytes(out)
+
+
+def string(s):
+    s = s.encode() if isinstance(s, str) else s
+    return long(len(s)) + s
+
+
+def decimal_bytes(unscaled):
+    # the shortest big-endian two's complement that holds it
+    size = 1
+    while not -(1 << (8 * size - 1)) <= unscaled < 1 << (8 * size - 1):
+        size += 1
+    return string(unscaled.to_bytes(size, "big", signed=True))
+
+
+def container(path, schema, codec, blocks, sized_metadata=False):
+    metadata = [("avro.schema", json.dumps(schema)), ("avro.codec", codec)]
+    entries = b"".join(string(k) + string(v) for k, v in metadata)
+    out = bytearray(b"Obj\x01")
+    if sized_metadata:
+        out += long(-len(metadata)) + long(len(entries))
+    else:
+        out += long(len(metadata))
+    out += entries + long(0) + SYNC
+    for records in blocks:
+        data = b"".join(records)
+        if codec == "deflate":
+            packer = zlib.compressobj(9, zlib.DEFLATED, -15)
+            data = packer.compress(data) + packer.flush()
+        elif codec == "snappy":
+            data = snappy(data) + struct.pack(">I", zlib.crc32(data))
+        elif codec == "zstandard":
+            data = subprocess.run(["zstd", "-q", "-c"], input=data, capture_output=True,
+                                  check=True).stdout
+        out += long(len(records)) + long(len(data)) + data + SYNC
+    with open(path, "wb") as f:
+        f.write(out)
+
+
+def null():
+    schema = {
+        "type": "record",
+        "name": "Transaction",
+        "namespace": "com.example.ledger",
+        "fields": [
+            {"name": "type", "type": {"type": "enum", "name": "Kind",
+                                      "symbols": ["deposit", "withdrawal", "dispute", "resolve",
+                                                  "chargeback"]}},
+            {"name": "client", "type": "int"},
+            {"name": "tx", "type": "long"},
+            {"name": "amount", "type": ["null", {"type": "bytes", "logicalType": "decimal",
+                                                 "precision": 18, "scale": 4}]},
+            {"name": "labels", "type": {"type": "map", "values": "string"}},
+            {"name": "timestamp", "type": ["null", {"type": "long",
+                                                    "logicalType": "timestamp-millis"}]},
+            {"name": "origin", "type": {"type": "record", "name": "Origin", "fields": [
+                {"name": "system", "type": "string"},
+                {"name": "id", "type": {"type": "fixed", "name": "Id", "size": 4}},
+            ]}},
+            {"name": "tags", "type": {"type": "array", "items": "string"}},
+            {"name": "currency", "type": ["null", "string"]},
+            {"name": "earlier", "type": ["null", "com.example.ledger.Origin"]},
+        ],
+    }
+    kinds = schema["fields"][0]["type"]["symbols"]
+
+    def record(kind, client, tx, amount, ts, currency=None, labels=(), tags=(), earlier=False):
+        out = long(kinds.index(kind)) + long(client) + long(tx)
+        out += long(0) if amount is None else long(1) + decimal_bytes(amount)
+        if labels:
+            out += long(len(labels)) + b"".join(string(k) + string(v) for k, v in labels)
+        out += long(0)
+        out += long(0) if ts is None else long(1) + long(ts)
+        out += string("core") + struct.pack(">I", tx)
+        if tags:
+            # a block given with its size in bytes, as writers may to let readers skip it
+            items = b"".join(string(t) for t in tags)
+            out += long(-len(tags)) + long(len(items)) + items
+        out += long(0)
+        out += long(0) if currency is None else long(1) + string(currency)
+        out += long(1) + string("batch") + b"\x00\x00\x00\x01" if earlier else long(0)
+        return out
+
+    container("null.avro", schema, "null", [
+        [
+            record("deposit", 1, 1, 100_000, 1_700_000_000_123, labels=[("via", "branch")]),
+            record("deposit", 2, 2, 5_000, None, currency="USD", tags=["a", "bc"]),
+            record("withdrawal", 1, 3, 25_000, 1_700_000_060_999, earlier=True),
+            record("dispute", 1, 1, None, 1_700_000_120_000),
+        ],
+        [
+            record("resolve", 1, 1, None, None),
+            record("deposit", 1, 4, 21_234, None),
+            # a value whose top byte has its high bit set, so it needs a zero byte before it
+            record("deposit", 3, 5, 32_768, 1_700_000_240_000, labels=[("a", "1"), ("b", "2")]),
+        ],
+    ])
+
+
+def deflate():
+    schema = {
+        "type": "record",
+        "name": "Transaction",
+        "fields": [
+            {"name": "type", "type": "string"},
+            {"name": "client", "type": "long"},
+            {"name": "tx", "type": "long"},
+            {"name": "amount", "type": "double"},
+            {"name": "to", "type": ["null", "long"], "default": None},
+            {"name": "effective_date", "type": ["null", "string"], "default": None},
+        ],
+    }
+    records = []
+    for i in range(1, 51):
+        transfer = i % 10 == 0
+        out = string("transfer" if transfer else "deposit") + long(i % 3 + 1) + long(i)
+        out += struct.pack("<d", i * 0.25)
+        out += long(1) + long(i % 3 + 2) if transfer else long(0)
+        out += long(1) + string("2024-02-%02d" % (i % 28 + 1)) if i % 4 == 0 else long(0)
+        records.append(out)
+    container("deflate.avro", schema, "deflate", [records[:20], records[20:45], records[45:]])
+
+
+def snappy_():
+    schema = {
+        "type": "record",
+        "name": "Transaction",
+        "fields": [
+            {"name": "type", "type": "string"},
+            {"name": "client", "type": "int"},
+            {"name": "tx", "type": "int"},
+            {"name": "amount", "type": {"type": "fixed", "name": "Amount", "size": 8,
+                                        "logicalType": "decimal", "precision": 18, "scale": 4}},
+            {"name": "timestamp", "type": {"type": "long", "logicalType": "timestamp-micros"}},
+            {"name": "currency", "type": "string"},
+        ],
+    }
+    records = []
+    for i in range(1, 201):
+        out = string("deposit" if i % 4 else "withdrawal") + long(i % 7 + 1) + long(i)
+        out += (i * 1001).to_bytes(8, "big", signed=True)
+        out += long(1_700_000_000_000_000 + i * 250_000) + string("EUR")
+        records.append(out)
+    container("snappy.avro", schema, "snappy",
+              [records[at : at + 64] for at in range(0, 200, 64)], sized_metadata=True)
+
+
+def zstandard():
+    schema = {
+        "type": "record",
+        "name": "Transaction",
+        "fields": [
+            {"name": "type", "type": "string"},
+            {"name": "client", "type": "int"},
+            {"name": "tx", "type": "int"},
+            {"name": "amount", "type": "string"},
+        ],
+    }
+    records = [
+        string("deposit") + long(1) + long(1) + string("1.25"),
+        string("withdrawal") + long(1) + long(2) + string("0.5"),
+    ]
+    container("zstandard.avro", schema, "zstandard", [records])
+
+
+def bzip2():
+    schema = {
+        "type": "record",
+        "name": "Transaction",
+        "fields": [{"name": name, "type": "string"} for name in ["type", "client", "tx", "amount"]],
+    }
+    container("bzip2.avro", schema, "bzip2", [])
+
+
+def bad_enum():
+    schema = {
+        "type": "record",
+        "name": "Transaction",
+        "fields": [
+            {"name": "type", "type": {"type": "enum", "name": "Kind",
+                                      "symbols": ["deposit", "reversal"]}},
+            {"name": "client", "type": "int"},
+            {"name": "tx", "type": "int"},
+            {"name": "amount", "type": "double"},
+        ],
+    }
+    container("bad_enum.avro", schema, "null", [])
+
+
+if __name__ == "__main__":
+    null()
+    deflate()
+    snappy_()
+    zstandard()
+    bzip2()
+    bad_enum()
@@ -0,0 +1,566 @@
+#!/usr/bin/env python3
+# Writes the Parquet files parquet.rs's tests read. Every byte is laid out here from the format
+# spec (github.com/apache/parquet-format) with nothing but the standard library and the zstd
+# command line tool, independently of the Rust code: no Parquet library is at hand to write them.
+# Each file follows the layout of a writer the reader has to handle, as that writer documents it:
+#
+#   dictionary_snappy.parquet  pyarrow's defaults: every column optional and dictionary encoded
+#                              (RLE_DICTIONARY over a PLAIN dictionary page), v1 data pages,
+#                              Snappy, three row groups, logical types as well as converted ones
+#   spark_int96.parquet        Spark's parquet-mr v1 writer: PLAIN_DICTIONARY pages, a chunk that
+#                              falls back to PLAIN part way, DECIMAL(18, 4) in an INT64, INT96
+#                              timestamps, page CRCs
+#   v2_delta_zstd.parquet      parquet-mr's v2 writer: DATA_PAGE_V2, DELTA_BINARY_PACKED integers
+#                              over more than one block, DECIMAL(20, 4) in a FIXED_LEN_BYTE_ARRAY,
+#                              millisecond timestamps as a logical type only, zstd, and a page
+#                              left uncompressed
+#   plain_gzip.parquet         required PLAIN columns in another order, with one the reader
+#                              doesn't know, DECIMAL(9, 2) in an INT32, gzip, two row groups
+#   brotli.parquet             an amount column marked as Brotli compressed
+#   nested.parquet             a footer whose schema has a nested group
+#
+# Run it from this directory to write them again: python3 make.py
+
+import gzip
+import struct
+import subprocess
+import zlib
+
+# --- Thrift compact protocol ---
+
+I32, I64, BINARY, LIST, STRUCT, BYTE = 5, 6, 8, 9, 12, 3
+
+
+def uleb(v):
+    out = bytearray()
+    while v >= 0x80:
+        out.append(v & 0x7F | 0x80)
+        v >>= 7
+    out.append(v)
+    return bytes(out)
+
+
+def zigzag(v):
+    return (v << 1) ^ (v >> 63)
+
+
+# A struct is a list of (id, kind, value): kind one of "i32", "i64", "byte", "bool", "bin",
+# "struct" (a list of fields), "list_i32", "list_bin" or "list_struct"
+def struct_(fields):
+    out = bytearray()
+    last = 0
+    for fid, kind, value in fields:
+        ty = {
+            "i32": I32,
+            "i64": I64,
+            "byte": BYTE,
+            "bin": BINARY,
+            "struct": STRUCT,
+            "list_i32": LIST,
+            "list_bin": LIST,
+            "list_struct": LIST,
+        }.get(kind)
+        if kind == "bool":
+            ty = 1 if value else 2
+        delta = fid - last
+        if 0 < delta <= 15:
+            out.append(delta << 4 | ty)
+        else:
+            out.append(ty)
+            out += uleb(zigzag(fid))
+        last = fid
+        if kind in ("i32", "i64"):
+            out += uleb(zigzag(value))
+        elif kind == "byte":
+            out.append(value & 0xFF)
+        elif kind == "bin":
+            value = value.encode() if isinstance(value, str) else value
+            out += uleb(len(value)) + value
+        elif kind == "struct":
+            out += struct_(value)
+        elif kind.startswith("list_"):
+            elem = {"list_i32": I32, "list_bin": BINARY, "list_struct": STRUCT}[kind]
+            out.append(len(value) << 4 | elem if len(value) < 15 else 0xF0 | elem)
+            if len(value) >= 15:
+                out += uleb(len(value))
+            for v in value:
+                if elem == I32:
+                    out += uleb(zigzag(v))
+                elif elem == BINARY:
+                    v = v.encode() if isinstance(v, str) else v
+                    out += uleb(len(v)) + v
+                else:
+                    out += struct_(v)
+    out.append(0)
+    return bytes(out)
+
+
+# --- codecs ---
+
+UNCOMPRESSED, SNAPPY, GZIP, BROTLI, ZSTD = 0, 1, 2, 4, 6
+
+
+def snappy(data):
+    # greedy matching of 4-byte sequences, emitting the three element kinds a block can have
+    out = bytearray(uleb(len(data)))
+    table = {}
+    literal = 0
+    i = 0
+
+    def emit_literal(start, end):
+        n = end - start - 1
+        if n < 60:
+            out.append(n << 2)
+        else:
+            size = (n.bit_length() + 7) // 8
+            out.append((59 + size) << 2)
+            out.extend(n.to_bytes(size, "little"))
+        out.extend(data[start:end])
+
+    def emit_copy(offset, length):
+        while length > 0:
+            n = min(length, 64)
+            if length - n in (1, 2, 3):
+                n = length - 4
+            if 4 <= n <= 11 and offset < 2048:
+                out.append((offset >> 8) << 5 | (n - 4) << 2 | 1)
+                out.append(offset & 0xFF)
+            elif offset < 65536:
+                out.append((n - 1) << 2 | 2)
+                out.extend(offset.to_bytes(2, "little"))
+            else:
+                out.append((n - 1) << 2 | 3)
+                out.extend(offset.to_bytes(4, "little"))
+            length -= n
+
+    while i + 4 <= len(data):
+        key = data[i : i + 4]
+        candidate = table.get(key)
+        table[key] = i
+        if candidate is not None:
+            length = 4
+            while i + length < len(data) and data[candidate + length] == data[i + length]:
+                length += 1
+            if literal < i:
+                emit_literal(literal, i)
+            emit_copy(i - candidate, length)
+            i += length
+            literal = i
+        else:
+            i += 1
+    if literal < len(data):
+        emit_literal(literal, len(data))
+    return bytes(out)
+
+
+def compress(codec, data):
+    if codec == UNCOMPRESSED or codec == BROTLI:
+        return data
+    if codec == SNAPPY:
+        return snappy(data)
+    if codec == GZIP:
+        return gzip.compress(data, mtime=0)
+    if codec == ZSTD:
+        return subprocess.run(["zstd", "-q", "-c"], input=data, capture_output=True, check=True).stdout
+    raise ValueError(codec)
+
+
+# --- encodings ---
+
+PLAIN, PLAIN_DICTIONARY, RLE, DELTA_BINARY_PACKED, RLE_DICTIONARY = 0, 2, 3, 5, 8
+DATA_PAGE, DICTIONARY_PAGE, DATA_PAGE_V2 = 0, 2, 3
+BOOLEAN, INT32, INT64, INT96, FLOAT, DOUBLE, BYTE_ARRAY, FIXED_LEN_BYTE_ARRAY = range(8)
+REQUIRED, OPTIONAL = 0, 1
+
+
+def pack(values, width):
+    # groups of eight values, lowest bits first
+    bits = 0
+    for i, v in enumerate(values):
+        bits |= v << (i * width)
+    return bits.to_bytes(len(values) * width // 8, "little")
+
+
+def rle(values, width):
+    # the RLE/bit-packing hybrid: runs of eight or more repeats as one value, the rest bit-packed
+    # eight at a time, the last group padded
+    out = bytearray()
+    pending = []
+
+    def flush():
+        if pending:
+            groups = (len(pending) + 7) // 8
+            out.extend(uleb(groups << 1 | 1))
+            out.extend(pack(pending + [0] * (groups * 8 - len(pending)), width))
+            pending.clear()
+
+    i = 0
+    while i < len(values):
+        run = 1
+        while i + run < len(values) and values[i + run] == values[i]:
+            run += 1
+        # a bit-packed group has to be whole before a run can follow it
+        while len(pending) % 8 and run:
+            pending.append(values[i])
+            i += 1
+            run -= 1
+        if run >= 8:
+            flush()
+            out.extend(uleb(run << 1))
+            out.extend(values[i].to_bytes((width + 7) // 8, "little"))
+        else:
+            pending.extend(values[i : i + run])
+        i += run
+    flush()
+    return bytes(out)
+
+
+def delta(values, block=128, miniblocks=4):
+    # DELTA_BINARY_PACKED: the first value, then blocks of differences over the block's minimum,
+    # bit-packed per miniblock
+    out = bytearray(uleb(block) + uleb(miniblocks) + uleb(len(values)))
+    out += uleb(zigzag(values[0]) if values else 0)
+    deltas = [b - a for a, b in zip(values, values[1:])]
+    per = block // miniblocks
+    for start in range(0, len(deltas), block):
+        chunk = deltas[start : start + block]
+        low = min(chunk)
+        out += uleb(zigzag(low))
+        minis = [[d - low for d in chunk[j : j + per]] for j in range(0, len(chunk), per)]
+        widths = [max(m).bit_length() for m in minis]
+        # the widths of miniblocks the last block doesn't need are still written, as zero
+        out += bytes(widths + [0] * (miniblocks - len(minis)))
+        for m, width in zip(minis, widths):
+            out += pack(m + [0] * (per - len(m)), width)
+    return bytes(out)
+
+
+def plain(physical, values, length=0):
+    out = bytearray()
+    if physical == BOOLEAN:
+        return pack(values + [0] * (-len(values) % 8), 1)
+    for v in values:
+        if physical == INT32:
+            out += struct.pack("<i", v)
+        elif physical == INT64:
+            out += struct.pack("<q", v)
+        elif physical == INT96:
+            seconds, nanos = v
+            day, second = divmod(seconds, 86400)
+            out += struct.pack("<qI", second * 1_000_000_000 + nanos, day + 2_440_588)
+        elif physical == DOUBLE:
+            out += struct.pack("<d", v)
+        elif physical == BYTE_ARRAY:
+            v = v.encode()
+            out += struct.pack("<I", len(v)) + v
+        elif physical == FIXED_LEN_BYTE_ARRAY:
+            out += v.to_bytes(length, "big", signed=True)
+    return bytes(out)
+
+
+def width(n):
+    return (n - 1).bit_length() if n > 1 else 0
+
+
+# --- file layout ---
+
+
+class Column:
+    def __init__(self, name, physical, optional=True, length=0, converted=None, scale=None,
+                 precision=None, logical=None, encoding="dictionary", codec=SNAPPY):
+        self.name = name
+        self.physical = physical
+        self.optional = optional
+        self.length = length
+        self.converted = converted
+        self.scale = scale
+        self.precision = precision
+        self.logical = logical
+        # "dictionary", "plain", "delta", or for a chunk that falls back to PLAIN when its
+        # dictionary grows, "fallback" after so many values
+        self.encoding = encoding
+        self.codec = codec
+
+    def schema(self):
+        fields = [(1, "i32", self.physical)]
+        if self.length:
+            fields.append((2, "i32", self.length))
+        fields += [(3, "i32", OPTIONAL if self.optional else REQUIRED), (4, "bin", self.name)]
+        if self.converted is not None:
+            fields.append((6, "i32", self.converted))
+        if self.scale is not None:
+            fields += [(7, "i32", self.scale), (8, "i32", self.precision)]
+        if self.logical is not None:
+            fields.append((10, "struct", self.logical))
+        return fields
+
+
+STRING = [(1, "struct", [])]
+
+
+def decimal(scale, precision):
+    return [(5, "struct", [(1, "i32", scale), (2, "i32", precision)])]
+
+
+def timestamp(unit):
+    return [(8, "struct", [(1, "bool", False), (2, "struct", [(unit, "struct", [])])])]
+
+
+def integer(bits, signed):
+    return [(10, "struct", [(1, "byte", bits), (2, "bool", signed)])]
+
+
+class Writer:
+    def __init__(self, columns, version, page_version=1, dictionary_encoding=RLE_DICTIONARY,
+                 crc=False, created_by="make.py", rows_per_page=None, uncompressed_pages=()):
+        self.columns = columns
+        self.version = version
+        self.page_version = page_version
+        # PLAIN_DICTIONARY for parquet-mr's v1 writer, RLE_DICTIONARY for everything newer
+        self.dictionary_encoding = dictionary_encoding
+        self.crc = crc
+        self.created_by = created_by
+        self.rows_per_page = rows_per_page
+        # (column name, page number) of pages written with is_compressed false
+        self.uncompressed_pages = uncompressed_pages
+        self.out = bytearray(b"PAR1")
+        self.groups = []
+        self.rows = 0
+
+    def page(self, kind, header, uncompressed, body):
+        fields = [(1, "i32", kind), (2, "i32", uncompressed), (3, "i32", len(body))]
+        if self.crc:
+            fields.append((4, "i32", struct.unpack("<i", struct.pack("<I", zlib.crc32(body)))[0]))
+        self.out += struct_(fields + header) + body
+
+    def chunk(self, column, values):
+        start = len(self.out)
+        present = [v for v in values if v is not None]
+        # where a chunk that falls back to PLAIN stops using its dictionary
+        fallback = len(values)
+        if column.encoding.startswith("fallback"):
+            fallback = int(column.encoding.split()[1])
+        dictionary = None
+        encodings = {RLE, PLAIN}
+        if column.encoding != "plain" and column.encoding != "delta":
+            dictionary = list(dict.fromkeys(v for v in values[:fallback] if v is not None))
+            data = plain(column.physical, dictionary, column.length)
+            encoding = PLAIN_DICTIONARY if self.dictionary_encoding == PLAIN_DICTIONARY else PLAIN
+            header = [(7, "struct", [(1, "i32", len(dictionary)), (2, "i32", encoding)])]
+            self.page(DICTIONARY_PAGE, header, len(data), compress(column.codec, data))
+            encodings.add(self.dictionary_encoding)
+        data_offset = len(self.out)
+        per_page = self.rows_per_page or len(values)
+        bounds = sorted({*range(0, len(values), per_page), fallback} - {len(values)})
+        for number, (at, to) in enumerate(zip(bounds, bounds[1:] + [len(values)])):
+            page = values[at:to]
+            page_present = [v for v in page if v is not None]
+            if dictionary is not None and at < fallback:
+                bits = width(len(dictionary))
+                encoded = bytes([bits]) + rle([dictionary.index(v) for v in page_present], bits)
+                encoding = self.dictionary_encoding
+            elif column.encoding == "delta":
+                encoded = delta(page_present)
+                encoding = DELTA_BINARY_PACKED
+            else:
+                encoded = plain(column.physical, page_present, column.length)
+                encoding = PLAIN
+            encodings.add(encoding)
+            levels = rle([int(v is not None) for v in page], 1) if column.optional else b""
+            if self.page_version == 1:
+                if column.optional:
+                    levels = struct.pack("<I", len(levels)) + levels
+                header = [(5, "struct", [(1, "i32", len(page)), (2, "i32", encoding),
+                                         (3, "i32", RLE), (4, "i32", RLE)])]
+                data = levels + encoded
+                self.page(DATA_PAGE, header, len(data), compress(column.codec, data))
+            else:
+                compressed = (column.name, number) not in self.uncompressed_pages
+                body = compress(column.codec, encoded) if compressed else encoded
+                header = [(8, "struct", [(1, "i32", len(page)), (2, "i32", len(page) - len(page_present)),
+                                         (3, "i32", len(page)), (4, "i32", encoding),
+                                         (5, "i32", len(levels)), (6, "i32", 0),
+                                         (7, "bool", compressed)])]
+                self.page(DATA_PAGE_V2, header, len(levels) + len(encoded), levels + body)
+        size = len(self.out) - start
+        stats = [(3, "i64", len(values) - len(present))]
+        if present and column.physical in (INT32, INT64):
+            stats = [(1, "bin", plain(column.physical, [max(present)])),
+                     (2, "bin", plain(column.physical, [min(present)]))] + stats
+        meta = [(1, "i32", column.physical), (2, "list_i32", sorted(encodings)),
+                (3, "list_bin", [column.name]), (4, "i32", column.codec), (5, "i64", len(values)),
+                (6, "i64", size), (7, "i64", size), (9, "i64", data_offset)]
+        if dictionary is not None:
+            meta.append((11, "i64", start))
+        meta.append((12, "struct", stats))
+        return [(2, "i64", start), (3, "struct", meta)], size
+
+    def row_group(self, rows):
+        chunks = []
+        total = 0
+        for column in self.columns:
+            chunk, size = self.chunk(column, [row.get(column.name) for row in rows])
+            chunks.append(chunk)
+            total += size
+        self.groups.append([(1, "list_struct", chunks), (2, "i64", total), (3, "i64", len(rows))])
+        self.rows += len(rows)
+
+    def finish(self, path, key_values=()):
+        schema = [[(4, "bin", "schema"), (5, "i32", len(self.columns))]]
+        schema += [column.schema() for column in self.columns]
+        footer = [(1, "i32", self.version), (2, "list_struct", schema), (3, "i64", self.rows),
+                  (4, "list_struct", self.groups)]
+        if key_values:
+            footer.append((5, "list_struct", [[(1, "bin", k), (2, "bin", v)] for k, v in key_values]))
+        footer.append((6, "bin", self.created_by))
+        footer.append((7, "list_struct", [[(1, "struct", [])] for _ in self.columns]))
+        footer = struct_(footer)
+        self.out += footer + struct.pack("<I", len(footer)) + b"PAR1"
+        with open(path, "wb") as f:
+            f.write(self.out)
+
+
+UTF8, DECIMAL, TIMESTAMP_MILLIS, TIMESTAMP_MICROS, UINT_16 = 0, 5, 9, 10, 12
+
+
+def dictionary_snappy():
+    w = Writer([
+        Column("type", BYTE_ARRAY, converted=UTF8, logical=STRING),
+        Column("client", INT32, converted=UINT_16, logical=integer(16, False)),
+        Column("tx", INT64),
+        Column("amount", DOUBLE),
+        Column("timestamp", INT64, converted=TIMESTAMP_MICROS, logical=timestamp(2)),
+    ], version=2)
+    ts = lambda s: (1_700_000_000 + s) * 1_000_000 + 250_000
+    w.row_group([
+        {"type": "deposit", "client": 1, "tx": 1, "amount": 10.5, "timestamp": ts(0)},
+        {"type": "deposit", "client": 2, "tx": 2, "amount": 2.25, "timestamp": ts(60)},
+        {"type": "withdrawal", "client": 1, "tx": 3, "amount": 1.5},
+        {"type": "dispute", "client": 1, "tx": 1, "timestamp": ts(180)},
+    ])
+    # one distinct type, so its indices are 0 bits wide
+    w.row_group([
+        {"type": "deposit", "client": 1, "tx": 4, "amount": 0.1, "timestamp": ts(240)},
+        {"type": "deposit", "client": 2, "tx": 5, "amount": 7.125, "timestamp": ts(300)},
+        {"type": "deposit", "client": 1, "tx": 6, "amount": 3.0, "timestamp": ts(360)},
+    ])
+    w.row_group([
+        {"type": "resolve", "client": 1, "tx": 1},
+        {"type": "withdrawal", "client": 2, "tx": 7, "amount": 1.25, "timestamp": ts(480)},
+    ])
+    w.finish("dictionary_snappy.parquet", [("pandas", '{"index_columns": []}')])
+
+
+def spark_int96():
+    w = Writer([
+        Column("type", BYTE_ARRAY, converted=UTF8),
+        Column("client", INT32),
+        Column("tx", INT64, encoding="fallback 6"),
+        Column("amount", INT64, converted=DECIMAL, scale=4, precision=18),
+        Column("timestamp", INT96),
+        Column("currency", BYTE_ARRAY, converted=UTF8),
+    ], version=1, dictionary_encoding=PLAIN_DICTIONARY, crc=True)
+    rows = [
+        ("deposit", 1, 1, 1_005_000, None),
+        ("deposit", 2, 2, 201_234, None),
+        ("withdrawal", 1, 3, 5_000, None),
+        ("dispute", 2, 2, None, None),
+        ("chargeback", 2, 2, None, None),
+        ("deposit", 3, 4, 10_000, None),
+        ("deposit", 3, 5, 20_000, None),
+        ("withdrawal", 3, 6, 25_000, None),
+        ("dispute", 3, 5, None, None),
+        ("deposit", 4, 7, 123_400, "EUR"),
+        ("deposit", 4, 8, 1, "EUR"),
+        ("withdrawal", 4, 9, 50_000, "EUR"),
+    ]
+    w.row_group([
+        {"type": t, "client": c, "tx": tx, "amount": a, "currency": cur,
+         # nanoseconds into the second, which a whole-second timestamp drops
+         "timestamp": (1_700_000_000 + 60 * i, 999_999_999 if i % 2 else 0)}
+        for i, (t, c, tx, a, cur) in enumerate(rows)
+    ])
+    w.finish("spark_int96.parquet", [("org.apache.spark.version", "3.5.0")])
+
+
+def v2_rows():
+    rows = []
+    for i in range(1, 301):
+        client = i % 5 + 1
+        row = {"client": client, "tx": i, "amount": i * 12_345 % 1_000_000}
+        if i % 10 == 0:
+            row["type"], row["to"] = "transfer", client % 5 + 1
+        else:
+            row["type"] = "deposit"
+        if i % 7:
+            row["timestamp"] = 1_700_000_000_000 + i * 1_500
+        if i % 3 == 0:
+            row["effective_date"] = "2024-01-%02d" % (i % 28 + 1)
+        rows.append(row)
+    return rows
+
+
+def v2_delta_zstd():
+    w = Writer([
+        Column("type", BYTE_ARRAY, optional=False, converted=UTF8, logical=STRING, codec=ZSTD),
+        Column("client", INT32, optional=False, encoding="delta", codec=ZSTD),
+        Column("tx", INT64, optional=False, encoding="delta", codec=ZSTD),
+        Column("amount", FIXED_LEN_BYTE_ARRAY, optional=False, length=9, converted=DECIMAL, scale=4,
+               precision=20, logical=decimal(4, 20), encoding="plain", codec=ZSTD),
+        Column("to", INT64, encoding="delta", codec=ZSTD),
+        Column("timestamp", INT64, logical=timestamp(1), encoding="delta", codec=ZSTD),
+        Column("effective_date", BYTE_ARRAY, converted=UTF8, logical=STRING, codec=ZSTD),
+    ], version=2, page_version=2, rows_per_page=150, uncompressed_pages=[("effective_date", 1)])
+    w.row_group(v2_rows())
+    w.finish("v2_delta_zstd.parquet")
+
+
+def plain_gzip():
+    w = Writer([
+        Column("amount", INT32, optional=False, converted=DECIMAL, scale=2, precision=9,
+               logical=decimal(2, 9), encoding="plain", codec=GZIP),
+        Column("memo", BYTE_ARRAY, optional=False, converted=UTF8, encoding="plain", codec=GZIP),
+        Column("tx", INT32, optional=False, encoding="plain", codec=GZIP),
+        Column("client", INT64, optional=False, encoding="plain", codec=GZIP),
+        Column("type", BYTE_ARRAY, optional=False, converted=UTF8, encoding="plain", codec=GZIP),
+    ], version=1)
+    row = lambda t, c, tx, a: {"type": t, "client": c, "tx": tx, "amount": a, "memo": "m%d" % tx}
+    w.row_group([row("deposit", 7, 100, 150), row("deposit", 8, 101, 99_999_999)])
+    w.row_group([row("withdrawal", 7, 102, 25), row("dispute", 8, 101, 0), row("resolve", 8, 101, 0)])
+    w.finish("plain_gzip.parquet")
+
+
+def brotli():
+    w = Writer([
+        Column("type", BYTE_ARRAY, optional=False, converted=UTF8, encoding="plain", codec=UNCOMPRESSED),
+        Column("client", INT32, optional=False, encoding="plain", codec=UNCOMPRESSED),
+        Column("tx", INT32, optional=False, encoding="plain", codec=UNCOMPRESSED),
+        Column("amount", DOUBLE, optional=False, encoding="plain", codec=BROTLI),
+    ], version=1)
+    w.row_group([{"type": "deposit", "client": 1, "tx": 1, "amount": 1.0}])
+    w.finish("brotli.parquet")
+
+
+def nested():
+    schema = [
+        [(4, "bin", "schema"), (5, "i32", 5)],
+        Column("type", BYTE_ARRAY, converted=UTF8).schema(),
+        Column("client", INT32).schema(),
+        Column("tx", INT32).schema(),
+        Column("amount", DOUBLE).schema(),
+        [(3, "i32", OPTIONAL), (4, "bin", "meta"), (5, "i32", 1)],
+        Column("note", BYTE_ARRAY, converted=UTF8).schema(),
+    ]
+    footer = struct_([(1, "i32", 1), (2, "list_struct", schema), (3, "i64", 0),
+                      (4, "list_struct", []), (6, "bin", "make.py")])
+    with open("nested.parquet", "wb") as f:
+        f.write(b"PAR1" + footer + struct.pack("<I", len(footer)) + b"PAR1")
+
+
+if __name__ == "__main__":
+    dictionary_snappy()
+    spark_int96()
+    v2_delta_zstd()
+    plain_gzip()
+    brotli()
+    nested()